@@ -0,0 +1,192 @@
+// Copyright (c) 2021 Dentrax
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software is furnished to do so,
+// subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+use crate::api_builder::ApiBuilder;
+use crate::error::Result;
+use crate::eval;
+use crate::parser::ApiAggregates;
+use crate::planner::{Aggregate, GroupBy, Having, Projection};
+use crate::quantity;
+use k8s_openapi::api::core::v1::{Namespace, Node, Pod};
+use kube::api::ListParams;
+use kube::{Api, ResourceExt};
+use sqlparser::ast::BinaryOperator;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+#[derive(Debug)]
+pub struct AggregateRow {
+    pub context: String,
+    /// The name of the grouped resource, i.e. node name
+    pub group: String,
+    /// Values of the SELECT columns, in the same order
+    pub values: Vec<String>,
+}
+
+/// The pods of a group, with the grouped resource itself, i.e. the node
+struct Group {
+    name: String,
+    object: serde_json::Value,
+    pods: Vec<serde_json::Value>,
+}
+
+/// Runs the given GROUP BY statement on all given contexts
+pub async fn aggregate(aggregates: &ApiAggregates) -> Result<Vec<AggregateRow>> {
+    let mut rows: Vec<AggregateRow> = Vec::new();
+
+    for ctx in &aggregates.contexts {
+        let api = ApiBuilder::builder().context(ctx.clone()).build().await?;
+
+        let groups = match aggregates.group_by {
+            GroupBy::Node => group_by_node(&api).await?,
+            GroupBy::Namespace => group_by_namespace(&api).await?,
+        };
+
+        for group in groups {
+            // HAVING ...
+            if !aggregates.having.iter().all(|h| having(h, &group)) {
+                continue;
+            }
+
+            rows.push(AggregateRow {
+                context: ctx.clone(),
+                values: aggregates
+                    .columns
+                    .iter()
+                    .map(|c| value(c, &group))
+                    .collect(),
+                group: group.name,
+            });
+        }
+    }
+
+    Ok(rows)
+}
+
+/// Terminated pods do not allocate any resources anymore, so they are excluded
+async fn pods(api: &ApiBuilder<'_>) -> Result<Vec<Pod>> {
+    Ok(Api::<Pod>::all(api.get_client().clone())
+        .list(&ListParams::default().fields("status.phase!=Succeeded,status.phase!=Failed"))
+        .await?
+        .items)
+}
+
+/// Joins the nodes with the pods scheduled on them via 'spec.nodeName'
+async fn group_by_node(api: &ApiBuilder<'_>) -> Result<Vec<Group>> {
+    let nodes = Api::<Node>::all(api.get_client().clone())
+        .list(&ListParams::default())
+        .await?;
+
+    // Represents 'Node - Pods' mapping
+    let mut node_pods: HashMap<String, Vec<serde_json::Value>> = HashMap::new();
+    for pod in pods(api).await? {
+        if let Some(node) = pod.spec.as_ref().and_then(|s| s.node_name.clone()) {
+            node_pods
+                .entry(node)
+                .or_default()
+                .push(serde_json::to_value(&pod)?);
+        }
+    }
+
+    let mut groups: Vec<Group> = Vec::new();
+    for node in nodes.items {
+        let name = node.name_any();
+        groups.push(Group {
+            object: serde_json::to_value(&node)?,
+            pods: node_pods.remove(&name).unwrap_or_default(),
+            name,
+        });
+    }
+
+    Ok(groups)
+}
+
+/// Joins the namespaces with the pods in them
+async fn group_by_namespace(api: &ApiBuilder<'_>) -> Result<Vec<Group>> {
+    let namespaces = Api::<Namespace>::all(api.get_client().clone())
+        .list(&ListParams::default())
+        .await?;
+
+    // Represents 'Namespace - Pods' mapping
+    let mut namespace_pods: HashMap<String, Vec<serde_json::Value>> = HashMap::new();
+    for pod in pods(api).await? {
+        if let Some(ns) = pod.namespace() {
+            namespace_pods
+                .entry(ns)
+                .or_default()
+                .push(serde_json::to_value(&pod)?);
+        }
+    }
+
+    let mut groups: Vec<Group> = Vec::new();
+    for namespace in namespaces.items {
+        let name = namespace.name_any();
+        groups.push(Group {
+            object: serde_json::to_value(&namespace)?,
+            pods: namespace_pods.remove(&name).unwrap_or_default(),
+            name,
+        });
+    }
+
+    Ok(groups)
+}
+
+/// Returns the value of the given column for the group
+fn value(aggregate: &Aggregate, group: &Group) -> String {
+    match aggregate {
+        Aggregate::Field(p) => {
+            eval::resolve(&p.kind, &p.fields, &group.object).unwrap_or_else(|| "-".to_string())
+        }
+        Aggregate::Sum(p) => sum(p, &group.pods),
+        Aggregate::Count => group.pods.len().to_string(),
+    }
+}
+
+/// Whether the given HAVING condition holds for the group, the values are compared as quantities
+fn having(having: &Having, group: &Group) -> bool {
+    let ordering = eval::compare(&value(&having.aggregate, group), &having.value);
+
+    match having.op {
+        BinaryOperator::Eq => ordering == Ordering::Equal,
+        BinaryOperator::NotEq => ordering != Ordering::Equal,
+        BinaryOperator::Gt => ordering == Ordering::Greater,
+        BinaryOperator::GtEq => ordering != Ordering::Less,
+        BinaryOperator::Lt => ordering == Ordering::Less,
+        BinaryOperator::LtEq => ordering != Ordering::Greater,
+        _ => false,
+    }
+}
+
+/// Sums the given field of all objects as quantities
+fn sum(projection: &Projection, objects: &[serde_json::Value]) -> String {
+    let total: f64 = objects
+        .iter()
+        .filter_map(|o| eval::resolve(&projection.kind, &projection.fields, o))
+        .filter_map(|v| quantity::parse(&v))
+        .sum();
+
+    quantity::format(
+        projection
+            .fields
+            .last()
+            .map(|f| f.as_str())
+            .unwrap_or_default(),
+        total,
+    )
+}
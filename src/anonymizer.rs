@@ -0,0 +1,276 @@
+// Copyright (c) 2021 Dentrax
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software is furnished to do so,
+// subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+use crate::aggregator::AggregateRow;
+use crate::contexts::{Context, Health, Namespace};
+use crate::planner::GroupBy;
+use crate::printer::{ContextFailure, ResultTable};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock};
+
+/// The pseudonyms given so far by what they are of, i.e. 'context', then by the names they replace
+pub type Pseudonyms = Arc<Mutex<HashMap<String, HashMap<String, String>>>>;
+
+/// Replaces the names of the contexts, namespaces, servers and resources with pseudonyms numbered
+/// in the order they are printed, i.e. 'context-1'. A name gets the same pseudonym during a run
+#[derive(Debug, Clone, Default)]
+pub struct Anonymizer {
+    pseudonyms: Pseudonyms,
+}
+
+/// Returns the anonymizer shared by all the queries of the process, so a name gets the same
+/// pseudonym in all of them, i.e. in the REPL
+pub fn shared() -> Anonymizer {
+    static ANONYMIZER: OnceLock<Anonymizer> = OnceLock::new();
+    ANONYMIZER.get_or_init(Anonymizer::default).clone()
+}
+
+impl Anonymizer {
+    pub fn context(&self, context: &str) -> String {
+        self.pseudonym("context", context)
+    }
+
+    pub fn namespace(&self, namespace: &str) -> String {
+        self.pseudonym("namespace", namespace)
+    }
+
+    pub fn server(&self, server: &str) -> String {
+        self.pseudonym("server", server)
+    }
+
+    /// Returns the pseudonym of a resource prefixed with its kind, i.e. 'pod-1'
+    pub fn name(&self, kind: &str, name: &str) -> String {
+        self.pseudonym(kind, name)
+    }
+
+    pub fn failure(&self, failure: &ContextFailure) -> ContextFailure {
+        ContextFailure {
+            context: self.context(&failure.context),
+            namespace: failure.namespace.as_deref().map(|ns| self.namespace(ns)),
+            status: failure.status,
+        }
+    }
+
+    /// Returns the pseudonym of the given value of a SELECT field holding a name, i.e. the node of
+    /// 'spec.nodeName' or the image of 'spec.containers.image'. None if the field is kept as it
+    /// is, i.e. 'status.phase'. The fields wrapped into functions are matched by their path
+    pub fn field(&self, kind: &str, field: &str, value: &str) -> Option<String> {
+        if value == "-" {
+            return None;
+        }
+
+        // i.e. "split_part(spec.containers.image, ':', 1)" => 'spec.containers.image'
+        let path = field
+            .rsplit('(')
+            .next()
+            .and_then(|f| f.split([',', ')']).next())
+            .unwrap_or(field)
+            .trim();
+        let last = path.rsplit('.').next().unwrap_or(path);
+        match path {
+            "metadata.name" => Some(self.name(kind, value)),
+            "metadata.namespace" => Some(self.namespace(value)),
+            "spec.nodeName" | "node.metadata.name" => Some(self.name("node", value)),
+            "spec.serviceAccountName" => Some(self.name("serviceaccount", value)),
+            _ if path.starts_with("metadata.ownerReferences") && last == "name" => {
+                Some(self.pseudonym("owner", value))
+            }
+            _ if ["metadata.labels", "labels", "node.metadata.labels"]
+                .iter()
+                .any(|l| path == *l || path.starts_with(&format!("{}.", l))) =>
+            {
+                Some(self.pseudonym("label", value))
+            }
+            _ if ["metadata.annotations", "annotations"]
+                .iter()
+                .any(|a| path == *a || path.starts_with(&format!("{}.", a))) =>
+            {
+                Some(self.pseudonym("annotation", value))
+            }
+            _ if matches!(last, "image" | "imageID") => Some(self.pseudonym("image", value)),
+            _ if matches!(last, "podIP" | "hostIP" | "clusterIP") => {
+                Some(self.pseudonym("ip", value))
+            }
+            _ => None,
+        }
+    }
+
+    /// Anonymizes the context, namespace and name columns of the given tables along with the
+    /// metadata columns, i.e. the results of --exec, and the SELECT fields holding names
+    pub fn tables(&self, mut tables: Vec<ResultTable>) -> Vec<ResultTable> {
+        for table in &mut tables {
+            for row in &mut table.rows {
+                for (column, value) in table.columns.iter().zip(row.iter_mut()) {
+                    *value = match column.as_str() {
+                        "context" | "__context" => self.context(value),
+                        "namespace" | "__namespace" => self.namespace(value),
+                        "__cluster_server" => self.server(value),
+                        "name" => self.name(&table.kind, value),
+                        _ => match self.field(&table.kind, column, value) {
+                            Some(pseudonym) => pseudonym,
+                            None => continue,
+                        },
+                    };
+                }
+            }
+            table.failures = table.failures.iter().map(|f| self.failure(f)).collect();
+        }
+        tables
+    }
+
+    /// Anonymizes the context, cluster, server, user and namespace columns of the given
+    /// 'kubeconfig' table, the other columns are kept as they are
+    pub fn kubeconfig(&self, mut table: ResultTable) -> ResultTable {
+        for row in &mut table.rows {
+            for (column, value) in table.columns.iter().zip(row.iter_mut()) {
+                *value = match column.as_str() {
+                    "name" => self.context(value),
+                    "namespace" => self.namespace(value),
+                    "cluster.server" => self.server(value),
+                    "cluster.name" => self.pseudonym("cluster", value),
+                    "user.name" => self.pseudonym("user", value),
+                    _ => continue,
+                };
+            }
+        }
+        table
+    }
+
+    /// Anonymizes the names, clusters, servers, users, namespaces and label values of the given
+    /// contexts of SHOW CONTEXTS
+    pub fn contexts(&self, contexts: Vec<Context>) -> Vec<Context> {
+        contexts
+            .into_iter()
+            .map(|c| Context {
+                name: self.context(&c.name),
+                cluster: c.cluster.map(|n| self.pseudonym("cluster", &n)),
+                server: c.server.map(|s| self.server(&s)),
+                user: c.user.map(|u| self.pseudonym("user", &u)),
+                namespace: c.namespace.map(|ns| self.namespace(&ns)),
+                current: c.current,
+                labels: c
+                    .labels
+                    .into_iter()
+                    .map(|(k, v)| (k, self.pseudonym("label", &v)))
+                    .collect(),
+            })
+            .collect()
+    }
+
+    /// Anonymizes the contexts and servers of the given results of ping, in their errors as well
+    pub fn health(&self, health: Vec<Health>) -> Vec<Health> {
+        health
+            .into_iter()
+            .map(|h| {
+                let context = self.context(&h.context);
+                let server = h.server.as_deref().map(|s| self.server(s));
+                let error = h.error.as_ref().map(|e| {
+                    let e = match (&h.server, &server) {
+                        (Some(s), Some(pseudonym)) => e.replace(s.as_str(), pseudonym),
+                        _ => e.clone(),
+                    };
+                    e.replace(&h.context, &context)
+                });
+                Health {
+                    context,
+                    server,
+                    error,
+                    ..h
+                }
+            })
+            .collect()
+    }
+
+    /// Anonymizes the contexts and names of the given namespaces of SHOW NAMESPACES
+    pub fn namespaces(&self, namespaces: Vec<Namespace>) -> Vec<Namespace> {
+        namespaces
+            .into_iter()
+            .map(|ns| Namespace {
+                context: self.context(&ns.context),
+                name: self.namespace(&ns.name),
+                status: ns.status,
+            })
+            .collect()
+    }
+
+    /// Anonymizes the contexts and the grouped namespaces or nodes of the given GROUP BY rows
+    pub fn aggregates(&self, group_by: &GroupBy, rows: &mut [AggregateRow]) {
+        for row in rows {
+            row.context = self.context(&row.context);
+            row.group = match group_by {
+                GroupBy::Node => self.name("node", &row.group),
+                GroupBy::Namespace => self.namespace(&row.group),
+            };
+        }
+    }
+
+    /// Returns the pseudonym of the given name, given on its first occurrence. The empty names stay
+    /// empty, i.e. the namespace of the cluster-scoped kinds
+    fn pseudonym(&self, of: &str, name: &str) -> String {
+        if name.is_empty() {
+            return String::new();
+        }
+
+        let mut pseudonyms = self.pseudonyms.lock().unwrap();
+        let names = pseudonyms.entry(of.to_string()).or_default();
+        let next = names.len() + 1;
+        names
+            .entry(name.to_string())
+            .or_insert_with(|| format!("{}-{}", of, next))
+            .clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pseudonyms_of_the_name_fields() {
+        let anonymizer = Anonymizer::default();
+        assert_eq!(anonymizer.name("pod", "api-1"), "pod-1");
+        assert_eq!(
+            anonymizer.field("pod", "metadata.name", "api-1"),
+            Some("pod-1".to_string())
+        );
+        assert_eq!(
+            anonymizer.field("pod", "spec.nodeName", "ip-10-0-0-1"),
+            Some("node-1".to_string())
+        );
+        assert_eq!(
+            anonymizer.field("pod", "metadata.labels.team", "payments"),
+            Some("label-1".to_string())
+        );
+        assert_eq!(
+            anonymizer.field("pod", "image_tag(spec.containers.image)", "1.25"),
+            Some("image-1".to_string())
+        );
+        assert_eq!(
+            anonymizer.field("pod", "metadata.ownerReferences.name", "api-5d8"),
+            Some("owner-1".to_string())
+        );
+    }
+
+    #[test]
+    fn keeps_the_other_fields() {
+        let anonymizer = Anonymizer::default();
+        assert_eq!(anonymizer.field("pod", "status.phase", "Running"), None);
+        assert_eq!(anonymizer.field("pod", "metadata.name", "-"), None);
+    }
+}
@@ -17,12 +17,156 @@
 // IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
 // CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
 
+use crate::daemon::DaemonError;
+use crate::error::{KubesqlError, Result};
+use crate::executor::Stats;
+use crate::helm;
+use crate::parser::ResourceType;
 use crate::planner::Query;
-use anyhow::{bail, Context, Result};
+use crate::pool;
+use crate::source::DataSource;
 use k8s_openapi::api::apps::v1::Deployment;
-use k8s_openapi::api::core::v1::{Pod, Service};
-use kube::Api;
-use std::convert::TryFrom;
+use k8s_openapi::api::core::v1::{
+    ConfigMap, LimitRange, Namespace, Node, PersistentVolume, PersistentVolumeClaim, Pod,
+    ResourceQuota, Secret, Service, ServiceAccount,
+};
+use k8s_openapi::api::rbac::v1::{ClusterRole, ClusterRoleBinding, Role, RoleBinding};
+use k8s_openapi::ByteString;
+use kube::api::{ApiResource, DynamicObject, ListParams, ObjectList};
+use kube::core::GroupVersionKind;
+use kube::{Api, Resource, ResourceExt};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fmt::{self, Debug};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+/// Asks the API Server for the metadata of the resources only, or for the whole resources if it
+/// can not, i.e. 'kubectl get -o name'
+const METADATA_ACCEPT: &str =
+    "application/json;as=PartialObjectMetadataList;g=meta.k8s.io;v=v1,application/json";
+
+/// The annotation of 'kubectl apply' with the last applied configuration, values included
+const LAST_APPLIED: &str = "kubectl.kubernetes.io/last-applied-configuration";
+
+/// The namespaced resources of a context listed once for all of its namespaces, by request URI,
+/// shared by the APIs of the context. None if listing all the namespaces is forbidden or failed
+pub type AllNamespaces = Arc<tokio::sync::Mutex<HashMap<String, Option<serde_json::Value>>>>;
+
+/// The nodes of a context by name, listed once on the first join and shared by the APIs of the
+/// context, i.e. for 'pod.node.metadata.labels'
+pub type Nodes = Arc<tokio::sync::OnceCell<HashMap<String, serde_json::Value>>>;
+
+/// The kinds a context could not list and why, shared by the APIs of the context. The failures
+/// of a namespace, i.e. Forbidden by its RBAC, are kept by its API instead
+pub type Failures = Arc<Mutex<HashMap<ResourceType, Failure>>>;
+
+/// Why a kind could not be listed on a context, reported in its results instead of failing the
+/// query, so the other contexts are still queried
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Failure {
+    /// The credentials of the context are rejected or could not be refreshed, i.e. an expired token
+    AuthExpired,
+    /// The API Server could not be connected
+    Unreachable,
+    /// The RBAC of the context does not allow listing the kind
+    Forbidden,
+    /// The cluster does not serve the kind, i.e. a missing CRD
+    KindNotFound,
+}
+
+impl Failure {
+    /// Returns the failure the given error of listing the kind is classified as, None if it is not
+    /// one of them and fails the query
+    fn of(kind: &ResourceType, error: &kube::Error) -> Option<Failure> {
+        match error {
+            kube::Error::Api(e) if e.code == 401 => Some(Failure::AuthExpired),
+            kube::Error::Api(e) if e.code == 403 => Some(Failure::Forbidden),
+            kube::Error::Api(e) if e.code == 404 && kind.is_optional() => {
+                Some(Failure::KindNotFound)
+            }
+            kube::Error::Auth(_) => Some(Failure::AuthExpired),
+            kube::Error::HyperError(_) | kube::Error::Service(_) => Some(Failure::Unreachable),
+            _ => None,
+        }
+    }
+
+    /// Whether the failure is of a namespace only, the others apply to all the namespaces
+    pub fn is_namespaced(&self) -> bool {
+        *self == Failure::Forbidden
+    }
+
+    /// Whether none of the kinds can be listed on the context anymore
+    fn is_context_wide(&self) -> bool {
+        matches!(self, Failure::AuthExpired | Failure::Unreachable)
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Failure::AuthExpired => write!(f, "auth expired"),
+            Failure::Unreachable => write!(f, "unreachable"),
+            Failure::Forbidden => write!(f, "forbidden"),
+            Failure::KindNotFound => write!(f, "kind not found"),
+        }
+    }
+}
+
+/// The resources and bytes the lists of a query may return across all its contexts, shared by all
+/// its APIs, i.e. with --max-rows and --max-bytes
+pub type Budget = Arc<Mutex<Limits>>;
+
+/// The number of resources requested per page of a list within a budget
+const PAGE_SIZE: u32 = 500;
+
+#[derive(Debug, Default)]
+pub struct Limits {
+    pub max_rows: Option<usize>,
+    pub max_bytes: Option<usize>,
+    /// The resources and bytes listed so far
+    pub rows: usize,
+    pub bytes: usize,
+    /// Whether a list stopped before its end as the budget was used up
+    pub truncated: bool,
+}
+
+impl Limits {
+    pub fn new(max_rows: Option<usize>, max_bytes: Option<usize>) -> Limits {
+        Limits {
+            max_rows,
+            max_bytes,
+            ..Default::default()
+        }
+    }
+
+    /// Returns the number of resources to request on the next page, None if the budget is used up,
+    /// which truncates the results
+    fn next_page(&mut self) -> Option<u32> {
+        let rows = self.max_rows.map(|max| max.saturating_sub(self.rows));
+        if rows == Some(0) || self.max_bytes.is_some_and(|max| self.bytes >= max) {
+            self.truncated = true;
+            return None;
+        }
+        Some(rows.map_or(PAGE_SIZE, |r| r.min(PAGE_SIZE as usize) as u32))
+    }
+
+    /// Records the resources and bytes of a listed page
+    fn spend(&mut self, rows: usize, bytes: usize) {
+        self.rows += rows;
+        self.bytes += bytes;
+    }
+}
+
+/// Returns the name the given field selector fixes, i.e. 'metadata.name=api-1'
+fn fixed_name(list_params: &ListParams) -> Option<&str> {
+    list_params
+        .field_selector
+        .as_deref()?
+        .strip_prefix("metadata.name=")
+        .filter(|name| !name.is_empty() && !name.contains(','))
+}
 
 /// A high level wrapper for kube::Api struct
 pub struct ApiBuilder<'a> {
@@ -35,6 +179,36 @@ pub struct ApiBuilder<'a> {
     //queries: &'a Vec<Query>,
     queries: Option<&'a [Query]>,
 
+    /// The resources are read from this data source instead of the API Server, if given
+    source: Option<&'a dyn DataSource>,
+
+    /// The kube client built for the given context
+    client: Option<kube::Client>,
+
+    /// The statistics of the list calls, gathered only if given
+    stats: Option<Mutex<Stats>>,
+
+    /// The namespaced resources are listed from all the namespaces at once, if given
+    all_namespaces: Option<AllNamespaces>,
+
+    /// The nodes the pods are joined with, shared with the other APIs of the context
+    nodes: Nodes,
+
+    /// The kinds the context could not list, shared with the other APIs of the context
+    failures: Failures,
+
+    /// The kinds the namespace could not list, the RBAC of the other namespaces may allow them
+    forbidden: Mutex<HashSet<ResourceType>>,
+
+    /// The major and minor version of the API Server, the latest API versions are used if unknown
+    server_version: Option<(u32, u32)>,
+
+    /// The resources are listed page by page until it is used up, all at once if not given
+    budget: Option<Budget>,
+
+    /// The kinds listed with their metadata only, i.e. the ones whose names or labels only are queried
+    metadata_only: HashSet<ResourceType>,
+
     /// Api::Namespaced Deployment value
     deployment: Option<Api<Deployment>>,
 
@@ -43,6 +217,69 @@ pub struct ApiBuilder<'a> {
 
     // Api::Namespaced Service value
     service: Option<Api<Service>>,
+
+    // Api::All Node value, nodes are not namespaced
+    node: Option<Api<Node>>,
+
+    // Api::Namespaced PodMetrics value of metrics.k8s.io
+    podmetrics: Option<Api<DynamicObject>>,
+
+    // Api::Namespaced EndpointSlice value of discovery.k8s.io
+    endpointslice: Option<Api<DynamicObject>>,
+
+    // Api::Namespaced HorizontalPodAutoscaler value of autoscaling/v2, or v2beta2 before 1.23
+    hpa: Option<Api<DynamicObject>>,
+
+    // Api::Namespaced ResourceQuota value
+    resourcequota: Option<Api<ResourceQuota>>,
+
+    // Api::Namespaced LimitRange value
+    limitrange: Option<Api<LimitRange>>,
+
+    // Api::Namespaced ServiceAccount value
+    serviceaccount: Option<Api<ServiceAccount>>,
+
+    // Api::Namespaced Role value
+    role: Option<Api<Role>>,
+
+    // Api::Namespaced RoleBinding value
+    rolebinding: Option<Api<RoleBinding>>,
+
+    // Api::All ClusterRole value, cluster roles are not namespaced
+    clusterrole: Option<Api<ClusterRole>>,
+
+    // Api::All ClusterRoleBinding value, cluster role bindings are not namespaced
+    clusterrolebinding: Option<Api<ClusterRoleBinding>>,
+
+    // Api::All PersistentVolume value, persistent volumes are not namespaced
+    pv: Option<Api<PersistentVolume>>,
+
+    // Api::All Namespace value
+    ns: Option<Api<Namespace>>,
+
+    // Api::Namespaced Application value of argoproj.io
+    application: Option<Api<DynamicObject>>,
+
+    // Api::Namespaced Kustomization value of kustomize.toolkit.fluxcd.io
+    kustomization: Option<Api<DynamicObject>>,
+
+    // Api::Namespaced PersistentVolumeClaim value
+    pvc: Option<Api<PersistentVolumeClaim>>,
+
+    // Api::Namespaced ConfigMap value
+    configmap: Option<Api<ConfigMap>>,
+
+    // Api::Namespaced Secret value, their values are redacted once listed
+    secret: Option<Api<Secret>>,
+
+    // Api::Namespaced Secret value, the Helm releases are stored in them
+    helmsecret: Option<Api<Secret>>,
+
+    // Api::Namespaced ConfigMap value, the Helm releases are stored in them with HELM_DRIVER=configmap
+    helmconfigmap: Option<Api<ConfigMap>>,
+
+    // Api::Namespaced PodDisruptionBudget value of policy/v1, or v1beta1 before 1.21
+    pdb: Option<Api<DynamicObject>>,
 }
 
 impl<'a> Default for ApiBuilder<'a> {
@@ -51,9 +288,40 @@ impl<'a> Default for ApiBuilder<'a> {
             context: None,
             namespace: Option::from("default".to_string()),
             queries: None,
+            source: None,
+            client: None,
+            stats: None,
+            all_namespaces: None,
+            nodes: Nodes::default(),
+            failures: Failures::default(),
+            forbidden: Mutex::default(),
+            server_version: None,
+            budget: None,
+            metadata_only: HashSet::new(),
             deployment: None,
             pod: None,
             service: None,
+            node: None,
+            podmetrics: None,
+            endpointslice: None,
+            hpa: None,
+            resourcequota: None,
+            limitrange: None,
+            serviceaccount: None,
+            role: None,
+            rolebinding: None,
+            clusterrole: None,
+            clusterrolebinding: None,
+            pv: None,
+            ns: None,
+            application: None,
+            kustomization: None,
+            pvc: None,
+            configmap: None,
+            secret: None,
+            helmsecret: None,
+            helmconfigmap: None,
+            pdb: None,
         }
     }
 }
@@ -86,54 +354,762 @@ impl<'a> ApiBuilder<'a> {
         self
     }
 
+    /// Set the given data source to read the resources from, the API Server is not called
+    pub fn source(mut self, source: Option<&'a dyn DataSource>) -> ApiBuilder<'a> {
+        self.source = source;
+        self
+    }
+
+    /// Gather the statistics of the list calls, i.e. to print them with '--stats'
+    pub fn stats(mut self, enabled: bool) -> ApiBuilder<'a> {
+        self.stats = enabled.then(|| Mutex::new(Stats::default()));
+        self
+    }
+
+    /// List the namespaced resources from all the namespaces at once, shared with the other APIs
+    /// of the context, instead of a call for each namespace
+    pub fn all_namespaces(mut self, all_namespaces: Option<AllNamespaces>) -> ApiBuilder<'a> {
+        self.all_namespaces = all_namespaces;
+        self
+    }
+
+    /// Share the nodes joined with the pods with the other APIs of the context
+    pub fn nodes(mut self, nodes: Nodes) -> ApiBuilder<'a> {
+        self.nodes = nodes;
+        self
+    }
+
+    /// Share the kinds the context could not list with the other APIs of the context
+    pub fn failures(mut self, failures: Failures) -> ApiBuilder<'a> {
+        self.failures = failures;
+        self
+    }
+
+    /// Set the version of the API Server, i.e. to list the resources from the API versions it serves
+    pub fn server_version(mut self, version: Option<(u32, u32)>) -> ApiBuilder<'a> {
+        self.server_version = version;
+        self
+    }
+
+    /// List the resources page by page within the given budget, shared with all the other APIs
+    pub fn budget(mut self, budget: Option<Budget>) -> ApiBuilder<'a> {
+        self.budget = budget;
+        self
+    }
+
+    /// Lists the given kinds with their metadata only, the others are listed as a whole
+    pub fn metadata_only(mut self, kinds: HashSet<ResourceType>) -> ApiBuilder<'a> {
+        self.metadata_only = kinds;
+        self
+    }
+
+    /// Returns whether the API Server is older than the given version, false if unknown
+    fn older_than(&self, major: u32, minor: u32) -> bool {
+        self.server_version
+            .is_some_and(|version| version < (major, minor))
+    }
+
     /// Try build the whole API
     pub(crate) async fn build(mut self) -> Result<ApiBuilder<'a>> {
-        let client_config = kube::Config::from_kubeconfig(&kube::config::KubeConfigOptions {
-            context: Some(self.context.clone().unwrap()),
-            ..Default::default()
-        })
-        .await?;
-
-        match kube::Client::try_from(client_config)
-            .with_context(|| "failed to create the kube client with context".to_string())
-        {
-            Ok(c) => {
-                self.deployment = Option::from(Api::namespaced(
-                    c.clone(),
-                    self.namespace.clone().unwrap().as_str(),
-                ));
-                self.pod = Option::from(Api::namespaced(
-                    c.clone(),
-                    self.namespace.clone().unwrap().as_str(),
-                ));
-                self.service =
-                    Option::from(Api::namespaced(c, self.namespace.clone().unwrap().as_str()));
-            }
-            Err(e) => {
-                bail!("an error occurred during creating kube client: {:?}", e)
-            }
+        let context = self.context.clone().ok_or(KubesqlError::ContextRequired)?;
+        let namespace = self
+            .namespace
+            .clone()
+            .unwrap_or_else(|| "default".to_string());
+
+        // Offline, no client is required
+        if self.source.is_some() {
+            self.namespace = Option::from(namespace);
+            return Ok(self);
         }
 
+        let c = pool::shared().client(&context).await?;
+
+        // discovery.k8s.io/v1 and policy/v1 are served from 1.21 on and autoscaling/v2 from 1.23 on
+        let endpointslice_version = if self.older_than(1, 21) {
+            "v1beta1"
+        } else {
+            "v1"
+        };
+        let pdb_version = endpointslice_version;
+        let hpa_version = if self.older_than(1, 23) {
+            "v2beta2"
+        } else {
+            "v2"
+        };
+
+        self.deployment = Option::from(Api::namespaced(c.clone(), &namespace));
+        self.pod = Option::from(Api::namespaced(c.clone(), &namespace));
+        self.service = Option::from(Api::namespaced(c.clone(), &namespace));
+        self.node = Option::from(Api::all(c.clone()));
+        self.podmetrics = Option::from(Api::namespaced_with(
+            c.clone(),
+            &namespace,
+            &ApiResource::from_gvk_with_plural(
+                &GroupVersionKind::gvk("metrics.k8s.io", "v1beta1", "PodMetrics"),
+                "pods",
+            ),
+        ));
+        self.endpointslice = Option::from(Api::namespaced_with(
+            c.clone(),
+            &namespace,
+            &ApiResource::from_gvk_with_plural(
+                &GroupVersionKind::gvk("discovery.k8s.io", endpointslice_version, "EndpointSlice"),
+                "endpointslices",
+            ),
+        ));
+        self.hpa = Option::from(Api::namespaced_with(
+            c.clone(),
+            &namespace,
+            &ApiResource::from_gvk_with_plural(
+                &GroupVersionKind::gvk("autoscaling", hpa_version, "HorizontalPodAutoscaler"),
+                "horizontalpodautoscalers",
+            ),
+        ));
+        self.resourcequota = Option::from(Api::namespaced(c.clone(), &namespace));
+        self.limitrange = Option::from(Api::namespaced(c.clone(), &namespace));
+        self.serviceaccount = Option::from(Api::namespaced(c.clone(), &namespace));
+        self.role = Option::from(Api::namespaced(c.clone(), &namespace));
+        self.rolebinding = Option::from(Api::namespaced(c.clone(), &namespace));
+        self.clusterrole = Option::from(Api::all(c.clone()));
+        self.clusterrolebinding = Option::from(Api::all(c.clone()));
+        self.pv = Option::from(Api::all(c.clone()));
+        self.ns = Option::from(Api::all(c.clone()));
+        self.application = Option::from(Api::namespaced_with(
+            c.clone(),
+            &namespace,
+            &ApiResource::from_gvk_with_plural(
+                &GroupVersionKind::gvk("argoproj.io", "v1alpha1", "Application"),
+                "applications",
+            ),
+        ));
+        self.kustomization = Option::from(Api::namespaced_with(
+            c.clone(),
+            &namespace,
+            &ApiResource::from_gvk_with_plural(
+                &GroupVersionKind::gvk("kustomize.toolkit.fluxcd.io", "v1", "Kustomization"),
+                "kustomizations",
+            ),
+        ));
+        self.pvc = Option::from(Api::namespaced(c.clone(), &namespace));
+        self.configmap = Option::from(Api::namespaced(c.clone(), &namespace));
+        self.secret = Option::from(Api::namespaced(c.clone(), &namespace));
+        self.helmsecret = Option::from(Api::namespaced(c.clone(), &namespace));
+        self.helmconfigmap = Option::from(Api::namespaced(c.clone(), &namespace));
+        self.pdb = Option::from(Api::namespaced_with(
+            c.clone(),
+            &namespace,
+            &ApiResource::from_gvk_with_plural(
+                &GroupVersionKind::gvk("policy", pdb_version, "PodDisruptionBudget"),
+                "poddisruptionbudgets",
+            ),
+        ));
+        self.client = Option::from(c);
+
         Ok(self)
     }
 
-    pub fn get_context(&'a self) -> &String {
+    pub fn get_context(&'a self) -> &'a String {
         self.context.as_ref().unwrap()
     }
 
-    pub fn get_namespace(&'a self) -> &String {
+    pub fn get_namespace(&'a self) -> &'a String {
         self.namespace.as_ref().unwrap()
     }
 
-    pub fn get_deployment(&'a self) -> &Api<Deployment> {
+    /// Returns the statistics of the list calls so far, if gathered
+    pub fn get_stats(&self) -> Option<Stats> {
+        self.stats
+            .as_ref()
+            .and_then(|s| s.lock().ok())
+            .map(|s| s.clone())
+    }
+
+    /// Updates the statistics with the given function, if gathered
+    pub fn record(&self, f: impl FnOnce(&mut Stats)) {
+        if let Some(mut stats) = self.stats.as_ref().and_then(|s| s.lock().ok()) {
+            f(&mut stats);
+        }
+    }
+
+    /// Returns why the given kind could not be listed on the namespace, None if it was listed
+    pub fn failure(&self, kind: &ResourceType) -> Option<Failure> {
+        match self.forbidden.lock().unwrap().contains(kind) {
+            true => Some(Failure::Forbidden),
+            false => self.failures.lock().unwrap().get(kind).copied(),
+        }
+    }
+
+    /// Records the failure of listing the kind if the error is classified as one, returns the
+    /// error otherwise
+    fn fail(&self, kind: &ResourceType, error: kube::Error) -> kube::Result<()> {
+        pool::shared().evict_unauthorized(self.get_context(), &error);
+        let failure = match Failure::of(kind, &error) {
+            Some(failure) => failure,
+            None => return Err(error),
+        };
+
+        if failure != Failure::KindNotFound {
+            tracing::warn!(
+                "Unable to list {} of '{}': {}",
+                kind,
+                self.get_context(),
+                error
+            );
+        }
+        match failure {
+            Failure::Forbidden if !kind.is_cluster_scoped() => {
+                self.forbidden.lock().unwrap().insert(kind.clone());
+            }
+            _ => {
+                self.failures.lock().unwrap().insert(kind.clone(), failure);
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the failure of the context all the kinds fail with, i.e. its API Server is
+    /// unreachable, so it is not requested again for each of them
+    fn context_failure(&self) -> Option<Failure> {
+        self.failures
+            .lock()
+            .unwrap()
+            .values()
+            .copied()
+            .find(Failure::is_context_wide)
+    }
+
+    pub fn get_client(&'a self) -> &'a kube::Client {
+        self.client.as_ref().unwrap()
+    }
+
+    pub fn get_deployment(&'a self) -> &'a Api<Deployment> {
         self.deployment.as_ref().unwrap()
     }
 
-    pub fn get_pod(&'a self) -> &Api<Pod> {
+    pub fn get_pod(&'a self) -> &'a Api<Pod> {
         self.pod.as_ref().unwrap()
     }
 
-    pub fn get_service(&'a self) -> &Api<Service> {
+    pub fn get_service(&'a self) -> &'a Api<Service> {
         self.service.as_ref().unwrap()
     }
+
+    pub fn get_node(&'a self) -> &'a Api<Node> {
+        self.node.as_ref().unwrap()
+    }
+
+    pub fn get_podmetrics(&'a self) -> &'a Api<DynamicObject> {
+        self.podmetrics.as_ref().unwrap()
+    }
+
+    pub fn get_endpointslice(&'a self) -> &'a Api<DynamicObject> {
+        self.endpointslice.as_ref().unwrap()
+    }
+
+    pub fn get_hpa(&'a self) -> &'a Api<DynamicObject> {
+        self.hpa.as_ref().unwrap()
+    }
+
+    pub fn get_resourcequota(&'a self) -> &'a Api<ResourceQuota> {
+        self.resourcequota.as_ref().unwrap()
+    }
+
+    pub fn get_limitrange(&'a self) -> &'a Api<LimitRange> {
+        self.limitrange.as_ref().unwrap()
+    }
+
+    pub fn get_serviceaccount(&'a self) -> &'a Api<ServiceAccount> {
+        self.serviceaccount.as_ref().unwrap()
+    }
+
+    pub fn get_role(&'a self) -> &'a Api<Role> {
+        self.role.as_ref().unwrap()
+    }
+
+    pub fn get_rolebinding(&'a self) -> &'a Api<RoleBinding> {
+        self.rolebinding.as_ref().unwrap()
+    }
+
+    pub fn get_clusterrole(&'a self) -> &'a Api<ClusterRole> {
+        self.clusterrole.as_ref().unwrap()
+    }
+
+    pub fn get_clusterrolebinding(&'a self) -> &'a Api<ClusterRoleBinding> {
+        self.clusterrolebinding.as_ref().unwrap()
+    }
+
+    pub fn get_pv(&'a self) -> &'a Api<PersistentVolume> {
+        self.pv.as_ref().unwrap()
+    }
+
+    pub fn get_ns(&'a self) -> &'a Api<Namespace> {
+        self.ns.as_ref().unwrap()
+    }
+
+    pub fn get_application(&'a self) -> &'a Api<DynamicObject> {
+        self.application.as_ref().unwrap()
+    }
+
+    pub fn get_kustomization(&'a self) -> &'a Api<DynamicObject> {
+        self.kustomization.as_ref().unwrap()
+    }
+
+    pub fn get_pvc(&'a self) -> &'a Api<PersistentVolumeClaim> {
+        self.pvc.as_ref().unwrap()
+    }
+
+    pub fn get_configmap(&'a self) -> &'a Api<ConfigMap> {
+        self.configmap.as_ref().unwrap()
+    }
+
+    pub fn get_secret(&'a self) -> &'a Api<Secret> {
+        self.secret.as_ref().unwrap()
+    }
+
+    pub fn get_helmsecret(&'a self) -> &'a Api<Secret> {
+        self.helmsecret.as_ref().unwrap()
+    }
+
+    pub fn get_helmconfigmap(&'a self) -> &'a Api<ConfigMap> {
+        self.helmconfigmap.as_ref().unwrap()
+    }
+
+    pub fn get_pdb(&'a self) -> &'a Api<DynamicObject> {
+        self.pdb.as_ref().unwrap()
+    }
+
+    /// Returns the URL listing the resources of the given kind from all the namespaces,
+    /// i.e. '/api/v1/pods'. Fails if the API is not built, i.e. reading a data source
+    pub fn resource_url(&'a self, kind: &ResourceType) -> std::result::Result<String, DaemonError> {
+        let url = match kind {
+            ResourceType::Deployment => self.deployment.as_ref().map(Api::resource_url),
+            ResourceType::Pod => self.pod.as_ref().map(Api::resource_url),
+            ResourceType::Service => self.service.as_ref().map(Api::resource_url),
+            ResourceType::PodMetrics => self.podmetrics.as_ref().map(Api::resource_url),
+            ResourceType::Node => self.node.as_ref().map(Api::resource_url),
+            ResourceType::EndpointSlice => self.endpointslice.as_ref().map(Api::resource_url),
+            ResourceType::HorizontalPodAutoscaler => self.hpa.as_ref().map(Api::resource_url),
+            ResourceType::ResourceQuota => self.resourcequota.as_ref().map(Api::resource_url),
+            ResourceType::LimitRange => self.limitrange.as_ref().map(Api::resource_url),
+            ResourceType::ServiceAccount => self.serviceaccount.as_ref().map(Api::resource_url),
+            ResourceType::Role => self.role.as_ref().map(Api::resource_url),
+            ResourceType::RoleBinding => self.rolebinding.as_ref().map(Api::resource_url),
+            ResourceType::ClusterRole => self.clusterrole.as_ref().map(Api::resource_url),
+            ResourceType::ClusterRoleBinding => {
+                self.clusterrolebinding.as_ref().map(Api::resource_url)
+            }
+            ResourceType::PersistentVolume => self.pv.as_ref().map(Api::resource_url),
+            ResourceType::Namespace => self.ns.as_ref().map(Api::resource_url),
+            // Decoded from the Secrets or ConfigMaps of Helm, there is no API of its own
+            ResourceType::HelmRelease => None,
+            ResourceType::Application => self.application.as_ref().map(Api::resource_url),
+            ResourceType::Kustomization => self.kustomization.as_ref().map(Api::resource_url),
+            ResourceType::PersistentVolumeClaim => self.pvc.as_ref().map(Api::resource_url),
+            ResourceType::ConfigMap => self.configmap.as_ref().map(Api::resource_url),
+            ResourceType::Secret => self.secret.as_ref().map(Api::resource_url),
+            ResourceType::PodDisruptionBudget => self.pdb.as_ref().map(Api::resource_url),
+        };
+        url.map(|url| all_namespaces_url(url, self.get_namespace()))
+            .ok_or_else(|| DaemonError::NoApi(kind.clone(), self.get_context().clone()))
+    }
+
+    /// Lists the resources of the given API, or reads them from the data source if given
+    #[tracing::instrument(
+        name = "list",
+        skip_all,
+        fields(context = %self.get_context(), namespace = %self.get_namespace(), kind = %kind)
+    )]
+    pub async fn list<K>(
+        &'a self,
+        kind: &ResourceType,
+        api: fn(&'a Self) -> &'a Api<K>,
+        list_params: &ListParams,
+    ) -> Result<ObjectList<K>>
+    where
+        K: Resource + Clone + DeserializeOwned + Debug + Serialize,
+    {
+        let started = Instant::now();
+        let empty = || ObjectList {
+            types: Default::default(),
+            metadata: Default::default(),
+            items: vec![],
+        };
+        let list = match self.source {
+            Some(source) => ObjectList {
+                types: Default::default(),
+                metadata: Default::default(),
+                items: self.read(source, kind)?,
+            },
+            None => {
+                if let Some(failure) = self.context_failure() {
+                    self.failures.lock().unwrap().insert(kind.clone(), failure);
+                    return Ok(empty());
+                }
+
+                match self
+                    .list_all_namespaces(kind, api(self), list_params)
+                    .await?
+                {
+                    Some(list) => list,
+                    None => match self.list_named(kind, api(self), list_params).await {
+                        Ok(list) => list,
+                        Err(e) => {
+                            self.fail(kind, e)?;
+                            empty()
+                        }
+                    },
+                }
+            }
+        };
+
+        self.record(|stats| {
+            stats.scanned += list.items.len();
+            stats.duration += started.elapsed();
+        });
+
+        Ok(list)
+    }
+
+    /// Reads the resources of the kind in the context and namespace from the given data source
+    fn read<K: DeserializeOwned>(
+        &self,
+        source: &dyn DataSource,
+        kind: &ResourceType,
+    ) -> serde_json::Result<Vec<K>> {
+        source
+            .list(self.get_context(), self.get_namespace(), kind)
+            .into_iter()
+            .map(serde_json::from_value)
+            .collect()
+    }
+
+    /// Reads the resource of the given API by its name if the field selector fixes it, i.e.
+    /// 'metadata.name=api-1' in the namespace of the API, instead of listing them. Lists them
+    /// page by page otherwise
+    async fn list_named<K>(
+        &self,
+        kind: &ResourceType,
+        api: &Api<K>,
+        list_params: &ListParams,
+    ) -> kube::Result<ObjectList<K>>
+    where
+        K: Resource + Clone + DeserializeOwned + Debug + Serialize,
+    {
+        let name = match fixed_name(list_params) {
+            Some(name) => name,
+            None => return self.list_pages(kind, api, list_params).await,
+        };
+
+        let mut items = Vec::new();
+        if self.next_page().is_some() {
+            let item = api.get_opt(name).await;
+            self.record(|stats| stats.api_calls += 1);
+            let item = item?;
+            self.spend(
+                item.iter().count(),
+                serde_json::to_vec(&item).map_or(0, |b| b.len()),
+            );
+            items.extend(item);
+        }
+
+        Ok(ObjectList {
+            types: Default::default(),
+            metadata: Default::default(),
+            items,
+        })
+    }
+
+    /// Lists the resources of the given API page by page within the budget if given, all at once
+    /// otherwise. The pages are not requested anymore once the budget is used up
+    async fn list_pages<K>(
+        &self,
+        kind: &ResourceType,
+        api: &Api<K>,
+        list_params: &ListParams,
+    ) -> kube::Result<ObjectList<K>>
+    where
+        K: Resource + Clone + DeserializeOwned + Debug + Serialize,
+    {
+        if self.lists_metadata(kind, self.get_namespace()) {
+            let list = self
+                .request_pages(api.resource_url(), list_params, true)
+                .await?;
+            return Ok(ObjectList {
+                types: Default::default(),
+                metadata: Default::default(),
+                items: serde_json::from_value(list["items"].clone())
+                    .map_err(kube::Error::SerdeError)?,
+            });
+        }
+
+        let mut params = list_params.clone();
+        let mut items = Vec::new();
+        while let Some(limit) = self.next_page() {
+            params.limit = limit.or(list_params.limit);
+            let page = api.list(&params).await;
+            self.record(|stats| stats.api_calls += 1);
+            let page = page?;
+
+            self.spend(
+                page.items.len(),
+                serde_json::to_vec(&page).map_or(0, |b| b.len()),
+            );
+            items.extend(page.items);
+            match page.metadata.continue_ {
+                Some(token) if !token.is_empty() => params.continue_token = Some(token),
+                _ => break,
+            }
+        }
+
+        Ok(ObjectList {
+            types: Default::default(),
+            metadata: Default::default(),
+            items,
+        })
+    }
+
+    /// Whether the given kind is listed with its metadata only, as decided by the executor of the
+    /// fields queried. The kinds listed with their metadata only have neither spec nor status
+    fn lists_metadata(&self, kind: &ResourceType, namespace: &str) -> bool {
+        let metadata_only = self.metadata_only.contains(kind);
+        if metadata_only {
+            tracing::debug!(
+                kind = %kind,
+                context = %self.get_context(),
+                namespace = %namespace,
+                "Listing the metadata only"
+            );
+        }
+        metadata_only
+    }
+
+    /// Requests the list of the given URL page by page within the budget if given, all at once
+    /// otherwise, and returns the items of all the pages as a single list
+    async fn request_pages(
+        &self,
+        url: &str,
+        list_params: &ListParams,
+        metadata_only: bool,
+    ) -> kube::Result<serde_json::Value> {
+        let mut params = list_params.clone();
+        let mut items = Vec::new();
+        while let Some(limit) = self.next_page() {
+            params.limit = limit.or(list_params.limit);
+            let mut request = kube::core::Request::new(url)
+                .list(&params)
+                .map_err(kube::Error::BuildRequest)?;
+            if metadata_only {
+                request.headers_mut().insert(
+                    http::header::ACCEPT,
+                    http::HeaderValue::from_static(METADATA_ACCEPT),
+                );
+            }
+            let page = self
+                .get_client()
+                .request::<serde_json::Value>(request)
+                .await;
+            self.record(|stats| stats.api_calls += 1);
+            let mut page = page?;
+
+            let listed = page["items"].as_array().map_or(0, Vec::len);
+            self.spend(listed, serde_json::to_vec(&page).map_or(0, |b| b.len()));
+            if let Some(page_items) = page["items"].as_array_mut() {
+                // i.e. 'meta.k8s.io/v1', the items are read as the resources of the kind
+                if metadata_only {
+                    page_items
+                        .iter_mut()
+                        .filter_map(serde_json::Value::as_object_mut)
+                        .for_each(|item| {
+                            item.remove("apiVersion");
+                            item.remove("kind");
+                        });
+                }
+                items.append(page_items);
+            }
+            match page["metadata"]["continue"].as_str() {
+                Some(token) if !token.is_empty() => params.continue_token = Some(token.to_string()),
+                _ => break,
+            }
+        }
+
+        Ok(serde_json::json!({ "items": items }))
+    }
+
+    /// Returns the size of the next page within the budget, Some(None) to list all at once without
+    /// a budget, None if the budget is used up
+    fn next_page(&self) -> Option<Option<u32>> {
+        match &self.budget {
+            Some(budget) => budget.lock().unwrap().next_page().map(Some),
+            None => Some(None),
+        }
+    }
+
+    /// Records the resources and bytes of a listed page into the statistics and the budget
+    fn spend(&self, rows: usize, bytes: usize) {
+        self.record(|stats| stats.bytes += bytes);
+        if let Some(budget) = &self.budget {
+            budget.lock().unwrap().spend(rows, bytes);
+        }
+    }
+
+    /// Returns the nodes of the context by name, listed on the first call
+    pub async fn nodes_by_name(&'a self) -> Result<&'a HashMap<String, serde_json::Value>> {
+        self.nodes
+            .get_or_try_init(|| async {
+                let nodes = self
+                    .list(
+                        &ResourceType::Node,
+                        ApiBuilder::get_node,
+                        &ListParams::default(),
+                    )
+                    .await?;
+                let mut by_name = HashMap::new();
+                for node in nodes.items {
+                    by_name.insert(node.name_any(), serde_json::to_value(node)?);
+                }
+                Ok(by_name)
+            })
+            .await
+    }
+
+    /// Lists the Helm releases decoded from their Secrets and ConfigMaps, or reads the decoded ones
+    /// from the data source if given
+    pub async fn list_helm_releases(
+        &'a self,
+        kind: &ResourceType,
+    ) -> Result<ObjectList<DynamicObject>> {
+        if let Some(source) = self.source {
+            // The snapshots saved before the values were redacted may still have them
+            let items: Vec<DynamicObject> = self.read(source, kind)?;
+            let items: Vec<DynamicObject> = items.into_iter().map(helm::redacted).collect();
+            self.record(|stats| stats.scanned += items.len());
+            return Ok(ObjectList {
+                types: Default::default(),
+                metadata: Default::default(),
+                items,
+            });
+        }
+
+        let list_params = ListParams::default().labels(helm::OWNER_LABEL);
+        let secrets = self
+            .list(kind, ApiBuilder::get_helmsecret, &list_params)
+            .await?;
+        let config_maps = self
+            .list(kind, ApiBuilder::get_helmconfigmap, &list_params)
+            .await?;
+
+        Ok(ObjectList {
+            types: Default::default(),
+            metadata: Default::default(),
+            items: helm::releases(secrets.items, config_maps.items),
+        })
+    }
+
+    /// Lists the Secrets with their values redacted, so they are neither printed nor saved into the
+    /// snapshots. Only the keys are kept, i.e. to query the Secrets by their type or keys
+    pub async fn list_secrets(
+        &'a self,
+        kind: &ResourceType,
+        list_params: &ListParams,
+    ) -> Result<ObjectList<Secret>> {
+        let mut list = self.list(kind, ApiBuilder::get_secret, list_params).await?;
+        list.items = list.items.into_iter().map(redact).collect();
+        Ok(list)
+    }
+
+    /// Returns the resources of the namespace from the list of all the namespaces, listed on the
+    /// first call. None if not enabled, the kind is cluster-scoped or the list forbidden or failed
+    async fn list_all_namespaces<K>(
+        &self,
+        kind: &ResourceType,
+        api: &Api<K>,
+        list_params: &ListParams,
+    ) -> Result<Option<ObjectList<K>>>
+    where
+        K: Resource + Clone + DeserializeOwned + Debug,
+    {
+        let all_namespaces = match &self.all_namespaces {
+            Some(all_namespaces) if !kind.is_cluster_scoped() => all_namespaces,
+            _ => return Ok(None),
+        };
+
+        let url = all_namespaces_url(api.resource_url(), self.get_namespace());
+        let uri = kube::core::Request::new(&url)
+            .list(list_params)
+            .map_err(kube::Error::BuildRequest)?
+            .uri()
+            .to_string();
+
+        // Locked until listed, so the other namespaces wait for it instead of listing it again
+        let mut lists = all_namespaces.lock().await;
+        if !lists.contains_key(&uri) {
+            let metadata_only = self.lists_metadata(kind, "*");
+            let list = match self.request_pages(&url, list_params, metadata_only).await {
+                Ok(list) => Some(list),
+                // Listing all the namespaces requires a cluster-wide permission. On any failure
+                // the namespaces are listed one by one instead, each one records its own failure
+                Err(e) => {
+                    tracing::debug!(
+                        kind = %kind,
+                        context = %self.get_context(),
+                        "Unable to list all the namespaces: {}",
+                        e
+                    );
+                    None
+                }
+            };
+            lists.insert(uri.clone(), list);
+        }
+
+        let items = match &lists[&uri] {
+            Some(list) => list["items"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter(|item| item["metadata"]["namespace"] == self.get_namespace().as_str())
+                .map(|item| serde_json::from_value(item.clone()))
+                .collect::<serde_json::Result<Vec<K>>>()?,
+            None => return Ok(None),
+        };
+
+        Ok(Some(ObjectList {
+            types: Default::default(),
+            metadata: Default::default(),
+            items,
+        }))
+    }
+}
+
+/// Clears the values of the given Secret, the last applied configuration of 'kubectl apply'
+/// has them too
+fn redact(mut secret: Secret) -> Secret {
+    if let Some(data) = secret.data.as_mut() {
+        data.values_mut().for_each(|v| *v = ByteString(Vec::new()));
+    }
+    if let Some(data) = secret.string_data.as_mut() {
+        data.values_mut().for_each(String::clear);
+    }
+    if let Some(annotations) = secret.metadata.annotations.as_mut() {
+        annotations.remove(LAST_APPLIED);
+    }
+    secret
+}
+
+/// Clears the values of the given Secret read as a dynamic object, i.e. watched by the daemon
+pub fn redact_object(secret: &mut DynamicObject) {
+    for field in &["data", "stringData"] {
+        if let Some(data) = secret.data.get_mut(field).and_then(|d| d.as_object_mut()) {
+            data.values_mut()
+                .for_each(|v| *v = serde_json::Value::String(String::new()));
+        }
+    }
+    if let Some(annotations) = secret.metadata.annotations.as_mut() {
+        annotations.remove(LAST_APPLIED);
+    }
+}
+
+/// i.e. '/api/v1/namespaces/default/pods' => '/api/v1/pods'
+fn all_namespaces_url(url: &str, namespace: &str) -> String {
+    url.replacen(&format!("/namespaces/{}", namespace), "", 1)
 }
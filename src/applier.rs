@@ -0,0 +1,179 @@
+// Copyright (c) 2021 Dentrax
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software is furnished to do so,
+// subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+use crate::api_builder::ApiBuilder;
+use crate::error::{KubesqlError, Result};
+use crate::parser::ApiInserts;
+use kube::api::{ApiResource, DynamicObject, Patch, PatchParams};
+use kube::core::GroupVersionKind;
+use kube::discovery::{self, Scope};
+use kube::{Api, ResourceExt};
+use std::convert::TryFrom;
+
+/// The field manager name to use for server-side apply
+const FIELD_MANAGER: &str = "kubesql";
+
+#[derive(Debug)]
+pub struct AppliedItem {
+    pub context: String,
+    pub namespace: Option<String>,
+    pub kind: String,
+    pub name: String,
+    /// Why the manifest could not be applied to the context and namespace, None if applied
+    pub error: Option<String>,
+}
+
+/// Server-side apply all the given manifests to the given contexts and namespaces.
+/// If no namespace is given, the manifest's own namespace is used. The cluster-scoped ones are
+/// applied once per context. A failure does not stop the others, it is returned with the result
+/// of its context and namespace
+pub async fn apply_inserts(inserts: &ApiInserts) -> Vec<AppliedItem> {
+    let mut applied: Vec<AppliedItem> = Vec::new();
+    for context in &inserts.contexts {
+        applied.extend(apply_context(inserts, context).await);
+    }
+    applied
+}
+
+async fn apply_context(inserts: &ApiInserts, context: &str) -> Vec<AppliedItem> {
+    let failed = |manifest: &serde_json::Value, ns: Option<&str>, e: &KubesqlError| AppliedItem {
+        context: context.to_string(),
+        namespace: ns.map(str::to_string),
+        kind: manifest["kind"].as_str().unwrap_or_default().to_string(),
+        name: manifest["metadata"]["name"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string(),
+        error: Some(e.to_string()),
+    };
+
+    let api = match ApiBuilder::builder()
+        .context(context.to_string())
+        .build()
+        .await
+    {
+        Ok(api) => api,
+        Err(e) => {
+            return inserts
+                .manifests
+                .iter()
+                .map(|m| failed(m, None, &e))
+                .collect()
+        }
+    };
+
+    let mut applied: Vec<AppliedItem> = Vec::new();
+    for manifest in &inserts.manifests {
+        let namespaces = if inserts.namespaces.is_empty() {
+            vec![manifest
+                .pointer("/metadata/namespace")
+                .and_then(|v| v.as_str())
+                .unwrap_or("default")
+                .to_string()]
+        } else {
+            inserts.namespaces.clone()
+        };
+
+        let resource = match resolve(&api, manifest).await {
+            Ok(resource) => resource,
+            Err(e) => {
+                applied.push(failed(manifest, None, &e));
+                continue;
+            }
+        };
+
+        // The cluster-scoped ones are not of any namespace, they are applied once
+        let namespaces: Vec<Option<&str>> = match resource.scope {
+            Scope::Namespaced => namespaces.iter().map(|ns| Some(ns.as_str())).collect(),
+            Scope::Cluster => vec![None],
+        };
+        for ns in namespaces {
+            applied.push(match apply(&api, ns, &resource).await {
+                Ok(item) => item,
+                Err(e) => failed(manifest, ns, &e),
+            });
+        }
+    }
+
+    applied
+}
+
+/// A manifest with the API resource of its apiVersion and kind
+struct Resource {
+    object: DynamicObject,
+    gvk: GroupVersionKind,
+    api_resource: ApiResource,
+    scope: Scope,
+}
+
+/// Resolves the API resource of the given manifest, the CRDs are included as well
+async fn resolve(api: &ApiBuilder<'_>, manifest: &serde_json::Value) -> Result<Resource> {
+    let object: DynamicObject = serde_json::from_value(manifest.clone())
+        .map_err(|e| KubesqlError::Manifest(e.to_string()))?;
+    let types = object.types.as_ref().ok_or_else(|| {
+        KubesqlError::Manifest("'apiVersion' and 'kind' are required".to_string())
+    })?;
+    let gvk =
+        GroupVersionKind::try_from(types).map_err(|e| KubesqlError::Manifest(e.to_string()))?;
+
+    let (api_resource, caps) = discovery::pinned_kind(api.get_client(), &gvk)
+        .await
+        .map_err(|e| KubesqlError::Apply(gvk.kind.clone(), api.get_context().clone(), e))?;
+
+    Ok(Resource {
+        object,
+        gvk,
+        api_resource,
+        scope: caps.scope,
+    })
+}
+
+/// Applies the given resource to the given namespace, or to the cluster if it is cluster-scoped
+async fn apply(api: &ApiBuilder<'_>, ns: Option<&str>, resource: &Resource) -> Result<AppliedItem> {
+    let mut obj = resource.object.clone();
+    let name = obj.name_any();
+    obj.metadata.namespace = ns.map(str::to_string);
+    let dyn_api: Api<DynamicObject> = match ns {
+        Some(ns) => Api::namespaced_with(api.get_client().clone(), ns, &resource.api_resource),
+        None => Api::all_with(api.get_client().clone(), &resource.api_resource),
+    };
+
+    dyn_api
+        .patch(
+            &name,
+            &PatchParams::apply(FIELD_MANAGER),
+            &Patch::Apply(&obj),
+        )
+        .await
+        .map_err(|e| {
+            KubesqlError::Apply(
+                format!("{}/{}", resource.gvk.kind, name),
+                api.get_context().clone(),
+                e,
+            )
+        })?;
+
+    Ok(AppliedItem {
+        context: api.get_context().clone(),
+        namespace: ns.map(str::to_string),
+        kind: resource.gvk.kind.clone(),
+        name,
+        error: None,
+    })
+}
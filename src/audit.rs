@@ -0,0 +1,180 @@
+// Copyright (c) 2021 Dentrax
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software is furnished to do so,
+// subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+use crate::error::{KubesqlError, Result};
+use crate::executor;
+use crate::parser::{self, ApiStatement};
+use crate::planner::PlanContext;
+use crate::sarif::{self, Issue, Rule, SarifError};
+use crate::source::Sources;
+use serde::Serialize;
+use std::path::Path;
+use std::str::FromStr;
+
+/// The built-in reports of 'kubesql audit'
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Report {
+    /// The PVCs not bound, the ConfigMaps and Secrets not referenced by any pod and the services
+    /// selecting no pods
+    Orphans,
+}
+
+impl Report {
+    pub fn checks(&self) -> &'static [Check] {
+        match self {
+            Report::Orphans => ORPHANS,
+        }
+    }
+}
+
+impl FromStr for Report {
+    type Err = String;
+
+    fn from_str(input: &str) -> std::result::Result<Report, Self::Err> {
+        match input {
+            "orphans" => Ok(Report::Orphans),
+            _ => Err(format!("Unexpected report: {}", input)),
+        }
+    }
+}
+
+/// A canned query of a report, each one is a single predicate since the resources matching any
+/// of the predicates of a query are returned
+pub struct Check {
+    /// The rule of the SARIF logs, i.e. 'unbound-pvc'
+    pub name: &'static str,
+    pub kind: &'static str,
+    pub condition: &'static str,
+    pub reason: &'static str,
+}
+
+/// The dangling resources, left behind once their workloads are gone
+static ORPHANS: &[Check] = &[
+    Check {
+        name: "unbound-pvc",
+        kind: "pvc",
+        condition: "pvc.status.phase != 'Bound'",
+        reason: "not bound to a volume",
+    },
+    Check {
+        name: "unused-configmap",
+        kind: "configmap",
+        condition: "configmap.consumers.count = 0",
+        reason: "not referenced by any pod",
+    },
+    Check {
+        name: "unused-secret",
+        kind: "secret",
+        condition: "secret.consumers.count = 0",
+        reason: "not referenced by any pod",
+    },
+    Check {
+        name: "service-without-pods",
+        kind: "service",
+        condition: "service.backends.count = 0",
+        reason: "selects no pods",
+    },
+];
+
+/// A resource matched by a check of a report
+#[derive(Debug, Serialize)]
+pub struct Finding {
+    pub check: String,
+    pub context: String,
+    pub namespace: String,
+    pub kind: String,
+    pub name: String,
+    pub reason: String,
+}
+
+/// Runs the checks of the given report in the given contexts and namespaces, or on the snapshot
+/// if given, and returns the matched resources in the order of the checks
+pub async fn run(
+    report: Report,
+    contexts: &[String],
+    namespaces: &[String],
+    sources: &Sources<'_>,
+) -> Result<Vec<Finding>> {
+    let namespaces = namespaces
+        .iter()
+        .map(|ns| format!("\"{}\"", ns))
+        .collect::<Vec<String>>()
+        .join(", ");
+
+    let mut findings = Vec::new();
+    for check in report.checks() {
+        let sql = format!("SELECT {} WHERE {}", namespaces, check.condition);
+        let plan_context = PlanContext {
+            default_contexts: contexts.to_vec(),
+            ..Default::default()
+        };
+        let api_queries = match parser::parse_sql(&sql, plan_context)? {
+            ApiStatement::Query(q) => q,
+            _ => {
+                return Err(KubesqlError::Unsupported(format!(
+                    "Only SELECT statements are supported as checks: {}",
+                    check.condition
+                )))
+            }
+        };
+
+        for table in executor::select(&api_queries, sources).await? {
+            findings.extend(table.rows.into_iter().map(|row| Finding {
+                check: check.name.to_string(),
+                context: row[0].clone(),
+                namespace: row[1].clone(),
+                kind: check.kind.to_string(),
+                name: row[2].clone(),
+                reason: check.reason.to_string(),
+            }));
+        }
+    }
+
+    Ok(findings)
+}
+
+/// Writes the given findings into the given file as a SARIF log, the checks of the report are
+/// its rules
+pub fn write_sarif(
+    report: Report,
+    findings: &[Finding],
+    path: &Path,
+) -> std::result::Result<(), SarifError> {
+    let rules: Vec<Rule> = report
+        .checks()
+        .iter()
+        .map(|check| Rule {
+            id: check.name.to_string(),
+            description: Some(format!("{}: {}", check.kind, check.reason)),
+            level: "warning",
+        })
+        .collect();
+    let issues: Vec<Issue> = findings
+        .iter()
+        .map(|f| Issue {
+            rule: f.check.clone(),
+            message: format!("{} '{}': {}", f.kind, f.name, f.reason),
+            context: f.context.clone(),
+            namespace: Some(f.namespace.clone()),
+            kind: Some(f.kind.clone()),
+            name: Some(f.name.clone()),
+        })
+        .collect();
+
+    sarif::write(path, &rules, &issues, &[], None)
+}
@@ -0,0 +1,609 @@
+// Copyright (c) 2021 Dentrax
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software is furnished to do so,
+// subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+use crate::derived;
+use crate::diagnostic;
+use crate::eval;
+use crate::helm::HelmRelease;
+use crate::parser::{ApiColumns, ResourceType};
+use k8s_openapi::api::apps::v1::Deployment;
+use k8s_openapi::api::autoscaling::v2::HorizontalPodAutoscaler;
+use k8s_openapi::api::core::v1::{
+    ConfigMap, LimitRange, Namespace, Node, PersistentVolume, PersistentVolumeClaim, Pod,
+    ResourceQuota, Secret, Service, ServiceAccount,
+};
+use k8s_openapi::api::discovery::v1::EndpointSlice;
+use k8s_openapi::api::policy::v1::PodDisruptionBudget;
+use k8s_openapi::api::rbac::v1::{ClusterRole, ClusterRoleBinding, Role, RoleBinding};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+use serde::de::{self, DeserializeSeed, IntoDeserializer, Visitor};
+use serde::Deserialize;
+use std::cell::RefCell;
+use std::fmt;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum CatalogError {
+    #[error("Unable to read the schema of {0}: {1}")]
+    Schema(String, String),
+}
+
+/// A queryable field of a kind, i.e. 'spec.containers.image' of 'string'. The keys of the
+/// maps are '*', i.e. 'metadata.labels.*'
+#[derive(Debug, Clone)]
+pub struct Column {
+    pub path: String,
+    /// i.e. 'string', 'integer', 'Quantity', '[]Container' or 'map[string]string'
+    pub ty: String,
+}
+
+/// The fields of the kinds that are not in the schemas of k8s-openapi: PodMetrics and the custom
+/// resources have no schema at all, EndpointSlice and HorizontalPodAutoscaler are queried with
+/// newer API versions, the backends are joined with the services, the consumers with the
+/// ConfigMaps and Secrets and the blocking PodDisruptionBudgets with the pods
+static EXTRA_COLUMNS: &[(ResourceType, &str, &str)] = &[
+    (ResourceType::PodMetrics, "apiVersion", "string"),
+    (ResourceType::PodMetrics, "kind", "string"),
+    (ResourceType::PodMetrics, "timestamp", "Time"),
+    (ResourceType::PodMetrics, "window", "string"),
+    (ResourceType::PodMetrics, "containers", "[]ContainerMetrics"),
+    (ResourceType::PodMetrics, "containers.name", "string"),
+    (
+        ResourceType::PodMetrics,
+        "containers.usage",
+        "map[string]Quantity",
+    ),
+    (ResourceType::PodMetrics, "containers.usage.*", "Quantity"),
+    (ResourceType::EndpointSlice, "endpoints.zone", "string"),
+    (
+        ResourceType::EndpointSlice,
+        "endpoints.deprecatedTopology",
+        "map[string]string",
+    ),
+    (
+        ResourceType::EndpointSlice,
+        "endpoints.deprecatedTopology.*",
+        "string",
+    ),
+    (
+        ResourceType::EndpointSlice,
+        "endpoints.hints",
+        "EndpointHints",
+    ),
+    (
+        ResourceType::EndpointSlice,
+        "endpoints.hints.forZones",
+        "[]ForZone",
+    ),
+    (
+        ResourceType::EndpointSlice,
+        "endpoints.hints.forZones.name",
+        "string",
+    ),
+    (ResourceType::Service, "backends", "Backends"),
+    (ResourceType::Service, "backends.count", "integer"),
+    (ResourceType::Service, "backends.ready", "integer"),
+    (ResourceType::ConfigMap, "consumers", "Consumers"),
+    (ResourceType::ConfigMap, "consumers.count", "integer"),
+    (ResourceType::Secret, "consumers", "Consumers"),
+    (ResourceType::Secret, "consumers.count", "integer"),
+    (ResourceType::Pod, "blocking_pdb", "string"),
+    (ResourceType::Application, "apiVersion", "string"),
+    (ResourceType::Application, "kind", "string"),
+    (ResourceType::Application, "spec", "any"),
+    (ResourceType::Application, "status", "any"),
+    (ResourceType::Kustomization, "apiVersion", "string"),
+    (ResourceType::Kustomization, "kind", "string"),
+    (ResourceType::Kustomization, "spec", "any"),
+    (ResourceType::Kustomization, "status", "any"),
+];
+
+/// The types whose values are arbitrary JSON, any path below them is valid
+const OPAQUE_TYPES: &[&str] = &["any", "FieldsV1", "RawExtension"];
+
+/// Returns the fields of the given kind read from its k8s-openapi schema, in the order of their
+/// paths, i.e. 'metadata.name' before 'spec.containers.image', followed by the derived ones
+pub fn columns(kind: &ResourceType) -> Result<Vec<Column>, CatalogError> {
+    let mut columns = match kind {
+        ResourceType::Deployment => trace::<Deployment>(kind, "")?,
+        ResourceType::Pod => trace::<Pod>(kind, "")?,
+        ResourceType::Service => trace::<Service>(kind, "")?,
+        ResourceType::PodMetrics => trace::<ObjectMeta>(kind, "metadata")?,
+        ResourceType::Node => trace::<Node>(kind, "")?,
+        ResourceType::EndpointSlice => trace::<EndpointSlice>(kind, "")?,
+        ResourceType::HorizontalPodAutoscaler => trace::<HorizontalPodAutoscaler>(kind, "")?,
+        ResourceType::ResourceQuota => trace::<ResourceQuota>(kind, "")?,
+        ResourceType::LimitRange => trace::<LimitRange>(kind, "")?,
+        ResourceType::ServiceAccount => trace::<ServiceAccount>(kind, "")?,
+        ResourceType::Role => trace::<Role>(kind, "")?,
+        ResourceType::RoleBinding => trace::<RoleBinding>(kind, "")?,
+        ResourceType::ClusterRole => trace::<ClusterRole>(kind, "")?,
+        ResourceType::ClusterRoleBinding => trace::<ClusterRoleBinding>(kind, "")?,
+        ResourceType::PersistentVolume => trace::<PersistentVolume>(kind, "")?,
+        ResourceType::Namespace => trace::<Namespace>(kind, "")?,
+        ResourceType::HelmRelease => trace::<HelmRelease>(kind, "")?,
+        ResourceType::Application | ResourceType::Kustomization => {
+            trace::<ObjectMeta>(kind, "metadata")?
+        }
+        ResourceType::PersistentVolumeClaim => trace::<PersistentVolumeClaim>(kind, "")?,
+        ResourceType::ConfigMap => trace::<ConfigMap>(kind, "")?,
+        ResourceType::Secret => trace::<Secret>(kind, "")?,
+        ResourceType::PodDisruptionBudget => trace::<PodDisruptionBudget>(kind, "")?,
+    };
+
+    for (k, path, ty) in EXTRA_COLUMNS {
+        if k == kind && !columns.iter().any(|c| c.path == *path) {
+            columns.push(Column {
+                path: path.to_string(),
+                ty: ty.to_string(),
+            });
+        }
+    }
+
+    columns.sort_by(|a, b| a.path.cmp(&b.path));
+    columns.extend(derived::paths(kind).map(|path| Column {
+        path: path.to_string(),
+        ty: "derived".to_string(),
+    }));
+
+    Ok(columns)
+}
+
+/// Returns the fields of the kind that match the pattern of SHOW COLUMNS, if given
+pub fn find(query: &ApiColumns) -> Result<Vec<Column>, CatalogError> {
+    let mut columns = columns(&query.kind)?;
+    if let Some(pattern) = &query.pattern {
+        columns.retain(|c| eval::like(&c.path, pattern, query.case_insensitive));
+    }
+
+    Ok(columns)
+}
+
+/// Whether the given field path exists on the kind, the derived fields included. The array
+/// indexes, the lookups and the keys of the maps match any value, i.e. 'spec.containers.0.image',
+/// "status.conditions['Ready'].status" or 'metadata.labels.app'. The kinds without a readable
+/// schema contain all the paths.
+pub fn contains(kind: &ResourceType, path: &str) -> bool {
+    let columns = match columns(kind) {
+        Ok(columns) => columns,
+        Err(_) => return true,
+    };
+
+    // i.e. 'pod.node.metadata.name' is a field of the node the pod is scheduled on
+    if let (ResourceType::Pod, Some(node)) = (kind, path.strip_prefix("node.")) {
+        return contains(&ResourceType::Node, node);
+    }
+
+    // i.e. "conditions['Ready']" is the shorthand of "status.conditions['Ready']"
+    let segments = segments(path);
+    let parent = eval::shorthand(&kind.to_string(), &segments, None);
+    let fields: Vec<&str> = parent
+        .into_iter()
+        .chain(segments.iter().map(String::as_str))
+        .filter(|f| f.parse::<usize>().is_err() && eval::lookup(f).is_none())
+        .collect();
+
+    columns.iter().any(|c| {
+        let column: Vec<&str> = c.path.split('.').collect();
+        let matches = |len: usize| {
+            column
+                .iter()
+                .zip(&fields[..len])
+                .all(|(c, f)| *c == "*" || c == f)
+        };
+
+        match column.len() {
+            len if len == fields.len() => matches(len),
+            len if len < fields.len() => {
+                OPAQUE_TYPES.contains(&c.ty.trim_start_matches("[]")) && matches(len)
+            }
+            _ => false,
+        }
+    })
+}
+
+/// Splits the given path into its fields, the lookups may contain dots,
+/// i.e. "metadata.labels['app.kubernetes.io/name']" => ["metadata", "labels", "[app.kubernetes.io/name]"]
+fn segments(path: &str) -> Vec<String> {
+    let mut segments = Vec::new();
+    let mut rest = path;
+    while !rest.is_empty() {
+        if let Some(lookup) = rest.strip_prefix("['") {
+            let (key, after) = lookup.split_once("']").unwrap_or((lookup, ""));
+            segments.push(format!("[{}]", key));
+            rest = after.strip_prefix('.').unwrap_or(after);
+            continue;
+        }
+
+        let end = [rest.find('.'), rest.find("['")]
+            .iter()
+            .flatten()
+            .min()
+            .copied()
+            .unwrap_or(rest.len());
+        segments.push(rest[..end].to_string());
+        rest = rest[end..].strip_prefix('.').unwrap_or(&rest[end..]);
+    }
+    segments
+}
+
+/// Returns the given fields named as in the schema of the kind, if they match it only regardless
+/// of the case or the plural, i.e. 'status.phase' of 'Status.Phase' or 'spec.containers.image' of
+/// 'spec.container.image'. The array indexes, the lookups, the keys of the maps and the fields
+/// below the opaque types are kept as given. None if any of the fields matches no field
+pub fn resolve(kind: &ResourceType, fields: &[String]) -> Option<Vec<String>> {
+    let columns = columns(kind).ok()?;
+    let columns: Vec<(Vec<&str>, &str)> = columns
+        .iter()
+        .map(|c| (c.path.split('.').collect(), c.ty.trim_start_matches("[]")))
+        .collect();
+
+    let mut resolved: Vec<String> = Vec::new();
+    // The schema fields resolved so far, without the array indexes and the lookups
+    let mut names: Vec<&str> = Vec::new();
+    for (i, field) in fields.iter().enumerate() {
+        if field.parse::<usize>().is_ok() || eval::lookup(field).is_some() {
+            resolved.push(field.clone());
+            continue;
+        }
+
+        let parents: Vec<&(Vec<&str>, &str)> = columns
+            .iter()
+            .filter(|(c, _)| {
+                c.len() >= names.len() && c.iter().zip(&names).all(|(c, n)| *c == "*" || c == n)
+            })
+            .collect();
+
+        // i.e. 'metadata.managedFields.fieldsV1.f:spec', any path below is valid
+        if parents
+            .iter()
+            .any(|(c, ty)| c.len() == names.len() && OPAQUE_TYPES.contains(ty))
+        {
+            resolved.extend(fields[i..].iter().cloned());
+            return Some(resolved);
+        }
+
+        let candidates: Vec<&str> = parents
+            .iter()
+            .filter_map(|(c, _)| c.get(names.len()).copied())
+            .collect();
+        let name = match candidates.iter().find(|c| **c == field || **c == "*") {
+            // i.e. 'metadata.labels.app', the keys of the maps are kept as given
+            Some(&"*") => "*",
+            Some(c) => c,
+            None => candidates.iter().find(|c| same_name(c, field)).copied()?,
+        };
+
+        resolved.push(match name {
+            "*" => field.clone(),
+            _ => name.to_string(),
+        });
+        names.push(name);
+    }
+
+    Some(resolved)
+}
+
+/// Whether the given field names are the same regardless of the case and the plural,
+/// i.e. 'containers' and 'Container'
+fn same_name(name: &str, field: &str) -> bool {
+    let (name, field) = (name.to_lowercase(), field.to_lowercase());
+    let singular = |s: &str| -> String {
+        s.strip_suffix("es")
+            .filter(|s| s.ends_with('s') || s.ends_with("ch") || s.ends_with('x'))
+            .or_else(|| s.strip_suffix('s'))
+            .unwrap_or(s)
+            .to_string()
+    };
+
+    name == field || singular(&name) == singular(&field)
+}
+
+/// Returns the closest field path of the kind to the given unknown one, if any is close enough,
+/// i.e. 'status.phase' for 'status.phaze'
+pub fn suggest(kind: &ResourceType, path: &str) -> Option<String> {
+    let columns = columns(kind).ok()?;
+
+    columns
+        .into_iter()
+        .map(|c| c.path)
+        .filter(|p| !p.contains('*'))
+        .map(|p| {
+            (
+                diagnostic::distance(&path.to_lowercase(), &p.to_lowercase()),
+                p,
+            )
+        })
+        .filter(|(d, _)| *d <= 3)
+        .min_by_key(|(d, _)| *d)
+        .map(|(_, p)| p)
+}
+
+/// Reads the fields of the given type by deserializing it from a tracer, which answers each
+/// field of the structs, a single element of the arrays and the maps, and a placeholder
+/// value of the primitives
+fn trace<'de, T: Deserialize<'de>>(
+    kind: &ResourceType,
+    path: &str,
+) -> Result<Vec<Column>, CatalogError> {
+    let columns = RefCell::new(Vec::new());
+    T::deserialize(Tracer {
+        columns: &columns,
+        path: path.to_string(),
+        array: String::new(),
+    })
+    .map_err(|e| CatalogError::Schema(kind.to_string(), e.0))?;
+
+    Ok(columns.into_inner())
+}
+
+#[derive(Debug)]
+struct TraceError(String);
+
+impl fmt::Display for TraceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for TraceError {}
+
+impl de::Error for TraceError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        TraceError(msg.to_string())
+    }
+}
+
+#[derive(Clone)]
+struct Tracer<'a> {
+    columns: &'a RefCell<Vec<Column>>,
+    path: String,
+    /// The '[]' of each array the value is an element of, i.e. '[]' for 'spec.containers'
+    array: String,
+}
+
+impl<'a> Tracer<'a> {
+    fn record(&self, ty: &str) {
+        if !self.path.is_empty() {
+            self.columns.borrow_mut().push(Column {
+                path: self.path.clone(),
+                ty: format!("{}{}", self.array, ty),
+            });
+        }
+    }
+
+    fn field(&self, name: &str) -> Tracer<'a> {
+        Tracer {
+            columns: self.columns,
+            path: match self.path.is_empty() {
+                true => name.to_string(),
+                false => format!("{}.{}", self.path, name),
+            },
+            array: String::new(),
+        }
+    }
+
+    fn element(&self) -> Tracer<'a> {
+        Tracer {
+            columns: self.columns,
+            path: self.path.clone(),
+            array: format!("{}[]", self.array),
+        }
+    }
+}
+
+macro_rules! primitive {
+    ($method:ident, $ty:expr, $visit:ident, $value:expr) => {
+        fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, TraceError> {
+            self.record($ty);
+            visitor.$visit($value)
+        }
+    };
+}
+
+impl<'de, 'a> de::Deserializer<'de> for Tracer<'a> {
+    type Error = TraceError;
+
+    // i.e. IntOrString or serde_json::Value, both accept a string
+    primitive!(deserialize_any, "any", visit_str, "");
+    primitive!(deserialize_bool, "boolean", visit_bool, false);
+    primitive!(deserialize_i8, "integer", visit_i64, 0);
+    primitive!(deserialize_i16, "integer", visit_i64, 0);
+    primitive!(deserialize_i32, "integer", visit_i64, 0);
+    primitive!(deserialize_i64, "integer", visit_i64, 0);
+    primitive!(deserialize_u8, "integer", visit_u64, 0);
+    primitive!(deserialize_u16, "integer", visit_u64, 0);
+    primitive!(deserialize_u32, "integer", visit_u64, 0);
+    primitive!(deserialize_u64, "integer", visit_u64, 0);
+    primitive!(deserialize_f32, "number", visit_f64, 0.0);
+    primitive!(deserialize_f64, "number", visit_f64, 0.0);
+    primitive!(deserialize_char, "string", visit_str, "");
+    primitive!(deserialize_str, "string", visit_str, "");
+    primitive!(deserialize_string, "string", visit_str, "");
+    primitive!(deserialize_bytes, "string", visit_str, "");
+    primitive!(deserialize_byte_buf, "string", visit_str, "");
+    primitive!(deserialize_identifier, "string", visit_str, "");
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, TraceError> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, TraceError> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, TraceError> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, TraceError> {
+        visitor.visit_unit()
+    }
+
+    // i.e. Time or Quantity, the inner value is not traced
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, TraceError> {
+        self.record(name);
+        let value = match name {
+            "Time" | "MicroTime" => "1970-01-01T00:00:00Z",
+            _ => "",
+        };
+        visitor.visit_newtype_struct(value.into_deserializer())
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, TraceError> {
+        visitor.visit_seq(Elements(vec![self.element()]))
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(
+        self,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, TraceError> {
+        visitor.visit_seq(Elements((0..len).map(|_| self.element()).collect()))
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, TraceError> {
+        self.deserialize_tuple(len, visitor)
+    }
+
+    // The map is recorded after its value to tell its type, i.e. 'map[string]string'
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, TraceError> {
+        let index = self.columns.borrow().len();
+        let value = visitor.visit_map(Entries {
+            fields: vec![""],
+            tracer: self.field("*"),
+            values: true,
+        })?;
+
+        let mut columns = self.columns.borrow_mut();
+        let ty = columns.get(index).map(|c| c.ty.clone()).unwrap_or_default();
+        columns.insert(
+            index,
+            Column {
+                path: self.path.clone(),
+                ty: format!("{}map[string]{}", self.array, ty),
+            },
+        );
+        Ok(value)
+    }
+
+    // The type of the kind is validated on deserialization, so they are not traced
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, TraceError> {
+        self.record(name);
+        for field in fields
+            .iter()
+            .filter(|f| matches!(**f, "apiVersion" | "kind"))
+        {
+            self.field(field).record("string");
+        }
+
+        visitor.visit_map(Entries {
+            fields: fields
+                .iter()
+                .copied()
+                .filter(|f| !matches!(*f, "apiVersion" | "kind"))
+                .collect(),
+            tracer: self,
+            values: false,
+        })
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, TraceError> {
+        self.record("string");
+        visitor.visit_enum(
+            variants
+                .first()
+                .copied()
+                .unwrap_or_default()
+                .into_deserializer(),
+        )
+    }
+}
+
+/// The elements of an array, a single one to trace the element type
+struct Elements<'a>(Vec<Tracer<'a>>);
+
+impl<'de, 'a> de::SeqAccess<'de> for Elements<'a> {
+    type Error = TraceError;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, TraceError> {
+        match self.0.pop() {
+            Some(tracer) => seed.deserialize(tracer).map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+/// The fields of a struct, or a single entry of a map whose value is traced under '*'
+struct Entries<'a> {
+    fields: Vec<&'static str>,
+    tracer: Tracer<'a>,
+    /// Whether the values are the values of a map, not the fields of a struct
+    values: bool,
+}
+
+impl<'de, 'a> de::MapAccess<'de> for Entries<'a> {
+    type Error = TraceError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, TraceError> {
+        match self.fields.first() {
+            Some(field) => seed.deserialize((*field).into_deserializer()).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(
+        &mut self,
+        seed: V,
+    ) -> Result<V::Value, TraceError> {
+        let field = self.fields.remove(0);
+        match self.values {
+            true => seed.deserialize(self.tracer.clone()),
+            false => seed.deserialize(self.tracer.field(field)),
+        }
+    }
+}
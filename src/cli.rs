@@ -0,0 +1,801 @@
+// Copyright (c) 2021 Dentrax
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software is furnished to do so,
+// subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+use crate::anonymizer;
+use crate::api_builder::{Budget, Limits};
+use crate::audit::Report;
+use crate::config::{Color, Config, Guard};
+use crate::daemon;
+use crate::error::{KubesqlError, Result};
+use crate::exec::{self, ExecOptions};
+use crate::exporter::Export;
+use crate::library;
+use crate::notify::{Notification, Target};
+use crate::parser::ResourceType;
+use crate::printer::{self, DedupeBy, Layout, Output, PrintOptions, SortBy};
+use crate::quantity;
+use crate::sink::Destination;
+use crate::snapshot;
+use crate::telemetry::LogFormat;
+use crate::template::{Syntax, Template};
+use crate::validator::Protection;
+use clap::{Args, Parser, Subcommand};
+use clap_complete::Shell;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+#[derive(Debug, Parser)]
+#[command(
+    name = "kubesql",
+    version,
+    author = "Dentrax <furkan.turkal@hotmail.com>",
+    about = "kubesql is an experimental tool for querying your Kubernetes API Server using SQL"
+)]
+pub struct Cli {
+    #[command(flatten)]
+    pub global: GlobalArgs,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Runs the query without a subcommand, same as 'kubesql query'
+    #[command(flatten)]
+    pub query: QueryArgs,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Runs the given query
+    Query(QueryArgs),
+    /// Runs the queries read from the standard input interactively, each one ends with ';'
+    Repl(ExecArgs),
+    /// Serves the queries over HTTP, 'POST /query' with the query as body returns the results as JSON
+    Serve(ServeArgs),
+    /// Saves the resources of the given contexts and namespaces to query them offline
+    Snapshot(SnapshotArgs),
+    /// Lists the executed queries, or re-runs one of them
+    History(HistoryArgs),
+    /// Shows how the number of rows of a query run with --record evolved, per context and namespace
+    Trend(TrendArgs),
+    /// Lists the queries of the library, or runs them by name or tag
+    #[command(subcommand)]
+    Library(LibraryCommand),
+    /// Lists the unique container images of the pods, with the contexts and namespaces running them
+    Images(ImagesArgs),
+    /// Prints the recent log lines of the matched pods, prefixed with the context, namespace, pod and container
+    Logs(LogsArgs),
+    /// Forwards a local port to the single pod, service or deployment matched, until interrupted
+    #[command(name = "port-forward")]
+    PortForward(PortForwardArgs),
+    /// Watches the given kinds of the contexts and serves the queries on them over a local socket
+    Daemon(DaemonArgs),
+    /// Checks the connectivity, the version and the credentials of the API server of each context
+    Ping(PingArgs),
+    /// Runs a built-in report of canned queries, 'orphans' lists the dangling resources
+    Audit(AuditArgs),
+    /// Runs the queries of the given policies file and checks their number of rows, fails if any
+    /// policy does not pass on any context
+    Check(CheckArgs),
+    /// Applies the manifests of the given file or directory to each of the given contexts and
+    /// namespaces with server-side apply, same as INSERT
+    Apply(ApplyArgs),
+    /// Prints the completion script of the given shell
+    Completions(CompletionsArgs),
+}
+
+/// The flags shared by all the subcommands, the unset ones default to
+/// their environment variables, then to the config file
+#[derive(Debug, Args)]
+pub struct GlobalArgs {
+    /// One of table, vertical, ndjson, chart, jsonpath=TEMPLATE or go-template=TEMPLATE [default: table]
+    #[arg(short, long, global = true, env = "KUBESQL_OUTPUT", value_parser = parse_output)]
+    pub output: Option<String>,
+
+    /// The auto layout picks the first one of nested, flat and vertical fitting in the terminal [default: auto]
+    #[arg(long, global = true, env = "KUBESQL_LAYOUT", value_parser = ["auto", "nested", "flat", "vertical"])]
+    pub layout: Option<String>,
+
+    /// Values longer than this are truncated [default: 48]
+    #[arg(long, global = true, env = "KUBESQL_MAX_COL_WIDTH")]
+    pub max_col_width: Option<usize>,
+
+    /// Do not truncate the long values
+    #[arg(long, global = true, env = "KUBESQL_WIDE")]
+    pub wide: bool,
+
+    /// Replaces the names of the contexts, namespaces and resources with pseudonyms, i.e. to share the results
+    #[arg(long, global = true, env = "KUBESQL_ANONYMIZE")]
+    pub anonymize: bool,
+
+    /// Sorts the resources across the contexts and namespaces, 'age' is the newest first
+    #[arg(long, global = true, env = "KUBESQL_SORT_BY", value_parser = ["name", "age", "namespace", "context"])]
+    pub sort_by: Option<String>,
+
+    /// Prints the same resource of many contexts once, listing the contexts it exists in,
+    /// 'name' matches them by namespace, name and the SELECT field values
+    #[arg(long, global = true, env = "KUBESQL_DEDUPE_BY", value_parser = ["name"])]
+    pub dedupe_by: Option<String>,
+
+    /// Colors the headers and the errors [default: auto]
+    #[arg(long, global = true, env = "KUBESQL_COLOR", value_parser = ["auto", "always", "never"])]
+    pub color: Option<String>,
+
+    /// The kubeconfig file to use instead of $KUBECONFIG or ~/.kube/config
+    #[arg(long, global = true, env = "KUBESQL_KUBECONFIG", value_name = "FILE")]
+    pub kubeconfig: Option<PathBuf>,
+
+    /// Aborts the query if not finished in the given seconds
+    #[arg(long, global = true, env = "KUBESQL_TIMEOUT", value_name = "SECONDS")]
+    pub timeout: Option<u64>,
+
+    /// The contexts to query if FROM statement is not given
+    #[arg(
+        long,
+        global = true,
+        env = "KUBESQL_CONTEXTS",
+        value_name = "CONTEXT",
+        value_delimiter = ','
+    )]
+    pub default_contexts: Vec<String>,
+
+    /// Logs the planned queries and the duration of each step to stderr
+    #[arg(short, long, global = true)]
+    pub verbose: bool,
+
+    /// Writes the logs as text or as one JSON object per line [default: text]
+    #[arg(long, global = true, env = "KUBESQL_LOG_FORMAT", value_parser = ["text", "json"])]
+    pub log_format: Option<String>,
+
+    /// The snapshot file to write or to query offline [default: ~/.kubesql/snapshot.json]
+    #[arg(long, global = true, env = "KUBESQL_SNAPSHOT", value_name = "FILE")]
+    pub snapshot: Option<PathBuf>,
+
+    /// The socket of the daemon to serve or to query [default: ~/.kubesql/daemon.sock]
+    #[arg(long, global = true, env = "KUBESQL_SOCKET", value_name = "FILE")]
+    pub socket: Option<PathBuf>,
+
+    /// The contexts INSERT and --exec are guarded on, as LIKE patterns, i.e. 'prod-%'
+    #[arg(
+        long,
+        global = true,
+        env = "KUBESQL_PROTECTED_CONTEXTS",
+        value_name = "PATTERN",
+        value_delimiter = ','
+    )]
+    pub protected_contexts: Vec<String>,
+
+    /// Whether INSERT and --exec run on the protected contexts with --i-know-what-im-doing,
+    /// or never [default: confirm]
+    #[arg(long, global = true, env = "KUBESQL_GUARD", value_parser = ["confirm", "deny"])]
+    pub guard: Option<String>,
+
+    /// The directory of the '.sql' files of the library [default: ~/.kubesql/library]
+    #[arg(long, global = true, env = "KUBESQL_LIBRARY", value_name = "DIR")]
+    pub library: Option<PathBuf>,
+
+    /// The field printed next to the names of a kind if none of its fields is selected,
+    /// i.e. 'pod=status.phase'
+    #[arg(long, global = true, value_name = "KIND=FIELD", value_parser = parse_param)]
+    pub display: Vec<(String, String)>,
+}
+
+impl GlobalArgs {
+    /// Sets the defaults of the unset flags from the given config
+    pub fn with_config(mut self, config: Config) -> GlobalArgs {
+        self.output = self.output.or(config.output);
+        self.layout = self.layout.or(config.layout);
+        self.max_col_width = self.max_col_width.or(config.max_col_width);
+        self.wide = self.wide || config.wide.unwrap_or_default();
+        self.color = self.color.or(config.color);
+        self.sort_by = self.sort_by.or(config.sort_by);
+        self.dedupe_by = self.dedupe_by.or(config.dedupe_by);
+        self.kubeconfig = self.kubeconfig.or(config.kubeconfig);
+        self.timeout = self.timeout.or(config.timeout);
+        self.snapshot = self.snapshot.or(config.snapshot);
+        self.socket = self.socket.or(config.socket);
+        self.library = self.library.or(config.library);
+        self.log_format = self.log_format.or(config.log_format);
+        self.guard = self.guard.or(config.guard);
+        if self.default_contexts.is_empty() {
+            self.default_contexts = config.contexts;
+        }
+        if self.protected_contexts.is_empty() {
+            self.protected_contexts = config.protected_contexts;
+        }
+        // The kinds given by --display take precedence over the ones of the config
+        let mut display = config.display;
+        display.extend(self.display.drain(..));
+        self.display = display.into_iter().collect();
+        self
+    }
+
+    /// The values are already validated by their possible values
+    pub fn print_options(&self) -> PrintOptions {
+        let color = self.color.as_deref().unwrap_or("auto");
+        PrintOptions {
+            output: Output::from_str(self.output.as_deref().unwrap_or("table")).unwrap_or_default(),
+            layout: Layout::from_str(self.layout.as_deref().unwrap_or("auto")).unwrap_or_default(),
+            max_col_width: match self.wide {
+                true => None,
+                false => Some(self.max_col_width.unwrap_or(48)),
+            },
+            color: Color::from_str(color).unwrap_or_default().enabled(),
+            width: printer::terminal_width(),
+            anonymizer: self.anonymize.then(anonymizer::shared),
+            sort_by: self
+                .sort_by
+                .as_deref()
+                .and_then(|s| SortBy::from_str(s).ok()),
+            dedupe_by: self
+                .dedupe_by
+                .as_deref()
+                .and_then(|s| DedupeBy::from_str(s).ok()),
+        }
+    }
+
+    /// The value is already validated by its possible values
+    pub fn log_format(&self) -> LogFormat {
+        LogFormat::from_str(self.log_format.as_deref().unwrap_or("text")).unwrap_or_default()
+    }
+
+    /// Returns the given contexts, or the default ones if none given
+    pub fn contexts<'a>(&'a self, given: &'a [String]) -> &'a [String] {
+        match given.is_empty() {
+            true => &self.default_contexts,
+            false => given,
+        }
+    }
+
+    pub fn snapshot_path(&self) -> PathBuf {
+        self.snapshot.clone().unwrap_or_else(snapshot::default_path)
+    }
+
+    pub fn socket_path(&self) -> PathBuf {
+        self.socket.clone().unwrap_or_else(daemon::default_path)
+    }
+
+    /// The value is already validated by its possible values
+    pub fn protection(&self, confirmed: bool) -> Protection {
+        Protection {
+            contexts: self.protected_contexts.clone(),
+            guard: Guard::from_str(self.guard.as_deref().unwrap_or("confirm")).unwrap_or_default(),
+            confirmed,
+        }
+    }
+
+    pub fn library_path(&self) -> PathBuf {
+        self.library.clone().unwrap_or_else(library::default_path)
+    }
+}
+
+/// The flags of running a query
+#[derive(Debug, Args)]
+pub struct ExecArgs {
+    /// Binds the given value to the $KEY placeholder in the query
+    #[arg(short, long = "param", value_name = "KEY=VALUE", value_parser = parse_param)]
+    pub params: Vec<(String, String)>,
+
+    /// Compare the strings case-insensitively
+    #[arg(short = 'i', long)]
+    pub case_insensitive: bool,
+
+    /// The contexts to query instead of the ones of FROM statement, which can be omitted then
+    #[arg(
+        long,
+        value_name = "CONTEXT",
+        value_delimiter = ',',
+        conflicts_with = "daemon"
+    )]
+    pub contexts: Vec<String>,
+
+    /// The namespaces to query instead of the ones of SELECT statement, which can select only
+    /// the fields then, i.e. 'SELECT pod.status.phase WHERE ...'
+    #[arg(
+        long,
+        value_name = "NAMESPACE",
+        value_delimiter = ',',
+        conflicts_with = "daemon"
+    )]
+    pub namespaces: Vec<String>,
+
+    /// The namespaces not to query out of all of them, i.e. 'kube-system,kube-public' for 'SELECT *'
+    #[arg(long, value_name = "NAMESPACE", value_delimiter = ',')]
+    pub exclude_namespaces: Vec<String>,
+
+    /// Writes the results into the given file as well, i.e. sqlite:results.db
+    #[arg(long, value_name = "FORMAT:PATH", value_parser = Export::from_str)]
+    pub export: Option<Export>,
+
+    /// Sends the results to 'webhook:URL' or 'slack:CHANNEL' as well if there is any row,
+    /// the Slack messages are posted through $KUBESQL_SLACK_WEBHOOK
+    #[arg(long, value_name = "TARGET", value_parser = Target::from_str)]
+    pub notify: Option<Target>,
+
+    /// The go-template of the notification, rendered against the rows as
+    /// '{"query": ..., "rows": N, "items": [...]}', i.e. '{{range .items}}{{.name}} {{end}}'
+    #[arg(long, value_name = "TEMPLATE", value_parser = parse_go_template, requires = "notify")]
+    pub notify_template: Option<Template>,
+
+    /// Sends the results to the given sink instead of printing them, can be given many times:
+    /// 'stdout', 'json:PATH', 'webhook:URL', 'sqlite:PATH', 'parquet:PATH' or 'prometheus:PATH'
+    #[arg(long = "sink", value_name = "SINK", value_parser = Destination::from_str)]
+    pub sinks: Vec<Destination>,
+
+    /// Appends the number of result rows of each context and namespace to '~/.kubesql/trends.jsonl',
+    /// to show how they evolve with 'kubesql trend'
+    #[arg(long)]
+    pub record: bool,
+
+    /// Run the query on the snapshot instead of the clusters
+    #[arg(long)]
+    pub offline: bool,
+
+    /// Run the query on the resources cached by 'kubesql daemon' instead of the clusters
+    #[arg(long, conflicts_with_all = ["offline", "command", "stats"])]
+    pub daemon: bool,
+
+    /// Prints the API calls, the scanned and matched resources, the transferred bytes
+    /// and the time spent of each context to stderr after the results
+    #[arg(long)]
+    pub stats: bool,
+
+    /// Runs the given command in each matched pod and adds its output as a column,
+    /// i.e. 'cat /etc/hostname'. It runs without a shell.
+    #[arg(
+        long = "exec",
+        value_name = "COMMAND",
+        value_parser = parse_command,
+        conflicts_with = "offline"
+    )]
+    pub command: Option<String>,
+
+    /// Runs the command in the given container, in the default one of the pod otherwise
+    #[arg(long, value_name = "CONTAINER", requires = "command")]
+    pub exec_container: Option<String>,
+
+    /// Stops waiting for the command of a pod after the given seconds
+    #[arg(long, value_name = "SECONDS", default_value_t = 10)]
+    pub exec_timeout: u64,
+
+    /// Stops listing once the given number of resources are listed across all the contexts,
+    /// the results are truncated then
+    #[arg(long, value_name = "ROWS", conflicts_with_all = ["command", "daemon"])]
+    pub max_rows: Option<usize>,
+
+    /// Stops listing once the given size of resources is listed across all the contexts,
+    /// i.e. '500Mi'. The results are truncated then
+    #[arg(long, value_name = "BYTES", value_parser = parse_bytes, conflicts_with_all = ["command", "daemon"])]
+    pub max_bytes: Option<usize>,
+
+    /// Asks before running a query making more list calls than the given ones, estimated as
+    /// contexts × namespaces × kinds. It fails instead if the input is not a terminal
+    #[arg(
+        long,
+        value_name = "CALLS",
+        default_value_t = 1000,
+        conflicts_with = "daemon"
+    )]
+    pub confirm_calls: usize,
+
+    /// Runs the queries making more list calls than --confirm-calls without asking
+    #[arg(long, conflicts_with = "daemon")]
+    pub force: bool,
+
+    /// Runs INSERT and --exec on the protected contexts as well, unless guarded with 'deny'
+    #[arg(long = "i-know-what-im-doing")]
+    pub confirmed: bool,
+}
+
+impl ExecArgs {
+    pub fn params(&self) -> HashMap<String, String> {
+        self.params.iter().cloned().collect()
+    }
+
+    /// Returns the notification of the results of the given query, if --notify is given
+    pub fn notification(&self, query: &str) -> Option<Notification> {
+        self.notify.as_ref().map(|target| Notification {
+            target: target.clone(),
+            query: query.to_string(),
+            template: self.notify_template.clone(),
+        })
+    }
+
+    /// Returns the budget of the lists, if --max-rows or --max-bytes is given
+    pub fn budget(&self) -> Option<Budget> {
+        (self.max_rows.is_some() || self.max_bytes.is_some())
+            .then(|| Arc::new(Mutex::new(Limits::new(self.max_rows, self.max_bytes))))
+    }
+
+    /// Returns the list calls a query may make without asking, unlimited if --force is given
+    pub fn max_calls(&self) -> Option<usize> {
+        (!self.force).then_some(self.confirm_calls)
+    }
+
+    /// Returns the options of running the --exec command, if given
+    pub fn exec_options(&self) -> Option<ExecOptions> {
+        self.command.as_ref().map(|c| ExecOptions {
+            // Already validated while parsing the arguments
+            command: shell_words::split(c).unwrap_or_default(),
+            container: self.exec_container.clone(),
+            timeout: Duration::from_secs(self.exec_timeout),
+            concurrency: exec::CONCURRENCY,
+        })
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct QueryArgs {
+    /// The query to run
+    #[arg(value_name = "SQL", conflicts_with_all = ["query", "file"])]
+    pub sql: Option<String>,
+
+    /// The query to run
+    #[arg(short, long, value_name = "SQL", overrides_with = "file")]
+    pub query: Option<String>,
+
+    /// Reads the query from the given file
+    #[arg(short, long, value_name = "FILE", overrides_with = "query")]
+    pub file: Option<PathBuf>,
+
+    #[command(flatten)]
+    pub exec: ExecArgs,
+}
+
+impl QueryArgs {
+    /// Returns the given query, or reads it from the given file
+    pub async fn sql(&self) -> Result<String> {
+        if let Some(q) = self.sql.as_ref().or(self.query.as_ref()) {
+            return Ok(q.clone());
+        }
+
+        match &self.file {
+            Some(f) => tokio::fs::read_to_string(f)
+                .await
+                .map_err(|e| KubesqlError::QueryFile(f.display().to_string(), e)),
+            None => Err(KubesqlError::QueryRequired),
+        }
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct ServeArgs {
+    /// The address to listen on
+    #[arg(long, default_value = "127.0.0.1:8080")]
+    pub listen: String,
+
+    /// Run the queries on the snapshot instead of the clusters
+    #[arg(long)]
+    pub offline: bool,
+
+    /// Runs the queries of the given schedules file on their cron schedules as well,
+    /// and sends their results to their sinks
+    #[arg(long, value_name = "FILE")]
+    pub schedule: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub struct SnapshotArgs {
+    #[arg(long, value_name = "CONTEXT", required = true, value_delimiter = ',')]
+    pub contexts: Vec<String>,
+
+    #[arg(
+        long,
+        value_name = "NAMESPACE",
+        default_value = "default",
+        value_delimiter = ','
+    )]
+    pub namespaces: Vec<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct ImagesArgs {
+    /// [default: --default-contexts]
+    #[arg(long, value_name = "CONTEXT", value_delimiter = ',')]
+    pub contexts: Vec<String>,
+
+    #[arg(
+        long,
+        value_name = "NAMESPACE",
+        default_value = "default",
+        value_delimiter = ','
+    )]
+    pub namespaces: Vec<String>,
+
+    /// List the images of the snapshot instead of the clusters
+    #[arg(long)]
+    pub offline: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct LogsArgs {
+    /// The WHERE statement matching the pods, i.e. "pod.metadata.labels.app = 'api'"
+    #[arg(long = "where", value_name = "CONDITION")]
+    pub condition: String,
+
+    /// [default: --default-contexts]
+    #[arg(long, value_name = "CONTEXT", value_delimiter = ',')]
+    pub contexts: Vec<String>,
+
+    #[arg(
+        long,
+        value_name = "NAMESPACE",
+        default_value = "default",
+        value_delimiter = ','
+    )]
+    pub namespaces: Vec<String>,
+
+    /// Prints only the lines matching the given regex
+    #[arg(long, value_name = "REGEX")]
+    pub grep: Option<String>,
+
+    /// Reads only the logs of the given container, of all containers otherwise
+    #[arg(short, long)]
+    pub container: Option<String>,
+
+    /// The number of the recent lines to read of each container
+    #[arg(long, default_value_t = 100)]
+    pub tail: i64,
+
+    /// Reads only the lines of the last given seconds
+    #[arg(long, value_name = "SECONDS")]
+    pub since: Option<i64>,
+
+    /// Streams the new lines until interrupted
+    #[arg(long)]
+    pub follow: bool,
+
+    /// The number of the containers read at the same time
+    #[arg(long, default_value_t = 8)]
+    pub concurrency: usize,
+}
+
+#[derive(Debug, Args)]
+pub struct PortForwardArgs {
+    /// The WHERE statement matching a single resource, i.e. "service.metadata.name = 'api'"
+    #[arg(long = "where", value_name = "CONDITION")]
+    pub condition: String,
+
+    /// The local and the remote port, the same one if only one is given
+    #[arg(value_name = "[LOCAL:]REMOTE", value_parser = parse_ports)]
+    pub ports: (u16, u16),
+
+    /// [default: --default-contexts]
+    #[arg(long, value_name = "CONTEXT", value_delimiter = ',')]
+    pub contexts: Vec<String>,
+
+    #[arg(
+        long,
+        value_name = "NAMESPACE",
+        default_value = "default",
+        value_delimiter = ','
+    )]
+    pub namespaces: Vec<String>,
+
+    /// The local address to listen on
+    #[arg(long, default_value = "127.0.0.1")]
+    pub address: String,
+}
+
+#[derive(Debug, Args)]
+pub struct DaemonArgs {
+    /// [default: --default-contexts]
+    #[arg(long, value_name = "CONTEXT", value_delimiter = ',')]
+    pub contexts: Vec<String>,
+
+    /// The kinds to watch, the others have no resources in the queries
+    #[arg(
+        long,
+        value_name = "KIND",
+        default_value = "pod,deployment,service,node",
+        value_delimiter = ',',
+        value_parser = parse_kind
+    )]
+    pub kinds: Vec<ResourceType>,
+
+    /// Runs the queries of the given schedules file on the watched resources on their cron
+    /// schedules as well, and sends their results to their sinks
+    #[arg(long, value_name = "FILE")]
+    pub schedule: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub struct ApplyArgs {
+    /// The YAML or JSON manifests to apply, a file or a directory
+    #[arg(short, long, value_name = "FILE")]
+    pub file: PathBuf,
+
+    #[arg(long, value_name = "CONTEXT", required = true, value_delimiter = ',')]
+    pub contexts: Vec<String>,
+
+    /// [default: the namespace of each manifest]
+    #[arg(long, value_name = "NAMESPACE", value_delimiter = ',')]
+    pub namespaces: Vec<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct PingArgs {
+    /// [default: all the contexts of the kubeconfig]
+    #[arg(long, value_name = "CONTEXT", value_delimiter = ',')]
+    pub contexts: Vec<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct AuditArgs {
+    /// The report to run
+    #[arg(value_name = "REPORT", value_parser = Report::from_str)]
+    pub report: Report,
+
+    /// [default: --default-contexts]
+    #[arg(long, value_name = "CONTEXT", value_delimiter = ',')]
+    pub contexts: Vec<String>,
+
+    #[arg(
+        long,
+        value_name = "NAMESPACE",
+        default_value = "default",
+        value_delimiter = ','
+    )]
+    pub namespaces: Vec<String>,
+
+    /// Run the report on the snapshot instead of the clusters
+    #[arg(long)]
+    pub offline: bool,
+
+    /// Writes the findings into the given file as a SARIF log as well, i.e. for code scanning
+    #[arg(long, value_name = "FILE")]
+    pub sarif: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub struct CheckArgs {
+    /// The YAML file of the policies, each one is a name, a query and its expected rows
+    #[arg(value_name = "FILE")]
+    pub file: PathBuf,
+
+    /// Run the policies on the snapshot instead of the clusters
+    #[arg(long)]
+    pub offline: bool,
+
+    /// Writes the outcomes into the given file as JUnit XML as well, a test case per policy
+    /// and context
+    #[arg(long, value_name = "FILE")]
+    pub junit: Option<PathBuf>,
+
+    /// Writes the failed policies into the given file as a SARIF log as well, a result per
+    /// returned resource, i.e. for code scanning
+    #[arg(long, value_name = "FILE")]
+    pub sarif: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub struct HistoryArgs {
+    /// Lists only the queries or contexts containing the given text
+    #[arg(value_name = "TEXT")]
+    pub search: Option<String>,
+
+    #[arg(long, default_value_t = 20)]
+    pub limit: usize,
+
+    /// Re-runs the query of the given ID
+    #[arg(long, value_name = "ID")]
+    pub run: Option<usize>,
+
+    #[command(flatten)]
+    pub exec: ExecArgs,
+}
+
+// Parsed once per invocation, boxing the run flags is not worth it
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Subcommand)]
+pub enum LibraryCommand {
+    /// Lists the queries with their tags and descriptions
+    List(LibraryListArgs),
+    /// Runs the queries of the given names or tags one after the other, all of them if none given,
+    /// and prints the number of rows of each one
+    Run(LibraryRunArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct LibraryListArgs {
+    /// Lists only the queries with any of the given tags
+    #[arg(long, value_name = "TAG", value_delimiter = ',')]
+    pub tag: Vec<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct LibraryRunArgs {
+    /// The names of the queries, their paths in the library without '.sql', i.e. 'security/root-pods'
+    #[arg(value_name = "NAME")]
+    pub names: Vec<String>,
+
+    /// Runs the queries with any of the given tags as well
+    #[arg(long, value_name = "TAG", value_delimiter = ',')]
+    pub tag: Vec<String>,
+
+    #[command(flatten)]
+    pub exec: ExecArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct TrendArgs {
+    /// The query as run with --record, its whitespace and trailing ';' are ignored
+    #[arg(value_name = "QUERY")]
+    pub query: String,
+
+    /// Shows only the given number of the last runs
+    #[arg(long, default_value_t = 20)]
+    pub limit: usize,
+}
+
+#[derive(Debug, Args)]
+pub struct CompletionsArgs {
+    #[arg(value_enum)]
+    pub shell: Shell,
+}
+
+fn parse_param(v: &str) -> std::result::Result<(String, String), String> {
+    v.split_once('=')
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .ok_or_else(|| "must be in KEY=VALUE format".to_string())
+}
+
+fn parse_bytes(v: &str) -> std::result::Result<usize, String> {
+    match quantity::parse(v) {
+        Some(bytes) if bytes >= 0.0 => Ok(bytes as usize),
+        _ => Err("must be a size, i.e. '500Mi'".to_string()),
+    }
+}
+
+fn parse_command(v: &str) -> std::result::Result<String, String> {
+    match shell_words::split(v) {
+        Ok(words) if words.is_empty() => Err("must not be empty".to_string()),
+        Ok(_) => Ok(v.to_string()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+fn parse_ports(v: &str) -> std::result::Result<(u16, u16), String> {
+    let (local, remote) = v.split_once(':').unwrap_or((v, v));
+    match (local.parse(), remote.parse()) {
+        (Ok(local), Ok(remote)) => Ok((local, remote)),
+        _ => Err("must be in [LOCAL:]REMOTE format".to_string()),
+    }
+}
+
+fn parse_kind(v: &str) -> std::result::Result<ResourceType, String> {
+    match ResourceType::from_str(&v.to_lowercase()) {
+        // The metrics are not watchable
+        Ok(ResourceType::PodMetrics) => Err("podmetrics can not be watched".to_string()),
+        // The releases are decoded from the Secrets of Helm, which would be cached undecoded
+        Ok(ResourceType::HelmRelease) => Err("helmrelease can not be watched".to_string()),
+        Ok(kind) => Ok(kind),
+        Err(_) => Err(format!("unknown kind: {}", v)),
+    }
+}
+
+fn parse_go_template(v: &str) -> std::result::Result<Template, String> {
+    Template::parse(v, Syntax::GoTemplate)
+}
+
+fn parse_output(v: &str) -> std::result::Result<String, String> {
+    Output::from_str(v).map(|_| v.to_string())
+}
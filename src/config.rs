@@ -0,0 +1,183 @@
+// Copyright (c) 2021 Dentrax
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software is furnished to do so,
+// subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+use crate::printer::{DedupeBy, Layout, Output, SortBy};
+use crate::telemetry::LogFormat;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::str::FromStr;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum ConfigError {
+    #[error("Unable to read the config '{0}': {1}")]
+    Read(String, std::io::Error),
+
+    #[error("Invalid config '{0}': {1}")]
+    Invalid(String, String),
+}
+
+/// The defaults of the global flags, read from '~/.kubesql.toml'.
+/// The flags and the environment variables take precedence over them.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Config {
+    pub output: Option<String>,
+    pub layout: Option<String>,
+    pub max_col_width: Option<usize>,
+    pub wide: Option<bool>,
+    pub kubeconfig: Option<PathBuf>,
+    pub timeout: Option<u64>,
+    pub snapshot: Option<PathBuf>,
+    pub socket: Option<PathBuf>,
+    pub library: Option<PathBuf>,
+    /// The contexts to query if FROM statement is not given
+    pub contexts: Vec<String>,
+    /// One of 'auto', 'always' or 'never'
+    pub color: Option<String>,
+    /// One of 'text' or 'json'
+    pub log_format: Option<String>,
+    /// One of 'name', 'age', 'namespace' or 'context'
+    pub sort_by: Option<String>,
+    /// Only 'name'
+    pub dedupe_by: Option<String>,
+    /// The LIKE patterns of the contexts INSERT and --exec are guarded on, i.e. 'prod-%'
+    pub protected_contexts: Vec<String>,
+    /// One of 'confirm' or 'deny'
+    pub guard: Option<String>,
+    /// The field printed next to the names of each kind if none of its fields is selected,
+    /// i.e. 'status.phase' of 'pod'
+    pub display: BTreeMap<String, String>,
+}
+
+impl Config {
+    /// Whether the values are one of the possible values of their flags
+    fn validate(&self) -> Result<(), String> {
+        if let Some(output) = &self.output {
+            Output::from_str(output)?;
+        }
+        if let Some(layout) = &self.layout {
+            Layout::from_str(layout)?;
+        }
+        if let Some(color) = &self.color {
+            Color::from_str(color)?;
+        }
+        if let Some(sort_by) = &self.sort_by {
+            SortBy::from_str(sort_by)?;
+        }
+        if let Some(dedupe_by) = &self.dedupe_by {
+            DedupeBy::from_str(dedupe_by)?;
+        }
+        if let Some(log_format) = &self.log_format {
+            LogFormat::from_str(log_format)?;
+        }
+        if let Some(guard) = &self.guard {
+            Guard::from_str(guard)?;
+        }
+        Ok(())
+    }
+}
+
+/// Whether the output is colored
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Color {
+    /// Only if the output is a terminal and $NO_COLOR is not set
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl Color {
+    pub fn enabled(&self) -> bool {
+        use std::io::IsTerminal;
+
+        match self {
+            Color::Auto => {
+                std::io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none()
+            }
+            Color::Always => true,
+            Color::Never => false,
+        }
+    }
+}
+
+impl FromStr for Color {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Color, Self::Err> {
+        match input {
+            "auto" => Ok(Color::Auto),
+            "always" => Ok(Color::Always),
+            "never" => Ok(Color::Never),
+            _ => Err(format!("Unexpected color: {}", input)),
+        }
+    }
+}
+
+/// What INSERT and --exec require on the protected contexts
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Guard {
+    /// They run only with --i-know-what-im-doing
+    #[default]
+    Confirm,
+    /// They never run
+    Deny,
+}
+
+impl FromStr for Guard {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Guard, Self::Err> {
+        match input {
+            "confirm" => Ok(Guard::Confirm),
+            "deny" => Ok(Guard::Deny),
+            _ => Err(format!("Unexpected guard: {}", input)),
+        }
+    }
+}
+
+/// The config file, '$KUBESQL_CONFIG' or '~/.kubesql.toml'
+pub fn path() -> PathBuf {
+    match std::env::var_os("KUBESQL_CONFIG") {
+        Some(path) => PathBuf::from(path),
+        None => PathBuf::from(std::env::var_os("HOME").unwrap_or_default()).join(".kubesql.toml"),
+    }
+}
+
+/// Reads the config file, all the defaults are unset if it does not exist
+pub fn load() -> Result<Config, ConfigError> {
+    let path = path();
+    let file = path.display().to_string();
+
+    let content = match std::fs::read_to_string(&path) {
+        Ok(c) => c,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Config::default()),
+        Err(e) => return Err(ConfigError::Read(file, e)),
+    };
+
+    let config: Config =
+        toml::from_str(&content).map_err(|e| ConfigError::Invalid(file.clone(), e.to_string()))?;
+    config
+        .validate()
+        .map_err(|e| ConfigError::Invalid(file, e))?;
+
+    Ok(config)
+}
@@ -0,0 +1,435 @@
+// Copyright (c) 2021 Dentrax
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software is furnished to do so,
+// subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+use crate::api_builder::ApiBuilder;
+use crate::error::Result;
+use crate::parser::ResourceType;
+use crate::pool;
+use crate::source::Sources;
+use k8s_openapi::apimachinery::pkg::version::Info;
+use kube::config::{AuthInfo, Kubeconfig, NamedAuthInfo, NamedCluster};
+use kube::ResourceExt;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// The time to wait for the API server of each context when pinging
+const PING_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The extension of a kubeconfig context holding its labels, i.e. '{"labels": {"env": "prod"}}'
+const LABELS_EXTENSION: &str = "kubesql";
+
+/// The group versions of the supported custom resources, queried for the short names of their CRDs
+pub const CUSTOM_RESOURCES: [(&str, ResourceType); 2] = [
+    ("argoproj.io/v1alpha1", ResourceType::Application),
+    (
+        "kustomize.toolkit.fluxcd.io/v1",
+        ResourceType::Kustomization,
+    ),
+];
+
+/// A context of the kubeconfig with the server of its cluster
+#[derive(Debug, Serialize)]
+pub struct Context {
+    pub name: String,
+    pub cluster: Option<String>,
+    pub server: Option<String>,
+    pub user: Option<String>,
+    /// The namespace of the context, if given
+    pub namespace: Option<String>,
+    /// Whether it is the current context of the kubeconfig
+    pub current: bool,
+    /// The labels of the context in its 'kubesql' extension, to select it in FROM statement
+    pub labels: BTreeMap<String, String>,
+}
+
+/// A namespace of a context, with its phase, i.e. 'Active' or 'Terminating'
+#[derive(Debug, Serialize)]
+pub struct Namespace {
+    pub context: String,
+    pub name: String,
+    pub status: Option<String>,
+}
+
+/// The result of pinging the API server of a context
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Ok,
+    /// The credentials of the context are rejected
+    Unauthorized,
+    /// The API server did not respond in time or the connection failed
+    Unreachable,
+    /// The kubeconfig of the context can not be loaded, i.e. a missing certificate
+    Invalid,
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Status::Ok => write!(f, "OK"),
+            Status::Unauthorized => write!(f, "Unauthorized"),
+            Status::Unreachable => write!(f, "Unreachable"),
+            Status::Invalid => write!(f, "Invalid"),
+        }
+    }
+}
+
+/// The health of the API server of a context
+#[derive(Debug, Serialize)]
+pub struct Health {
+    pub context: String,
+    pub server: Option<String>,
+    pub status: Status,
+    /// i.e. 'v1.27.3'
+    pub version: Option<String>,
+    /// The round trip of the version request in milliseconds
+    pub latency_ms: Option<u128>,
+    pub error: Option<String>,
+}
+
+/// Returns the labels of the given context, the values which are not strings are ignored
+fn labels(context: &kube::config::Context) -> BTreeMap<String, String> {
+    context
+        .extensions
+        .iter()
+        .flatten()
+        .filter(|e| e.name == LABELS_EXTENSION)
+        .filter_map(|e| e.extension["labels"].as_object())
+        .flatten()
+        .filter_map(|(k, v)| Some((k.clone(), v.as_str()?.to_string())))
+        .collect()
+}
+
+/// Returns the contexts of the given kubeconfig in their order
+pub fn list(kubeconfig: &Kubeconfig) -> Vec<Context> {
+    kubeconfig
+        .contexts
+        .iter()
+        .map(|c| {
+            let context = c.context.as_ref();
+            let cluster = context.map(|c| c.cluster.clone());
+            let server = kubeconfig
+                .clusters
+                .iter()
+                .find(|n| Some(&n.name) == cluster.as_ref())
+                .and_then(|n| n.cluster.as_ref())
+                .and_then(|c| c.server.clone());
+
+            Context {
+                name: c.name.clone(),
+                cluster,
+                server,
+                user: context.map(|c| c.user.clone()),
+                namespace: context.and_then(|c| c.namespace.clone()),
+                current: kubeconfig.current_context.as_ref() == Some(&c.name),
+                labels: context.map(labels).unwrap_or_default(),
+            }
+        })
+        .collect()
+}
+
+/// Returns each context of the given kubeconfig as a row of the 'kubeconfig' table, along with its
+/// cluster and user. The credentials themselves are left out, only how the user authenticates
+pub fn rows(kubeconfig: &Kubeconfig) -> Vec<serde_json::Value> {
+    kubeconfig
+        .contexts
+        .iter()
+        .map(|c| {
+            let context = c.context.as_ref();
+            let cluster = context.and_then(|context| {
+                kubeconfig
+                    .clusters
+                    .iter()
+                    .find(|n| n.name == context.cluster)
+            });
+            let user = context.and_then(|context| {
+                kubeconfig
+                    .auth_infos
+                    .iter()
+                    .find(|n| n.name == context.user)
+            });
+
+            serde_json::json!({
+                "name": c.name,
+                "namespace": context.and_then(|c| c.namespace.clone()),
+                "current": kubeconfig.current_context.as_ref() == Some(&c.name),
+                "labels": context.map(labels).unwrap_or_default(),
+                "cluster": cluster_row(context.map(|c| c.cluster.as_str()), cluster),
+                "user": user_row(context.map(|c| c.user.as_str()), user),
+            })
+        })
+        .collect()
+}
+
+/// Returns the cluster of a context as the 'cluster' column of the 'kubeconfig' table
+fn cluster_row(name: Option<&str>, cluster: Option<&NamedCluster>) -> serde_json::Value {
+    let cluster = cluster.and_then(|c| c.cluster.as_ref());
+    serde_json::json!({
+        "name": name,
+        "server": cluster.and_then(|c| c.server.clone()),
+        "insecure_skip_tls_verify": cluster
+            .and_then(|c| c.insecure_skip_tls_verify)
+            .unwrap_or(false),
+        "certificate_authority": cluster.and_then(|c| c.certificate_authority.clone()),
+        "proxy_url": cluster.and_then(|c| c.proxy_url.clone()),
+        "tls_server_name": cluster.and_then(|c| c.tls_server_name.clone()),
+    })
+}
+
+/// Returns the user of a context as the 'user' column of the 'kubeconfig' table
+fn user_row(name: Option<&str>, user: Option<&NamedAuthInfo>) -> serde_json::Value {
+    let user = user.and_then(|u| u.auth_info.as_ref());
+    let exec = user.and_then(|u| u.exec.as_ref());
+    serde_json::json!({
+        "name": name,
+        "auth": user.and_then(auth_method),
+        "auth_provider": user.and_then(|u| u.auth_provider.as_ref()).map(|p| p.name.clone()),
+        "exec": exec.map(|e| serde_json::json!({
+            "command": e.command,
+            "args": e.args,
+            "api_version": e.api_version,
+        })),
+        "username": user.and_then(|u| u.username.clone()),
+        "token_file": user.and_then(|u| u.token_file.clone()),
+        "client_certificate": user.and_then(|u| u.client_certificate.clone()),
+        "impersonate": user.and_then(|u| u.impersonate.clone()),
+        // i.e. a token or a client key written into the kubeconfig instead of a file
+        "embedded_credentials": user.is_some_and(|u| {
+            u.token.is_some() || u.password.is_some() || u.client_key_data.is_some()
+        }),
+    })
+}
+
+/// Returns how the given user authenticates, i.e. 'exec' or 'client-certificate'
+fn auth_method(user: &AuthInfo) -> Option<&'static str> {
+    if user.auth_provider.is_some() {
+        Some("auth-provider")
+    } else if user.exec.is_some() {
+        Some("exec")
+    } else if user.token.is_some() || user.token_file.is_some() {
+        Some("token")
+    } else if user.client_certificate.is_some() || user.client_certificate_data.is_some() {
+        Some("client-certificate")
+    } else if user.username.is_some() {
+        Some("basic")
+    } else {
+        None
+    }
+}
+
+/// Lists the namespaces of each given context, sorted by name per context
+pub async fn namespaces(contexts: &[String], sources: &Sources<'_>) -> Result<Vec<Namespace>> {
+    let mut namespaces = Vec::new();
+
+    for ctx in contexts {
+        let api = ApiBuilder::builder()
+            .context(ctx.clone())
+            .source(sources.get(ctx))
+            .build()
+            .await?;
+
+        let kind = ResourceType::Namespace;
+        let mut items = api
+            .list(&kind, ApiBuilder::get_ns, &Default::default())
+            .await?
+            .items;
+        items.sort_by_key(|ns| ns.name_any());
+
+        namespaces.extend(items.into_iter().map(|ns| Namespace {
+            context: ctx.clone(),
+            name: ns.name_any(),
+            status: ns.status.and_then(|s| s.phase),
+        }));
+    }
+
+    Ok(namespaces)
+}
+
+/// Returns the supported custom resources by the short names and plurals their CRDs declare on
+/// the clusters of the given contexts, i.e. 'app' of Argo CD applications. The clusters without
+/// them or failing the discovery are skipped
+pub async fn short_names(contexts: &[String]) -> BTreeMap<String, ResourceType> {
+    let lookups = contexts.iter().flat_map(|ctx| {
+        CUSTOM_RESOURCES
+            .iter()
+            .map(move |(group_version, kind)| async move {
+                let client = pool::shared().client(ctx).await.ok()?;
+                let list = client.list_api_group_resources(group_version).await.ok()?;
+                let names = list
+                    .resources
+                    .into_iter()
+                    // i.e. 'applications/status', the subresources have no short names
+                    .filter(|r| {
+                        !r.name.contains('/') && r.kind.eq_ignore_ascii_case(&kind.to_string())
+                    })
+                    .flat_map(|r| {
+                        let mut names = r.short_names.unwrap_or_default();
+                        names.push(r.name);
+                        names.push(r.singular_name);
+                        names
+                    })
+                    .filter(|name| !name.is_empty())
+                    .map(|name| (name.to_lowercase(), kind.clone()))
+                    .collect::<Vec<(String, ResourceType)>>();
+                Some(names)
+            })
+    });
+
+    let names: BTreeMap<String, ResourceType> = futures::future::join_all(lookups)
+        .await
+        .into_iter()
+        .flatten()
+        .flatten()
+        .collect();
+    tracing::debug!(names = ?names.keys(), "discovered");
+    names
+}
+
+/// Pings the API servers of the given contexts concurrently, in their order
+pub async fn ping(kubeconfig: &Kubeconfig, contexts: &[String]) -> Vec<Health> {
+    let servers = list(kubeconfig);
+    let checks = contexts.iter().map(|ctx| {
+        let server = servers
+            .iter()
+            .find(|c| &c.name == ctx)
+            .and_then(|c| c.server.clone());
+        check(ctx, server)
+    });
+
+    futures::future::join_all(checks).await
+}
+
+/// Requests the version of the API server, which is public, then the core API versions to
+/// check the credentials. Forbidden is healthy, the credentials are valid.
+async fn check(context: &str, server: Option<String>) -> Health {
+    let mut health = Health {
+        context: context.to_string(),
+        server,
+        status: Status::Invalid,
+        version: None,
+        latency_ms: None,
+        error: None,
+    };
+
+    let client = match pool::shared().client(context).await {
+        Ok(client) => client,
+        Err(e) => {
+            health.error = Some(e.to_string());
+            return health;
+        }
+    };
+
+    let started = Instant::now();
+    health.status = Status::Unreachable;
+    match tokio::time::timeout(PING_TIMEOUT, client.apiserver_version()).await {
+        Ok(Ok(info)) => {
+            health.version = Some(info.git_version);
+            health.latency_ms = Some(started.elapsed().as_millis());
+        }
+        Ok(Err(e)) => {
+            health.error = Some(e.to_string());
+            return health;
+        }
+        Err(_) => {
+            health.error = Some(format!("No response in {}s", PING_TIMEOUT.as_secs()));
+            return health;
+        }
+    }
+
+    match tokio::time::timeout(PING_TIMEOUT, client.list_core_api_versions()).await {
+        Ok(Ok(_)) => health.status = Status::Ok,
+        Ok(Err(kube::Error::Api(e))) if e.code == 403 => health.status = Status::Ok,
+        Ok(Err(kube::Error::Api(e))) if e.code == 401 => {
+            health.status = Status::Unauthorized;
+            health.error = Some(e.message);
+        }
+        Ok(Err(e)) => health.error = Some(e.to_string()),
+        Err(_) => health.error = Some(format!("No response in {}s", PING_TIMEOUT.as_secs())),
+    }
+
+    health
+}
+
+/// Returns the version of the API server of the given context
+pub async fn version(context: &str) -> Result<Info> {
+    Ok(pool::shared()
+        .client(context)
+        .await?
+        .apiserver_version()
+        .await?)
+}
+
+/// Returns the version of the API server as the 'cluster' pseudo-resource, its fields with
+/// 'version' as the git version without the 'v' prefix and the build suffix,
+/// i.e. '1.27.3' of 'v1.27.3-eks-2d98532'
+pub fn cluster(info: &Info) -> serde_json::Value {
+    let version = info
+        .git_version
+        .trim_start_matches('v')
+        .split(['-', '+'])
+        .next()
+        .unwrap_or_default();
+
+    let mut cluster = serde_json::to_value(info).unwrap_or_default();
+    cluster["version"] = serde_json::Value::from(version);
+    cluster
+}
+
+/// Returns the servers of the clusters of the given contexts by their names. It is empty if the
+/// kubeconfig can not be read, i.e. querying a snapshot on another machine
+pub fn servers(contexts: &[String]) -> BTreeMap<String, String> {
+    let kubeconfig = match Kubeconfig::read() {
+        Ok(kubeconfig) => kubeconfig,
+        Err(e) => {
+            tracing::debug!(error = %e, "unable to read the servers of the contexts");
+            return BTreeMap::new();
+        }
+    };
+
+    list(&kubeconfig)
+        .into_iter()
+        .filter(|c| contexts.contains(&c.name))
+        .filter_map(|c| Some((c.name, c.server?)))
+        .collect()
+}
+
+/// Returns the metadata columns of the rows listed from the given context and namespace, the
+/// cluster-scoped kinds have no namespace, i.e. '__context'
+pub fn source(context: &str, namespace: &str, server: Option<&String>) -> serde_json::Value {
+    serde_json::json!({
+        "__context": context,
+        "__namespace": Some(namespace).filter(|ns| !ns.is_empty()),
+        "__cluster_server": server,
+    })
+}
+
+/// Returns the major and minor version of the API server, the managed ones may suffix the minor
+/// version with '+', i.e. (1, 27) of '27+'
+pub fn minor_version(info: &Info) -> Option<(u32, u32)> {
+    let digits = |s: &str| {
+        s.chars()
+            .take_while(char::is_ascii_digit)
+            .collect::<String>()
+            .parse::<u32>()
+            .ok()
+    };
+
+    Some((digits(&info.major)?, digits(&info.minor)?))
+}
@@ -0,0 +1,313 @@
+// Copyright (c) 2021 Dentrax
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software is furnished to do so,
+// subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+use crate::api_builder::{self, ApiBuilder};
+use crate::cli::{DaemonArgs, GlobalArgs};
+use crate::error::{KubesqlError, Result};
+use crate::parser::ResourceType;
+use crate::printer::ResultTable;
+use crate::schedule::{self, Scheduler};
+use crate::snapshot::Snapshot;
+use crate::{parser, server, validator};
+use futures::{StreamExt, TryStreamExt};
+use kube::api::{Api, ApiResource, DynamicObject};
+use kube::core::GroupVersionKind;
+use kube::runtime::reflector::{self, Store};
+use kube::runtime::{watcher, WatchStreamExt};
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{UnixListener, UnixStream};
+
+/// The maximum size of a query sent to the daemon
+const MAX_QUERY_SIZE: u64 = 1024 * 1024;
+
+#[derive(Error, Debug)]
+pub enum DaemonError {
+    #[error("Unable to connect to the daemon on '{0}': {1}, is 'kubesql daemon' running?")]
+    Connect(String, std::io::Error),
+
+    #[error("Unable to serve on '{0}': {1}")]
+    Serve(String, std::io::Error),
+
+    #[error("Invalid response of the daemon: {0}")]
+    Response(String),
+
+    #[error("{0}")]
+    Query(String),
+
+    #[error("There is no API listing {0} on '{1}'")]
+    NoApi(ResourceType, String),
+
+    #[error("Unable to watch {1} of '{0}': {2}")]
+    Watch(String, ResourceType, Box<watcher::Error>),
+}
+
+/// The socket used if not given, '~/.kubesql/daemon.sock'
+pub fn default_path() -> PathBuf {
+    let home = std::env::var_os("HOME").unwrap_or_default();
+    PathBuf::from(home).join(".kubesql").join("daemon.sock")
+}
+
+/// Watches the given kinds of the contexts and serves the queries on the cached resources over
+/// the socket until interrupted, one query at a time. A query is the whole content sent before
+/// shutting down the writing half, the response is '{"tables": [...]}' or '{"error": "..."}'.
+/// The scheduled queries run on the cached resources between the queries
+pub async fn run(global: &GlobalArgs, args: &DaemonArgs) -> Result<()> {
+    let contexts = global.contexts(&args.contexts);
+    if contexts.is_empty() {
+        return Err(KubesqlError::ContextRequired);
+    }
+    validator::validate_contexts(parser::parse_kubeconfig()?, contexts)?;
+    let mut scheduler = match &args.schedule {
+        Some(path) => Scheduler::new(schedule::load(path)?)?,
+        None => Scheduler::default(),
+    };
+
+    // The resources are listed before serving, so the queries never see a partial cache
+    let mut informers = Vec::new();
+    for ctx in contexts {
+        let api = ApiBuilder::builder().context(ctx.clone()).build().await?;
+        for kind in &args.kinds {
+            match Informer::start(&api, ctx, kind).await {
+                Ok(informer) => informers.push(informer),
+                // The CRD is not installed on this cluster, there is nothing to watch
+                Err(KubesqlError::Daemon(DaemonError::Watch(_, _, e)))
+                    if kind.is_optional() && is_not_found(&e) =>
+                {
+                    tracing::warn!("{} is not installed on '{}'", kind, ctx);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+    let cache = Cache {
+        contexts: contexts.to_vec(),
+        informers,
+    };
+
+    let socket = global.socket_path();
+    let file = socket.display().to_string();
+    if let Some(parent) = socket.parent().filter(|p| !p.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent).map_err(|e| DaemonError::Serve(file.clone(), e))?;
+    }
+    // The socket of a previous daemon is left behind if it was killed
+    let _ = std::fs::remove_file(&socket);
+    let listener = UnixListener::bind(&socket).map_err(|e| DaemonError::Serve(file.clone(), e))?;
+    eprintln!(
+        "Watching {} resources of {} context(s), listening on {}",
+        cache.snapshot().len(),
+        contexts.len(),
+        file
+    );
+
+    loop {
+        let stream = tokio::select! {
+            accepted = listener.accept() => accepted.map_err(|e| DaemonError::Serve(file.clone(), e))?.0,
+            due = scheduler.due() => {
+                for schedule in due {
+                    let result = server::query(&schedule.query, Some(&cache.snapshot())).await;
+                    scheduler.dispatch(&schedule, result).await;
+                }
+                continue;
+            }
+            _ = tokio::signal::ctrl_c() => break,
+        };
+
+        // A broken connection does not stop the daemon
+        if let Err(e) = handle(stream, &cache).await {
+            tracing::warn!("Unable to handle the query: {}", e);
+        }
+    }
+
+    let _ = std::fs::remove_file(&socket);
+    Ok(())
+}
+
+async fn handle(mut stream: UnixStream, cache: &Cache) -> std::io::Result<()> {
+    let mut sql = String::new();
+    (&mut stream)
+        .take(MAX_QUERY_SIZE)
+        .read_to_string(&mut sql)
+        .await?;
+
+    let response = match server::query(&sql, Some(&cache.snapshot())).await {
+        Ok(tables) => serde_json::json!({ "tables": tables }),
+        Err(e) => serde_json::json!({ "error": e.to_string() }),
+    };
+
+    stream.write_all(response.to_string().as_bytes()).await?;
+    stream.shutdown().await
+}
+
+/// Runs the given query on the resources cached by the daemon listening on the given socket
+pub async fn query(socket: &Path, sql: &str) -> Result<Vec<ResultTable>> {
+    let file = socket.display().to_string();
+    let io = |e| DaemonError::Connect(file.clone(), e);
+
+    let mut stream = UnixStream::connect(socket).await.map_err(io)?;
+    stream.write_all(sql.as_bytes()).await.map_err(io)?;
+    stream.shutdown().await.map_err(io)?;
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.map_err(io)?;
+
+    let mut response: serde_json::Value =
+        serde_json::from_str(&response).map_err(|e| DaemonError::Response(e.to_string()))?;
+    if let Some(error) = response["error"].as_str() {
+        return Err(DaemonError::Query(error.to_string()).into());
+    }
+    Ok(serde_json::from_value(response["tables"].take())
+        .map_err(|e| DaemonError::Response(e.to_string()))?)
+}
+
+/// The resources of all the kinds watched in all the contexts
+struct Cache {
+    contexts: Vec<String>,
+    informers: Vec<Informer>,
+}
+
+impl Cache {
+    /// Copies the cached resources into a snapshot, so the queries run on it without blocking
+    /// the watches and the watches do not change the resources in the middle of a query
+    fn snapshot(&self) -> Snapshot {
+        let mut snapshot = Snapshot::default();
+        self.contexts.iter().for_each(|ctx| {
+            snapshot.contexts.entry(ctx.clone()).or_default();
+        });
+        for informer in &self.informers {
+            let items = informer
+                .store
+                .state()
+                .iter()
+                .filter_map(|object| serde_json::to_value(&**object).ok())
+                .collect();
+            snapshot.replace(&informer.context, &informer.kind, items);
+        }
+        snapshot
+    }
+}
+
+/// Keeps the resources of a kind in a context up to date in the store of a reflector, the
+/// watcher lists them again if the watch fails, i.e. expired
+struct Informer {
+    context: String,
+    kind: ResourceType,
+    store: Store<DynamicObject>,
+}
+
+impl Informer {
+    /// Lists the resources of the kind from all the namespaces, then watches their changes in
+    /// the background until the daemon stops. Fails if they can not be listed
+    async fn start(api: &ApiBuilder<'_>, ctx: &str, kind: &ResourceType) -> Result<Informer> {
+        let resource = api_resource(kind, &api.resource_url(kind)?);
+        let writer = reflector::store::Writer::new(resource.clone());
+        let store = writer.as_reader();
+        let api = Api::<DynamicObject>::all_with(api.get_client().clone(), &resource);
+        // The values of the Secrets are redacted before they are cached, as they are when listed
+        let redacts = *kind == ResourceType::Secret;
+        let mut events = watcher(api, watcher::Config::default())
+            .default_backoff()
+            .modify(move |object| {
+                if redacts {
+                    api_builder::redact_object(object)
+                }
+            })
+            .reflect(writer)
+            .boxed();
+
+        let error = |e| DaemonError::Watch(ctx.to_string(), kind.clone(), Box::new(e));
+        while let Some(event) = events.try_next().await.map_err(error)? {
+            if let watcher::Event::InitDone = event {
+                break;
+            }
+        }
+
+        let (context, watched) = (ctx.to_string(), kind.clone());
+        tokio::spawn(async move {
+            while let Some(event) = events.next().await {
+                if let Err(e) = event {
+                    tracing::warn!("Unable to watch {} of '{}': {}", watched, context, e);
+                }
+            }
+        });
+
+        Ok(Informer {
+            context: ctx.to_string(),
+            kind: kind.clone(),
+            store,
+        })
+    }
+}
+
+/// Whether the resources could not be listed as the kind is not served, i.e. a missing CRD
+fn is_not_found(error: &watcher::Error) -> bool {
+    matches!(error, watcher::Error::InitialListFailed(kube::Error::Api(e)) if e.code == 404)
+}
+
+/// The API resource listed by the given URL of all the namespaces, i.e. '/api/v1/pods' or
+/// '/apis/apps/v1/deployments'
+fn api_resource(kind: &ResourceType, url: &str) -> ApiResource {
+    let mut segments = url.trim_start_matches('/').split('/');
+    let group = match segments.next() {
+        Some("apis") => segments.next().unwrap_or_default(),
+        _ => "",
+    };
+    let version = segments.next().unwrap_or_default();
+    let plural = segments.next().unwrap_or_default();
+    ApiResource::from_gvk_with_plural(
+        &GroupVersionKind::gvk(group, version, &kind.to_string()),
+        plural,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn api_resource_of_the_core_and_the_named_groups() {
+        let pods = api_resource(&ResourceType::Pod, "/api/v1/pods");
+        assert_eq!((pods.group.as_str(), pods.version.as_str()), ("", "v1"));
+        assert_eq!(pods.plural, "pods");
+
+        let deployments = api_resource(&ResourceType::Deployment, "/apis/apps/v1/deployments");
+        assert_eq!(deployments.api_version, "apps/v1");
+        assert_eq!(deployments.plural, "deployments");
+    }
+
+    #[test]
+    fn redacts_the_values_of_the_watched_secrets() {
+        let mut secret: DynamicObject = serde_json::from_value(serde_json::json!({
+            "apiVersion": "v1",
+            "kind": "Secret",
+            "metadata": {
+                "name": "token",
+                "annotations": { "kubectl.kubernetes.io/last-applied-configuration": "{}" }
+            },
+            "data": { "token": "c2VjcmV0" },
+            "type": "Opaque"
+        }))
+        .unwrap();
+        api_builder::redact_object(&mut secret);
+
+        assert_eq!(secret.data["data"], serde_json::json!({ "token": "" }));
+        assert_eq!(secret.data["type"], "Opaque");
+        assert!(secret.metadata.annotations.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,534 @@
+// Copyright (c) 2021 Dentrax
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software is furnished to do so,
+// subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+use crate::parser::ResourceType;
+use crate::quantity;
+use k8s_openapi::chrono::{DateTime, Utc};
+use serde_json::Value;
+
+/// A computed field which does not exist on the object itself, but derived from it
+pub struct DerivedField {
+    pub kind: ResourceType,
+    pub path: &'static str,
+    pub derive: fn(&Value) -> Option<String>,
+}
+
+static DERIVED_FIELDS: &[DerivedField] = &[
+    DerivedField {
+        kind: ResourceType::Deployment,
+        path: "rollout.status",
+        derive: deployment_rollout_status,
+    },
+    DerivedField {
+        kind: ResourceType::Pod,
+        path: "ready",
+        derive: pod_ready,
+    },
+    DerivedField {
+        kind: ResourceType::Pod,
+        path: "restarts",
+        derive: pod_restarts,
+    },
+    DerivedField {
+        kind: ResourceType::Pod,
+        path: "age",
+        derive: age,
+    },
+    DerivedField {
+        kind: ResourceType::Pod,
+        path: "requests.cpu",
+        derive: |o| container_resources(o, "requests", "cpu"),
+    },
+    DerivedField {
+        kind: ResourceType::Pod,
+        path: "requests.memory",
+        derive: |o| container_resources(o, "requests", "memory"),
+    },
+    DerivedField {
+        kind: ResourceType::Pod,
+        path: "limits.cpu",
+        derive: |o| container_resources(o, "limits", "cpu"),
+    },
+    DerivedField {
+        kind: ResourceType::Pod,
+        path: "limits.memory",
+        derive: |o| container_resources(o, "limits", "memory"),
+    },
+    DerivedField {
+        kind: ResourceType::Node,
+        path: "allocatable.cpu",
+        derive: |o| string_at(o, "/status/allocatable/cpu"),
+    },
+    DerivedField {
+        kind: ResourceType::Node,
+        path: "allocatable.memory",
+        derive: |o| string_at(o, "/status/allocatable/memory"),
+    },
+    DerivedField {
+        kind: ResourceType::Node,
+        path: "capacity.cpu",
+        derive: |o| string_at(o, "/status/capacity/cpu"),
+    },
+    DerivedField {
+        kind: ResourceType::Node,
+        path: "capacity.memory",
+        derive: |o| string_at(o, "/status/capacity/memory"),
+    },
+    DerivedField {
+        kind: ResourceType::Node,
+        path: "cordoned",
+        derive: |o| {
+            Some(
+                o.pointer("/spec/unschedulable")
+                    .and_then(|v| v.as_bool())
+                    .unwrap_or(false)
+                    .to_string(),
+            )
+        },
+    },
+    DerivedField {
+        kind: ResourceType::Node,
+        path: "memory_pressure",
+        derive: |o| condition_holds(o, "MemoryPressure"),
+    },
+    DerivedField {
+        kind: ResourceType::Node,
+        path: "disk_pressure",
+        derive: |o| condition_holds(o, "DiskPressure"),
+    },
+    DerivedField {
+        kind: ResourceType::PodMetrics,
+        path: "cpu",
+        derive: podmetrics_cpu,
+    },
+    DerivedField {
+        kind: ResourceType::PodMetrics,
+        path: "memory",
+        derive: podmetrics_memory,
+    },
+    DerivedField {
+        kind: ResourceType::HorizontalPodAutoscaler,
+        path: "cpu.current",
+        derive: |o| hpa_utilization(o, "/status/currentMetrics", "current", "cpu"),
+    },
+    DerivedField {
+        kind: ResourceType::HorizontalPodAutoscaler,
+        path: "cpu.target",
+        derive: |o| hpa_utilization(o, "/spec/metrics", "target", "cpu"),
+    },
+    DerivedField {
+        kind: ResourceType::HorizontalPodAutoscaler,
+        path: "memory.current",
+        derive: |o| hpa_utilization(o, "/status/currentMetrics", "current", "memory"),
+    },
+    DerivedField {
+        kind: ResourceType::HorizontalPodAutoscaler,
+        path: "memory.target",
+        derive: |o| hpa_utilization(o, "/spec/metrics", "target", "memory"),
+    },
+    DerivedField {
+        kind: ResourceType::ResourceQuota,
+        path: "hard.cpu",
+        derive: |o| quota(o, "hard", "cpu"),
+    },
+    DerivedField {
+        kind: ResourceType::ResourceQuota,
+        path: "used.cpu",
+        derive: |o| quota(o, "used", "cpu"),
+    },
+    DerivedField {
+        kind: ResourceType::ResourceQuota,
+        path: "usage.cpu",
+        derive: |o| quota_usage(o, "cpu"),
+    },
+    DerivedField {
+        kind: ResourceType::ResourceQuota,
+        path: "hard.memory",
+        derive: |o| quota(o, "hard", "memory"),
+    },
+    DerivedField {
+        kind: ResourceType::ResourceQuota,
+        path: "used.memory",
+        derive: |o| quota(o, "used", "memory"),
+    },
+    DerivedField {
+        kind: ResourceType::ResourceQuota,
+        path: "usage.memory",
+        derive: |o| quota_usage(o, "memory"),
+    },
+    DerivedField {
+        kind: ResourceType::ResourceQuota,
+        path: "hard.pods",
+        derive: |o| quota(o, "hard", "pods"),
+    },
+    DerivedField {
+        kind: ResourceType::ResourceQuota,
+        path: "used.pods",
+        derive: |o| quota(o, "used", "pods"),
+    },
+    DerivedField {
+        kind: ResourceType::ResourceQuota,
+        path: "usage.pods",
+        derive: |o| quota_usage(o, "pods"),
+    },
+    DerivedField {
+        kind: ResourceType::LimitRange,
+        path: "max.cpu",
+        derive: |o| container_limit(o, "max", "cpu"),
+    },
+    DerivedField {
+        kind: ResourceType::LimitRange,
+        path: "max.memory",
+        derive: |o| container_limit(o, "max", "memory"),
+    },
+    DerivedField {
+        kind: ResourceType::LimitRange,
+        path: "default.cpu",
+        derive: |o| container_limit(o, "default", "cpu"),
+    },
+    DerivedField {
+        kind: ResourceType::LimitRange,
+        path: "default.memory",
+        derive: |o| container_limit(o, "default", "memory"),
+    },
+    DerivedField {
+        kind: ResourceType::Application,
+        path: "health",
+        derive: |o| string_at(o, "/status/health/status"),
+    },
+    DerivedField {
+        kind: ResourceType::Application,
+        path: "sync",
+        derive: |o| string_at(o, "/status/sync/status"),
+    },
+    DerivedField {
+        kind: ResourceType::Application,
+        path: "revision",
+        derive: |o| string_at(o, "/status/sync/revision"),
+    },
+    DerivedField {
+        kind: ResourceType::Application,
+        path: "operation",
+        derive: |o| string_at(o, "/status/operationState/phase"),
+    },
+    DerivedField {
+        kind: ResourceType::Kustomization,
+        path: "ready",
+        derive: |o| condition(o, "Ready", "status"),
+    },
+    DerivedField {
+        kind: ResourceType::Kustomization,
+        path: "reason",
+        derive: |o| condition(o, "Ready", "reason"),
+    },
+    DerivedField {
+        kind: ResourceType::Kustomization,
+        path: "message",
+        derive: |o| condition(o, "Ready", "message"),
+    },
+    DerivedField {
+        kind: ResourceType::Kustomization,
+        path: "revision",
+        derive: |o| string_at(o, "/status/lastAppliedRevision"),
+    },
+    DerivedField {
+        kind: ResourceType::PodDisruptionBudget,
+        path: "blocking",
+        derive: |o| o.get("status").map(|_| blocks_disruptions(o).to_string()),
+    },
+    DerivedField {
+        kind: ResourceType::PodDisruptionBudget,
+        path: "healthy",
+        derive: pdb_healthy,
+    },
+    DerivedField {
+        kind: ResourceType::Kustomization,
+        path: "suspended",
+        derive: |o| {
+            Some(
+                o.pointer("/spec/suspend")
+                    .and_then(|v| v.as_bool())
+                    .unwrap_or(false)
+                    .to_string(),
+            )
+        },
+    },
+];
+
+/// Returns the derived field for the given kind and path, i.e. 'rollout.status'
+pub fn find(kind: &str, path: &str) -> Option<&'static DerivedField> {
+    DERIVED_FIELDS
+        .iter()
+        .find(|f| f.kind.to_string().eq_ignore_ascii_case(kind) && f.path == path)
+}
+
+/// Returns the paths of the derived fields of the given kind
+pub fn paths(kind: &ResourceType) -> impl Iterator<Item = &'static str> + '_ {
+    DERIVED_FIELDS
+        .iter()
+        .filter(move |f| f.kind == *kind)
+        .map(|f| f.path)
+}
+
+fn string_at(object: &Value, pointer: &str) -> Option<String> {
+    object
+        .pointer(pointer)
+        .and_then(|v| v.as_str())
+        .map(|v| v.to_string())
+}
+
+fn i64_at(object: &Value, pointer: &str) -> Option<i64> {
+    object.pointer(pointer).and_then(|v| v.as_i64())
+}
+
+/// Progressing, Complete or Stalled; follows the same logic as 'kubectl rollout status'
+fn deployment_rollout_status(object: &Value) -> Option<String> {
+    let stalled = object
+        .pointer("/status/conditions")
+        .and_then(|v| v.as_array())
+        .map(|conditions| {
+            conditions
+                .iter()
+                .any(|c| c["type"] == "Progressing" && c["reason"] == "ProgressDeadlineExceeded")
+        })
+        .unwrap_or(false);
+    if stalled {
+        return Some("Stalled".to_string());
+    }
+
+    let generation = i64_at(object, "/metadata/generation").unwrap_or(0);
+    let observed_generation = i64_at(object, "/status/observedGeneration").unwrap_or(0);
+    let desired = i64_at(object, "/spec/replicas").unwrap_or(1);
+    let replicas = i64_at(object, "/status/replicas").unwrap_or(0);
+    let updated = i64_at(object, "/status/updatedReplicas").unwrap_or(0);
+    let available = i64_at(object, "/status/availableReplicas").unwrap_or(0);
+
+    if observed_generation < generation
+        || updated < desired
+        || replicas > updated
+        || available < updated
+    {
+        return Some("Progressing".to_string());
+    }
+
+    Some("Complete".to_string())
+}
+
+/// Ready containers over all containers, i.e. '2/3'
+fn pod_ready(object: &Value) -> Option<String> {
+    let containers = object
+        .pointer("/spec/containers")
+        .and_then(|v| v.as_array())
+        .map(|v| v.len())
+        .unwrap_or(0);
+    let ready = object
+        .pointer("/status/containerStatuses")
+        .and_then(|v| v.as_array())
+        .map(|statuses| statuses.iter().filter(|s| s["ready"] == true).count())
+        .unwrap_or(0);
+
+    Some(format!("{}/{}", ready, containers))
+}
+
+/// The maximum restart count across all containers
+fn pod_restarts(object: &Value) -> Option<String> {
+    let restarts = object
+        .pointer("/status/containerStatuses")
+        .and_then(|v| v.as_array())
+        .and_then(|statuses| {
+            statuses
+                .iter()
+                .filter_map(|s| s["restartCount"].as_i64())
+                .max()
+        })
+        .unwrap_or(0);
+
+    Some(restarts.to_string())
+}
+
+/// The time passed since creation, in the same format as 'kubectl get'
+fn age(object: &Value) -> Option<String> {
+    let created = object
+        .pointer("/metadata/creationTimestamp")
+        .and_then(|v| v.as_str())
+        .and_then(|v| DateTime::parse_from_rfc3339(v).ok())?;
+
+    Some(human_duration(
+        Utc::now().signed_duration_since(created).num_seconds(),
+    ))
+}
+
+/// Adopted from k8s.io/apimachinery/pkg/util/duration.HumanDuration
+fn human_duration(seconds: i64) -> String {
+    if seconds < 0 {
+        return "<invalid>".to_string();
+    }
+
+    let minutes = seconds / 60;
+    let hours = minutes / 60;
+    let days = hours / 24;
+    let years = days / 365;
+
+    if seconds < 60 * 2 {
+        format!("{}s", seconds)
+    } else if minutes < 10 {
+        match seconds % 60 {
+            0 => format!("{}m", minutes),
+            s => format!("{}m{}s", minutes, s),
+        }
+    } else if minutes < 60 * 3 {
+        format!("{}m", minutes)
+    } else if hours < 8 {
+        match minutes % 60 {
+            0 => format!("{}h", hours),
+            m => format!("{}h{}m", hours, m),
+        }
+    } else if hours < 48 {
+        format!("{}h", hours)
+    } else if hours < 24 * 8 {
+        match hours % 24 {
+            0 => format!("{}d", days),
+            h => format!("{}d{}h", days, h),
+        }
+    } else if hours < 24 * 365 * 2 {
+        format!("{}d", days)
+    } else if hours < 24 * 365 * 8 {
+        match days % 365 {
+            0 => format!("{}y", years),
+            d => format!("{}y{}d", years, d),
+        }
+    } else {
+        format!("{}y", years)
+    }
+}
+
+/// Sums the given resource requests or limits of all containers, i.e. '500m'
+fn container_resources(object: &Value, requirement: &str, resource: &str) -> Option<String> {
+    let total = object
+        .pointer("/spec/containers")
+        .and_then(|v| v.as_array())
+        .map(|c| {
+            c.iter()
+                .filter_map(|c| c["resources"][requirement][resource].as_str())
+                .filter_map(quantity::parse)
+                .sum()
+        })
+        .unwrap_or(0f64);
+
+    Some(quantity::format(resource, total))
+}
+
+/// Sums the given resource usage of all containers
+fn container_usage(object: &Value, resource: &str) -> Option<f64> {
+    object
+        .get("containers")
+        .and_then(|v| v.as_array())
+        .map(|c| {
+            c.iter()
+                .filter_map(|c| c["usage"][resource].as_str())
+                .filter_map(quantity::parse)
+                .sum()
+        })
+}
+
+/// The CPU usage of all containers, i.e. '250m'
+fn podmetrics_cpu(object: &Value) -> Option<String> {
+    container_usage(object, "cpu").map(quantity::format_cpu)
+}
+
+/// The memory usage of all containers, i.e. '128Mi'
+fn podmetrics_memory(object: &Value) -> Option<String> {
+    container_usage(object, "memory").map(quantity::format_memory)
+}
+
+/// The average utilization in percent of the given resource metric, i.e. '80' of the cpu target
+fn hpa_utilization(object: &Value, metrics: &str, value: &str, resource: &str) -> Option<String> {
+    object
+        .pointer(metrics)
+        .and_then(|v| v.as_array())?
+        .iter()
+        .find(|m| m["type"] == "Resource" && m["resource"]["name"] == resource)
+        .and_then(|m| m["resource"][value]["averageUtilization"].as_i64())
+        .map(|v| v.to_string())
+}
+
+/// The hard limit or the used amount of the given resource, 'cpu' and 'memory' are the requests
+fn quota(object: &Value, side: &str, resource: &str) -> Option<String> {
+    let resources = object.pointer(&format!("/status/{}", side))?;
+    resources
+        .get(format!("requests.{}", resource))
+        .or_else(|| resources.get(resource))
+        .and_then(|v| v.as_str())
+        .map(|v| v.to_string())
+}
+
+/// The used amount of the given resource in percent of the hard limit, i.e. '90'
+fn quota_usage(object: &Value, resource: &str) -> Option<String> {
+    let hard = quota(object, "hard", resource).and_then(|v| quantity::parse(&v))?;
+    let used = quota(object, "used", resource).and_then(|v| quantity::parse(&v))?;
+    if hard == 0f64 {
+        return None;
+    }
+
+    Some(format!("{:.0}", used / hard * 100f64))
+}
+
+/// The given limit of the containers, i.e. 'max' or 'default'
+fn container_limit(object: &Value, limit: &str, resource: &str) -> Option<String> {
+    object
+        .pointer("/spec/limits")
+        .and_then(|v| v.as_array())?
+        .iter()
+        .find(|l| l["type"] == "Container")
+        .and_then(|l| l[limit][resource].as_str())
+        .map(|v| v.to_string())
+}
+
+/// Whether the PodDisruptionBudget allows no more disruption of the pods it selects, i.e. a drain
+/// would not evict them. Kubernetes omits the zero counts
+pub fn blocks_disruptions(pdb: &Value) -> bool {
+    i64_at(pdb, "/status/disruptionsAllowed").unwrap_or(0) == 0
+        && i64_at(pdb, "/status/expectedPods").unwrap_or(0) > 0
+}
+
+/// Healthy pods over the desired healthy ones, i.e. '2/3'
+fn pdb_healthy(object: &Value) -> Option<String> {
+    object.get("status")?;
+    Some(format!(
+        "{}/{}",
+        i64_at(object, "/status/currentHealthy").unwrap_or(0),
+        i64_at(object, "/status/desiredHealthy").unwrap_or(0)
+    ))
+}
+
+/// Whether the status condition of the given type is 'True', None if the condition is not reported
+fn condition_holds(object: &Value, kind: &str) -> Option<String> {
+    condition(object, kind, "status").map(|status| (status == "True").to_string())
+}
+
+/// The given field of the status condition of the given type, i.e. the 'status' of 'Ready'
+fn condition(object: &Value, condition: &str, field: &str) -> Option<String> {
+    object
+        .pointer("/status/conditions")
+        .and_then(|v| v.as_array())?
+        .iter()
+        .find(|c| c["type"] == condition)
+        .and_then(|c| c[field].as_str())
+        .map(|v| v.to_string())
+}
@@ -0,0 +1,264 @@
+// Copyright (c) 2021 Dentrax
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software is furnished to do so,
+// subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+use crate::parser::{ResourceType, RewrittenSql};
+use crate::planner::PlanError;
+use sqlparser::parser::{Parser, ParserError};
+use sqlparser::tokenizer::{Token, TokenizerError};
+use std::fmt;
+
+/// A rustc-style diagnostic pointing at the offending part of the given SQL, i.e.
+///
+/// ```text
+/// Expected end of statement, found: .0
+///  --> line 1, column 59
+///   |
+/// 1 | SELECT default FROM ctx WHERE pod.status.containerStatuses.0.restartCount > 0
+///   |                                                           ^^
+///   = help: array indexes in field paths must be double quoted, i.e. "0"
+/// ```
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    message: String,
+    sql: String,
+    /// Char offset and length of the offending part of the SQL
+    span: Option<(usize, usize)>,
+    help: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn new(message: impl Into<String>, sql: &str) -> Diagnostic {
+        Diagnostic {
+            message: message.into(),
+            sql: sql.to_string(),
+            span: None,
+            help: None,
+        }
+    }
+
+    /// Set the char offset and length of the offending part
+    pub fn span(mut self, offset: usize, len: usize) -> Diagnostic {
+        self.span = Some((offset, len.max(1)));
+        self
+    }
+
+    /// Set the span to the first occurrence of the given fragment, if any
+    pub fn fragment(self, fragment: &str) -> Diagnostic {
+        match self.sql.find(fragment) {
+            Some(i) if !fragment.is_empty() => {
+                let offset = self.sql[..i].chars().count();
+                self.span(offset, fragment.chars().count())
+            }
+            _ => self,
+        }
+    }
+
+    pub fn help(mut self, help: Option<String>) -> Diagnostic {
+        self.help = help;
+        self
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)?;
+
+        if let Some((offset, len)) = self.span {
+            let before: String = self.sql.chars().take(offset).collect();
+            let line = before.matches('\n').count() + 1;
+            let column = before.chars().rev().take_while(|c| *c != '\n').count() + 1;
+            let text = self.sql.lines().nth(line - 1).unwrap_or_default();
+            let gutter = " ".repeat(line.to_string().len());
+
+            write!(f, "\n{}--> line {}, column {}", gutter, line, column)?;
+            write!(f, "\n{} |", gutter)?;
+            write!(f, "\n{} | {}", line, text)?;
+            write!(
+                f,
+                "\n{} | {}{}",
+                gutter,
+                " ".repeat(column - 1),
+                "^".repeat(len)
+            )?;
+        }
+
+        if let Some(help) = &self.help {
+            write!(f, "\n  = help: {}", help)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Renders the error of the tokenizer that ran on the rewritten SQL against the given SQL
+pub fn tokenizer_error(sql: &str, rewritten: &RewrittenSql, e: &TokenizerError) -> Diagnostic {
+    let offset = rewritten.original_offset(offset_of(&rewritten.sql, e.line, e.col));
+
+    let help = if e.message.starts_with("Unterminated string literal") {
+        Some("close the string literal with a single quote, i.e. 'Running'".to_string())
+    } else {
+        None
+    };
+
+    Diagnostic::new(e.message.clone(), sql)
+        .span(offset, 1)
+        .help(help)
+}
+
+/// Renders the error of the given parser that ran on the rewritten SQL against the given SQL.
+/// The parser does not tell the error location, so the 'found' token is looked up around
+/// the current parser position.
+pub fn parser_error(
+    sql: &str,
+    rewritten: &RewrittenSql,
+    parser: &mut Parser,
+    e: &ParserError,
+) -> Diagnostic {
+    let message = match e {
+        ParserError::TokenizerError(m) | ParserError::ParserError(m) => m.clone(),
+        ParserError::RecursionLimitExceeded => "Recursion limit exceeded".to_string(),
+    };
+    let found = message
+        .rsplit_once("found: ")
+        .map(|(_, f)| f.to_string())
+        .unwrap_or_default();
+
+    let mut token = parser.peek_token();
+    if token.to_string() != found {
+        parser.prev_token();
+        if parser.peek_token().to_string() == found {
+            token = parser.peek_token();
+        }
+    }
+
+    let diagnostic = Diagnostic::new(message.clone(), sql);
+    let diagnostic = if token.token == Token::EOF {
+        diagnostic
+            .span(sql.chars().count(), 1)
+            .help(Some("the query ends unexpectedly".to_string()))
+    } else {
+        let start = offset_of(&rewritten.sql, token.location.line, token.location.column);
+        let end = start + token.to_string().chars().count();
+        let offset = rewritten.original_offset(start);
+        diagnostic.span(offset, rewritten.original_offset(end) - offset)
+    };
+
+    if found.starts_with('.') && found[1..].starts_with(|c: char| c.is_ascii_digit()) {
+        return diagnostic.help(Some(format!(
+            "array indexes in field paths must be double quoted, i.e. \"{}\"",
+            &found[1..]
+        )));
+    }
+
+    diagnostic
+}
+
+/// Renders the given plan error of the WHERE statement against the given SQL
+pub fn plan_error(sql: &str, e: &PlanError) -> Diagnostic {
+    let diagnostic = Diagnostic::new(e.to_string(), sql);
+
+    match e {
+        PlanError::UnknownKind(path) => {
+            // The kind may be scoped with a context, i.e. 'ctx_prod.pods.status.phase'
+            let help = match path.split('.').take(2).find_map(suggest_kind) {
+                Some(k) => format!("did you mean '{}'?", k),
+                None => format!(
+                    "supported kinds are: {}",
+                    ResourceType::all()
+                        .iter()
+                        .map(|k| k.to_string())
+                        .collect::<Vec<String>>()
+                        .join(", ")
+                ),
+            };
+            diagnostic.fragment(path).help(Some(help))
+        }
+        // i.e. 'Running', '"Running"' or '-1'
+        PlanError::Unsupported(what, expr)
+            if what == "Expr"
+                && expr
+                    .trim_matches('"')
+                    .chars()
+                    .all(|c| c.is_alphanumeric() || "-_.".contains(c)) =>
+        {
+            let value = expr.trim_matches('"');
+            let diagnostic = if sql.contains(expr.as_str()) {
+                diagnostic.fragment(expr)
+            } else {
+                diagnostic.fragment(value)
+            };
+            diagnostic.help(Some(format!(
+                "values must be single quoted, i.e. '{}'",
+                value
+            )))
+        }
+        PlanError::UnknownScope(path, _) => diagnostic.fragment(path).help(Some(
+            "a predicate can only be scoped with a context given in FROM and a namespace given in SELECT, i.e. 'context.namespace.pod.status.phase'".to_string(),
+        )),
+        PlanError::Unsupported(_, expr) => diagnostic.fragment(expr),
+        PlanError::InvalidPattern(pattern, _) => diagnostic.fragment(pattern),
+        PlanError::Unknown(_) => diagnostic,
+    }
+}
+
+/// Returns the closest kind to the given unknown one, i.e. 'pods' => 'pod'
+fn suggest_kind(kind: &str) -> Option<ResourceType> {
+    let kind = kind.to_lowercase();
+
+    ResourceType::all()
+        .iter()
+        .map(|k| (distance(&kind, &k.to_string()), k))
+        .filter(|(d, _)| *d <= 2)
+        .min_by_key(|(d, _)| *d)
+        .map(|(_, k)| k.clone())
+}
+
+/// Levenshtein distance of the given strings
+pub fn distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut prev = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let cur = row[j + 1];
+            row[j + 1] = if ca == *cb {
+                prev
+            } else {
+                1 + prev.min(row[j]).min(cur)
+            };
+            prev = cur;
+        }
+    }
+
+    row[b.len()]
+}
+
+/// Returns the char offset of the given 1-based line and column
+fn offset_of(sql: &str, line: u64, column: u64) -> usize {
+    let mut offset = 0;
+    for (i, l) in sql.split('\n').enumerate() {
+        if i as u64 + 1 == line {
+            return offset + (column.max(1) as usize - 1);
+        }
+        offset += l.chars().count() + 1;
+    }
+    offset
+}
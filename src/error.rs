@@ -0,0 +1,169 @@
+// Copyright (c) 2021 Dentrax
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software is furnished to do so,
+// subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+use crate::catalog::CatalogError;
+use crate::config::ConfigError;
+use crate::daemon::DaemonError;
+use crate::eval::EvalError;
+use crate::exporter::ExportError;
+use crate::history::HistoryError;
+use crate::library::LibraryError;
+use crate::manifests::ManifestError;
+use crate::notify::NotifyError;
+use crate::parser::ParserError;
+use crate::policy::PolicyError;
+use crate::sarif::SarifError;
+use crate::schedule::ScheduleError;
+use crate::sink::SinkError;
+use crate::snapshot::SnapshotError;
+use crate::source::SourceError;
+use crate::trend::TrendError;
+use crate::validator::ValidationError;
+use crate::views::ViewError;
+use kube::config::KubeconfigError;
+use thiserror::Error;
+
+/// The crate-wide error, all module errors are converted into this one
+#[derive(Error, Debug)]
+pub enum KubesqlError {
+    #[error(transparent)]
+    Parser(#[from] ParserError),
+
+    #[error(transparent)]
+    Eval(#[from] EvalError),
+
+    #[error(transparent)]
+    Validation(#[from] ValidationError),
+
+    #[error(transparent)]
+    Export(#[from] ExportError),
+
+    #[error(transparent)]
+    Snapshot(#[from] SnapshotError),
+
+    #[error(transparent)]
+    History(#[from] HistoryError),
+
+    #[error(transparent)]
+    Config(#[from] ConfigError),
+
+    #[error(transparent)]
+    View(#[from] ViewError),
+
+    #[error(transparent)]
+    Daemon(#[from] DaemonError),
+
+    #[error(transparent)]
+    Catalog(#[from] CatalogError),
+
+    #[error(transparent)]
+    Notify(#[from] NotifyError),
+
+    #[error(transparent)]
+    Policy(#[from] PolicyError),
+
+    #[error(transparent)]
+    Sarif(#[from] SarifError),
+
+    #[error(transparent)]
+    Schedule(#[from] ScheduleError),
+
+    #[error(transparent)]
+    Trend(#[from] TrendError),
+
+    #[error(transparent)]
+    Library(#[from] LibraryError),
+
+    #[error(transparent)]
+    Source(#[from] SourceError),
+
+    #[error(transparent)]
+    Sink(#[from] SinkError),
+
+    #[error(transparent)]
+    Manifests(#[from] ManifestError),
+
+    #[error("A query is required, either as argument, --query or --file")]
+    QueryRequired,
+
+    #[error("Unable to read the query file '{0}': {1}")]
+    QueryFile(String, std::io::Error),
+
+    #[error("A kubeconfig context is required to build the API")]
+    ContextRequired,
+
+    #[error("Unable to load the kubeconfig for context '{0}': {1}")]
+    KubeConfig(String, KubeconfigError),
+
+    #[error("Kubernetes API error: {0}")]
+    Kube(#[from] kube::Error),
+
+    #[error("Unable to apply {0} to context '{1}': {2}")]
+    Apply(String, String, kube::Error),
+
+    #[error("Invalid manifest: {0}")]
+    Manifest(String),
+
+    #[error("Unable to serialize the object: {0}")]
+    Serialize(#[from] serde_json::Error),
+
+    #[error("The query did not finish in {0} seconds")]
+    Timeout(u64),
+
+    #[error("Interrupted, the results may be incomplete")]
+    Interrupted,
+
+    #[error("Unable to read the query: {0}")]
+    Repl(std::io::Error),
+
+    #[error("Unable to read the logs of '{0}': {1}")]
+    Logs(String, std::io::Error),
+
+    #[error("Unable to run the command in '{0}': {1}")]
+    Exec(String, String),
+
+    #[error("Unable to forward the port of '{0}': {1}")]
+    Forward(String, String),
+
+    #[error("Unable to serve on '{0}': {1}")]
+    Serve(String, std::io::Error),
+
+    #[error("No resource found: '{0}'")]
+    NoResourceFound(String),
+
+    #[error("{0} of {1} contexts are not healthy")]
+    Unhealthy(usize, usize),
+
+    #[error("{0} of {1} policy checks did not pass")]
+    PolicyFailed(usize, usize),
+
+    #[error("{0} of {1} statements failed")]
+    StatementsFailed(usize, usize),
+
+    #[error("{0} of {1} manifests could not be applied to their context and namespace")]
+    ApplyFailed(usize, usize),
+
+    #[error("Expected a single match, found {0}: {1}")]
+    MultipleMatches(usize, String),
+
+    #[error("Unsupported: {0}")]
+    Unsupported(String),
+}
+
+pub type Result<T> = std::result::Result<T, KubesqlError>;
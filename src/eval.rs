@@ -1,4 +1,11 @@
-use crate::planner::Value;
+use crate::derived;
+use crate::functions;
+use crate::planner::{Projection, Quantifier, Query, Value};
+use crate::quantity;
+use k8s_openapi::chrono::{DateTime, NaiveDate, Utc};
+use serde::Serialize;
+use sqlparser::ast::BinaryOperator;
+use std::cmp::Ordering;
 
 use thiserror::Error;
 
@@ -7,14 +14,695 @@ pub enum EvalError {
     #[allow(dead_code)]
     #[error("Unknown EvalError: {0}")]
     Unknown(String),
+
+    #[error("Unsupported operator for {0}: {1}")]
+    UnsupportedOperator(String, String),
+
+    #[error("Unable to serialize the object: {0}")]
+    Serialize(String),
 }
 
 #[derive(Debug, Clone)]
-pub struct EvalContext {}
+pub struct EvalContext<'a> {
+    /// The object to evaluate queries on
+    pub object: &'a serde_json::Value,
+}
 
-#[allow(dead_code)]
 pub type EvalResult = Result<Value, EvalError>;
 
 pub trait Evaluate {
     fn evaluate(&self, context: &mut EvalContext) -> EvalResult;
 }
+
+impl Evaluate for Query {
+    fn evaluate(&self, context: &mut EvalContext) -> EvalResult {
+        // The arrays on the field path match if any of their values matches, and the negated
+        // operators if none of them matches, i.e. 'endpointslice.endpoints.conditions.ready'
+        let mut values = match resolve_all(&self.kind, &self.fields, context.object) {
+            values if !values.is_empty() => values,
+            _ => resolve(&self.kind, &self.fields, context.object)
+                .into_iter()
+                .collect(),
+        };
+
+        // i.e. 'image_tag(pod.spec.containers.image)', the values without a result are NULL
+        if !self.functions.is_empty() {
+            values = values
+                .iter()
+                .filter_map(|v| functions::apply(&self.functions, v))
+                .collect();
+        }
+
+        // i.e. 'IS NULL' and 'IS NOT TRUE', the only operators that can match the missing fields
+        if let BinaryOperator::PGCustomBinaryOperator(op) = &self.op {
+            let negated = op.iter().any(|o| o == "NOT");
+            match op.last().map(String::as_str) {
+                Some("NULL") => return Ok(Value::Bool(values.is_empty() != negated)),
+                Some(b @ ("TRUE" | "FALSE")) => {
+                    let expected = TypedValue::Bool(b == "TRUE");
+                    let is = |v: &String| (TypedValue::parse(v) == expected) != negated;
+                    let matched = match self.quantifier {
+                        Some(Quantifier::All) => !values.is_empty() && values.iter().all(is),
+                        Some(Quantifier::Any) => values.iter().any(is),
+                        None => values.iter().any(|v| TypedValue::parse(v) == expected) != negated,
+                    };
+                    return Ok(Value::Bool(matched));
+                }
+                _ => {}
+            }
+        }
+
+        // Missing fields are NULL as in SQL, so they never match any comparison
+        if values.is_empty() {
+            return Ok(Value::Bool(false));
+        }
+
+        // i.e. 'hpa.spec.maxReplicas' of the same object, a missing one never matches as well
+        let eq = match &self.other {
+            Some(other) => match resolve(&self.kind, other, context.object) {
+                Some(eq) => eq,
+                None => return Ok(Value::Bool(false)),
+            },
+            None => self.eq.clone(),
+        };
+
+        // i.e. 'ALL(pod.spec.containers.image) NOT LIKE ...', each value matches the negated
+        // operator itself
+        let negated = self.negated();
+        match self.quantifier {
+            Some(Quantifier::All) => {
+                for v in &values {
+                    if self.matches(v, &eq)? == negated {
+                        return Ok(Value::Bool(false));
+                    }
+                }
+                Ok(Value::Bool(true))
+            }
+            Some(Quantifier::Any) => {
+                for v in &values {
+                    if self.matches(v, &eq)? != negated {
+                        return Ok(Value::Bool(true));
+                    }
+                }
+                Ok(Value::Bool(false))
+            }
+            None => {
+                let mut matched = false;
+                for v in &values {
+                    if self.matches(v, &eq)? {
+                        matched = true;
+                        break;
+                    }
+                }
+                Ok(Value::Bool(matched != negated))
+            }
+        }
+    }
+}
+
+impl Query {
+    /// Whether the operator is negated, i.e. '!=' or 'NOT LIKE'
+    fn negated(&self) -> bool {
+        match &self.op {
+            BinaryOperator::NotEq
+            | BinaryOperator::PGRegexNotMatch
+            | BinaryOperator::PGRegexNotIMatch => true,
+            BinaryOperator::PGCustomBinaryOperator(op) => {
+                op.first().map(String::as_str) == Some("NOT")
+            }
+            _ => false,
+        }
+    }
+
+    /// Whether the value matches the operator without its negation, i.e. '=' instead of '!='
+    fn matches(&self, value: &str, eq: &str) -> Result<bool, EvalError> {
+        let typed = typed_field(&self.kind, &self.fields)
+            || self
+                .other
+                .as_ref()
+                .is_some_and(|o| typed_field(&self.kind, o));
+        let equals = |l: &str, r: &str| match self.case_insensitive {
+            true => l.to_lowercase() == r.to_lowercase(),
+            false => l == r,
+        } || (typed && typed_equals(l, r));
+
+        match &self.op {
+            BinaryOperator::Eq | BinaryOperator::NotEq => Ok(equals(value, eq)),
+            BinaryOperator::Gt => Ok(compare(value, eq) == Ordering::Greater),
+            BinaryOperator::GtEq => Ok(compare(value, eq) != Ordering::Less),
+            BinaryOperator::Lt => Ok(compare(value, eq) == Ordering::Less),
+            BinaryOperator::LtEq => Ok(compare(value, eq) != Ordering::Greater),
+            BinaryOperator::PGRegexMatch
+            | BinaryOperator::PGRegexIMatch
+            | BinaryOperator::PGRegexNotMatch
+            | BinaryOperator::PGRegexNotIMatch => {
+                Ok(self.pattern.as_ref().is_some_and(|p| p.is_match(value)))
+            }
+            BinaryOperator::PGCustomBinaryOperator(op) => match op.last().map(String::as_str) {
+                Some("LIKE") => Ok(like(value, eq, self.case_insensitive)),
+                Some("ILIKE") => Ok(like(value, eq, true)),
+                Some("IN") => Ok(self.values.iter().any(|v| equals(value, v))),
+                _ => Err(EvalError::UnsupportedOperator(self.path(), self.operator())),
+            },
+            _ => Err(EvalError::UnsupportedOperator(self.path(), self.operator())),
+        }
+    }
+}
+
+/// A field value or a literal typed by its format, so they are compared by their meaning
+/// instead of lexically, i.e. '1Gi' > '512Mi'
+#[derive(Debug, Clone, PartialEq)]
+pub enum TypedValue {
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    /// A Kubernetes quantity with a suffix in its base unit, i.e. 0.5 of '500m'
+    Quantity(f64),
+    /// An RFC 3339 timestamp or a date at midnight UTC, i.e. '2024-01-31'
+    Timestamp(DateTime<Utc>),
+    /// A version with a patch version or a 'v' prefix, i.e. [1, 27, 3] of 'v1.27.3-eks-2d98532'
+    Version(Vec<u64>),
+    String(String),
+}
+
+impl TypedValue {
+    /// Types the given value by the first format it has, a string otherwise
+    pub fn parse(value: &str) -> TypedValue {
+        if value.eq_ignore_ascii_case("true") || value.eq_ignore_ascii_case("false") {
+            return TypedValue::Bool(value.eq_ignore_ascii_case("true"));
+        }
+
+        if let Ok(i) = value.parse::<i64>() {
+            return TypedValue::Int(i);
+        }
+
+        if let Some(v) = version(value).filter(|v| value.starts_with('v') || v.len() > 2) {
+            return TypedValue::Version(v);
+        }
+
+        // f64 accepts 'inf' and 'NaN' as well, which are names rather than numbers
+        let numeric = |c: char| c.is_ascii_digit() || matches!(c, '.' | '+' | '-' | 'e' | 'E');
+        if value.chars().all(numeric) {
+            if let Ok(f) = value.parse::<f64>() {
+                return TypedValue::Float(f);
+            }
+        }
+
+        if let Some(q) = quantity::parse(value) {
+            return TypedValue::Quantity(q);
+        }
+
+        if let Ok(t) = DateTime::parse_from_rfc3339(value) {
+            return TypedValue::Timestamp(t.with_timezone(&Utc));
+        }
+        if let Some(t) = NaiveDate::parse_from_str(value, "%Y-%m-%d")
+            .ok()
+            .and_then(|d| d.and_hms_opt(0, 0, 0))
+        {
+            return TypedValue::Timestamp(DateTime::from_naive_utc_and_offset(t, Utc));
+        }
+
+        TypedValue::String(value.to_string())
+    }
+
+    /// Returns the value as a number, the integers and the quantities are coerced to floats
+    fn number(&self) -> Option<f64> {
+        match self {
+            TypedValue::Int(i) => Some(*i as f64),
+            TypedValue::Float(f) | TypedValue::Quantity(f) => Some(*f),
+            _ => None,
+        }
+    }
+
+    /// Compares the values of the same type, the numbers of any type with each other, i.e.
+    /// '500m' < '1'. None if they can not be compared
+    pub fn compare(&self, other: &TypedValue) -> Option<Ordering> {
+        match (self, other) {
+            (TypedValue::Bool(l), TypedValue::Bool(r)) => Some(l.cmp(r)),
+            (TypedValue::Int(l), TypedValue::Int(r)) => Some(l.cmp(r)),
+            (TypedValue::Timestamp(l), TypedValue::Timestamp(r)) => Some(l.cmp(r)),
+            (TypedValue::Version(l), TypedValue::Version(r)) => Some(l.cmp(r)),
+            (TypedValue::String(l), TypedValue::String(r)) => Some(l.cmp(r)),
+            (l, r) => l.number()?.partial_cmp(&r.number()?),
+        }
+    }
+}
+
+/// Compares the given values by their types, i.e. '1Gi' > '512Mi' and '1.9.2' < '1.27'. A version
+/// is compared with a number as a version, i.e. '1.27', the others that can not be coerced to the
+/// same type lexically
+pub fn compare(l: &str, r: &str) -> Ordering {
+    let (lt, rt) = (TypedValue::parse(l), TypedValue::parse(r));
+    if let (TypedValue::Version(_), _) | (_, TypedValue::Version(_)) = (&lt, &rt) {
+        if let (Some(lv), Some(rv)) = (version(l), version(r)) {
+            return lv.cmp(&rv);
+        }
+    }
+
+    lt.compare(&rt).unwrap_or_else(|| l.cmp(r))
+}
+
+/// The fields known to hold quantities, numbers, booleans or timestamps, by the name of any field
+/// on their path, i.e. 'requests' of 'pod.spec.containers.resources.requests.cpu'
+const TYPED_FIELDS: &[&str] = &[
+    // quantities
+    "resources",
+    "requests",
+    "limits",
+    "capacity",
+    "allocatable",
+    "hard",
+    "used",
+    "usage",
+    "max",
+    "min",
+    "default",
+    "defaultRequest",
+    "cpu",
+    "memory",
+    "storage",
+    "pods",
+    // numbers
+    "replicas",
+    "readyReplicas",
+    "availableReplicas",
+    "updatedReplicas",
+    "unavailableReplicas",
+    "currentReplicas",
+    "desiredReplicas",
+    "minReplicas",
+    "maxReplicas",
+    "restartCount",
+    "restarts",
+    "port",
+    "targetPort",
+    "containerPort",
+    "nodePort",
+    "observedGeneration",
+    "priority",
+    "minAvailable",
+    "maxUnavailable",
+    "maxSurge",
+    "currentHealthy",
+    "desiredHealthy",
+    "disruptionsAllowed",
+    "expectedPods",
+    "averageUtilization",
+    "current",
+    "target",
+    "count",
+    "age",
+    // booleans
+    "conditions",
+    "ready",
+    "started",
+    "unschedulable",
+    "hostNetwork",
+    "privileged",
+    "readOnlyRootFilesystem",
+    "runAsNonRoot",
+    "allowPrivilegeEscalation",
+    "automountServiceAccountToken",
+    "immutable",
+    "paused",
+    "suspend",
+    "suspended",
+    "cordoned",
+    "blocking",
+    "healthy",
+    "memory_pressure",
+    "disk_pressure",
+    // timestamps
+    "startTime",
+    "startedAt",
+    "finishedAt",
+    "lastTransitionTime",
+    "lastUpdateTime",
+    "lastScaleTime",
+];
+
+/// Whether the values of the given field are equal by their types as well, i.e. '1Gi' = '1024Mi'
+/// of 'pod.requests.memory'. The metadata, i.e. the names and the labels, is only equal as it is
+fn typed_field(kind: &str, fields: &[String]) -> bool {
+    shorthand(kind, fields, None) != Some("metadata")
+        && !fields.iter().any(|f| f == "metadata")
+        && fields.iter().any(|f| TYPED_FIELDS.contains(&f.as_str()))
+}
+
+/// Whether the given values are equal by their types but not as strings, i.e. '1Gi' and '1024Mi'.
+/// Only the quantities, numbers, booleans and timestamps are, the others are equal as they are
+fn typed_equals(l: &str, r: &str) -> bool {
+    match (TypedValue::parse(l), TypedValue::parse(r)) {
+        (TypedValue::String(_) | TypedValue::Version(_), _)
+        | (_, TypedValue::String(_) | TypedValue::Version(_)) => false,
+        (lt, rt) => lt.compare(&rt) == Some(Ordering::Equal),
+    }
+}
+
+/// Returns the numbers of the given version without the pre-release and build suffixes,
+/// i.e. [1, 27, 3] of 'v1.27.3-eks-2d98532', at least the minor version is required
+fn version(value: &str) -> Option<Vec<u64>> {
+    let version = value
+        .strip_prefix('v')
+        .unwrap_or(value)
+        .split(['-', '+'])
+        .next()?;
+
+    let numbers = version
+        .split('.')
+        .map(|n| n.parse::<u64>().ok())
+        .collect::<Option<Vec<u64>>>()?;
+    match numbers.len() {
+        2 | 3 => Some(numbers),
+        _ => None,
+    }
+}
+
+/// Matches the value with the given LIKE pattern, '%' matches any sequence and '_' any single char.
+/// Both can be escaped with '\', i.e. '100\%'
+pub fn like(value: &str, pattern: &str, case_insensitive: bool) -> bool {
+    let (value, pattern) = match case_insensitive {
+        true => (value.to_lowercase(), pattern.to_lowercase()),
+        false => (value.to_string(), pattern.to_string()),
+    };
+    let value: Vec<char> = value.chars().collect();
+    let pattern: Vec<char> = pattern.chars().collect();
+
+    // matched[j]: whether the value so far matches the first j chars of the pattern
+    let mut matched = vec![false; pattern.len() + 1];
+    matched[0] = true;
+    let mut j = 0;
+    while j < pattern.len() && pattern[j] == '%' {
+        matched[j + 1] = true;
+        j += 1;
+    }
+
+    for c in &value {
+        let mut next = vec![false; pattern.len() + 1];
+        let mut j = 0;
+        while j < pattern.len() {
+            let (token, width) = match pattern[j] {
+                '\\' if j + 1 < pattern.len() => (Some(pattern[j + 1]), 2),
+                '_' => (None, 1),
+                '%' => {
+                    next[j + 1] = next[j] || matched[j + 1];
+                    j += 1;
+                    continue;
+                }
+                p => (Some(p), 1),
+            };
+            next[j + width] = matched[j] && token.is_none_or(|t| t == *c);
+            j += width;
+        }
+        matched = next;
+    }
+
+    matched[pattern.len()]
+}
+
+/// Returns the key of the given lookup segment, i.e. 'Ready' of "conditions['Ready']". The
+/// elements of the arrays are looked up by their type or name, the maps by their keys
+pub fn lookup(field: &str) -> Option<&str> {
+    field.strip_prefix('[')?.strip_suffix(']')
+}
+
+/// Whether the given fields refer to the node the pod is scheduled on, i.e. 'pod.node.metadata.name'
+pub fn joins_node(kind: &str, fields: &[String]) -> bool {
+    kind.eq_ignore_ascii_case("pod") && fields.len() > 1 && fields[0] == "node"
+}
+
+/// Whether the given fields refer to the pods the service selects, i.e. 'service.backends.count'
+pub fn joins_backends(kind: &str, fields: &[String]) -> bool {
+    kind.eq_ignore_ascii_case("service") && fields.first().is_some_and(|f| f == "backends")
+}
+
+/// Whether the given fields refer to the PodDisruptionBudgets blocking the eviction of the pod,
+/// i.e. 'pod.blocking_pdb'
+pub fn joins_pdbs(kind: &str, fields: &[String]) -> bool {
+    kind.eq_ignore_ascii_case("pod") && fields.first().is_some_and(|f| f == "blocking_pdb")
+}
+
+/// Whether the given fields refer to the pods consuming the ConfigMap or Secret, i.e.
+/// 'configmap.consumers.count'
+pub fn joins_consumers(kind: &str, fields: &[String]) -> bool {
+    (kind.eq_ignore_ascii_case("configmap") || kind.eq_ignore_ascii_case("secret"))
+        && fields.first().is_some_and(|f| f == "consumers")
+}
+
+/// Returns the parent of the given fields if they start with a shorthand and the object has no
+/// such field itself, i.e. 'spec' of 'node.taints', 'metadata' of 'pod.labels.app' or 'status'
+/// of "pod.conditions['Ready']"
+pub fn shorthand(
+    kind: &str,
+    fields: &[String],
+    field: Option<&serde_json::Value>,
+) -> Option<&'static str> {
+    if field.is_some() {
+        return None;
+    }
+
+    match (kind.to_lowercase().as_str(), fields) {
+        (_, [c, l, ..]) if c == "conditions" && lookup(l).is_some() => Some("status"),
+        (_, [m, ..]) if m == "labels" || m == "annotations" => Some("metadata"),
+        ("node", [t, ..]) if t == "taints" => Some("spec"),
+        ("pod", [t, ..]) if t == "tolerations" => Some("spec"),
+        _ => None,
+    }
+}
+
+/// Returns the value of the given field path of the object as string, if exists and not null.
+/// Derived fields take precedence over the object fields.
+pub fn resolve(kind: &str, fields: &[String], object: &serde_json::Value) -> Option<String> {
+    if let Some(d) = derived::find(kind, &fields.join(".")) {
+        return (d.derive)(object);
+    }
+
+    if let Some(v) = object
+        .pointer(&format!("/{}", fields.join("/")))
+        .filter(|v| !v.is_null())
+    {
+        return Some(to_string(v));
+    }
+
+    // i.e. 'spec.containers.image' of all containers
+    let values = resolve_all(kind, fields, object);
+    if values.is_empty() {
+        return None;
+    }
+    Some(values.join(","))
+}
+
+/// Returns all the values of the given field path, the arrays on and at the end of the path
+/// are flattened, i.e. 'spec.containers.image' returns the image of each container
+pub fn resolve_all(kind: &str, fields: &[String], object: &serde_json::Value) -> Vec<String> {
+    if let Some(d) = derived::find(kind, &fields.join(".")) {
+        return (d.derive)(object).into_iter().collect();
+    }
+
+    // i.e. 'pod.node.metadata.labels.zone' is resolved on the node joined as 'node'
+    if joins_node(kind, fields) {
+        return object
+            .get("node")
+            .map_or_else(Vec::new, |node| resolve_all("node", &fields[1..], node));
+    }
+
+    let mut values = match fields
+        .first()
+        .and_then(|f| shorthand(kind, fields, object.get(f)))
+    {
+        Some(parent) => object.get(parent).into_iter().collect(),
+        None => vec![object],
+    };
+    for field in fields {
+        values = values
+            .into_iter()
+            .flat_map(|v| match (v, field.parse::<usize>()) {
+                (serde_json::Value::Array(a), _) if lookup(field).is_some() => {
+                    let key = lookup(field);
+                    a.iter()
+                        .filter(|e| e["type"].as_str() == key || e["name"].as_str() == key)
+                        .collect()
+                }
+                (serde_json::Value::Object(o), _) if lookup(field).is_some() => {
+                    lookup(field).and_then(|k| o.get(k)).into_iter().collect()
+                }
+                (serde_json::Value::Array(a), Ok(i)) => a.get(i).into_iter().collect(),
+                (serde_json::Value::Array(a), Err(_)) => {
+                    a.iter().filter_map(|e| e.get(field)).collect()
+                }
+                _ => v
+                    .get(field)
+                    .into_iter()
+                    .collect::<Vec<&serde_json::Value>>(),
+            })
+            .collect();
+    }
+
+    // i.e. 'metadata.finalizers' returns each finalizer
+    values
+        .into_iter()
+        .flat_map(|v| match v {
+            serde_json::Value::Array(a) => a.iter().collect(),
+            _ => vec![v],
+        })
+        .filter(|v| !v.is_null())
+        .map(to_string)
+        .collect()
+}
+
+/// Returns the value of the given SELECT field of the object, the functions are applied to
+/// each value of the arrays on the field path
+pub fn resolve_projection(p: &Projection, object: &serde_json::Value) -> Option<String> {
+    if p.functions.is_empty() {
+        return resolve(&p.kind, &p.fields, object);
+    }
+
+    let values = resolve_projection_all(p, object);
+    if values.is_empty() {
+        return None;
+    }
+    Some(values.join(","))
+}
+
+/// Returns all the values of the given SELECT field of the object, the functions applied
+pub fn resolve_projection_all(p: &Projection, object: &serde_json::Value) -> Vec<String> {
+    resolve_all(&p.kind, &p.fields, object)
+        .iter()
+        .filter_map(|v| functions::apply(&p.functions, v))
+        .collect()
+}
+
+fn to_string(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::String(s) => s.clone(),
+        _ => value.to_string(),
+    }
+}
+
+/// Whether the given queries match the object, combined with their keys from left to right
+pub fn matches_all(queries: &[Query], object: &serde_json::Value) -> Result<bool, EvalError> {
+    let mut context = EvalContext { object };
+    let mut matched = true;
+
+    for (i, q) in queries.iter().enumerate() {
+        let m = matches!(q.evaluate(&mut context)?, Value::Bool(true));
+        matched = match (i, &q.key) {
+            (0, _) => m,
+            (_, Some(BinaryOperator::Or)) => matched || m,
+            _ => matched && m,
+        };
+    }
+
+    Ok(matched)
+}
+
+/// Keeps only the items that the given query matches
+pub fn filter<K: Serialize>(query: &Query, items: Vec<K>) -> Result<Vec<K>, EvalError> {
+    let mut filtered = Vec::new();
+
+    for item in items {
+        let object =
+            serde_json::to_value(&item).map_err(|e| EvalError::Serialize(e.to_string()))?;
+        let mut context = EvalContext { object: &object };
+        if let Value::Bool(true) = query.evaluate(&mut context)? {
+            filtered.push(item);
+        }
+    }
+
+    Ok(filtered)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fields(path: &str) -> Vec<String> {
+        path.split('.').map(str::to_string).collect()
+    }
+
+    #[test]
+    fn typed_fields() {
+        assert!(typed_field(
+            "pod",
+            &fields("spec.containers.resources.requests.cpu")
+        ));
+        assert!(typed_field("pod", &fields("requests.memory")));
+        assert!(typed_field("deployment", &fields("spec.replicas")));
+        assert!(typed_field("node", &fields("spec.unschedulable")));
+    }
+
+    #[test]
+    fn metadata_fields_are_not_typed() {
+        assert!(!typed_field("pod", &fields("metadata.name")));
+        assert!(!typed_field("pod", &fields("metadata.labels.replicas")));
+        assert!(!typed_field("pod", &fields("labels.cpu")));
+        assert!(!typed_field("pod", &fields("status.phase")));
+    }
+
+    #[test]
+    fn typed_equality() {
+        assert!(typed_equals("1Gi", "1024Mi"));
+        assert!(typed_equals("500m", "0.5"));
+        assert!(typed_equals("TRUE", "true"));
+        assert!(!typed_equals("v1.27", "1.27"));
+        assert!(!typed_equals("api", "API"));
+    }
+
+    #[test]
+    fn typed_values_in_order() {
+        assert_eq!(TypedValue::parse("True"), TypedValue::Bool(true));
+        assert_eq!(TypedValue::parse("42"), TypedValue::Int(42));
+        assert_eq!(TypedValue::parse("1.27"), TypedValue::Float(1.27));
+        assert_eq!(TypedValue::parse("v1.27"), TypedValue::Version(vec![1, 27]));
+        assert_eq!(
+            TypedValue::parse("1.27.3"),
+            TypedValue::Version(vec![1, 27, 3])
+        );
+        assert_eq!(TypedValue::parse("500m"), TypedValue::Quantity(0.5));
+        assert_eq!(TypedValue::parse("1Ki"), TypedValue::Quantity(1024.0));
+        assert!(matches!(
+            TypedValue::parse("2024-01-31"),
+            TypedValue::Timestamp(_)
+        ));
+        assert!(matches!(
+            TypedValue::parse("2024-01-31T12:00:00Z"),
+            TypedValue::Timestamp(_)
+        ));
+        assert_eq!(
+            TypedValue::parse("inf"),
+            TypedValue::String("inf".to_string())
+        );
+        assert_eq!(
+            TypedValue::parse("api"),
+            TypedValue::String("api".to_string())
+        );
+    }
+
+    #[test]
+    fn compared_by_types() {
+        assert_eq!(compare("500m", "1"), Ordering::Less);
+        assert_eq!(compare("1Gi", "512Mi"), Ordering::Greater);
+        assert_eq!(compare("10", "9"), Ordering::Greater);
+        assert_eq!(compare("1.9.2", "1.27"), Ordering::Less);
+        assert_eq!(
+            compare("2024-01-30T23:59:59Z", "2024-01-31"),
+            Ordering::Less
+        );
+        assert_eq!(compare("api", "web"), Ordering::Less);
+    }
+
+    #[test]
+    fn like_patterns() {
+        assert!(like("nginx:1.25", "nginx%", false));
+        assert!(like("nginx:1.25", "%:1._5", false));
+        assert!(like("api", "%", false));
+        assert!(like("", "%", false));
+        assert!(!like("nginx", "nginx_", false));
+        assert!(!like("NGINX", "nginx%", false));
+        assert!(like("NGINX", "nginx%", true));
+        assert!(like("100%", "100\\%", false));
+        assert!(!like("1000", "100\\%", false));
+        assert!(like("a_b", "a\\_b", false));
+        assert!(!like("axb", "a\\_b", false));
+    }
+}
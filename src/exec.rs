@@ -0,0 +1,129 @@
+// Copyright (c) 2021 Dentrax
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software is furnished to do so,
+// subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+use crate::api_builder::ApiBuilder;
+use crate::error::{KubesqlError, Result};
+use crate::printer::ResultTable;
+use futures::StreamExt;
+use kube::api::AttachParams;
+use std::time::Duration;
+use tokio::io::AsyncReadExt;
+
+/// The number of the pods the command runs in at the same time, by default
+pub const CONCURRENCY: usize = 8;
+
+/// The options of running a command in the matched pods
+pub struct ExecOptions {
+    /// The command and its arguments, it does not run in a shell
+    pub command: Vec<String>,
+    /// Runs in the given container, in the only or default one of the pod otherwise
+    pub container: Option<String>,
+    /// Stops waiting for the command of a pod after the given time
+    pub timeout: Duration,
+    /// The number of the pods the command runs in at the same time
+    pub concurrency: usize,
+}
+
+/// Runs the command in each matched pod and adds its output as the 'exec' column of the pod
+/// tables. The failing ones are warned and their column is '-'.
+pub async fn run(tables: &mut [ResultTable], options: &ExecOptions) {
+    for table in tables.iter_mut().filter(|t| t.kind == "pod") {
+        let outputs = futures::stream::iter(table.rows.iter())
+            .map(|row| async move {
+                let (context, namespace, pod) = match row.as_slice() {
+                    [c, n, p, ..] => (c, n, p),
+                    _ => return "-".to_string(),
+                };
+                let target = format!("{}/{}/{}", context, namespace, pod);
+
+                match tokio::time::timeout(options.timeout, exec(context, namespace, pod, options))
+                    .await
+                {
+                    Ok(Ok(output)) => output,
+                    Ok(Err(e)) => {
+                        tracing::warn!("{}", e);
+                        "-".to_string()
+                    }
+                    Err(_) => {
+                        tracing::warn!(
+                            "{}",
+                            KubesqlError::Exec(
+                                target,
+                                format!("did not finish in {} seconds", options.timeout.as_secs())
+                            )
+                        );
+                        "-".to_string()
+                    }
+                }
+            })
+            .buffered(options.concurrency.max(1))
+            .collect::<Vec<String>>()
+            .await;
+
+        table.columns.push("exec".to_string());
+        for (row, output) in table.rows.iter_mut().zip(outputs) {
+            row.push(output);
+        }
+    }
+}
+
+/// Runs the command in the given pod and returns its standard output without the trailing newlines
+#[tracing::instrument(name = "exec", skip(options))]
+async fn exec(context: &str, namespace: &str, pod: &str, options: &ExecOptions) -> Result<String> {
+    let target = format!("{}/{}/{}", context, namespace, pod);
+    let api = ApiBuilder::builder()
+        .context(context.to_string())
+        .namespace(namespace.to_string())
+        .build()
+        .await?;
+
+    // The standard error is not read, the failures are reported with the status
+    let params = AttachParams {
+        container: options.container.clone(),
+        stderr: false,
+        ..Default::default()
+    };
+    let mut process = api
+        .get_pod()
+        .exec(pod, options.command.clone(), &params)
+        .await
+        .map_err(|e| KubesqlError::Exec(target.clone(), e.to_string()))?;
+
+    let mut output = String::new();
+    if let Some(mut stdout) = process.stdout() {
+        stdout
+            .read_to_string(&mut output)
+            .await
+            .map_err(|e| KubesqlError::Exec(target.clone(), e.to_string()))?;
+    }
+
+    let status = match process.take_status() {
+        Some(status) => status.await,
+        None => None,
+    };
+    if let Some(status) = status.filter(|s| s.status.as_deref() == Some("Failure")) {
+        return Err(KubesqlError::Exec(
+            target,
+            status
+                .message
+                .unwrap_or_else(|| "the command failed".to_string()),
+        ));
+    }
+
+    Ok(output.trim_end_matches('\n').to_string())
+}
@@ -0,0 +1,952 @@
+// Copyright (c) 2021 Dentrax
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software is furnished to do so,
+// subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+use crate::api_builder::{AllNamespaces, ApiBuilder, Budget, Failures, Nodes};
+use crate::contexts;
+use crate::derived;
+use crate::error::{KubesqlError, Result};
+use crate::eval::{self, EvalError};
+use crate::parser::{self, ApiLocal, ApiQueries, LocalTable, ParserError, ResourceType};
+use crate::planner::{self, Projection, Query, SubQuery};
+use crate::printer::{Printer, ResultTable};
+use crate::source::Sources;
+use k8s_openapi::api::core::v1::Pod;
+use k8s_openapi::apimachinery::pkg::version::Info;
+use kube::api::{Api, DynamicObject, ListParams, ObjectList};
+use kube::{Resource, ResourceExt};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use sqlparser::ast::BinaryOperator;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt::Debug;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// The statistics of the queries run on a context, i.e. to print them with '--stats'
+#[derive(Debug, Default, Clone)]
+pub struct Stats {
+    /// The number of the list calls made to the API Server, none if offline
+    pub api_calls: usize,
+    /// The number of the listed resources the queries are evaluated on
+    pub scanned: usize,
+    /// The number of the resources matched by the queries
+    pub matched: usize,
+    /// The size of the listed resources as JSON, the response bodies are not compressed
+    pub bytes: usize,
+    /// The time spent on listing the resources
+    pub duration: Duration,
+}
+
+impl Stats {
+    pub fn add(&mut self, other: &Stats) {
+        self.api_calls += other.api_calls;
+        self.scanned += other.scanned;
+        self.matched += other.matched;
+        self.bytes += other.bytes;
+        self.duration += other.duration;
+    }
+}
+
+/// Listing all the namespaces at once is cheaper than a call for each of them from this many on
+const ALL_NAMESPACES_MIN: usize = 3;
+
+/// The pod fields referring to a ConfigMap by its name
+const CONFIGMAP_REFERENCES: &[&str] = &[
+    "spec.volumes.configMap.name",
+    "spec.volumes.projected.sources.configMap.name",
+    "spec.containers.env.valueFrom.configMapKeyRef.name",
+    "spec.containers.envFrom.configMapRef.name",
+    "spec.initContainers.env.valueFrom.configMapKeyRef.name",
+    "spec.initContainers.envFrom.configMapRef.name",
+];
+
+/// The pod fields referring to a Secret by its name
+const SECRET_REFERENCES: &[&str] = &[
+    "spec.volumes.secret.secretName",
+    "spec.volumes.projected.sources.secret.name",
+    "spec.containers.env.valueFrom.secretKeyRef.name",
+    "spec.containers.envFrom.secretRef.name",
+    "spec.initContainers.env.valueFrom.secretKeyRef.name",
+    "spec.initContainers.envFrom.secretRef.name",
+    "spec.imagePullSecrets.name",
+];
+
+/// Lists all the namespaces of the contexts into the namespaces of the queries if 'SELECT *',
+/// without the excluded ones. The ones of a data source are the namespaces having any resource
+/// in it instead. The contexts query the namespaces of each other as well, the missing ones have
+/// no resources
+pub async fn resolve_namespaces(api_queries: &mut ApiQueries, sources: &Sources<'_>) -> Result<()> {
+    if !api_queries.all_namespaces {
+        return Ok(());
+    }
+
+    let mut namespaces: BTreeSet<String> = api_queries
+        .contexts
+        .iter()
+        .filter_map(|ctx| Some(sources.get(ctx)?.namespaces(ctx)))
+        .flatten()
+        .collect();
+    let live = sources.live(&api_queries.contexts);
+    if !live.is_empty() {
+        let listed = contexts::namespaces(&live, sources).await?;
+        namespaces.extend(listed.into_iter().map(|ns| ns.name));
+    }
+    api_queries.namespaces = namespaces
+        .into_iter()
+        .filter(|ns| !api_queries.excluded_namespaces.contains(ns))
+        .collect();
+    tracing::debug!(namespaces = ?api_queries.namespaces, "resolved");
+
+    Ok(())
+}
+
+/// The list calls of a query at most, the pages of the long lists are not counted
+#[derive(Debug)]
+pub struct Estimate {
+    pub contexts: usize,
+    /// The listed kinds with the number of the namespaces they are listed in, 1 if cluster-scoped
+    pub kinds: Vec<(ResourceType, usize)>,
+}
+
+impl Estimate {
+    /// Returns the list calls of all the kinds on all the contexts
+    pub fn list_calls(&self) -> usize {
+        self.contexts * self.kinds.iter().map(|(_, ns)| ns).sum::<usize>()
+    }
+}
+
+/// Estimates the list calls of the given queries as contexts × namespaces × kinds, along with the
+/// kinds joined and the ones of the subqueries. The namespaces must be resolved already
+pub fn estimate(api_queries: &ApiQueries) -> std::result::Result<Estimate, ParserError> {
+    let joins = Joins::of(&api_queries.queries, &api_queries.projections);
+
+    let mut kinds: Vec<ResourceType> = Vec::new();
+    for q in &api_queries.queries {
+        let kind = ResourceType::from_str(&q.kind.to_lowercase())?;
+        let mut listed = joins.kinds(&kind);
+        listed.insert(0, kind);
+
+        if let Some(subquery) = &q.subquery {
+            let kind = ResourceType::from_str(&subquery.projection.kind.to_lowercase())?;
+            let joins = Joins::of(
+                &subquery.queries,
+                std::slice::from_ref(&subquery.projection),
+            );
+            listed.extend(joins.kinds(&kind));
+            listed.push(kind);
+        }
+
+        for kind in listed {
+            if !kinds.contains(&kind) {
+                kinds.push(kind);
+            }
+        }
+    }
+
+    Ok(Estimate {
+        contexts: api_queries.contexts.len(),
+        kinds: kinds
+            .into_iter()
+            .map(|kind| match kind.is_cluster_scoped() {
+                true => (kind, 1),
+                false => (kind, api_queries.namespaces.len()),
+            })
+            .collect(),
+    })
+}
+
+/// Builds an API for each of the given context and namespace pairs, in FROM and SELECT order.
+/// The resources are read from the data source of the context instead, if any. The contexts
+/// whose API Server does not match the 'cluster' predicates are skipped
+pub async fn build_apis<'a>(
+    api_queries: &'a ApiQueries,
+    sources: &'a Sources<'_>,
+    stats: bool,
+    budget: Option<&Budget>,
+) -> Result<Vec<ApiBuilder<'a>>> {
+    let mut apis: Vec<ApiBuilder> = Vec::new();
+    let versions = versions(api_queries, sources).await?;
+    let metadata_only = metadata_only(api_queries);
+    let servers = match api_queries.sources.is_empty() {
+        true => BTreeMap::new(),
+        false => servers(api_queries),
+    };
+
+    for ctx in &api_queries.contexts {
+        let info = versions.get(ctx);
+        if let Some(info) = info {
+            if !eval::matches_all(&api_queries.clusters, &contexts::cluster(info))? {
+                tracing::debug!(context = %ctx, version = %info.git_version, "skipped");
+                continue;
+            }
+        }
+
+        let data_source = sources.get(ctx);
+        let all_namespaces = (data_source.is_none()
+            && api_queries.namespaces.len() >= ALL_NAMESPACES_MIN)
+            .then(AllNamespaces::default);
+        let nodes = Nodes::default();
+        let failures = Failures::default();
+
+        for ns in &api_queries.namespaces {
+            let source = contexts::source(ctx, ns, servers.get(ctx));
+            if !eval::matches_all(&api_queries.sources, &source)? {
+                tracing::debug!(context = %ctx, namespace = %ns, "skipped");
+                continue;
+            }
+
+            let api = ApiBuilder::builder()
+                .context(ctx.clone())
+                .namespace(ns.clone())
+                .queries(api_queries.queries.as_slice())
+                .source(data_source)
+                .metadata_only(metadata_only.clone())
+                .stats(stats)
+                .all_namespaces(all_namespaces.clone())
+                .nodes(nodes.clone())
+                .failures(failures.clone())
+                .budget(budget.cloned())
+                .server_version(info.and_then(contexts::minor_version));
+            apis.push(api.build().await?)
+        }
+    }
+
+    Ok(apis)
+}
+
+/// Returns the servers of the clusters of the contexts, only read from the kubeconfig if the
+/// metadata columns are queried or selected
+pub fn servers(api_queries: &ApiQueries) -> BTreeMap<String, String> {
+    let selected = api_queries
+        .projections
+        .iter()
+        .any(|p| p.kind == planner::SOURCE);
+    if api_queries.sources.is_empty() && !selected {
+        return BTreeMap::new();
+    }
+
+    contexts::servers(&api_queries.contexts)
+}
+
+/// Returns the versions of the API Servers of the given contexts, only fetched if the queries
+/// have 'cluster' predicates or list kinds whose API version depends on the server version.
+/// The contexts read from a data source have no API Server
+async fn versions(
+    api_queries: &ApiQueries,
+    sources: &Sources<'_>,
+) -> Result<HashMap<String, Info>> {
+    let live = sources.live(&api_queries.contexts);
+    if live.len() < api_queries.contexts.len() {
+        if let Some(q) = api_queries.clusters.first() {
+            return Err(KubesqlError::Unsupported(format!(
+                "'{}' predicates are not supported on the contexts read from a data source: {}",
+                planner::CLUSTER,
+                q
+            )));
+        }
+    }
+
+    let versioned = api_queries
+        .queries
+        .iter()
+        .chain(
+            api_queries
+                .queries
+                .iter()
+                .filter_map(|q| q.subquery.as_ref())
+                .flat_map(|s| s.queries.iter()),
+        )
+        .any(|q| {
+            matches!(
+                ResourceType::from_str(&q.kind.to_lowercase()),
+                Ok(ResourceType::EndpointSlice
+                    | ResourceType::HorizontalPodAutoscaler
+                    | ResourceType::PodDisruptionBudget)
+            )
+        });
+    if api_queries.clusters.is_empty() && !versioned {
+        return Ok(HashMap::new());
+    }
+
+    let versions = futures::future::try_join_all(live.iter().map(|ctx| async move {
+        Ok::<_, KubesqlError>((ctx.clone(), contexts::version(ctx).await?))
+    }))
+    .await?;
+
+    Ok(versions.into_iter().collect())
+}
+
+/// Runs all the given queries on the given APIs and inserts the matched resources into the printer
+pub async fn execute<'a>(
+    api_queries: &ApiQueries,
+    apis: &'a [ApiBuilder<'a>],
+    printer: &mut Printer<'a>,
+) -> Result<()> {
+    //1. Query { key: None, kind: "pod", fields: ["status", "phase"], eq: "Running", op: Eq }
+    //2. Query { key: Some(And), kind: "deployment", fields: ["metadata", "name"], eq: "my-awesome-deployment", op: Eq }
+    let joins = Joins::of(&api_queries.queries, &api_queries.projections);
+    for q in &api_queries.queries {
+        // a.k.a '--field-selector': https://v1-18.docs.kubernetes.io/docs/concepts/overview/working-with-objects/field-selectors/
+        let list_params = match q.field_selector() {
+            Some(selector) => ListParams::default().fields(&selector),
+            None => ListParams::default(),
+        };
+
+        let kind = ResourceType::from_str(&q.kind.to_lowercase())?;
+
+        let mut listed: HashSet<&str> = HashSet::new();
+        for api in apis {
+            // i.e. 'ctx_prod.pod.status.phase' does only run on 'ctx_prod'
+            if q.context.as_ref().is_some_and(|c| c != api.get_context())
+                || (q
+                    .namespace
+                    .as_ref()
+                    .is_some_and(|n| n != api.get_namespace())
+                    && !kind.is_cluster_scoped())
+            {
+                continue;
+            }
+
+            // The cluster-scoped kinds are listed once per context, without the namespace
+            let ns = match kind.is_cluster_scoped() {
+                true if !listed.insert(api.get_context()) => continue,
+                true => "",
+                false => api.get_namespace(),
+            };
+
+            // The subquery is executed first, its results are substituted into the query
+            let q = &match &q.subquery {
+                Some(subquery) => {
+                    let mut q = q.clone();
+                    q.values = execute_subquery(subquery, api).await?;
+                    q
+                }
+                None => q.clone(),
+            };
+
+            let mut o = to_objects(list_values(&kind, api, &list_params, &joins).await?)?;
+            o.items = eval::filter(q, o.items)?;
+            let matched = o.items.len();
+            if !o.items.is_empty() {
+                printer.insert(api.get_context(), ns, kind.clone(), o);
+            }
+
+            api.record(|stats| stats.matched += matched);
+
+            // Reported for the context instead of failing the query, it has none of them. The
+            // kinds joined with the resources or listed by the subquery fail the kind as well
+            let joined = joins.kinds(&kind);
+            let subquery = q
+                .subquery
+                .as_ref()
+                .and_then(|s| ResourceType::from_str(&s.projection.kind).ok());
+            let failure = std::iter::once(&kind)
+                .chain(joined.iter())
+                .chain(subquery.iter())
+                .find_map(|k| Some((k, api.failure(k)?)));
+            if let Some((failed, failure)) = failure {
+                // i.e. the nodes joined with the pods are listed once for all the namespaces
+                let ns = match failed.is_cluster_scoped() {
+                    true => "",
+                    false => ns,
+                };
+                printer.failed(api.get_context(), ns, kind.clone(), failure);
+                continue;
+            }
+
+            // we will decide according to given operator, in case if resource not found or empty
+            if matched == 0 && q.key == Some(BinaryOperator::And) {
+                return Err(KubesqlError::NoResourceFound(q.to_string()));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the statistics of the given APIs summed up per context, in FROM order
+pub fn stats(apis: &[ApiBuilder<'_>]) -> Vec<(String, Stats)> {
+    let mut stats: Vec<(String, Stats)> = Vec::new();
+    for (api, s) in apis.iter().filter_map(|api| Some((api, api.get_stats()?))) {
+        match stats.iter_mut().find(|(c, _)| c == api.get_context()) {
+            Some((_, total)) => total.add(&s),
+            None => stats.push((api.get_context().clone(), s)),
+        }
+    }
+    stats
+}
+
+/// Runs the given queries and returns the matched resources of each kind without printing them,
+/// i.e. to run an action on them. The resources are read from the data sources of the contexts
+/// instead, if any. Only the unique values of the SELECT fields are returned if distinct
+pub async fn select(api_queries: &ApiQueries, sources: &Sources<'_>) -> Result<Vec<ResultTable>> {
+    let apis = build_apis(api_queries, sources, false, None).await?;
+
+    let mut printer = Printer::builder()
+        .contexts(&api_queries.contexts)
+        .namespaces(&api_queries.namespaces)
+        .queries(api_queries.queries.as_slice())
+        .projections(api_queries.projections.as_slice())
+        .servers(servers(api_queries))
+        .distinct(api_queries.distinct);
+
+    execute(api_queries, &apis, &mut printer).await?;
+
+    Ok(printer.tables())
+}
+
+/// Reads the rows of the given local table on this machine, without any call to the clusters
+pub fn local_rows(table: LocalTable) -> std::result::Result<Vec<serde_json::Value>, ParserError> {
+    match table {
+        LocalTable::Kubeconfig => Ok(contexts::rows(&parser::parse_kubeconfig()?)),
+    }
+}
+
+/// Runs the given query on the rows of its local table and returns the matched ones with their
+/// SELECT fields, in the order of the table. Only the unique rows are returned if distinct
+pub fn select_local(
+    local: &ApiLocal,
+    rows: &[serde_json::Value],
+) -> std::result::Result<ResultTable, EvalError> {
+    let mut table = ResultTable {
+        kind: local.table.to_string(),
+        columns: local.projections.iter().map(Projection::name).collect(),
+        rows: Vec::new(),
+        failures: Vec::new(),
+    };
+
+    for row in rows {
+        if !eval::matches_all(&local.queries, row)? {
+            continue;
+        }
+        let values: Vec<String> = local
+            .projections
+            .iter()
+            .map(|p| eval::resolve_projection(p, row).unwrap_or_default())
+            .collect();
+        if !local.distinct || !table.rows.contains(&values) {
+            table.rows.push(values);
+        }
+    }
+
+    Ok(table)
+}
+
+/// Returns the SELECT field values of the resources the given subquery matches
+async fn execute_subquery(subquery: &SubQuery, api: &ApiBuilder<'_>) -> Result<Vec<String>> {
+    if subquery.queries.iter().any(|q| q.subquery.is_some()) {
+        return Err(KubesqlError::Unsupported(format!(
+            "Nested subqueries are not supported: {}",
+            subquery
+        )));
+    }
+
+    let list_params = match subquery.queries.as_slice() {
+        [q] => match q.field_selector() {
+            Some(selector) => ListParams::default().fields(&selector),
+            None => ListParams::default(),
+        },
+        _ => ListParams::default(),
+    };
+
+    let kind = ResourceType::from_str(&subquery.projection.kind)?;
+    let joins = Joins::of(
+        &subquery.queries,
+        std::slice::from_ref(&subquery.projection),
+    );
+    let objects = list_values(&kind, api, &list_params, &joins).await?;
+
+    let mut values: Vec<String> = Vec::new();
+    for object in objects {
+        if eval::matches_all(&subquery.queries, &object)? {
+            values.extend(eval::resolve_all(
+                &subquery.projection.kind,
+                &subquery.projection.fields,
+                &object,
+            ));
+        }
+    }
+
+    Ok(values)
+}
+
+/// Lists the resources of the given kind, joined with the kinds the given joins refer to
+async fn list_values(
+    kind: &ResourceType,
+    api: &ApiBuilder<'_>,
+    list_params: &ListParams,
+    joins: &Joins,
+) -> Result<Vec<serde_json::Value>> {
+    let mut values = list_kind(kind, api, list_params).await?;
+
+    if *kind == ResourceType::Pod && joins.node {
+        values = join_nodes(api, values).await?;
+    }
+    if *kind == ResourceType::Pod && joins.pdbs {
+        values = join_pdbs(api, values).await?;
+    }
+    if *kind == ResourceType::Service && joins.backends {
+        values = join_backends(api, values).await?;
+    }
+    if matches!(kind, ResourceType::ConfigMap | ResourceType::Secret) && joins.consumers {
+        values = join_consumers(api, kind, values).await?;
+    }
+    Ok(values)
+}
+
+/// Lists the resources of the given kind as JSON values through `ApiBuilder::list`, so the failures
+/// are recorded by the API instead of failing the listing
+pub(crate) async fn list_kind(
+    kind: &ResourceType,
+    api: &ApiBuilder<'_>,
+    list_params: &ListParams,
+) -> Result<Vec<serde_json::Value>> {
+    let values = match kind {
+        ResourceType::Deployment => {
+            values_of(api, kind, ApiBuilder::get_deployment, list_params).await?
+        }
+        ResourceType::Pod => values_of(api, kind, ApiBuilder::get_pod, list_params).await?,
+        ResourceType::Service => values_of(api, kind, ApiBuilder::get_service, list_params).await?,
+        ResourceType::Node => values_of(api, kind, ApiBuilder::get_node, list_params).await?,
+        ResourceType::PodMetrics => {
+            values_of(api, kind, ApiBuilder::get_podmetrics, list_params).await?
+        }
+        ResourceType::EndpointSlice => {
+            values_of(api, kind, ApiBuilder::get_endpointslice, list_params).await?
+        }
+        ResourceType::HorizontalPodAutoscaler => {
+            values_of(api, kind, ApiBuilder::get_hpa, list_params).await?
+        }
+        ResourceType::ResourceQuota => {
+            values_of(api, kind, ApiBuilder::get_resourcequota, list_params).await?
+        }
+        ResourceType::LimitRange => {
+            values_of(api, kind, ApiBuilder::get_limitrange, list_params).await?
+        }
+        ResourceType::ServiceAccount => {
+            values_of(api, kind, ApiBuilder::get_serviceaccount, list_params).await?
+        }
+        ResourceType::Role => values_of(api, kind, ApiBuilder::get_role, list_params).await?,
+        ResourceType::RoleBinding => {
+            values_of(api, kind, ApiBuilder::get_rolebinding, list_params).await?
+        }
+        ResourceType::ClusterRole => {
+            values_of(api, kind, ApiBuilder::get_clusterrole, list_params).await?
+        }
+        ResourceType::ClusterRoleBinding => {
+            values_of(api, kind, ApiBuilder::get_clusterrolebinding, list_params).await?
+        }
+        ResourceType::PersistentVolume => {
+            values_of(api, kind, ApiBuilder::get_pv, list_params).await?
+        }
+        ResourceType::Namespace => values_of(api, kind, ApiBuilder::get_ns, list_params).await?,
+        ResourceType::HelmRelease => to_values(api.list_helm_releases(kind).await?.items)?,
+        ResourceType::Application => {
+            values_of(api, kind, ApiBuilder::get_application, list_params).await?
+        }
+        ResourceType::Kustomization => {
+            values_of(api, kind, ApiBuilder::get_kustomization, list_params).await?
+        }
+        ResourceType::PersistentVolumeClaim => {
+            values_of(api, kind, ApiBuilder::get_pvc, list_params).await?
+        }
+        ResourceType::ConfigMap => {
+            values_of(api, kind, ApiBuilder::get_configmap, list_params).await?
+        }
+        ResourceType::Secret => to_values(api.list_secrets(kind, list_params).await?.items)?,
+        ResourceType::PodDisruptionBudget => {
+            values_of(api, kind, ApiBuilder::get_pdb, list_params).await?
+        }
+    };
+    Ok(values)
+}
+
+/// Lists the resources of the given kind through the given API as JSON values
+async fn values_of<'a, K>(
+    api: &'a ApiBuilder<'a>,
+    kind: &ResourceType,
+    get: fn(&'a ApiBuilder<'a>) -> &'a Api<K>,
+    list_params: &ListParams,
+) -> Result<Vec<serde_json::Value>>
+where
+    K: Resource + Clone + DeserializeOwned + Debug + Serialize,
+{
+    Ok(to_values(api.list(kind, get, list_params).await?.items)?)
+}
+
+/// The kinds joined with the matched resources, each one listed along with them
+struct Joins {
+    /// i.e. 'pod.node.metadata.labels.zone', the pods are joined with their nodes
+    node: bool,
+    /// i.e. 'service.backends.count', the services are joined with the pods they select
+    backends: bool,
+    /// i.e. 'configmap.consumers.count', the ConfigMaps and Secrets are joined with the pods
+    consumers: bool,
+    /// i.e. 'pod.blocking_pdb', the pods are joined with the PodDisruptionBudgets selecting them
+    pdbs: bool,
+}
+
+impl Joins {
+    fn of(queries: &[Query], projections: &[Projection]) -> Joins {
+        Joins {
+            node: joins(queries, projections, eval::joins_node),
+            backends: joins(queries, projections, eval::joins_backends),
+            consumers: joins(queries, projections, eval::joins_consumers),
+            pdbs: joins(queries, projections, eval::joins_pdbs),
+        }
+    }
+
+    /// Returns the kinds listed along with the given kind to join them
+    fn kinds(&self, kind: &ResourceType) -> Vec<ResourceType> {
+        match kind {
+            ResourceType::Pod => [
+                (self.node, ResourceType::Node),
+                (self.pdbs, ResourceType::PodDisruptionBudget),
+            ]
+            .iter()
+            .filter(|(joins, _)| *joins)
+            .map(|(_, kind)| kind.clone())
+            .collect(),
+            ResourceType::Service if self.backends => vec![ResourceType::Pod],
+            ResourceType::ConfigMap | ResourceType::Secret if self.consumers => {
+                vec![ResourceType::Pod]
+            }
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// Returns the kinds whose metadata only is queried, i.e. their names or labels, they are listed
+/// as PartialObjectMetadata. None of the other fields is selected, compared or joined
+fn metadata_only(api_queries: &ApiQueries) -> HashSet<ResourceType> {
+    let subqueries = api_queries
+        .queries
+        .iter()
+        .filter_map(|q| q.subquery.as_deref());
+    let queries: Vec<Query> = api_queries
+        .queries
+        .iter()
+        .chain(subqueries.clone().flat_map(|s| s.queries.iter()))
+        .cloned()
+        .collect();
+    let projections: Vec<Projection> = api_queries
+        .projections
+        .iter()
+        .chain(subqueries.map(|s| &s.projection))
+        .cloned()
+        .collect();
+
+    let kind_of = |kind: &str| ResourceType::from_str(&kind.to_lowercase()).ok();
+    let joins = Joins::of(&queries, &projections);
+    let joined: HashSet<ResourceType> = queries
+        .iter()
+        .filter_map(|q| kind_of(&q.kind))
+        .flat_map(|kind| joins.kinds(&kind))
+        .collect();
+
+    queries
+        .iter()
+        .filter_map(|q| kind_of(&q.kind))
+        .filter(|kind| kind.supports_metadata_list() && !joined.contains(kind))
+        .filter(|kind| {
+            queries
+                .iter()
+                .filter(|q| kind_of(&q.kind).as_ref() == Some(kind))
+                .all(|q| {
+                    in_metadata(&q.kind, &q.fields)
+                        && q.other.as_ref().is_none_or(|o| in_metadata(&q.kind, o))
+                })
+                && projections
+                    .iter()
+                    .filter(|p| kind_of(&p.kind).as_ref() == Some(kind))
+                    .all(|p| in_metadata(&p.kind, &p.fields))
+        })
+        .collect()
+}
+
+/// Whether the given field path is in the metadata, i.e. 'metadata.name' or the 'labels.app'
+/// shorthand of 'metadata.labels.app'
+fn in_metadata(kind: &str, fields: &[String]) -> bool {
+    fields.first().is_some_and(|f| f == "metadata")
+        || eval::shorthand(kind, fields, None) == Some("metadata")
+}
+
+/// Whether any of the given queries or SELECT fields refers to the given join
+fn joins(queries: &[Query], projections: &[Projection], join: fn(&str, &[String]) -> bool) -> bool {
+    queries
+        .iter()
+        .any(|q| join(&q.kind, &q.fields) || q.other.as_ref().is_some_and(|o| join(&q.kind, o)))
+        || projections.iter().any(|p| join(&p.kind, &p.fields))
+}
+
+/// Adds the node each pod is scheduled on as 'node', null if not scheduled yet
+async fn join_nodes(
+    api: &ApiBuilder<'_>,
+    mut pods: Vec<serde_json::Value>,
+) -> Result<Vec<serde_json::Value>> {
+    let nodes = api.nodes_by_name().await?;
+    for pod in &mut pods {
+        let node = pod["spec"]["nodeName"]
+            .as_str()
+            .and_then(|n| nodes.get(n))
+            .cloned()
+            .unwrap_or_default();
+        pod["node"] = node;
+    }
+    Ok(pods)
+}
+
+/// Adds the count of the pods the selector of each service matches as 'backends.count', and the
+/// ready ones as 'backends.ready'. The terminated pods are excluded, the services without
+/// selector have no backends, their endpoints are managed manually
+async fn join_backends(
+    api: &ApiBuilder<'_>,
+    mut services: Vec<serde_json::Value>,
+) -> Result<Vec<serde_json::Value>> {
+    let pods = api
+        .list(
+            &ResourceType::Pod,
+            ApiBuilder::get_pod,
+            &ListParams::default(),
+        )
+        .await?
+        .items;
+    let pods: Vec<&Pod> = pods
+        .iter()
+        .filter(|p| {
+            let phase = p.status.as_ref().and_then(|s| s.phase.as_deref());
+            !matches!(phase, Some("Succeeded") | Some("Failed"))
+        })
+        .collect();
+
+    for service in &mut services {
+        let selector = match service["spec"]["selector"].as_object() {
+            Some(selector) if !selector.is_empty() => selector.clone(),
+            _ => continue,
+        };
+
+        let backends: Vec<&&Pod> = pods
+            .iter()
+            .filter(|p| {
+                selector
+                    .iter()
+                    .all(|(k, v)| p.labels().get(k).map(String::as_str) == v.as_str())
+            })
+            .collect();
+        let ready = backends
+            .iter()
+            .filter(|p| {
+                p.status
+                    .as_ref()
+                    .and_then(|s| s.conditions.as_ref())
+                    .into_iter()
+                    .flatten()
+                    .any(|c| c.type_ == "Ready" && c.status == "True")
+            })
+            .count();
+        service["backends"] = serde_json::json!({ "count": backends.len(), "ready": ready });
+    }
+
+    Ok(services)
+}
+
+/// Adds the names of the PodDisruptionBudgets selecting each pod which allow no more disruption as
+/// 'blocking_pdb', comma separated, i.e. a drain would not evict the pod. Null if none blocks it
+async fn join_pdbs(
+    api: &ApiBuilder<'_>,
+    mut pods: Vec<serde_json::Value>,
+) -> Result<Vec<serde_json::Value>> {
+    let pdbs = api
+        .list(
+            &ResourceType::PodDisruptionBudget,
+            ApiBuilder::get_pdb,
+            &ListParams::default(),
+        )
+        .await?
+        .items;
+    let blocking: Vec<(String, serde_json::Value)> = to_values(pdbs)?
+        .into_iter()
+        .filter(derived::blocks_disruptions)
+        .map(|pdb| {
+            let name = pdb["metadata"]["name"].as_str().unwrap_or_default();
+            (name.to_string(), pdb["spec"]["selector"].clone())
+        })
+        .collect();
+
+    for pod in &mut pods {
+        let names: Vec<&str> = blocking
+            .iter()
+            .filter(|(_, selector)| selects(selector, &pod["metadata"]["labels"]))
+            .map(|(name, _)| name.as_str())
+            .collect();
+        pod["blocking_pdb"] = match names.is_empty() {
+            true => serde_json::Value::Null,
+            false => serde_json::Value::from(names.join(",")),
+        };
+    }
+
+    Ok(pods)
+}
+
+/// Whether the label selector matches the given labels, all of its 'matchLabels' and
+/// 'matchExpressions' have to. A missing selector matches nothing, an empty one everything
+fn selects(selector: &serde_json::Value, labels: &serde_json::Value) -> bool {
+    if selector.is_null() {
+        return false;
+    }
+
+    let label = |key: &str| labels[key].as_str();
+    let match_labels = selector["matchLabels"]
+        .as_object()
+        .into_iter()
+        .flatten()
+        .all(|(k, v)| label(k) == v.as_str());
+    let match_expressions = selector["matchExpressions"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .all(|e| {
+            let value = label(e["key"].as_str().unwrap_or_default());
+            let mut values = e["values"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|v| v.as_str());
+            match e["operator"].as_str() {
+                Some("In") => value.is_some_and(|l| values.any(|v| v == l)),
+                Some("NotIn") => value.is_none_or(|l| !values.any(|v| v == l)),
+                Some("Exists") => value.is_some(),
+                Some("DoesNotExist") => value.is_none(),
+                _ => false,
+            }
+        });
+
+    match_labels && match_expressions
+}
+
+/// Adds the count of the pods referring to each ConfigMap or Secret as 'consumers.count', by their
+/// volumes, environment variables or image pull secrets. The terminated pods are counted as well,
+/// i.e. of the jobs. The ones managed by Kubernetes or Helm have no consumers, i.e. the service
+/// account tokens, 'kube-root-ca.crt' and the Helm releases
+async fn join_consumers(
+    api: &ApiBuilder<'_>,
+    kind: &ResourceType,
+    mut objects: Vec<serde_json::Value>,
+) -> Result<Vec<serde_json::Value>> {
+    let references = match kind {
+        ResourceType::ConfigMap => CONFIGMAP_REFERENCES,
+        _ => SECRET_REFERENCES,
+    };
+
+    let pods = to_values(
+        api.list(
+            &ResourceType::Pod,
+            ApiBuilder::get_pod,
+            &ListParams::default(),
+        )
+        .await?
+        .items,
+    )?;
+    let referred: Vec<HashSet<String>> = pods
+        .iter()
+        .map(|pod| {
+            references
+                .iter()
+                .flat_map(|path| {
+                    let fields: Vec<String> = path.split('.').map(String::from).collect();
+                    eval::resolve_all("pod", &fields, pod)
+                })
+                .collect()
+        })
+        .collect();
+
+    for object in &mut objects {
+        let managed = object["metadata"]["labels"]["owner"] == "helm"
+            || object["type"] == "kubernetes.io/service-account-token"
+            || (*kind == ResourceType::ConfigMap
+                && object["metadata"]["name"] == "kube-root-ca.crt");
+        if managed {
+            continue;
+        }
+
+        let name = object["metadata"]["name"].as_str().unwrap_or_default();
+        let count = referred.iter().filter(|r| r.contains(name)).count();
+        object["consumers"] = serde_json::json!({ "count": count });
+    }
+
+    Ok(objects)
+}
+
+/// Returns the given objects as a list to filter and print, i.e. the joined ones
+fn to_objects(values: Vec<serde_json::Value>) -> serde_json::Result<ObjectList<DynamicObject>> {
+    Ok(ObjectList {
+        types: Default::default(),
+        metadata: Default::default(),
+        items: values
+            .into_iter()
+            .map(serde_json::from_value)
+            .collect::<serde_json::Result<_>>()?,
+    })
+}
+
+fn to_values<K: Serialize>(items: Vec<K>) -> serde_json::Result<Vec<serde_json::Value>> {
+    items.iter().map(serde_json::to_value).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::ApiStatement;
+    use crate::planner::PlanContext;
+
+    fn metadata_only_of(sql: &str) -> HashSet<ResourceType> {
+        match parser::parse_sql(sql, PlanContext::default()) {
+            Ok(ApiStatement::Query(q)) => metadata_only(&q),
+            other => panic!("not a query: {:?}", other.err()),
+        }
+    }
+
+    #[test]
+    fn metadata_only_of_the_kinds_whose_metadata_only_is_queried_and_not_joined() {
+        let kinds = metadata_only_of(
+            "SELECT *, pod.metadata.ownerReferences.kind FROM ctx WHERE pod.labels.app = 'api'",
+        );
+        assert_eq!(kinds, vec![ResourceType::Pod].into_iter().collect());
+
+        let kinds = metadata_only_of(
+            "SELECT * FROM ctx WHERE pod.metadata.name = 'api' AND deployment.spec.replicas > 1",
+        );
+        assert_eq!(kinds, vec![ResourceType::Pod].into_iter().collect());
+
+        assert!(
+            metadata_only_of("SELECT * FROM ctx WHERE pod.status.phase = 'Running'").is_empty()
+        );
+        assert!(metadata_only_of(
+            "SELECT *, pod.spec.nodeName FROM ctx WHERE pod.metadata.name = 'api'"
+        )
+        .is_empty());
+        assert!(metadata_only_of(
+            "SELECT * FROM ctx WHERE service.backends.count = 0 AND pod.metadata.name = 'api'"
+        )
+        .is_empty());
+        assert!(
+            metadata_only_of("SELECT * FROM ctx WHERE podmetrics.metadata.name = 'api'").is_empty()
+        );
+    }
+}
@@ -0,0 +1,171 @@
+// Copyright (c) 2021 Dentrax
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software is furnished to do so,
+// subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+use crate::printer::ResultTable;
+use rusqlite::Connection;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum ExportError {
+    #[error("Unable to export the results to SQLite database '{0}': {1}")]
+    Sqlite(String, rusqlite::Error),
+
+    #[cfg(feature = "parquet")]
+    #[error("Unable to export the results to Parquet file '{0}': {1}")]
+    Parquet(String, parquet::errors::ParquetError),
+}
+
+/// The target file to export the results into, i.e. 'sqlite:results.db'
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Export {
+    /// A SQLite database, one table per kind
+    Sqlite(PathBuf),
+    /// A Parquet file, the rows of all kinds with a 'kind' column
+    #[cfg(feature = "parquet")]
+    Parquet(PathBuf),
+}
+
+impl fmt::Display for Export {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Export::Sqlite(path) => write!(f, "sqlite:{}", path.display()),
+            #[cfg(feature = "parquet")]
+            Export::Parquet(path) => write!(f, "parquet:{}", path.display()),
+        }
+    }
+}
+
+impl FromStr for Export {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Export, Self::Err> {
+        match input.split_once(':') {
+            Some(("sqlite", path)) if !path.is_empty() => Ok(Export::Sqlite(PathBuf::from(path))),
+            #[cfg(feature = "parquet")]
+            Some(("parquet", path)) if !path.is_empty() => Ok(Export::Parquet(PathBuf::from(path))),
+            #[cfg(not(feature = "parquet"))]
+            Some(("parquet", _)) => Err(
+                "Parquet export is not supported by this build, it requires the 'parquet' feature"
+                    .to_string(),
+            ),
+            _ => Err(format!(
+                "Unexpected export target: {}, must be in FORMAT:PATH format, i.e. 'sqlite:results.db'",
+                input
+            )),
+        }
+    }
+}
+
+/// Writes the given tables into the export target, the existing tables of the same kinds are replaced
+pub fn export(export: &Export, tables: &[ResultTable]) -> Result<(), ExportError> {
+    match export {
+        Export::Sqlite(path) => export_sqlite(path, tables)
+            .map_err(|e| ExportError::Sqlite(path.display().to_string(), e)),
+        #[cfg(feature = "parquet")]
+        Export::Parquet(path) => export_parquet(path, tables)
+            .map_err(|e| ExportError::Parquet(path.display().to_string(), e)),
+    }
+}
+
+fn export_sqlite(path: &Path, tables: &[ResultTable]) -> rusqlite::Result<()> {
+    let mut conn = Connection::open(path)?;
+    let tx = conn.transaction()?;
+
+    for table in tables {
+        let name = quote(&table.kind);
+        let columns = table
+            .columns
+            .iter()
+            .map(|c| format!("{} TEXT", quote(c)))
+            .collect::<Vec<String>>();
+
+        tx.execute(&format!("DROP TABLE IF EXISTS {}", name), [])?;
+        tx.execute(
+            &format!("CREATE TABLE {} ({})", name, columns.join(", ")),
+            [],
+        )?;
+
+        let placeholders = vec!["?"; table.columns.len()].join(", ");
+        let mut insert = tx.prepare(&format!("INSERT INTO {} VALUES ({})", name, placeholders))?;
+        for row in &table.rows {
+            insert.execute(rusqlite::params_from_iter(row))?;
+        }
+    }
+
+    tx.commit()
+}
+
+/// Writes the rows of all the tables into a single file, the columns missing in a kind are null
+#[cfg(feature = "parquet")]
+fn export_parquet(path: &Path, tables: &[ResultTable]) -> parquet::errors::Result<()> {
+    use arrow_array::{ArrayRef, RecordBatch, StringArray};
+    use arrow_schema::{DataType, Field, Schema};
+    use parquet::arrow::ArrowWriter;
+    use std::sync::Arc;
+
+    let mut columns = vec!["kind".to_string()];
+    for table in tables {
+        for c in &table.columns {
+            if !columns.contains(c) {
+                columns.push(c.clone());
+            }
+        }
+    }
+
+    let schema = Arc::new(Schema::new(
+        columns
+            .iter()
+            .map(|c| Field::new(c, DataType::Utf8, true))
+            .collect::<Vec<Field>>(),
+    ));
+
+    let file = std::fs::File::create(path)?;
+    let mut writer = ArrowWriter::try_new(file, schema.clone(), None)?;
+
+    for table in tables {
+        let arrays = columns
+            .iter()
+            .map(|c| {
+                let values: Vec<Option<&str>> = match table.columns.iter().position(|t| t == c) {
+                    Some(i) => table
+                        .rows
+                        .iter()
+                        .map(|r| r.get(i).map(String::as_str))
+                        .collect(),
+                    None if c == "kind" => vec![Some(table.kind.as_str()); table.rows.len()],
+                    None => vec![None; table.rows.len()],
+                };
+                Arc::new(StringArray::from(values)) as ArrayRef
+            })
+            .collect::<Vec<ArrayRef>>();
+
+        writer.write(&RecordBatch::try_new(schema.clone(), arrays)?)?;
+    }
+
+    writer.close()?;
+    Ok(())
+}
+
+/// Quotes the given identifier, i.e. 'status.phase' => '"status.phase"'
+fn quote(identifier: &str) -> String {
+    format!("\"{}\"", identifier.replace('"', "\"\""))
+}
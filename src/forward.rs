@@ -0,0 +1,188 @@
+// Copyright (c) 2021 Dentrax
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software is furnished to do so,
+// subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+use crate::api_builder::ApiBuilder;
+use crate::error::{KubesqlError, Result};
+use crate::printer::ResultTable;
+use k8s_openapi::api::core::v1::Pod;
+use k8s_openapi::apimachinery::pkg::util::intstr::IntOrString;
+use kube::api::ListParams;
+use kube::{Api, ResourceExt};
+use std::collections::BTreeMap;
+use std::fmt;
+use tokio::net::{TcpListener, TcpStream};
+
+/// The single matched resource to forward the port of
+#[derive(Debug, Clone)]
+pub struct Target {
+    pub kind: String,
+    pub context: String,
+    pub namespace: String,
+    pub name: String,
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}/{}/{}/{}",
+            self.context, self.namespace, self.kind, self.name
+        )
+    }
+}
+
+/// Returns the matched resources of the given tables
+pub fn targets(tables: &[ResultTable]) -> Vec<Target> {
+    let mut targets = Vec::new();
+    for table in tables {
+        for row in &table.rows {
+            if let [context, namespace, name, ..] = row.as_slice() {
+                targets.push(Target {
+                    kind: table.kind.clone(),
+                    context: context.clone(),
+                    namespace: namespace.clone(),
+                    name: name.clone(),
+                });
+            }
+        }
+    }
+    targets
+}
+
+/// Returns the pod to forward to and its port. A service or deployment is resolved to its first
+/// running pod as kubectl does, the service port to its target port.
+pub async fn resolve(target: &Target, api: &ApiBuilder<'_>, port: u16) -> Result<(Pod, u16)> {
+    let (selector, target_port) = match target.kind.as_str() {
+        "pod" => return Ok((api.get_pod().get(&target.name).await?, port)),
+        "service" => {
+            let spec = api.get_service().get(&target.name).await?.spec;
+            let spec = spec.unwrap_or_default();
+            let target_port = spec
+                .ports
+                .unwrap_or_default()
+                .into_iter()
+                .find(|p| p.port == port as i32)
+                .and_then(|p| p.target_port);
+            (spec.selector.unwrap_or_default(), target_port)
+        }
+        "deployment" => {
+            let spec = api.get_deployment().get(&target.name).await?.spec;
+            let selector = spec.and_then(|s| s.selector.match_labels);
+            (selector.unwrap_or_default(), None)
+        }
+        kind => {
+            return Err(KubesqlError::Unsupported(format!(
+                "Only pods, services and deployments can be port-forwarded: {}",
+                kind
+            )))
+        }
+    };
+
+    let pod = running_pod(api.get_pod(), &selector)
+        .await?
+        .ok_or_else(|| KubesqlError::NoResourceFound(format!("running pod of {}", target)))?;
+
+    let port = match target_port {
+        Some(IntOrString::Int(p)) => p as u16,
+        // i.e. 'targetPort: http', the container port with the same name
+        Some(IntOrString::String(name)) => pod
+            .spec
+            .iter()
+            .flat_map(|s| s.containers.iter())
+            .flat_map(|c| c.ports.iter().flatten())
+            .find(|p| p.name.as_deref() == Some(name.as_str()))
+            .map(|p| p.container_port as u16)
+            .ok_or_else(|| {
+                KubesqlError::NoResourceFound(format!("port '{}' of {}", name, target))
+            })?,
+        None => port,
+    };
+
+    Ok((pod, port))
+}
+
+/// Returns the first running pod matching the given labels, by name
+async fn running_pod(api: &Api<Pod>, selector: &BTreeMap<String, String>) -> Result<Option<Pod>> {
+    // An empty selector would match all the pods of the namespace
+    if selector.is_empty() {
+        return Ok(None);
+    }
+
+    let labels = selector
+        .iter()
+        .map(|(k, v)| format!("{}={}", k, v))
+        .collect::<Vec<String>>()
+        .join(",");
+    let mut pods = api
+        .list(&ListParams::default().labels(&labels))
+        .await?
+        .items
+        .into_iter()
+        .filter(|p| {
+            p.status
+                .as_ref()
+                .and_then(|s| s.phase.as_deref())
+                .is_some_and(|phase| phase == "Running")
+        })
+        .collect::<Vec<Pod>>();
+
+    pods.sort_by_key(|p| p.name_any());
+    Ok(pods.into_iter().next())
+}
+
+/// Forwards the connections accepted on the given local address to the port of the pod,
+/// until interrupted. The failing connections are warned and closed.
+pub async fn forward(api: Api<Pod>, pod: String, address: String, port: u16) -> Result<()> {
+    let listener = TcpListener::bind(&address)
+        .await
+        .map_err(|e| KubesqlError::Serve(address.clone(), e))?;
+
+    loop {
+        let (connection, _) = tokio::select! {
+            accepted = listener.accept() => {
+                accepted.map_err(|e| KubesqlError::Serve(address.clone(), e))?
+            }
+            _ = tokio::signal::ctrl_c() => return Ok(()),
+        };
+
+        let (api, pod) = (api.clone(), pod.clone());
+        tokio::spawn(async move {
+            if let Err(e) = connect(connection, &api, &pod, port).await {
+                tracing::warn!("{}", e);
+            }
+        });
+    }
+}
+
+/// Copies the data between the given connection and the port of the pod until either is closed
+async fn connect(mut connection: TcpStream, api: &Api<Pod>, pod: &str, port: u16) -> Result<()> {
+    let mut forwarder = api.portforward(pod, &[port]).await?;
+    let mut upstream = forwarder
+        .take_stream(port)
+        .ok_or_else(|| KubesqlError::Forward(pod.to_string(), "no stream".to_string()))?;
+
+    tokio::io::copy_bidirectional(&mut connection, &mut upstream)
+        .await
+        .map_err(|e| KubesqlError::Forward(pod.to_string(), e.to_string()))?;
+
+    drop(upstream);
+    forwarder
+        .join()
+        .await
+        .map_err(|e| KubesqlError::Forward(pod.to_string(), e.to_string()))
+}
@@ -0,0 +1,193 @@
+// Copyright (c) 2021 Dentrax
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software is furnished to do so,
+// subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+use crate::images::Image;
+
+/// A function applied to the values of a field, i.e. 'image_registry(pod.spec.containers.image)'
+pub struct Function {
+    pub name: &'static str,
+    /// The number of the literal arguments after the field, i.e. 2 of "split_part(x, '/', 1)"
+    pub args: usize,
+    /// Whether any number of the literal arguments is accepted, at least args
+    pub variadic: bool,
+    /// Returns None if the value has no result, it is NULL then
+    pub call: fn(&str, &[String]) -> Option<String>,
+}
+
+/// The supported functions, a new one only needs to be registered here
+static FUNCTIONS: &[Function] = &[
+    Function {
+        name: "lower",
+        args: 0,
+        variadic: false,
+        call: |v, _| Some(v.to_lowercase()),
+    },
+    Function {
+        name: "upper",
+        args: 0,
+        variadic: false,
+        call: |v, _| Some(v.to_uppercase()),
+    },
+    Function {
+        name: "len",
+        args: 0,
+        variadic: false,
+        call: |v, _| Some(v.chars().count().to_string()),
+    },
+    Function {
+        name: "split_part",
+        args: 2,
+        variadic: false,
+        call: split_part,
+    },
+    Function {
+        name: "concat",
+        args: 1,
+        variadic: true,
+        call: |v, args| Some(format!("{}{}", v, args.concat())),
+    },
+    Function {
+        name: "starts_with",
+        args: 1,
+        variadic: false,
+        call: |v, args| Some(v.starts_with(args[0].as_str()).to_string()),
+    },
+    Function {
+        name: "image_registry",
+        args: 0,
+        variadic: false,
+        call: |v, _| Some(Image::new(v).registry()),
+    },
+    Function {
+        name: "image_repository",
+        args: 0,
+        variadic: false,
+        call: |v, _| Some(Image::new(v).repository),
+    },
+    Function {
+        name: "image_tag",
+        args: 0,
+        variadic: false,
+        call: |v, _| Image::new(v).tag,
+    },
+    Function {
+        name: "image_digest",
+        args: 0,
+        variadic: false,
+        call: |v, _| Image::new(v).digest,
+    },
+    Function {
+        name: "taint",
+        args: 0,
+        variadic: false,
+        call: |v, _| taint(v),
+    },
+    Function {
+        name: "tolerates",
+        args: 1,
+        variadic: false,
+        call: tolerates,
+    },
+];
+
+/// Returns the function of the given name, case-insensitive
+pub fn find(name: &str) -> Option<&'static Function> {
+    FUNCTIONS.iter().find(|f| f.name.eq_ignore_ascii_case(name))
+}
+
+/// The given part of the value split by the delimiter, 1-based, empty if out of range as in PostgreSQL
+fn split_part(value: &str, args: &[String]) -> Option<String> {
+    let n = args[1].parse::<usize>().ok().filter(|n| *n > 0)?;
+    Some(
+        value
+            .split(args[0].as_str())
+            .nth(n - 1)
+            .unwrap_or_default()
+            .to_string(),
+    )
+}
+
+/// The taint as given to 'kubectl taint', i.e. 'dedicated=gpu:NoSchedule'
+fn taint(value: &str) -> Option<String> {
+    let taint: serde_json::Value = serde_json::from_str(value).ok()?;
+    let key = taint["key"].as_str()?;
+    let effect = taint["effect"].as_str().unwrap_or_default();
+    match taint["value"].as_str().filter(|v| !v.is_empty()) {
+        Some(v) => Some(format!("{}={}:{}", key, v, effect)),
+        None => Some(format!("{}:{}", key, effect)),
+    }
+}
+
+/// Whether the toleration tolerates the taint given as 'key[=value][:effect]', with the
+/// semantics of the scheduler: an empty effect of the toleration matches all the effects, an
+/// empty key with 'Exists' all the keys, and 'Exists' all the values
+fn tolerates(value: &str, args: &[String]) -> Option<String> {
+    let toleration: serde_json::Value = serde_json::from_str(value).ok()?;
+    if !toleration.is_object() {
+        return None;
+    }
+
+    let (taint, effect) = match args[0].rsplit_once(':') {
+        Some((taint, effect)) => (taint, Some(effect)),
+        None => (args[0].as_str(), None),
+    };
+    let (key, taint_value) = taint.split_once('=').unwrap_or((taint, ""));
+    let field = |f: &str| toleration[f].as_str().unwrap_or_default().to_string();
+    let exists = field("operator") == "Exists";
+
+    let tolerated = (field("effect").is_empty() || effect.is_none_or(|e| e == field("effect")))
+        && ((field("key").is_empty() && exists) || field("key") == key)
+        && (exists || field("value") == taint_value);
+    Some(tolerated.to_string())
+}
+
+/// A planned function call with its literal arguments, i.e. "split_part(x, '/', 1)"
+#[derive(Debug, Clone)]
+pub struct Call {
+    pub name: String,
+    pub args: Vec<String>,
+}
+
+impl Call {
+    /// Applies the function to the given value
+    pub fn apply(&self, value: &str) -> Option<String> {
+        find(&self.name).and_then(|f| (f.call)(value, &self.args))
+    }
+}
+
+/// Applies the given calls from the innermost one, i.e. 'lower(image_registry(x))'
+pub fn apply(calls: &[Call], value: &str) -> Option<String> {
+    let mut value = value.to_string();
+    for call in calls {
+        value = call.apply(&value)?;
+    }
+    Some(value)
+}
+
+/// Wraps the given field into the given calls, i.e. 'lower(image_registry(spec.image))'
+pub fn display(calls: &[Call], field: &str) -> String {
+    calls.iter().fold(field.to_string(), |inner, call| {
+        let args = call
+            .args
+            .iter()
+            .map(|a| format!(", '{}'", a))
+            .collect::<String>();
+        format!("{}({}{})", call.name, inner, args)
+    })
+}
@@ -0,0 +1,244 @@
+// Copyright (c) 2021 Dentrax
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software is furnished to do so,
+// subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+use base64::Engine;
+use flate2::read::GzDecoder;
+use k8s_openapi::api::core::v1::{ConfigMap, Secret};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::{ObjectMeta, Time};
+use kube::api::DynamicObject;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::Read;
+use thiserror::Error;
+
+/// The label of the Secrets and ConfigMaps Helm stores the releases in
+pub const OWNER_LABEL: &str = "owner=helm";
+
+/// The magic header of gzip, Helm compresses the releases since v3
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b, 0x08];
+
+#[derive(Error, Debug)]
+pub enum HelmError {
+    #[error("'{0}' has no release data")]
+    Missing(String),
+
+    #[error("Unable to decode the release of '{0}': {1}")]
+    Decode(String, String),
+}
+
+/// A Helm release as the 'helmrelease' pseudo-kind, the latest revision of each release
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HelmRelease {
+    /// The name and namespace of the release, created at its first deployment
+    pub metadata: ObjectMeta,
+    pub name: String,
+    pub namespace: String,
+    /// The revision of the release, increased on each upgrade and rollback
+    pub revision: i64,
+    /// i.e. 'deployed', 'failed' or 'pending-upgrade'
+    pub status: String,
+    /// The name of the chart, i.e. 'ingress-nginx'
+    pub chart: String,
+    /// The version of the chart, i.e. '4.8.0'
+    pub version: String,
+    /// The version of the application of the chart, i.e. '1.9.0'
+    pub app_version: Option<String>,
+    /// The time of the last deployment
+    pub updated: Option<Time>,
+    pub description: Option<String>,
+    /// The kind the release is stored in, i.e. 'Secret' or 'ConfigMap'
+    pub storage: String,
+    /// The keys of the values given on install or upgrade, the defaults of the chart are not
+    /// included. Their values are redacted, they often hold passwords and tokens
+    pub values: Option<serde_json::Value>,
+}
+
+/// The release as stored by Helm, only the fields of HelmRelease are decoded
+#[derive(Deserialize)]
+struct Release {
+    name: String,
+    namespace: String,
+    version: i64,
+    info: Option<ReleaseInfo>,
+    chart: Option<Chart>,
+    config: Option<serde_json::Value>,
+}
+
+#[derive(Default, Deserialize)]
+struct ReleaseInfo {
+    first_deployed: Option<String>,
+    last_deployed: Option<String>,
+    description: Option<String>,
+    status: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Chart {
+    metadata: Option<ChartMetadata>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ChartMetadata {
+    name: String,
+    version: String,
+    app_version: Option<String>,
+}
+
+/// Decodes the releases stored in the given Secrets and ConfigMaps, keeping only the latest
+/// revision of each. The ones that can not be decoded are skipped with a warning
+pub fn releases(secrets: Vec<Secret>, config_maps: Vec<ConfigMap>) -> Vec<DynamicObject> {
+    let secrets = secrets.into_iter().map(|s| {
+        let name = s.metadata.name.unwrap_or_default();
+        let data = s.data.and_then(|mut d| d.remove("release")).map(|b| b.0);
+        ("Secret", name, data)
+    });
+    let config_maps = config_maps.into_iter().map(|c| {
+        let name = c.metadata.name.unwrap_or_default();
+        let data = c
+            .data
+            .and_then(|mut d| d.remove("release"))
+            .map(String::into_bytes);
+        ("ConfigMap", name, data)
+    });
+
+    // Each revision is stored separately, i.e. 'sh.helm.release.v1.ingress-nginx.v3'
+    let mut latest: BTreeMap<(String, String), HelmRelease> = BTreeMap::new();
+    for (storage, name, data) in secrets.chain(config_maps) {
+        let release = match decode(&name, data) {
+            Ok(release) => to_helm_release(release, storage),
+            Err(e) => {
+                tracing::warn!("Skipping Helm release: {}", e);
+                continue;
+            }
+        };
+
+        let key = (release.namespace.clone(), release.name.clone());
+        match latest.get(&key) {
+            Some(l) if l.revision >= release.revision => {}
+            _ => {
+                latest.insert(key, release);
+            }
+        }
+    }
+
+    latest
+        .into_values()
+        .filter_map(|r| serde_json::to_value(r).ok())
+        .filter_map(|v| serde_json::from_value(v).ok())
+        .collect()
+}
+
+/// The release data is the base64 of the gzipped JSON, on top of the base64 of the Secret data
+fn decode(name: &str, data: Option<Vec<u8>>) -> Result<Release, HelmError> {
+    let data = data.ok_or_else(|| HelmError::Missing(name.to_string()))?;
+    let err = |e: String| HelmError::Decode(name.to_string(), e);
+
+    let bytes = base64::engine::general_purpose::STANDARD
+        .decode(data)
+        .map_err(|e| err(e.to_string()))?;
+    let json = match bytes.starts_with(GZIP_MAGIC) {
+        true => {
+            let mut json = Vec::new();
+            GzDecoder::new(bytes.as_slice())
+                .read_to_end(&mut json)
+                .map_err(|e| err(e.to_string()))?;
+            json
+        }
+        false => bytes,
+    };
+
+    serde_json::from_slice(&json).map_err(|e| err(e.to_string()))
+}
+
+fn to_helm_release(release: Release, storage: &str) -> HelmRelease {
+    let info = release.info.unwrap_or_default();
+    let chart = release.chart.and_then(|c| c.metadata);
+    let time = |t: Option<String>| t.and_then(|t| serde_json::from_value(t.into()).ok());
+
+    HelmRelease {
+        metadata: ObjectMeta {
+            name: Some(release.name.clone()),
+            namespace: Some(release.namespace.clone()),
+            creation_timestamp: time(info.first_deployed),
+            ..Default::default()
+        },
+        name: release.name,
+        namespace: release.namespace,
+        revision: release.version,
+        status: info.status.unwrap_or_default(),
+        chart: chart.as_ref().map(|c| c.name.clone()).unwrap_or_default(),
+        version: chart
+            .as_ref()
+            .map(|c| c.version.clone())
+            .unwrap_or_default(),
+        app_version: chart.and_then(|c| c.app_version),
+        updated: time(info.last_deployed),
+        description: info.description,
+        storage: storage.to_string(),
+        values: release.config.map(redact),
+    }
+}
+
+/// Redacts the values of the given release read from a data source, i.e. a snapshot
+pub fn redacted(mut release: DynamicObject) -> DynamicObject {
+    if let Some(values) = release.data.get_mut("values") {
+        *values = redact(values.take());
+    }
+    release
+}
+
+/// Redacts the leaf values of the given release values, only their keys can be queried as the ones
+/// of the Secrets. Neither are they printed nor saved into the snapshots. The daemon does not
+/// watch the releases, and it caches the Secrets of Helm with their values redacted
+fn redact(value: serde_json::Value) -> serde_json::Value {
+    match value {
+        serde_json::Value::Object(map) => {
+            serde_json::Value::Object(map.into_iter().map(|(k, v)| (k, redact(v))).collect())
+        }
+        serde_json::Value::Array(items) => {
+            serde_json::Value::Array(items.into_iter().map(redact).collect())
+        }
+        serde_json::Value::Null => serde_json::Value::Null,
+        _ => serde_json::Value::String(String::new()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redacts_the_values_but_their_keys() {
+        let values = serde_json::json!({
+            "auth": { "password": "secret", "enabled": true },
+            "hosts": ["a.example.com"],
+            "tls": null,
+        });
+        assert_eq!(
+            redact(values),
+            serde_json::json!({
+                "auth": { "password": "", "enabled": "" },
+                "hosts": [""],
+                "tls": null,
+            })
+        );
+    }
+}
@@ -0,0 +1,152 @@
+// Copyright (c) 2021 Dentrax
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software is furnished to do so,
+// subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+use k8s_openapi::chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::Duration;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum HistoryError {
+    #[error("Unable to read the history '{0}': {1}")]
+    Read(String, std::io::Error),
+
+    #[error("Unable to write the history '{0}': {1}")]
+    Write(String, std::io::Error),
+
+    #[error("Invalid history entry at line {0}: {1}")]
+    Invalid(usize, serde_json::Error),
+
+    #[error("History entry not found: {0}")]
+    NotFound(usize),
+}
+
+/// An executed query, one line of the history file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    pub timestamp: DateTime<Utc>,
+    /// The query with the parameters bound, so it can be re-run as is
+    pub query: String,
+    pub contexts: Vec<String>,
+    /// The number of the matched resources or result rows
+    pub rows: usize,
+    pub duration_ms: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl Entry {
+    pub fn new(query: &str, contexts: Vec<String>) -> Entry {
+        Entry {
+            timestamp: Utc::now(),
+            query: query.to_string(),
+            contexts,
+            rows: 0,
+            duration_ms: 0,
+            error: None,
+        }
+    }
+
+    /// Set the outcome of the query, the number of rows or the error
+    pub fn finish<E: std::fmt::Display>(
+        mut self,
+        duration: Duration,
+        result: &Result<usize, E>,
+    ) -> Entry {
+        self.duration_ms = duration.as_millis() as u64;
+        match result {
+            Ok(rows) => self.rows = *rows,
+            Err(e) => self.error = Some(e.to_string()),
+        }
+        self
+    }
+}
+
+/// The history file, '~/.kubesql/history.jsonl'
+pub fn path() -> PathBuf {
+    let home = std::env::var_os("HOME").unwrap_or_default();
+    PathBuf::from(home).join(".kubesql").join("history.jsonl")
+}
+
+/// Appends the given entry to the history file
+pub fn record(entry: &Entry) -> Result<(), HistoryError> {
+    let path = path();
+    let file = path.display().to_string();
+
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| HistoryError::Write(file.clone(), e))?;
+    }
+
+    serde_json::to_string(entry)
+        .map_err(std::io::Error::from)
+        .and_then(|line| {
+            let mut f = OpenOptions::new().create(true).append(true).open(&path)?;
+            writeln!(f, "{}", line)
+        })
+        .map_err(|e| HistoryError::Write(file, e))
+}
+
+/// Returns all the entries, the oldest first. The ID of an entry is its position, starting from 1
+pub fn load() -> Result<Vec<Entry>, HistoryError> {
+    let path = path();
+    let content = match std::fs::read_to_string(&path) {
+        Ok(c) => c,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(HistoryError::Read(path.display().to_string(), e)),
+    };
+
+    content
+        .lines()
+        .enumerate()
+        .filter(|(_, l)| !l.trim().is_empty())
+        .map(|(i, l)| serde_json::from_str(l).map_err(|e| HistoryError::Invalid(i + 1, e)))
+        .collect()
+}
+
+/// Returns the query of the entry with the given ID
+pub fn find(id: usize) -> Result<String, HistoryError> {
+    load()?
+        .into_iter()
+        .nth(id.wrapping_sub(1))
+        .map(|e| e.query)
+        .ok_or(HistoryError::NotFound(id))
+}
+
+/// Returns the last entries with their IDs, whose query or contexts contain the given text case-insensitively
+pub fn search(text: Option<&str>, limit: usize) -> Result<Vec<(usize, Entry)>, HistoryError> {
+    let text = text.map(str::to_lowercase);
+    let mut entries = load()?
+        .into_iter()
+        .enumerate()
+        .map(|(i, e)| (i + 1, e))
+        .filter(|(_, e)| {
+            text.as_ref().is_none_or(|t| {
+                e.query.to_lowercase().contains(t)
+                    || e.contexts.iter().any(|c| c.to_lowercase().contains(t))
+            })
+        })
+        .collect::<Vec<(usize, Entry)>>();
+
+    let skip = entries.len().saturating_sub(limit);
+    entries.drain(..skip);
+    Ok(entries)
+}
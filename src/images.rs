@@ -0,0 +1,150 @@
+// Copyright (c) 2021 Dentrax
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software is furnished to do so,
+// subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+use crate::api_builder::ApiBuilder;
+use crate::error::Result;
+use crate::eval;
+use crate::parser::ResourceType;
+use crate::source::Sources;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// A container image in use, with the contexts and namespaces running it
+#[derive(Debug, Default, Serialize)]
+pub struct Image {
+    pub image: String,
+    pub repository: String,
+    pub tag: Option<String>,
+    /// The digest of the reference, otherwise the one of the pulled image if known
+    pub digest: Option<String>,
+    /// The 'context/namespace' pairs running the image
+    pub locations: BTreeSet<String>,
+    pub pods: usize,
+}
+
+impl Image {
+    /// Parses the given image reference, the tag is 'latest' if neither tag nor digest is given
+    pub fn new(image: &str) -> Image {
+        let (name, digest) = match image.split_once('@') {
+            Some((name, digest)) => (name, Some(digest.to_string())),
+            None => (image, None),
+        };
+
+        // The tag follows the last ':' after the registry, i.e. 'registry:5000/app:1.0'
+        let (repository, tag) = match name.rsplit_once(':') {
+            Some((repository, tag)) if !tag.contains('/') => (repository, Some(tag.to_string())),
+            _ => (name, None),
+        };
+
+        Image {
+            image: image.to_string(),
+            repository: repository.to_string(),
+            tag: match (&tag, &digest) {
+                (None, None) => Some("latest".to_string()),
+                _ => tag,
+            },
+            digest,
+            ..Default::default()
+        }
+    }
+
+    /// The registry of the repository, 'docker.io' if not given, i.e. 'registry.internal:5000'
+    pub fn registry(&self) -> String {
+        match self.repository.split_once('/') {
+            Some((registry, _))
+                if registry.contains('.') || registry.contains(':') || registry == "localhost" =>
+            {
+                registry.to_string()
+            }
+            _ => "docker.io".to_string(),
+        }
+    }
+}
+
+/// Lists the unique images of the containers, init containers included, of all the pods
+/// in the given contexts and namespaces, sorted by image
+pub async fn inventory(
+    contexts: &[String],
+    namespaces: &[String],
+    sources: &Sources<'_>,
+) -> Result<Vec<Image>> {
+    let mut images: BTreeMap<String, Image> = BTreeMap::new();
+
+    for ctx in contexts {
+        for ns in namespaces {
+            let api = ApiBuilder::builder()
+                .context(ctx.clone())
+                .namespace(ns.clone())
+                .source(sources.get(ctx))
+                .build()
+                .await?;
+
+            let kind = ResourceType::Pod;
+            let pods = api
+                .list(&kind, ApiBuilder::get_pod, &Default::default())
+                .await?;
+
+            for pod in pods.items {
+                let object = serde_json::to_value(&pod)?;
+                let mut seen: BTreeSet<String> = BTreeSet::new();
+
+                for containers in ["containers", "initContainers"] {
+                    let statuses = match containers {
+                        "containers" => "containerStatuses",
+                        _ => "initContainerStatuses",
+                    };
+                    let digests = resolve(&object, &["status", statuses, "name"])
+                        .into_iter()
+                        .zip(resolve(&object, &["status", statuses, "imageID"]))
+                        .collect::<BTreeMap<String, String>>();
+
+                    for (name, image) in resolve(&object, &["spec", containers, "name"])
+                        .into_iter()
+                        .zip(resolve(&object, &["spec", containers, "image"]))
+                    {
+                        let entry = images
+                            .entry(image.clone())
+                            .or_insert_with(|| Image::new(&image));
+                        if entry.digest.is_none() {
+                            // i.e. 'docker-pullable://nginx@sha256:...'
+                            entry.digest = digests
+                                .get(&name)
+                                .and_then(|id| id.split_once('@'))
+                                .map(|(_, digest)| digest.to_string());
+                        }
+                        entry.locations.insert(format!("{}/{}", ctx, ns));
+                        if seen.insert(image) {
+                            entry.pods += 1;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(images.into_values().collect())
+}
+
+fn resolve(object: &serde_json::Value, fields: &[&str]) -> Vec<String> {
+    let fields = fields
+        .iter()
+        .map(|f| f.to_string())
+        .collect::<Vec<String>>();
+    eval::resolve_all(&ResourceType::Pod.to_string(), &fields, object)
+}
@@ -0,0 +1,150 @@
+// Copyright (c) 2021 Dentrax
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software is furnished to do so,
+// subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum LibraryError {
+    #[error("Unable to read the library '{0}': {1}")]
+    Read(String, std::io::Error),
+
+    #[error("No query of the library matches the given names or tags")]
+    NoMatch,
+
+    #[error("{0} of {1} library queries failed")]
+    Failed(usize, usize),
+}
+
+/// A query of the library, read from a '.sql' file. Its leading comments are its front matter,
+/// i.e. '-- tags: security, prod' and '-- description: The pods running as root'
+#[derive(Debug, Clone, Serialize)]
+pub struct LibraryQuery {
+    /// The path of the file relative to the library, without the extension, i.e. 'security/root-pods'
+    pub name: String,
+    pub path: PathBuf,
+    pub tags: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip)]
+    pub sql: String,
+}
+
+impl LibraryQuery {
+    /// Reads the tags and the description from the leading comments of the given query
+    fn parse(name: String, path: PathBuf, sql: String) -> LibraryQuery {
+        let mut tags = Vec::new();
+        let mut description = None;
+
+        let comments = sql
+            .lines()
+            .map(str::trim)
+            .take_while(|l| l.is_empty() || l.starts_with("--"))
+            .filter_map(|l| l.strip_prefix("--"));
+        for comment in comments {
+            match comment.split_once(':') {
+                Some((key, value)) if key.trim().eq_ignore_ascii_case("tags") => tags.extend(
+                    value
+                        .split(',')
+                        .map(|t| t.trim().to_lowercase())
+                        .filter(|t| !t.is_empty()),
+                ),
+                Some((key, value)) if key.trim().eq_ignore_ascii_case("description") => {
+                    description = Some(value.trim().to_string())
+                }
+                _ => {}
+            }
+        }
+
+        LibraryQuery {
+            name,
+            path,
+            tags,
+            description,
+            sql,
+        }
+    }
+
+    /// Whether the query has any of the given tags, case-insensitively
+    pub fn tagged(&self, tags: &[String]) -> bool {
+        tags.iter()
+            .any(|t| self.tags.iter().any(|q| q.eq_ignore_ascii_case(t)))
+    }
+}
+
+/// The library used if not given, '~/.kubesql/library'
+pub fn default_path() -> PathBuf {
+    let home = std::env::var_os("HOME").unwrap_or_default();
+    PathBuf::from(home).join(".kubesql").join("library")
+}
+
+/// Returns the queries of the '.sql' files in the given directory and its subdirectories,
+/// ordered by their names
+pub fn load(dir: &Path) -> Result<Vec<LibraryQuery>, LibraryError> {
+    let mut queries = Vec::new();
+    read_dir(dir, dir, &mut queries)?;
+    queries.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(queries)
+}
+
+fn read_dir(root: &Path, dir: &Path, queries: &mut Vec<LibraryQuery>) -> Result<(), LibraryError> {
+    let error = |e| LibraryError::Read(dir.display().to_string(), e);
+
+    for entry in std::fs::read_dir(dir).map_err(error)? {
+        let path = entry.map_err(error)?.path();
+        if path.is_dir() {
+            read_dir(root, &path, queries)?;
+            continue;
+        }
+        if path.extension().is_none_or(|e| e != "sql") {
+            continue;
+        }
+
+        let sql = std::fs::read_to_string(&path)
+            .map_err(|e| LibraryError::Read(path.display().to_string(), e))?;
+        let name = path
+            .strip_prefix(root)
+            .unwrap_or(&path)
+            .with_extension("")
+            .to_string_lossy()
+            .to_string();
+        queries.push(LibraryQuery::parse(name, path, sql));
+    }
+
+    Ok(())
+}
+
+/// Returns the queries of the given names or with any of the given tags, all of them if none given
+pub fn find(
+    dir: &Path,
+    names: &[String],
+    tags: &[String],
+) -> Result<Vec<LibraryQuery>, LibraryError> {
+    let queries = load(dir)?
+        .into_iter()
+        .filter(|q| {
+            (names.is_empty() && tags.is_empty()) || names.contains(&q.name) || q.tagged(tags)
+        })
+        .collect::<Vec<LibraryQuery>>();
+
+    match queries.is_empty() {
+        true => Err(LibraryError::NoMatch),
+        false => Ok(queries),
+    }
+}
@@ -0,0 +1,133 @@
+// Copyright (c) 2021 Dentrax
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software is furnished to do so,
+// subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+use crate::api_builder::ApiBuilder;
+use crate::error::{KubesqlError, Result};
+use crate::printer::ResultTable;
+use futures::io::AsyncBufReadExt;
+use futures::StreamExt;
+use kube::api::LogParams;
+use regex::Regex;
+use std::fmt;
+
+/// A container of a matched pod to read the logs of
+#[derive(Debug, Clone)]
+pub struct Target {
+    pub context: String,
+    pub namespace: String,
+    pub pod: String,
+    pub container: String,
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}/{}/{}/{}",
+            self.context, self.namespace, self.pod, self.container
+        )
+    }
+}
+
+/// Returns a target for each container of the matched pods, or only for the given container.
+/// The pod table is expected to have the container names as its first SELECT field.
+pub fn targets(tables: &[ResultTable], container: Option<&str>) -> Vec<Target> {
+    let mut targets = Vec::new();
+
+    for table in tables.iter().filter(|t| t.kind == "pod") {
+        for row in &table.rows {
+            let (context, namespace, pod, containers) = match row.as_slice() {
+                [c, n, p, containers, ..] => (c, n, p, containers),
+                _ => continue,
+            };
+
+            for name in containers.split(',') {
+                if container.is_some_and(|c| c != name) {
+                    continue;
+                }
+                targets.push(Target {
+                    context: context.clone(),
+                    namespace: namespace.clone(),
+                    pod: pod.clone(),
+                    container: name.to_string(),
+                });
+            }
+        }
+    }
+
+    targets
+}
+
+/// The options of reading the logs
+pub struct LogOptions {
+    pub params: LogParams,
+    /// Prints only the lines matching this pattern, if given
+    pub pattern: Option<Regex>,
+    /// The number of the logs read at the same time
+    pub concurrency: usize,
+}
+
+/// Prints the log lines of the given targets prefixed with the target, i.e.
+/// '[context/namespace/pod/container] line'. The failing ones are warned and skipped.
+/// Returns the number of the printed lines.
+pub async fn search(targets: Vec<Target>, options: &LogOptions) -> usize {
+    futures::stream::iter(targets)
+        .map(|target| async move {
+            match read(&target, options).await {
+                Ok(lines) => lines,
+                Err(e) => {
+                    tracing::warn!("{}", e);
+                    0
+                }
+            }
+        })
+        .buffer_unordered(options.concurrency.max(1))
+        .fold(0, |total, lines| async move { total + lines })
+        .await
+}
+
+/// Streams the log lines of the given target, printing the matched ones as they arrive
+#[tracing::instrument(name = "read", skip_all, fields(target = %target))]
+async fn read(target: &Target, options: &LogOptions) -> Result<usize> {
+    let api = ApiBuilder::builder()
+        .context(target.context.clone())
+        .namespace(target.namespace.clone())
+        .build()
+        .await?;
+
+    let params = LogParams {
+        container: Some(target.container.clone()),
+        ..options.params.clone()
+    };
+    let stream = api.get_pod().log_stream(&target.pod, &params).await?;
+
+    let mut lines = stream.lines();
+
+    let mut printed = 0;
+    while let Some(line) = lines.next().await {
+        let line = line.map_err(|e| KubesqlError::Logs(target.to_string(), e))?;
+        if options.pattern.as_ref().is_some_and(|p| !p.is_match(&line)) {
+            continue;
+        }
+        println!("[{}] {}", target, line);
+        printed += 1;
+    }
+
+    Ok(printed)
+}
@@ -17,139 +17,1002 @@
 // IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
 // CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
 
+mod aggregator;
+mod anonymizer;
 mod api_builder;
+mod applier;
+mod audit;
+mod catalog;
+mod cli;
+mod config;
+mod contexts;
+mod daemon;
+mod derived;
+mod diagnostic;
+mod error;
 mod eval;
+mod exec;
+mod executor;
+mod exporter;
+mod forward;
+mod functions;
+mod helm;
+mod history;
+mod images;
+mod library;
+mod logs;
+mod manifests;
+mod notify;
 mod parser;
 mod planner;
+mod policy;
+mod pool;
 mod printer;
+mod quantity;
+mod sarif;
+mod schedule;
+mod server;
+mod sink;
+mod snapshot;
+mod source;
+mod telemetry;
+mod template;
+mod trend;
 mod validator;
+mod views;
 
-use crate::api_builder::ApiBuilder;
-use crate::parser::ResourceType;
-use crate::printer::Printer;
-use anyhow::{bail, Result};
-use clap::{App, Arg};
-use kube::api::ListParams;
-use sqlparser::ast::BinaryOperator;
-use std::fs::File;
-use std::io::prelude::*;
-use std::str::FromStr;
+use crate::api_builder::{ApiBuilder, Budget};
+use crate::cli::{
+    ApplyArgs, AuditArgs, CheckArgs, Cli, Command, ExecArgs, GlobalArgs, HistoryArgs, ImagesArgs,
+    LibraryCommand, LogsArgs, PingArgs, PortForwardArgs, SnapshotArgs, TrendArgs,
+};
+use crate::contexts::Status;
+use crate::error::{KubesqlError, Result};
+use crate::exec::ExecOptions;
+use crate::exporter::Export;
+use crate::library::{LibraryError, LibraryQuery};
+use crate::notify::Notification;
+use crate::parser::{ApiInserts, ApiQueries, ApiStatement, ParserError};
+use crate::planner::PlanContext;
+use crate::printer::{Output, PrintOptions, Printer, ResultTable};
+use crate::sink::Sink;
+use crate::snapshot::Snapshot;
+use crate::source::{DataSource, Sources};
+use crate::validator::Protection;
+use crate::views::ViewError;
+use clap::{CommandFactory, Parser};
+use kube::ResourceExt;
+use std::future::Future;
+use std::io::{BufRead, Write};
+use std::time::{Duration, Instant};
+
+/// The exit code if interrupted by Ctrl-C, as the shells do for SIGINT
+const INTERRUPTED_EXIT_CODE: i32 = 130;
 
 #[tokio::main]
-async fn main() -> Result<()> {
-    let matches = App::new("kubesql")
-        .version("0.1.0")
-        .author("Dentrax <furkan.turkal@hotmail.com>")
-        .about("kubesql is an experimental tool for querying your Kubernetes API Server using SQL")
-        .arg(
-            Arg::with_name("query")
-                .short("q")
-                .long("query")
-                .multiple(false)
-                .overrides_with("file")
-                .takes_value(true),
-        )
-        .arg(
-            Arg::with_name("file")
-                .short("f")
-                .long("file")
-                .multiple(false)
-                .overrides_with("query")
-                .value_name("FILE")
-                .takes_value(true),
-        )
-        .get_matches();
-
-    let sql = if matches.is_present("query") {
-        matches.value_of("query").unwrap().to_string()
-    } else if matches.is_present("file") {
-        let v = matches.value_of("file").unwrap();
-        let mut file = File::open(v).expect("Unable to open the query file");
-        let mut contents = String::new();
-        file.read_to_string(&mut contents)
-            .expect("Unable to read the query file");
-        contents
-    } else {
-        bail!("Either --query or --file required")
-    };
-
-    let api_queries = parser::parse_sql(&sql)?;
-
-    let mut apis: Vec<ApiBuilder> = Vec::new();
+async fn main() {
+    if let Err(e) = run().await {
+        eprintln!("Error: {}", e);
+        std::process::exit(match e {
+            KubesqlError::Interrupted => INTERRUPTED_EXIT_CODE,
+            _ => 1,
+        });
+    }
+}
 
-    let kubeconfig = parser::parse_kubeconfig()?;
+async fn run() -> Result<()> {
+    let mut cli = Cli::parse();
+    cli.global = cli.global.with_config(config::load()?);
+    telemetry::init(cli.global.log_format(), cli.global.verbose);
+
+    // All the clients read the kubeconfig from $KUBECONFIG, so it is overridden before any of them is built
+    if let Some(kubeconfig) = &cli.global.kubeconfig {
+        std::env::set_var("KUBECONFIG", kubeconfig);
+    }
+
+    match &cli.command {
+        None => query(&cli.global, &cli.query.exec, &cli.query.sql().await?).await,
+        Some(Command::Query(args)) => query(&cli.global, &args.exec, &args.sql().await?).await,
+        Some(Command::Repl(args)) => repl(&cli.global, args).await,
+        Some(Command::Serve(args)) => server::serve(&cli.global, args).await,
+        Some(Command::Snapshot(args)) => take_snapshot(&cli.global, args).await,
+        Some(Command::History(args)) => history(&cli.global, args).await,
+        Some(Command::Trend(args)) => trend(&cli.global, args).await,
+        Some(Command::Library(command)) => library(&cli.global, command).await,
+        Some(Command::Images(args)) => list_images(&cli.global, args).await,
+        Some(Command::Logs(args)) => search_logs(&cli.global, args).await,
+        Some(Command::PortForward(args)) => port_forward(&cli.global, args).await,
+        Some(Command::Daemon(args)) => daemon::run(&cli.global, args).await,
+        Some(Command::Ping(args)) => ping(&cli.global, args).await,
+        Some(Command::Audit(args)) => audit(&cli.global, args).await,
+        Some(Command::Check(args)) => check(&cli.global, args).await,
+        Some(Command::Apply(args)) => apply_manifests(&cli.global, args).await,
+        Some(Command::Completions(args)) => {
+            clap_complete::generate(
+                args.shell,
+                &mut Cli::command(),
+                "kubesql",
+                &mut std::io::stdout(),
+            );
+            Ok(())
+        }
+    }
+}
+
+/// Runs the given statements one after the other, each one in a section titled by its leading
+/// comment or by itself if many are given. The failed ones do not stop the others
+async fn query(global: &GlobalArgs, exec: &ExecArgs, sql: &str) -> Result<()> {
+    // Bound once for all the statements, a parameter may be used by any of them
+    let sql = &parser::bind_all(&[sql], &exec.params())?.remove(0);
+    let statements = parser::split_statements(sql);
+    if statements.len() < 2 {
+        return run_sql(global, exec, sql).await.map(|_| ());
+    }
+
+    let sections = statements
+        .iter()
+        .map(|s| (title(s), s.as_str()))
+        .collect::<Vec<(String, &str)>>();
+    let results = run_sections(global, exec, &sections).await?;
+
+    match results.iter().filter(|r| r.is_err()).count() {
+        0 => Ok(()),
+        failed => Err(KubesqlError::StatementsFailed(failed, results.len())),
+    }
+}
+
+/// Runs the given queries one after the other, each one in a section with its title unless the
+/// results are printed as JSON. Returns the number of rows of each one, or its error printed already.
+/// Only an interrupt stops the others
+async fn run_sections(
+    global: &GlobalArgs,
+    exec: &ExecArgs,
+    sections: &[(String, &str)],
+) -> Result<Vec<std::result::Result<usize, String>>> {
+    let options = global.print_options();
+    let mut results = Vec::new();
+    for (i, (title, sql)) in sections.iter().enumerate() {
+        if !options.output.is_json() {
+            if i > 0 {
+                println!();
+            }
+            printer::print_title(title, &options);
+        }
+
+        match run_sql(global, exec, sql).await {
+            Err(KubesqlError::Interrupted) => return Err(KubesqlError::Interrupted),
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                results.push(Err(e.to_string()));
+            }
+            Ok(rows) => results.push(Ok(rows)),
+        }
+    }
+    Ok(results)
+}
 
-    validator::validate_contexts(kubeconfig, &api_queries.contexts)?;
-
-    for ctx in &api_queries.contexts {
-        for ns in &api_queries.namespaces {
-            apis.push(
-                ApiBuilder::builder()
-                    .context(ctx.clone())
-                    .namespace(ns.clone())
-                    .queries(api_queries.queries.as_slice())
-                    .build()
-                    .await?,
+/// The title of a statement in a report, its first leading '--' comment or itself on one line
+fn title(statement: &str) -> String {
+    statement
+        .lines()
+        .map(str::trim)
+        .find(|l| !l.is_empty())
+        .and_then(|l| l.strip_prefix("--"))
+        .map(|c| c.trim().to_string())
+        .unwrap_or_else(|| {
+            statement
+                .split_whitespace()
+                .collect::<Vec<&str>>()
+                .join(" ")
+        })
+}
+
+/// Runs the given query and records it into the history, returns the number of the result rows
+async fn run_sql(global: &GlobalArgs, exec: &ExecArgs, sql: &str) -> Result<usize> {
+    let plan_context = PlanContext {
+        case_insensitive: exec.case_insensitive,
+        default_contexts: global.default_contexts.clone(),
+        override_contexts: exec.contexts.clone(),
+        override_namespaces: exec.namespaces.clone(),
+        excluded_namespaces: exec.exclude_namespaces.clone(),
+        views: views::load()?,
+        display: global.display.iter().cloned().collect(),
+        ..Default::default()
+    };
+    let statement = match parser::parse_sql(sql, plan_context.clone()) {
+        // The kind may be the short name of a custom resource, declared by its CRD on the clusters
+        Err(ParserError::UnknownKind(contexts, _)) if !exec.offline && !exec.daemon => {
+            let aliases = contexts::short_names(&contexts).await;
+            parser::parse_sql(
+                sql,
+                PlanContext {
+                    aliases,
+                    ..plan_context
+                },
+            )?
+        }
+        statement => statement?,
+    };
+
+    if let ApiStatement::Query(q) = &statement {
+        q.queries
+            .iter()
+            .for_each(|q| tracing::info!("Query: {}", q));
+    }
+
+    let offline = match exec.offline {
+        true => Some(Snapshot::load(&global.snapshot_path())?),
+        false => None,
+    };
+    // The contexts given with a scheme in FROM statement are read from their data sources,
+    // i.e. 'file:./manifests', and the others from the snapshot of --offline if given
+    let sources =
+        Sources::new(offline.as_ref().map(|s| s as &dyn DataSource)).open(&statement.contexts())?;
+
+    let entry = history::Entry::new(sql, statement.contexts());
+    let started = Instant::now();
+    let options = global.print_options();
+    let outputs = Outputs {
+        export: exec.export.as_ref(),
+        notification: exec.notification(sql),
+        record: exec.record.then_some(sql),
+        sinks: exec.sinks.iter().map(|s| s.open(sql, &options)).collect(),
+    };
+
+    let result = match exec.daemon {
+        true => with_timeout(global.timeout, query_daemon(global, sql, &outputs)).await,
+        false => {
+            with_timeout(
+                global.timeout,
+                execute(
+                    statement,
+                    &sources,
+                    &outputs,
+                    exec.exec_options().as_ref(),
+                    &Safeguards {
+                        protection: global.protection(exec.confirmed),
+                        budget: exec.budget(),
+                        max_calls: exec.max_calls(),
+                    },
+                    exec.stats,
+                    options.clone(),
+                ),
             )
+            .await
         }
+    };
+
+    tracing::info!("Finished in {}ms", started.elapsed().as_millis());
+
+    // The query itself succeeded, so it does not fail if the history is not writable
+    if let Err(e) = history::record(&entry.finish(started.elapsed(), &result)) {
+        tracing::warn!("{}", e);
     }
 
-    let mut printer = Printer::builder()
-        .contexts(&api_queries.contexts)
-        .namespaces(&api_queries.namespaces)
-        .queries(api_queries.queries.as_slice());
-
-    //1. Query { key: None, kind: "pod", field1: "status", field2: "phase", eq: "Running", op: Eq }
-    //2. Query { key: Some(And), kind: "deployment", field1: "metadata", field2: "name", eq: "my-awesome-deployment", op: Eq }
-    for q in api_queries.queries.clone() {
-        // a.k.a '--field-selector': https://v1-18.docs.kubernetes.io/docs/concepts/overview/working-with-objects/field-selectors/
-        let list_params =
-            ListParams::default().fields(&format!("{}.{}={}", q.field1, q.field2, q.eq));
-
-        for api in &apis {
-            let mut found: bool = false;
-            match parser::ResourceType::from_str(&q.kind.to_lowercase()).unwrap() {
-                ResourceType::Deployment => {
-                    let o = api.get_deployment().list(&list_params).await?;
-                    if !o.items.is_empty() {
-                        printer.insert_deployments(api.get_context(), api.get_namespace(), o);
-                        found = true;
-                    }
-                }
-                ResourceType::Pod => {
-                    let o = api.get_pod().list(&list_params).await?;
-                    if !o.items.is_empty() {
-                        printer.insert_pods(api.get_context(), api.get_namespace(), o);
-                        found = true;
-                    }
-                }
-                ResourceType::Service => {
-                    let o = api.get_service().list(&list_params).await?;
-                    if !o.items.is_empty() {
-                        printer.insert_services(api.get_context(), api.get_namespace(), o);
-                        found = true;
-                    }
-                }
+    result
+}
+
+/// Fails with a timeout error if the given future does not finish in the given seconds
+pub(crate) async fn with_timeout<T>(
+    timeout: Option<u64>,
+    future: impl Future<Output = Result<T>>,
+) -> Result<T> {
+    match timeout {
+        Some(secs) => tokio::time::timeout(Duration::from_secs(secs), future)
+            .await
+            .unwrap_or(Err(KubesqlError::Timeout(secs))),
+        None => future.await,
+    }
+}
+
+/// Fails with an interrupted error if Ctrl-C is pressed before the given future finishes,
+/// the future is dropped so its outstanding requests are aborted
+pub(crate) async fn interruptible<T>(future: impl Future<Output = Result<T>>) -> Result<T> {
+    tokio::select! {
+        result = future => result,
+        _ = tokio::signal::ctrl_c() => Err(KubesqlError::Interrupted),
+    }
+}
+
+/// Runs the queries read from the standard input until EOF or 'exit', the errors do not end the session
+async fn repl(global: &GlobalArgs, exec: &ExecArgs) -> Result<()> {
+    let stdin = std::io::stdin();
+    let mut sql = String::new();
+
+    loop {
+        print!(
+            "{}",
+            if sql.is_empty() {
+                "kubesql> "
+            } else {
+                "      -> "
             }
+        );
+        std::io::stdout().flush().ok();
+
+        let mut line = String::new();
+        if stdin
+            .lock()
+            .read_line(&mut line)
+            .map_err(KubesqlError::Repl)?
+            == 0
+        {
+            return Ok(());
+        }
+
+        if sql.is_empty() && matches!(line.trim(), "exit" | "quit" | "\\q") {
+            return Ok(());
+        }
+
+        sql.push_str(&line);
+        if !sql.trim_end().ends_with(';') {
+            continue;
+        }
+
+        // The parameters are given for the whole session, each input may use some of them only
+        let result = match parser::bind_params(&sql, &exec.params()) {
+            Ok(bound) => run_sql(global, exec, &bound).await,
+            Err(e) => Err(e.into()),
+        };
+        if let Err(e) = result {
+            eprintln!("Error: {}", e);
+        }
+        sql.clear();
+    }
+}
+
+/// Lists the executed queries, or re-runs one of them
+async fn history(global: &GlobalArgs, args: &HistoryArgs) -> Result<()> {
+    if let Some(id) = args.run {
+        let sql = parser::bind_all(&[&history::find(id)?], &args.exec.params())?.remove(0);
+        return run_sql(global, &args.exec, &sql).await.map(|_| ());
+    }
+
+    let entries = history::search(args.search.as_deref(), args.limit)?;
+    printer::print_history(&entries, &global.print_options());
+    Ok(())
+}
+
+/// Lists the queries of the library, or runs them in sections followed by the number of rows of each one
+async fn library(global: &GlobalArgs, command: &LibraryCommand) -> Result<()> {
+    let dir = global.library_path();
+    let args = match command {
+        LibraryCommand::List(args) => {
+            let queries = library::load(&dir)?
+                .into_iter()
+                .filter(|q| args.tag.is_empty() || q.tagged(&args.tag))
+                .collect::<Vec<LibraryQuery>>();
+            printer::print_library(&queries, &global.print_options());
+            return Ok(());
+        }
+        LibraryCommand::Run(args) => args,
+    };
+
+    let queries = library::find(&dir, &args.names, &args.tag)?;
+    // Bound once for all the queries, a parameter may be used by any of them
+    let sqls = queries
+        .iter()
+        .map(|q| q.sql.as_str())
+        .collect::<Vec<&str>>();
+    let bound = parser::bind_all(&sqls, &args.exec.params())?;
+    let sections = queries
+        .iter()
+        .zip(&bound)
+        .map(|(q, sql)| match &q.description {
+            Some(description) => (format!("{}: {}", q.name, description), sql.as_str()),
+            None => (q.name.clone(), sql.as_str()),
+        })
+        .collect::<Vec<(String, &str)>>();
+    let results = run_sections(global, &args.exec, &sections).await?;
+
+    let options = global.print_options();
+    if !options.output.is_json() {
+        println!();
+        printer::print_library_results(&queries, &results, &options);
+    }
+
+    match results.iter().filter(|r| r.is_err()).count() {
+        0 => Ok(()),
+        failed => Err(LibraryError::Failed(failed, results.len()).into()),
+    }
+}
+
+/// Prints how the number of rows of the given recorded query evolved
+async fn trend(global: &GlobalArgs, args: &TrendArgs) -> Result<()> {
+    let points = trend::trend(&args.query, args.limit)?;
+    printer::print_trend(&points, &global.print_options());
+    Ok(())
+}
+
+/// Runs the given statement and prints the results, returns the number of the result rows
+#[tracing::instrument(name = "execute", skip_all)]
+async fn execute(
+    statement: ApiStatement,
+    sources: &Sources<'_>,
+    outputs: &Outputs<'_>,
+    exec_options: Option<&ExecOptions>,
+    safeguards: &Safeguards,
+    stats: bool,
+    options: PrintOptions,
+) -> Result<usize> {
+    if exec_options.is_some() && !matches!(&statement, ApiStatement::Query(q) if !q.distinct) {
+        return Err(KubesqlError::Unsupported(
+            "Only SELECT statements without GROUP BY or DISTINCT can run with --exec".to_string(),
+        ));
+    }
 
-            // we will decide according to given operator, in case if resource not found or empty
-            if !found {
-                if let Some(k) = &q.key {
-                    if *k == BinaryOperator::And {
-                        bail!(
-                            "No resource found: 'kubectl get {} --field-selector={}.{}={}'",
-                            q.kind,
-                            q.field1,
-                            q.field2,
-                            q.eq
-                        );
-                    }
+    // The mutations are refused on the protected contexts before any API call
+    let mutation = match (&statement, exec_options) {
+        (ApiStatement::Insert(_), _) => Some("INSERT"),
+        (_, Some(_)) => Some("--exec"),
+        _ => None,
+    };
+    if let Some(mutation) = mutation {
+        validator::validate_mutation(&safeguards.protection, mutation, &statement.contexts())?;
+    }
+
+    // The statements other than SELECT require the API Servers of all their contexts
+    let live = sources.live(&statement.contexts()).len() == statement.contexts().len();
+    if exec_options.is_some() && !live {
+        return Err(KubesqlError::Unsupported(
+            "--exec is not supported on the contexts read from a data source".to_string(),
+        ));
+    }
+
+    let mut api_queries = match (statement, live) {
+        // The views are saved locally, so they do not require a cluster
+        (ApiStatement::CreateView(view), _) => {
+            if let Ok(kubeconfig) = parser::parse_kubeconfig() {
+                if kubeconfig.contexts.iter().any(|c| c.name == view.name) {
+                    return Err(ViewError::Context(view.name).into());
                 }
             }
+            views::create(&view)?;
+            println!("Created view '{}'", view.name);
+            return Ok(0);
+        }
+        (ApiStatement::DropView(names, if_exists), _) => {
+            views::drop(&names, if_exists)?;
+            names
+                .iter()
+                .for_each(|name| println!("Dropped view '{}'", name));
+            return Ok(0);
+        }
+        // The columns are read from the schemas, so they do not require a cluster either
+        (ApiStatement::ShowColumns(query), _) => {
+            let columns = catalog::find(&query)?;
+            printer::print_columns(&columns, &options);
+            return Ok(columns.len());
+        }
+        (ApiStatement::ShowContexts, _) => {
+            let mut contexts = contexts::list(&parser::parse_kubeconfig()?);
+            if let Some(anonymizer) = &options.anonymizer {
+                contexts = anonymizer.contexts(contexts);
+            }
+            printer::print_contexts(&contexts, &options);
+            return Ok(contexts.len());
+        }
+        // The local tables are read on this machine, so they do not require a cluster either
+        (ApiStatement::Local(local), _) => {
+            let mut table = executor::select_local(&local, &executor::local_rows(local.table)?)?;
+            if let Some(anonymizer) = &options.anonymizer {
+                table = anonymizer.kubeconfig(table);
+            }
+            let tables = [table];
+            if let Some(export) = outputs.export {
+                exporter::export(export, &tables)?;
+            }
+            if outputs.prints() {
+                printer::print_tables(&tables, &options);
+            }
+            deliver(outputs, &tables).await?;
+            return Ok(tables[0].rows.len());
+        }
+        (ApiStatement::ShowNamespaces(contexts), _) => {
+            sources.validate_contexts(&contexts)?;
+            let mut namespaces = interruptible(contexts::namespaces(&contexts, sources)).await?;
+            if let Some(anonymizer) = &options.anonymizer {
+                namespaces = anonymizer.namespaces(namespaces);
+            }
+            printer::print_namespaces(&namespaces, &options);
+            return Ok(namespaces.len());
+        }
+        // Only the namespaces are listed to estimate the list calls, none of the resources
+        (ApiStatement::Explain(mut q), _) => {
+            sources.validate_contexts(&q.contexts)?;
+            interruptible(executor::resolve_namespaces(&mut q, sources)).await?;
+            let estimate = executor::estimate(&q)?;
+            printer::print_estimate(&estimate, &options);
+            return Ok(estimate.kinds.len());
+        }
+        (ApiStatement::Query(q), _) => {
+            sources.validate_contexts(&q.contexts)?;
+            q
+        }
+        (_, false) => {
+            return Err(KubesqlError::Unsupported(
+                "Only SELECT statements without GROUP BY can run with --offline or on the \
+                 contexts read from a data source"
+                    .to_string(),
+            ))
+        }
+        (ApiStatement::Insert(inserts), true) => return apply(&inserts, &options).await,
+        (ApiStatement::Aggregate(aggregates), true) => {
+            validator::validate_contexts(parser::parse_kubeconfig()?, &aggregates.contexts)?;
+            let mut rows = interruptible(aggregator::aggregate(&aggregates)).await?;
+            if let Some(anonymizer) = &options.anonymizer {
+                anonymizer.aggregates(&aggregates.group_by, &mut rows);
+            }
+            let tables = [printer::aggregate_table(&aggregates, &rows)];
+            if let Some(export) = outputs.export {
+                exporter::export(export, &tables)?;
+            }
+            if outputs.prints() {
+                printer::print_aggregates(&aggregates, &rows, &options);
+            }
+            deliver(outputs, &tables).await?;
+            return Ok(rows.len());
+        }
+    };
+
+    interruptible(executor::resolve_namespaces(&mut api_queries, sources)).await?;
+    if !sources.live(&api_queries.contexts).is_empty() {
+        validator::confirm_cost(&executor::estimate(&api_queries)?, safeguards.max_calls)?;
+    }
+
+    // The matched pods are collected first to run the command in each one
+    if let Some(exec_options) = exec_options {
+        let mut tables = interruptible(executor::select(&api_queries, sources)).await?;
+        interruptible(async {
+            exec::run(&mut tables, exec_options).await;
+            Ok(())
+        })
+        .await?;
+        // The commands run in the pods of their real names
+        if let Some(anonymizer) = &options.anonymizer {
+            tables = anonymizer.tables(tables);
         }
+        if let Some(by) = options.dedupe_by {
+            tables = printer::dedupe(tables, by);
+        }
+        if let Some(export) = outputs.export {
+            exporter::export(export, &tables)?;
+        }
+        if outputs.prints() {
+            printer::print_tables(&tables, &options);
+        }
+        deliver(outputs, &tables).await?;
+        return Ok(tables.iter().map(|t| t.rows.len()).sum());
+    }
+
+    let apis =
+        executor::build_apis(&api_queries, sources, stats, safeguards.budget.as_ref()).await?;
+
+    let mut printer = Printer::builder()
+        .contexts(&api_queries.contexts)
+        .namespaces(&api_queries.namespaces)
+        .queries(api_queries.queries.as_slice())
+        .projections(api_queries.projections.as_slice())
+        .servers(executor::servers(&api_queries))
+        .distinct(api_queries.distinct)
+        .options(match outputs.prints() {
+            true => options.clone(),
+            // The sinks get the results once the query is done, none is streamed
+            false => PrintOptions {
+                output: Output::Table,
+                ..options.clone()
+            },
+        });
+
+    // The resources matched so far are still printed if interrupted
+    let interrupted =
+        match interruptible(executor::execute(&api_queries, &apis, &mut printer)).await {
+            Err(KubesqlError::Interrupted) => true,
+            result => result.map(|_| false)?,
+        };
+
+    // The deduped rows are printed as they are exported, one table per kind
+    let tables = match options.dedupe_by {
+        Some(by) => printer::dedupe(printer.tables(), by),
+        None => printer.tables(),
+    };
+    if let Some(export) = outputs.export {
+        exporter::export(export, &tables)?;
+    }
+
+    match (outputs.prints(), options.dedupe_by) {
+        (false, _) => {}
+        (true, Some(_)) => printer::print_tables(&tables, &options),
+        (true, None) => printer.print(),
+    }
+
+    if stats {
+        printer::print_stats(&executor::stats(&apis), &options);
+    }
+    if let Some(budget) = &safeguards.budget {
+        printer::print_truncated(&budget.lock().unwrap());
+    }
+
+    if interrupted {
+        return Err(KubesqlError::Interrupted);
+    }
+
+    deliver(outputs, &tables).await?;
+    Ok(tables.iter().map(|t| t.rows.len()).sum())
+}
+
+/// Runs the given query on the resources cached by the daemon and prints the results,
+/// returns the number of the result rows
+async fn query_daemon(global: &GlobalArgs, sql: &str, outputs: &Outputs<'_>) -> Result<usize> {
+    let options = global.print_options();
+    let mut tables = interruptible(daemon::query(&global.socket_path(), sql)).await?;
+    if let Some(anonymizer) = &options.anonymizer {
+        tables = anonymizer.tables(tables);
+    }
+    if let Some(by) = options.dedupe_by {
+        tables = printer::dedupe(tables, by);
+    }
+    if let Some(export) = outputs.export {
+        exporter::export(export, &tables)?;
+    }
+    if outputs.prints() {
+        printer::print_tables(&tables, &options);
+    }
+    deliver(outputs, &tables).await?;
+    Ok(tables.iter().map(|t| t.rows.len()).sum())
+}
+
+/// The guard rails of running a query
+struct Safeguards {
+    protection: Protection,
+    /// The resources and bytes the query may list, if limited
+    budget: Option<Budget>,
+    /// The list calls the query may make without asking, unlimited if None
+    max_calls: Option<usize>,
+}
+
+/// Where the results of a query go besides the output
+struct Outputs<'a> {
+    export: Option<&'a Export>,
+    notification: Option<Notification>,
+    /// The query whose result summary is recorded into the trends, if --record is given
+    record: Option<&'a str>,
+    /// The sinks of --sink, the results are printed only through them if any
+    sinks: Vec<Box<dyn Sink>>,
+}
+
+impl Outputs<'_> {
+    /// Whether the results are printed as the output, i.e. not sent to sinks instead
+    fn prints(&self) -> bool {
+        self.sinks.is_empty()
+    }
+}
+
+/// Sends the results to the target of the notification if given and there is any row, then
+/// writes them to the sinks in order and records their summary into the trends
+async fn deliver(outputs: &Outputs<'_>, tables: &[ResultTable]) -> Result<()> {
+    if let Some(notification) = &outputs.notification {
+        if notification.send(tables).await? {
+            tracing::info!("Sent the results to {}", notification.target);
+        }
+    }
+    for sink in &outputs.sinks {
+        sink.write(tables).await?;
+    }
+    if let Some(query) = outputs.record {
+        trend::record(&trend::Sample::new(query, tables))?;
+    }
+    Ok(())
+}
+
+/// Saves the resources of the given contexts and namespaces into the snapshot file
+async fn take_snapshot(global: &GlobalArgs, args: &SnapshotArgs) -> Result<()> {
+    validator::validate_contexts(parser::parse_kubeconfig()?, &args.contexts)?;
+
+    let path = global.snapshot_path();
+    let snapshot = with_timeout(
+        global.timeout,
+        snapshot::take(&args.contexts, &args.namespaces),
+    )
+    .await?;
+    snapshot.save(&path)?;
+
+    println!(
+        "Saved {} resources of {} context(s) to '{}'",
+        snapshot.len(),
+        args.contexts.len(),
+        path.display()
+    );
+    if snapshot.failed() > 0 {
+        eprintln!(
+            "{} kind(s) could not be listed, see the failures of the snapshot",
+            snapshot.failed()
+        );
+    }
+
+    Ok(())
+}
+
+/// Lists the unique container images of the given contexts and namespaces
+async fn list_images(global: &GlobalArgs, args: &ImagesArgs) -> Result<()> {
+    let contexts = global.contexts(&args.contexts);
+    if contexts.is_empty() {
+        return Err(KubesqlError::ContextRequired);
+    }
+
+    let offline = match args.offline {
+        true => Some(Snapshot::load(&global.snapshot_path())?),
+        false => None,
+    };
+    let sources = Sources::new(offline.as_ref().map(|s| s as &dyn DataSource)).open(contexts)?;
+    sources.validate_contexts(contexts)?;
+
+    let images = with_timeout(
+        global.timeout,
+        images::inventory(contexts, &args.namespaces, &sources),
+    )
+    .await?;
+    printer::print_images(&images, &global.print_options());
+
+    Ok(())
+}
+
+/// Prints the resources matched by the checks of the given report
+async fn audit(global: &GlobalArgs, args: &AuditArgs) -> Result<()> {
+    let contexts = global.contexts(&args.contexts);
+    if contexts.is_empty() {
+        return Err(KubesqlError::ContextRequired);
     }
 
-    printer.print();
+    let offline = match args.offline {
+        true => Some(Snapshot::load(&global.snapshot_path())?),
+        false => None,
+    };
+    let sources = Sources::new(offline.as_ref().map(|s| s as &dyn DataSource)).open(contexts)?;
+    sources.validate_contexts(contexts)?;
+
+    let findings = with_timeout(
+        global.timeout,
+        interruptible(audit::run(
+            args.report,
+            contexts,
+            &args.namespaces,
+            &sources,
+        )),
+    )
+    .await?;
+    if let Some(path) = &args.sarif {
+        audit::write_sarif(args.report, &findings, path)?;
+    }
+    printer::print_findings(&findings, &global.print_options());
 
     Ok(())
 }
+
+/// Prints the outcome of each policy of the given file on each of its contexts, fails if any
+/// does not pass
+async fn check(global: &GlobalArgs, args: &CheckArgs) -> Result<()> {
+    let policies = policy::load(&args.file)?;
+
+    let offline = match args.offline {
+        true => Some(Snapshot::load(&global.snapshot_path())?),
+        false => None,
+    };
+
+    let outcomes = with_timeout(
+        global.timeout,
+        interruptible(async {
+            let default = offline.as_ref().map(|s| s as &dyn DataSource);
+            Ok(policy::check(&policies, &global.default_contexts, default).await)
+        }),
+    )
+    .await?;
+    if let Some(path) = &args.junit {
+        policy::write_junit(&outcomes, path)?;
+    }
+    if let Some(path) = &args.sarif {
+        policy::write_sarif(&policies, &outcomes, path, &args.file)?;
+    }
+    printer::print_outcomes(&outcomes, &global.print_options());
+
+    match outcomes
+        .iter()
+        .filter(|o| o.verdict != policy::Verdict::Pass)
+        .count()
+    {
+        0 => Ok(()),
+        failed => Err(KubesqlError::PolicyFailed(failed, outcomes.len())),
+    }
+}
+
+/// Applies the manifests of the given file or directory to the given contexts and namespaces
+async fn apply_manifests(global: &GlobalArgs, args: &ApplyArgs) -> Result<()> {
+    let inserts = ApiInserts {
+        contexts: args.contexts.clone(),
+        namespaces: args.namespaces.clone(),
+        manifests: manifests::read(&args.file)?,
+    };
+    with_timeout(global.timeout, apply(&inserts, &global.print_options()))
+        .await
+        .map(|_| ())
+}
+
+/// Applies the given manifests and prints the result of each one per context and namespace,
+/// returns their number. Fails if any of them could not be applied, once all are tried
+async fn apply(inserts: &ApiInserts, options: &PrintOptions) -> Result<usize> {
+    validator::validate_contexts(parser::parse_kubeconfig()?, &inserts.contexts)?;
+    let applied = applier::apply_inserts(inserts).await;
+    printer::print_applied(&applied, options);
+
+    match applied.iter().filter(|a| a.error.is_some()).count() {
+        0 => Ok(applied.len()),
+        failed => Err(KubesqlError::ApplyFailed(failed, applied.len())),
+    }
+}
+
+/// Prints the health of the API server of each given context, fails if any is not healthy
+async fn ping(global: &GlobalArgs, args: &PingArgs) -> Result<()> {
+    let kubeconfig = parser::parse_kubeconfig()?;
+    let contexts = match args.contexts.is_empty() {
+        true => kubeconfig.contexts.iter().map(|c| c.name.clone()).collect(),
+        false => args.contexts.clone(),
+    };
+    validator::validate_contexts(kubeconfig.clone(), &contexts)?;
+
+    let health = with_timeout(global.timeout, async {
+        Ok(contexts::ping(&kubeconfig, &contexts).await)
+    })
+    .await?;
+    let (unhealthy, total) = (
+        health.iter().filter(|h| h.status != Status::Ok).count(),
+        health.len(),
+    );
+
+    let options = global.print_options();
+    let health = match &options.anonymizer {
+        Some(anonymizer) => anonymizer.health(health),
+        None => health,
+    };
+    printer::print_health(&health, &options);
+
+    match unhealthy {
+        0 => Ok(()),
+        unhealthy => Err(KubesqlError::Unhealthy(unhealthy, total)),
+    }
+}
+
+/// Prints the log lines of the pods matching the given WHERE statement
+async fn search_logs(global: &GlobalArgs, args: &LogsArgs) -> Result<()> {
+    let contexts = global.contexts(&args.contexts);
+    if contexts.is_empty() {
+        return Err(KubesqlError::ContextRequired);
+    }
+
+    // The container names are selected to read the logs of each one
+    let api_queries = where_query(
+        contexts,
+        &args.namespaces,
+        "pod.spec.containers.name",
+        &args.condition,
+        "logs",
+    )
+    .await?;
+
+    let pattern = match &args.grep {
+        Some(p) => Some(
+            regex::Regex::new(p)
+                .map_err(|e| KubesqlError::Unsupported(format!("--grep: {}", e)))?,
+        ),
+        None => None,
+    };
+    let options = logs::LogOptions {
+        params: kube::api::LogParams {
+            tail_lines: Some(args.tail),
+            since_seconds: args.since,
+            follow: args.follow,
+            ..Default::default()
+        },
+        pattern,
+        concurrency: args.concurrency,
+    };
+
+    with_timeout(global.timeout, async {
+        let tables = executor::select(&api_queries, &Sources::default()).await?;
+        let targets = logs::targets(&tables, args.container.as_deref());
+        tracing::info!("Reading the logs of {} container(s)", targets.len());
+        logs::search(targets, &options).await;
+        Ok(())
+    })
+    .await
+}
+
+/// Forwards the local port to the single resource matching the given WHERE statement
+async fn port_forward(global: &GlobalArgs, args: &PortForwardArgs) -> Result<()> {
+    let contexts = global.contexts(&args.contexts);
+    if contexts.is_empty() {
+        return Err(KubesqlError::ContextRequired);
+    }
+
+    let api_queries = where_query(
+        contexts,
+        &args.namespaces,
+        "",
+        &args.condition,
+        "port-forward",
+    )
+    .await?;
+    let (local, remote) = args.ports;
+
+    let (api, pod, port) = with_timeout(global.timeout, async {
+        let tables = executor::select(&api_queries, &Sources::default()).await?;
+        let target = match forward::targets(&tables).as_slice() {
+            [] => return Err(KubesqlError::NoResourceFound(args.condition.clone())),
+            [target] => target.clone(),
+            targets => {
+                return Err(KubesqlError::MultipleMatches(
+                    targets.len(),
+                    targets
+                        .iter()
+                        .map(forward::Target::to_string)
+                        .collect::<Vec<String>>()
+                        .join(", "),
+                ))
+            }
+        };
+        let api = ApiBuilder::builder()
+            .context(target.context.clone())
+            .namespace(target.namespace.clone())
+            .build()
+            .await?;
+        let (pod, port) = forward::resolve(&target, &api, remote).await?;
+
+        println!(
+            "Forwarding from {}:{} -> {} (pod {}:{})",
+            args.address,
+            local,
+            target,
+            pod.name_any(),
+            port
+        );
+        Ok((api.get_pod().clone(), pod.name_any(), port))
+    })
+    .await?;
+
+    forward::forward(api, pod, format!("{}:{}", args.address, local), port).await
+}
+
+/// Returns the queries selecting the given field of the resources matching the WHERE statement
+/// in the given contexts and namespaces, i.e. to run the given action on them
+async fn where_query(
+    contexts: &[String],
+    namespaces: &[String],
+    field: &str,
+    condition: &str,
+    action: &str,
+) -> Result<ApiQueries> {
+    let mut fields = namespaces
+        .iter()
+        .map(|ns| format!("\"{}\"", ns))
+        .collect::<Vec<String>>();
+    if !field.is_empty() {
+        fields.push(field.to_string());
+    }
+
+    let sql = format!("SELECT {} WHERE {}", fields.join(", "), condition);
+    let plan_context = PlanContext {
+        default_contexts: contexts.to_vec(),
+        ..Default::default()
+    };
+    let api_queries = match parser::parse_sql(&sql, plan_context)? {
+        ApiStatement::Query(q) => q,
+        _ => {
+            return Err(KubesqlError::Unsupported(format!(
+                "Only WHERE statements are supported with {}",
+                action
+            )))
+        }
+    };
+    validator::validate_contexts(parser::parse_kubeconfig()?, &api_queries.contexts)?;
+
+    Ok(api_queries)
+}
@@ -0,0 +1,158 @@
+// Copyright (c) 2021 Dentrax
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software is furnished to do so,
+// subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+use crate::contexts;
+use crate::parser::ResourceType;
+use crate::snapshot::Snapshot;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use thiserror::Error;
+
+/// The prefix of the contexts given in FROM statement which are manifest files or directories
+/// instead, i.e. 'file:./manifests'
+pub const PREFIX: &str = "file:";
+
+#[derive(Error, Debug)]
+pub enum ManifestError {
+    #[error("Unable to read the manifests '{0}': {1}")]
+    Read(String, std::io::Error),
+
+    #[error("Invalid manifests in '{0}': {1}")]
+    Invalid(String, serde_yaml::Error),
+}
+
+/// Reads the manifests of the given file or directory into a snapshot of the given context,
+/// i.e. 'file:./manifests' of './manifests'
+pub fn load(context: &str, path: &str) -> Result<Snapshot, ManifestError> {
+    let mut snapshot = Snapshot::default();
+    snapshot.contexts.entry(context.to_string()).or_default();
+
+    for file in files(Path::new(path))? {
+        let content = std::fs::read_to_string(&file)
+            .map_err(|e| ManifestError::Read(file.display().to_string(), e))?;
+        for manifest in manifests(&file, &content) {
+            if let Some(kind) = kind_of(&manifest) {
+                snapshot.upsert(context, &kind, with_namespace(&kind, manifest));
+            }
+        }
+    }
+
+    Ok(snapshot)
+}
+
+/// Returns the given file, or the YAML and JSON files in the given directory and its
+/// subdirectories sorted by path. The hidden ones are skipped, i.e. '.git'
+fn files(path: &Path) -> Result<Vec<PathBuf>, ManifestError> {
+    let read = |e| ManifestError::Read(path.display().to_string(), e);
+    if !path.is_dir() {
+        std::fs::metadata(path).map_err(read)?;
+        return Ok(vec![path.to_path_buf()]);
+    }
+
+    let mut paths = Vec::new();
+    for entry in std::fs::read_dir(path).map_err(read)? {
+        let path = entry.map_err(read)?.path();
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let extension = path.extension().unwrap_or_default().to_string_lossy();
+        if name.starts_with('.') {
+            continue;
+        }
+        if path.is_dir() {
+            paths.extend(files(&path)?);
+        } else if ["yaml", "yml", "json"].contains(&extension.as_ref()) {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+/// Reads the manifests of all the documents of the given file, or of the YAML and JSON files in
+/// the given directory, i.e. to apply them. Unlike the queried ones, an invalid file is an error
+pub fn read(path: &Path) -> Result<Vec<serde_json::Value>, ManifestError> {
+    let mut manifests = Vec::new();
+    for file in files(path)? {
+        let content = std::fs::read_to_string(&file)
+            .map_err(|e| ManifestError::Read(file.display().to_string(), e))?;
+        manifests.extend(
+            documents(&content)
+                .map_err(|e| ManifestError::Invalid(file.display().to_string(), e))?,
+        );
+    }
+    Ok(manifests)
+}
+
+/// Returns the manifests of all the documents of the given file, the items of the lists are
+/// returned one by one. The files which are not valid YAML are skipped, i.e. Helm templates,
+/// and so are the documents without a name, i.e. the values of a chart
+fn manifests(file: &Path, content: &str) -> Vec<serde_json::Value> {
+    match documents(content) {
+        Ok(manifests) => manifests,
+        Err(e) => {
+            tracing::warn!(file = %file.display(), error = %e, "Skipping the invalid manifests");
+            vec![]
+        }
+    }
+}
+
+/// Returns the manifests of all the YAML documents of the given content, the items of the lists
+/// one by one. The documents without a name are skipped
+fn documents(content: &str) -> Result<Vec<serde_json::Value>, serde_yaml::Error> {
+    let mut manifests = Vec::new();
+    for document in serde_yaml::Deserializer::from_str(content) {
+        let value: serde_json::Value = serde::Deserialize::deserialize(document)?;
+        match value["items"].as_array() {
+            Some(items) if value["kind"] == "List" => manifests.extend(
+                items
+                    .iter()
+                    .filter(|i| i["metadata"]["name"].is_string())
+                    .cloned(),
+            ),
+            _ if value["metadata"]["name"].is_string() => manifests.push(value),
+            _ => {}
+        }
+    }
+    Ok(manifests)
+}
+
+/// Returns the kind of the given manifest, None if it is not a supported one. The custom
+/// resources must be of their group, i.e. not the 'Kustomization' of Kustomize itself
+fn kind_of(manifest: &serde_json::Value) -> Option<ResourceType> {
+    let kind = ResourceType::from_str(&manifest["kind"].as_str()?.to_lowercase()).ok()?;
+    let group = |api_version: &str| api_version.split('/').next().map(str::to_string);
+    let api_version = manifest["apiVersion"].as_str().unwrap_or_default();
+
+    match kind {
+        // The releases are decoded from the secrets of Helm, i.e. not the 'HelmRelease' of Flux,
+        // and the metrics are never in manifests
+        ResourceType::HelmRelease | ResourceType::PodMetrics => None,
+        _ if kind.is_custom_resource() => contexts::CUSTOM_RESOURCES
+            .iter()
+            .any(|(gv, k)| *k == kind && group(gv) == group(api_version))
+            .then_some(kind),
+        _ => Some(kind),
+    }
+}
+
+/// The manifests without a namespace are in the 'default' namespace, as if they were applied
+fn with_namespace(kind: &ResourceType, mut manifest: serde_json::Value) -> serde_json::Value {
+    if !kind.is_cluster_scoped() && manifest["metadata"]["namespace"].as_str().is_none() {
+        manifest["metadata"]["namespace"] = serde_json::Value::from("default");
+    }
+    manifest
+}
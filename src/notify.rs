@@ -0,0 +1,195 @@
+// Copyright (c) 2021 Dentrax
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software is furnished to do so,
+// subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+use crate::printer::ResultTable;
+use crate::template::Template;
+use hyper::client::HttpConnector;
+use hyper::{Body, Client, Request};
+use hyper_openssl::HttpsConnector;
+use serde::Deserialize;
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+use thiserror::Error;
+
+/// The time a webhook has to accept a request
+const POST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The rows listed in the default message, the others are only counted
+const MAX_LINES: usize = 20;
+
+/// The incoming webhook of the Slack workspace to post the messages to
+pub const SLACK_WEBHOOK_ENV: &str = "KUBESQL_SLACK_WEBHOOK";
+
+#[derive(Error, Debug)]
+pub enum NotifyError {
+    #[error("Unable to post to '{0}': {1}")]
+    Post(String, String),
+
+    #[error("The Slack notifications require the incoming webhook of the workspace in ${0}")]
+    SlackWebhook(&'static str),
+}
+
+/// Where the non-empty results are sent to, i.e. 'slack:#ops'
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum Target {
+    /// Posts the results as JSON, or the rendered template as is
+    Webhook(String),
+    /// Posts a message to the channel through $KUBESQL_SLACK_WEBHOOK
+    Slack(String),
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Target::Webhook(url) => write!(f, "webhook:{}", url),
+            Target::Slack(channel) => write!(f, "slack:{}", channel),
+        }
+    }
+}
+
+impl FromStr for Target {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Target, Self::Err> {
+        match input.split_once(':') {
+            Some(("webhook", url)) if !url.is_empty() => Ok(Target::Webhook(url.to_string())),
+            Some(("slack", channel)) if !channel.is_empty() => {
+                Ok(Target::Slack(channel.to_string()))
+            }
+            _ => Err(format!(
+                "Unexpected notification target: {}, must be 'webhook:URL' or 'slack:CHANNEL'",
+                input
+            )),
+        }
+    }
+}
+
+impl TryFrom<String> for Target {
+    type Error = String;
+
+    fn try_from(input: String) -> Result<Target, Self::Error> {
+        Target::from_str(&input)
+    }
+}
+
+/// A notification about the results of a query, sent only if it returned any row
+#[derive(Debug, Clone)]
+pub struct Notification {
+    pub target: Target,
+    pub query: String,
+    /// Renders the message instead of the default summary, against
+    /// '{"query": ..., "rows": N, "items": [...]}' with a 'kind' and a field per column in each item
+    pub template: Option<Template>,
+}
+
+impl Notification {
+    /// Sends the given results to the target, returns whether there was any row to send
+    pub async fn send(&self, tables: &[ResultTable]) -> Result<bool, NotifyError> {
+        let rows: usize = tables.iter().map(|t| t.rows.len()).sum();
+        if rows == 0 {
+            return Ok(false);
+        }
+
+        let text = match &self.template {
+            Some(template) => template.render(&self.value(tables, rows)),
+            None => self.summary(tables, rows),
+        };
+
+        match &self.target {
+            Target::Webhook(url) => {
+                let body = match &self.template {
+                    Some(_) => text,
+                    None => serde_json::json!({
+                        "query": self.query,
+                        "rows": rows,
+                        "text": text,
+                        "tables": tables,
+                    })
+                    .to_string(),
+                };
+                post(url, body).await?;
+            }
+            Target::Slack(channel) => {
+                let url = std::env::var(SLACK_WEBHOOK_ENV)
+                    .map_err(|_| NotifyError::SlackWebhook(SLACK_WEBHOOK_ENV))?;
+                let body = serde_json::json!({ "channel": channel, "text": text });
+                post(&url, body.to_string()).await?;
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// The results as the template renders them, an item per row
+    fn value(&self, tables: &[ResultTable], rows: usize) -> serde_json::Value {
+        let items = tables
+            .iter()
+            .flat_map(|t| {
+                t.rows.iter().map(move |row| {
+                    let mut item = serde_json::Map::new();
+                    item.insert("kind".to_string(), t.kind.clone().into());
+                    t.columns.iter().zip(row).for_each(|(column, value)| {
+                        item.insert(column.clone(), value.clone().into());
+                    });
+                    serde_json::Value::Object(item)
+                })
+            })
+            .collect::<Vec<_>>();
+
+        serde_json::json!({ "query": self.query, "rows": rows, "items": items })
+    }
+
+    /// The number of the rows and the first ones, i.e. 'pod context1/default/web-1'
+    fn summary(&self, tables: &[ResultTable], rows: usize) -> String {
+        let mut lines = vec![format!("{} rows returned by: {}", rows, self.query)];
+        tables
+            .iter()
+            .flat_map(|t| t.rows.iter().map(move |row| (&t.kind, row)))
+            .take(MAX_LINES)
+            .for_each(|(kind, row)| lines.push(format!("- {} {}", kind, row.join("/"))));
+        if rows > MAX_LINES {
+            lines.push(format!("... and {} more", rows - MAX_LINES));
+        }
+        lines.join("\n")
+    }
+}
+
+/// Posts the given JSON body to the URL, fails unless the response is successful
+pub async fn post(url: &str, body: String) -> Result<(), NotifyError> {
+    let error = |e: String| NotifyError::Post(url.to_string(), e);
+
+    let connector = HttpsConnector::new().map_err(|e| error(e.to_string()))?;
+    let client: Client<HttpsConnector<HttpConnector>> = Client::builder().build(connector);
+    let request = Request::post(url)
+        .header("Content-Type", "application/json")
+        .body(Body::from(body))
+        .map_err(|e| error(e.to_string()))?;
+
+    let response = tokio::time::timeout(POST_TIMEOUT, client.request(request))
+        .await
+        .map_err(|_| error("timed out".to_string()))?
+        .map_err(|e| error(e.to_string()))?;
+    match response.status().is_success() {
+        true => Ok(()),
+        false => Err(error(format!("the webhook returned {}", response.status()))),
+    }
+}
@@ -17,12 +17,21 @@
 // IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
 // CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
 
-use crate::planner::{self, PlanQuery};
-use crate::planner::{Query, Value};
+use crate::contexts;
+use crate::diagnostic::{self, Diagnostic};
+use crate::planner::{self, PlanError, PlanQuery};
+use crate::planner::{Aggregate, GroupBy, Having, Projection, Query, Value};
+use crate::validator;
 use kube::config::{Kubeconfig, KubeconfigError};
-use sqlparser::ast::{SelectItem, SetExpr, Statement, TableFactor};
+use sqlparser::ast::{
+    self, BinaryOperator, Expr, FunctionArg, FunctionArgExpr, Ident, ObjectName, Select,
+    SelectItem, SetExpr, Statement, TableFactor, TableWithJoins,
+};
 use sqlparser::dialect::GenericDialect;
 use sqlparser::parser::Parser;
+use sqlparser::tokenizer::Tokenizer;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fmt;
 use std::fmt::Formatter;
 use std::str::FromStr;
@@ -46,20 +55,297 @@ pub enum ParserError {
 
     #[error("FROM statement is required to call the given context(s)!")]
     SelectFromRequired,
+
+    #[error("No context of the kubeconfig is selected by FROM statement!")]
+    NoContextSelected,
+
+    #[error("Invalid manifest given: {0}")]
+    InvalidManifest(String),
+
+    #[error("No value given for the parameter: ${0}")]
+    MissingParam(String),
+
+    #[error("The parameter is not used by the query: ${0}")]
+    UnusedParam(String),
+
+    #[error("{0}")]
+    Syntax(Diagnostic),
+
+    #[error("No SQL statement given!")]
+    EmptyStatement,
+
+    #[error("Only a single SQL statement is supported, {0} given!")]
+    MultipleStatements(usize),
+
+    #[error("{0}")]
+    Plan(Diagnostic),
+
+    /// The kind is neither supported nor a known short name, it may be one of the custom
+    /// resources discovered on the clusters of the given contexts
+    #[error("{1}")]
+    UnknownKind(Vec<String>, Diagnostic),
+}
+
+#[derive(Debug)]
+pub enum ApiStatement {
+    Query(ApiQueries),
+    Insert(ApiInserts),
+    Aggregate(ApiAggregates),
+    CreateView(ApiView),
+    /// The names of the views to drop, and whether the missing ones are ignored
+    DropView(Vec<String>, bool),
+    ShowColumns(ApiColumns),
+    ShowContexts,
+    /// The contexts to list the namespaces of
+    ShowNamespaces(Vec<String>),
+    /// The query to estimate the list calls of, without running it
+    Explain(ApiQueries),
+    /// A query of a table read on this machine, i.e. 'SELECT name FROM kubeconfig'
+    Local(ApiLocal),
+}
+
+impl ApiStatement {
+    /// Returns the contexts the statement runs on
+    pub fn contexts(&self) -> Vec<String> {
+        match self {
+            ApiStatement::Query(q) | ApiStatement::Explain(q) => q.contexts.clone(),
+            ApiStatement::Insert(i) => i.contexts.clone(),
+            ApiStatement::Aggregate(a) => a.contexts.clone(),
+            ApiStatement::ShowNamespaces(contexts) => contexts.clone(),
+            ApiStatement::CreateView(_)
+            | ApiStatement::DropView(..)
+            | ApiStatement::ShowColumns(_)
+            | ApiStatement::ShowContexts
+            | ApiStatement::Local(_) => vec![],
+        }
+    }
 }
 
 #[derive(Debug)]
 pub struct ApiQueries {
     pub namespaces: Vec<String>,
+    /// Query all the namespaces of each context, i.e. 'SELECT *'. They are listed into
+    /// the namespaces by the executor before running the queries
+    pub all_namespaces: bool,
+    /// The namespaces not to query out of all, i.e. "SELECT * EXCEPT ('kube-system')"
+    pub excluded_namespaces: Vec<String>,
     pub contexts: Vec<String>,
     pub queries: Vec<Query>,
+    pub projections: Vec<Projection>,
+    /// Print only the unique values of the SELECT fields, i.e. 'SELECT DISTINCT'
+    pub distinct: bool,
+    /// The predicates on the API servers, only the matching contexts are queried,
+    /// i.e. "cluster.version < '1.27'"
+    pub clusters: Vec<Query>,
+    /// The predicates on the metadata columns, only the matching contexts and namespaces are
+    /// queried, i.e. "__context LIKE 'prod-%'"
+    pub sources: Vec<Query>,
+}
+
+#[derive(Debug)]
+pub struct ApiAggregates {
+    pub contexts: Vec<String>,
+    pub group_by: GroupBy,
+    pub columns: Vec<Aggregate>,
+    /// The conditions of HAVING statement, all of them must hold
+    pub having: Vec<Having>,
+}
+
+/// A query saved by name, i.e. 'CREATE VIEW unhealthy_pods AS SELECT ...'
+#[derive(Debug)]
+pub struct ApiView {
+    pub name: String,
+    pub query: String,
+    /// Replace the view if it already exists, i.e. 'CREATE OR REPLACE VIEW'
+    pub or_replace: bool,
 }
 
-#[derive(Debug, Hash, Eq, PartialEq)]
+/// The fields of a kind to list, i.e. "SHOW COLUMNS FROM pod LIKE 'spec.%'"
+#[derive(Debug)]
+pub struct ApiColumns {
+    pub kind: ResourceType,
+    /// The LIKE pattern of the field paths, all of them are listed if not given
+    pub pattern: Option<String>,
+    /// Whether the pattern is matched case-insensitively, i.e. 'ILIKE'
+    pub case_insensitive: bool,
+}
+
+/// A query of a local table, its columns are given without a kind,
+/// i.e. "SELECT name, cluster.server FROM kubeconfig WHERE user.auth_provider = 'oidc'"
+#[derive(Debug)]
+pub struct ApiLocal {
+    pub table: LocalTable,
+    pub projections: Vec<Projection>,
+    /// The predicates on the rows, all the rows are printed without WHERE statement
+    pub queries: Vec<Query>,
+    /// Print only the unique rows, i.e. 'SELECT DISTINCT'
+    pub distinct: bool,
+}
+
+/// The tables read on this machine instead of the clusters, given in FROM statement in place
+/// of the contexts. They shadow the contexts with the same name
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LocalTable {
+    /// A row per context of the kubeconfig, along with its cluster and user
+    Kubeconfig,
+}
+
+impl LocalTable {
+    fn from_name(name: &str) -> Option<LocalTable> {
+        match name.to_lowercase().as_str() {
+            "kubeconfig" => Some(LocalTable::Kubeconfig),
+            _ => None,
+        }
+    }
+
+    /// Returns the columns selected by 'SELECT *'
+    pub fn columns(&self) -> &'static [&'static str] {
+        match self {
+            LocalTable::Kubeconfig => &[
+                "name",
+                "cluster.name",
+                "cluster.server",
+                "user.name",
+                "user.auth",
+                "namespace",
+                "current",
+            ],
+        }
+    }
+}
+
+impl fmt::Display for LocalTable {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            LocalTable::Kubeconfig => write!(f, "kubeconfig"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ApiInserts {
+    /// The context given in INSERT INTO, or the ones given by --contexts
+    pub contexts: Vec<String>,
+    pub namespaces: Vec<String>,
+    pub manifests: Vec<serde_json::Value>,
+}
+
+#[derive(Debug, Clone, Hash, Eq, PartialEq)]
 pub enum ResourceType {
     Deployment,
     Pod,
     Service,
+    PodMetrics,
+    Node,
+    EndpointSlice,
+    HorizontalPodAutoscaler,
+    ResourceQuota,
+    LimitRange,
+    ServiceAccount,
+    Role,
+    RoleBinding,
+    ClusterRole,
+    ClusterRoleBinding,
+    PersistentVolume,
+    Namespace,
+    HelmRelease,
+    Application,
+    Kustomization,
+    PersistentVolumeClaim,
+    ConfigMap,
+    Secret,
+    PodDisruptionBudget,
+}
+
+impl ResourceType {
+    /// All the supported kinds
+    pub fn all() -> &'static [ResourceType] {
+        &[
+            ResourceType::Deployment,
+            ResourceType::Pod,
+            ResourceType::Service,
+            ResourceType::PodMetrics,
+            ResourceType::Node,
+            ResourceType::EndpointSlice,
+            ResourceType::HorizontalPodAutoscaler,
+            ResourceType::ResourceQuota,
+            ResourceType::LimitRange,
+            ResourceType::ServiceAccount,
+            ResourceType::Role,
+            ResourceType::RoleBinding,
+            ResourceType::ClusterRole,
+            ResourceType::ClusterRoleBinding,
+            ResourceType::PersistentVolume,
+            ResourceType::Namespace,
+            ResourceType::HelmRelease,
+            ResourceType::Application,
+            ResourceType::Kustomization,
+            ResourceType::PersistentVolumeClaim,
+            ResourceType::ConfigMap,
+            ResourceType::Secret,
+            ResourceType::PodDisruptionBudget,
+        ]
+    }
+
+    /// Whether the '--field-selector' of the given field can be used to filter on API Server side,
+    /// i.e. it is one of the field labels of the kind. The metrics API does not support field
+    /// selectors, nor the Helm releases decoded from their Secrets. All the other kinds support the
+    /// metadata ones, i.e. 'metadata.name' for the point lookups, the API Server rejects the others
+    /// unless documented for the kind:
+    /// https://kubernetes.io/docs/concepts/overview/working-with-objects/field-selectors/
+    pub fn supports_field_selector(&self, path: &str) -> bool {
+        match (self, path) {
+            (ResourceType::PodMetrics | ResourceType::HelmRelease, _) => false,
+            (_, "metadata.name" | "metadata.namespace") => true,
+            (
+                ResourceType::Pod,
+                "spec.nodeName"
+                | "spec.restartPolicy"
+                | "spec.schedulerName"
+                | "spec.serviceAccountName"
+                | "spec.hostNetwork"
+                | "status.phase"
+                | "status.podIP"
+                | "status.nominatedNodeName",
+            ) => true,
+            (ResourceType::Node, "spec.unschedulable") => true,
+            _ => false,
+        }
+    }
+
+    /// Whether the metadata of the kind can be listed without the whole resources. The metrics API
+    /// does not serve it, and the Helm releases are decoded from the data of their Secrets
+    pub fn supports_metadata_list(&self) -> bool {
+        !matches!(self, ResourceType::PodMetrics | ResourceType::HelmRelease)
+    }
+
+    /// Whether the kind is not namespaced, i.e. nodes. They are listed once per context
+    /// and printed without the namespace dimension.
+    pub fn is_cluster_scoped(&self) -> bool {
+        matches!(
+            self,
+            ResourceType::Node
+                | ResourceType::ClusterRole
+                | ResourceType::ClusterRoleBinding
+                | ResourceType::PersistentVolume
+                | ResourceType::Namespace
+        )
+    }
+
+    /// Whether the kind is a custom resource that may not be installed, i.e. Argo CD
+    /// applications. The clusters without its CRD have none of them
+    pub fn is_custom_resource(&self) -> bool {
+        matches!(
+            self,
+            ResourceType::Application | ResourceType::Kustomization
+        )
+    }
+
+    /// Whether the kind may not be served by a cluster, i.e. a custom resource without its CRD
+    /// or the metrics API without the metrics-server
+    pub fn is_optional(&self) -> bool {
+        self.is_custom_resource() || *self == ResourceType::PodMetrics
+    }
 }
 
 impl fmt::Display for ResourceType {
@@ -68,6 +354,26 @@ impl fmt::Display for ResourceType {
             ResourceType::Deployment => write!(f, "deployment"),
             ResourceType::Pod => write!(f, "pod"),
             ResourceType::Service => write!(f, "service"),
+            ResourceType::PodMetrics => write!(f, "podmetrics"),
+            ResourceType::Node => write!(f, "node"),
+            ResourceType::EndpointSlice => write!(f, "endpointslice"),
+            ResourceType::HorizontalPodAutoscaler => write!(f, "hpa"),
+            ResourceType::ResourceQuota => write!(f, "resourcequota"),
+            ResourceType::LimitRange => write!(f, "limitrange"),
+            ResourceType::ServiceAccount => write!(f, "serviceaccount"),
+            ResourceType::Role => write!(f, "role"),
+            ResourceType::RoleBinding => write!(f, "rolebinding"),
+            ResourceType::ClusterRole => write!(f, "clusterrole"),
+            ResourceType::ClusterRoleBinding => write!(f, "clusterrolebinding"),
+            ResourceType::PersistentVolume => write!(f, "pv"),
+            ResourceType::Namespace => write!(f, "namespace"),
+            ResourceType::HelmRelease => write!(f, "helmrelease"),
+            ResourceType::Application => write!(f, "application"),
+            ResourceType::Kustomization => write!(f, "kustomization"),
+            ResourceType::PersistentVolumeClaim => write!(f, "pvc"),
+            ResourceType::ConfigMap => write!(f, "configmap"),
+            ResourceType::Secret => write!(f, "secret"),
+            ResourceType::PodDisruptionBudget => write!(f, "pdb"),
         }
     }
 }
@@ -76,10 +382,37 @@ impl FromStr for ResourceType {
     type Err = ParserError;
 
     fn from_str(input: &str) -> Result<ResourceType, Self::Err> {
+        // The short names and plurals of kubectl are accepted as well, i.e. 'po' and 'pods'
         match input {
-            "deployment" => Ok(ResourceType::Deployment),
-            "pod" => Ok(ResourceType::Pod),
-            "service" => Ok(ResourceType::Service),
+            "deployment" | "deployments" | "deploy" => Ok(ResourceType::Deployment),
+            "pod" | "pods" | "po" => Ok(ResourceType::Pod),
+            "service" | "services" | "svc" => Ok(ResourceType::Service),
+            "podmetrics" => Ok(ResourceType::PodMetrics),
+            "node" | "nodes" | "no" => Ok(ResourceType::Node),
+            "endpointslice" | "endpointslices" => Ok(ResourceType::EndpointSlice),
+            "hpa" | "horizontalpodautoscaler" | "horizontalpodautoscalers" => {
+                Ok(ResourceType::HorizontalPodAutoscaler)
+            }
+            "resourcequota" | "resourcequotas" | "quota" => Ok(ResourceType::ResourceQuota),
+            "limitrange" | "limitranges" | "limits" => Ok(ResourceType::LimitRange),
+            "serviceaccount" | "serviceaccounts" | "sa" => Ok(ResourceType::ServiceAccount),
+            "role" | "roles" => Ok(ResourceType::Role),
+            "rolebinding" | "rolebindings" => Ok(ResourceType::RoleBinding),
+            "clusterrole" | "clusterroles" => Ok(ResourceType::ClusterRole),
+            "clusterrolebinding" | "clusterrolebindings" => Ok(ResourceType::ClusterRoleBinding),
+            "pv" | "persistentvolume" | "persistentvolumes" => Ok(ResourceType::PersistentVolume),
+            "namespace" | "namespaces" | "ns" => Ok(ResourceType::Namespace),
+            "helmrelease" | "helmreleases" => Ok(ResourceType::HelmRelease),
+            "application" | "applications" | "app" | "apps" => Ok(ResourceType::Application),
+            "kustomization" | "kustomizations" | "ks" => Ok(ResourceType::Kustomization),
+            "pvc" | "persistentvolumeclaim" | "persistentvolumeclaims" => {
+                Ok(ResourceType::PersistentVolumeClaim)
+            }
+            "configmap" | "configmaps" | "cm" => Ok(ResourceType::ConfigMap),
+            "secret" | "secrets" => Ok(ResourceType::Secret),
+            "pdb" | "poddisruptionbudget" | "poddisruptionbudgets" => {
+                Ok(ResourceType::PodDisruptionBudget)
+            }
             _ => Err(ParserError::Unknown(format!(
                 "Unexpected ResourceType for {}",
                 input
@@ -88,29 +421,769 @@ impl FromStr for ResourceType {
     }
 }
 
-pub(crate) fn parse_sql(sql: &str) -> Result<ApiQueries, ParserError> {
+#[tracing::instrument(name = "parse", skip_all)]
+pub(crate) fn parse_sql(
+    sql: &str,
+    plan_context: planner::PlanContext,
+) -> Result<ApiStatement, ParserError> {
     let dialect = GenericDialect {};
 
-    // `-` is an incorrect char for SQL Queries, so we need to replace with another char
-    // We will undo this replace during parsing stage
-    let sql_replace = sql.replace('-', "_");
+    // `-` is an incorrect char for SQL identifiers, i.e. 'kube-system',
+    // so we need to quote them as '"kube-system"'
+    let rewritten = rewrite_sql(sql);
 
-    // Parse the given SQL to AST
-    let mut ast = Parser::parse_sql(&dialect, &sql_replace).unwrap();
+    // Parse the given SQL to AST, errors are rendered against the given SQL, not the rewritten one
+    let tokens = Tokenizer::new(&dialect, &rewritten.sql)
+        .tokenize_with_location()
+        .map_err(|e| ParserError::Syntax(diagnostic::tokenizer_error(sql, &rewritten, &e)))?;
+    let mut parser = Parser::new(&dialect).with_tokens_with_locations(tokens);
+    let mut ast = match parser.parse_statements() {
+        Ok(ast) => ast,
+        Err(e) => {
+            return Err(ParserError::Syntax(diagnostic::parser_error(
+                sql,
+                &rewritten,
+                &mut parser,
+                &e,
+            )))
+        }
+    };
 
-    let query = match ast.pop().unwrap() {
-        Statement::Query(query) => query,
+    if ast.len() > 1 {
+        return Err(ParserError::MultipleStatements(ast.len()));
+    }
+
+    match ast.pop().ok_or(ParserError::EmptyStatement)? {
+        Statement::Query(query) => parse_select(sql, &query, plan_context),
+        Statement::Explain { statement, .. } => match *statement {
+            Statement::Query(query) => match parse_select(sql, &query, plan_context)? {
+                ApiStatement::Query(queries) => Ok(ApiStatement::Explain(queries)),
+                _ => Err(ParserError::Unsupported(
+                    "Only SELECT statements without GROUP BY can be explained".to_string(),
+                )),
+            },
+            _ => Err(ParserError::Unsupported(
+                "Only SELECT statements without GROUP BY can be explained".to_string(),
+            )),
+        },
+        Statement::CreateView {
+            or_replace,
+            materialized,
+            name,
+            columns,
+            query,
+            ..
+        } => parse_view(
+            sql,
+            &name,
+            &columns,
+            materialized,
+            or_replace,
+            &query,
+            plan_context,
+        )
+        .map(ApiStatement::CreateView),
+        Statement::Drop {
+            object_type: ast::ObjectType::View,
+            if_exists,
+            names,
+            ..
+        } => Ok(ApiStatement::DropView(
+            names
+                .iter()
+                .map(|n| n.to_string().replace('"', ""))
+                .collect(),
+            if_exists,
+        )),
+        Statement::Insert {
+            table_name,
+            columns,
+            source,
+            ..
+        } => parse_insert(&table_name, &columns, &source, &plan_context).map(ApiStatement::Insert),
+        Statement::ShowColumns {
+            table_name, filter, ..
+        } => parse_show_columns(&table_name, filter).map(ApiStatement::ShowColumns),
+        // i.e. 'SHOW NAMESPACES FROM ctx1, ctx2', parsed as a variable of PostgreSQL
+        Statement::ShowVariable { variable } => parse_show(&variable, &plan_context),
+        _ => Err(ParserError::Unsupported(
+            "Only QUERY, INSERT, CREATE VIEW, DROP VIEW, SHOW and EXPLAIN statements are supported!"
+                .to_string(),
+        )),
+    }
+}
+
+/// Moves the predicates of the given pseudo-kind out of the queries, i.e. "cluster.version < '1.27'".
+/// They select the contexts or namespaces to query before any resource is listed, so they can
+/// only be combined with AND
+fn split_pseudo_kind(
+    queries: &mut ApiQueries,
+    kind: &str,
+    name: &str,
+) -> Result<Vec<Query>, ParserError> {
+    let is_kind = |q: &Query| q.kind.eq_ignore_ascii_case(kind);
+    if !queries.queries.iter().any(is_kind) {
+        return Ok(vec![]);
+    }
+
+    for (i, q) in queries.queries.iter().enumerate() {
+        let next = queries.queries.get(i + 1);
+        if is_kind(q)
+            && (q.key == Some(BinaryOperator::Or)
+                || next.is_some_and(|n| n.key == Some(BinaryOperator::Or)))
+        {
+            return Err(ParserError::Unsupported(format!(
+                "{} can only be combined with AND: {}",
+                name, q
+            )));
+        }
+    }
+
+    let (split, rest): (Vec<Query>, Vec<Query>) = queries.queries.drain(..).partition(is_kind);
+    if rest.is_empty() {
+        return Err(ParserError::Unsupported(format!(
+            "WHERE statement requires a predicate on a kind besides the {}",
+            name
+        )));
+    }
+
+    queries.queries = rest;
+    queries.queries[0].key = None;
+    Ok(split)
+}
+
+/// Parses 'SHOW CONTEXTS' and 'SHOW NAMESPACES [FROM ctx1, ctx2]', the namespaces of the default
+/// contexts are listed if FROM is not given, the ones of --contexts in any case if given
+fn parse_show(
+    variable: &[ast::Ident],
+    plan_context: &planner::PlanContext,
+) -> Result<ApiStatement, ParserError> {
+    let default_contexts = &plan_context.default_contexts;
+    let words: Vec<String> = variable.iter().map(|i| i.value.to_uppercase()).collect();
+    match words
+        .iter()
+        .map(String::as_str)
+        .collect::<Vec<&str>>()
+        .as_slice()
+    {
+        ["CONTEXTS"] => Ok(ApiStatement::ShowContexts),
+        ["NAMESPACES", ..] if !plan_context.override_contexts.is_empty() => Ok(
+            ApiStatement::ShowNamespaces(plan_context.override_contexts.clone()),
+        ),
+        ["NAMESPACES"] if default_contexts.is_empty() => Err(ParserError::SelectFromRequired),
+        ["NAMESPACES"] => Ok(ApiStatement::ShowNamespaces(default_contexts.to_vec())),
+        ["NAMESPACES", "FROM", _, ..] => Ok(ApiStatement::ShowNamespaces(select_contexts(
+            &variable[2..]
+                .iter()
+                .map(|i| ContextSelector::from(i.value.clone()))
+                .collect::<Vec<_>>(),
+        )?)),
+        _ => Err(ParserError::Unsupported(format!(
+            "SHOW statement does only support COLUMNS, CONTEXTS and NAMESPACES: SHOW {}",
+            variable
+                .iter()
+                .map(|i| i.to_string())
+                .collect::<Vec<String>>()
+                .join(" ")
+        ))),
+    }
+}
+
+/// Parses the kind and the LIKE filter of SHOW COLUMNS, i.e. "SHOW COLUMNS FROM pod LIKE 'spec.%'"
+fn parse_show_columns(
+    table_name: &ast::ObjectName,
+    filter: Option<ast::ShowStatementFilter>,
+) -> Result<ApiColumns, ParserError> {
+    let name = table_name.to_string().replace('"', "").to_lowercase();
+    let kind = ResourceType::from_str(&name).map_err(|_| {
+        ParserError::Unsupported(format!(
+            "SHOW COLUMNS requires a kind, supported kinds are: {}",
+            ResourceType::all()
+                .iter()
+                .map(|k| k.to_string())
+                .collect::<Vec<String>>()
+                .join(", ")
+        ))
+    })?;
+
+    let (pattern, case_insensitive) = match filter {
+        None => (None, false),
+        Some(ast::ShowStatementFilter::Like(p)) => (Some(p), false),
+        Some(ast::ShowStatementFilter::ILike(p)) => (Some(p), true),
+        Some(ast::ShowStatementFilter::Where(e)) => {
+            return Err(ParserError::Unsupported(format!(
+                "SHOW COLUMNS does only support LIKE and ILIKE filters: WHERE {}",
+                e
+            )))
+        }
+    };
+
+    Ok(ApiColumns {
+        kind,
+        pattern,
+        case_insensitive,
+    })
+}
+
+/// Validates the query of the given view by planning it, the view is saved as the normalized query
+fn parse_view(
+    sql: &str,
+    name: &ObjectName,
+    columns: &[Ident],
+    materialized: bool,
+    or_replace: bool,
+    query: &ast::Query,
+    plan_context: planner::PlanContext,
+) -> Result<ApiView, ParserError> {
+    if materialized || !columns.is_empty() {
+        return Err(ParserError::Unsupported(
+            "CREATE VIEW does not support MATERIALIZED views and column names!".to_string(),
+        ));
+    }
+
+    let name = match name.0.as_slice() {
+        [name] => name.value.clone(),
         _ => {
+            return Err(ParserError::Unsupported(format!(
+                "CREATE VIEW requires a single name, i.e. 'unhealthy_pods': {}",
+                name
+            )))
+        }
+    };
+
+    match expand_query(query, &plan_context.views, &mut vec![name.clone()])? {
+        expanded if expanded == *query => parse_query(sql, query, plan_context)?,
+        expanded => parse_query(&expanded.to_string(), &expanded, plan_context)?,
+    };
+
+    Ok(ApiView {
+        name,
+        query: query.to_string(),
+        or_replace,
+    })
+}
+
+/// Parses the given SELECT statement along with the views it selects from
+fn parse_select(
+    sql: &str,
+    query: &ast::Query,
+    plan_context: planner::PlanContext,
+) -> Result<ApiStatement, ParserError> {
+    // The errors are rendered against the expanded query, the views are not in the given SQL
+    match expand_query(query, &plan_context.views, &mut vec![])? {
+        expanded if expanded == *query => parse_query(sql, query, plan_context),
+        expanded => parse_query(&expanded.to_string(), &expanded, plan_context),
+    }
+}
+
+/// Expands the CTEs and the views given in FROM statements into their queries, i.e.
+/// 'WITH running AS (SELECT ...) SELECT * FROM running WHERE ...':
+/// * `*` selects the namespaces and the fields of the view, otherwise the given ones are selected
+/// * Both the WHERE statements of the view and of the query must hold
+/// * The subqueries of `IN` and `=` can select from a view as well, i.e. '(SELECT * FROM spot_nodes)',
+///   they still run on the contexts of the outer query
+///
+/// The CTEs are in scope of their query and shadow the views with the same name.
+/// Views can be built on other views, the already expanded ones are rejected to avoid cycles.
+fn expand_query(
+    query: &ast::Query,
+    views: &BTreeMap<String, String>,
+    expanded: &mut Vec<String>,
+) -> Result<ast::Query, ParserError> {
+    let mut views = Cow::Borrowed(views);
+    if let Some(with) = &query.with {
+        if with.recursive {
             return Err(ParserError::Unsupported(
-                "Only QUERY statements are supported!".to_string(),
+                "WITH RECURSIVE is not supported!".to_string(),
             ));
         }
+
+        for cte in &with.cte_tables {
+            if !cte.alias.columns.is_empty() {
+                return Err(ParserError::Unsupported(format!(
+                    "WITH statement does not support column names: {}",
+                    cte
+                )));
+            }
+            views
+                .to_mut()
+                .insert(cte.alias.name.value.clone(), cte.query.to_string());
+        }
+    }
+
+    let mut select = match &*query.body {
+        SetExpr::Select(s) => s.clone(),
+        _ => return Ok(query.clone()),
+    };
+
+    if let Some(selection) = &mut select.selection {
+        expand_subqueries(selection, &views, expanded)?;
+    }
+
+    let body = match view_name(&select, &views) {
+        Some(name) => SetExpr::Select(Box::new(expand_view(*select, &name, &views, expanded)?)),
+        None => SetExpr::Select(select),
+    };
+
+    Ok(ast::Query {
+        with: None,
+        body: Box::new(body),
+        ..query.clone()
+    })
+}
+
+/// Returns the name of the view given in FROM statement, if it is the only one
+fn view_name(select: &Select, views: &BTreeMap<String, String>) -> Option<String> {
+    match select.from.as_slice() {
+        [TableWithJoins {
+            relation: TableFactor::Table { name, .. },
+            joins,
+        }] if joins.is_empty() => match name.0.as_slice() {
+            [name] if views.contains_key(&name.value) => Some(name.value.clone()),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Merges the query of the given view into the given SELECT statement
+fn expand_view(
+    select: Select,
+    name: &str,
+    views: &BTreeMap<String, String>,
+    expanded: &mut Vec<String>,
+) -> Result<Select, ParserError> {
+    if expanded.iter().any(|e| e == name) {
+        return Err(ParserError::Unsupported(format!(
+            "View '{}' can not refer to itself!",
+            name
+        )));
+    }
+
+    let invalid = |e: String| ParserError::Unsupported(format!("Invalid view '{}': {}", name, e));
+    let mut statements =
+        Parser::parse_sql(&GenericDialect {}, &views[name]).map_err(|e| invalid(e.to_string()))?;
+    let view = match statements.pop() {
+        Some(Statement::Query(view)) if statements.is_empty() => view,
+        _ => return Err(invalid("a single SELECT statement is required".to_string())),
+    };
+
+    expanded.push(name.to_string());
+    let view = expand_query(&view, views, expanded)?;
+    expanded.pop();
+
+    let mut merged = match *view.body {
+        SetExpr::Select(s) => *s,
+        body => return Err(invalid(format!("unsupported query body: {}", body))),
+    };
+
+    if !matches!(select.projection.as_slice(), [SelectItem::Wildcard(o)] if o.opt_except.is_none())
+    {
+        merged.projection = select.projection;
+    }
+    merged.distinct |= select.distinct;
+    if !select.group_by.is_empty() {
+        merged.group_by = select.group_by;
+    }
+    merged.selection = match (merged.selection.take(), select.selection) {
+        (Some(v), Some(q)) => Some(Expr::BinaryOp {
+            left: Box::new(Expr::Nested(Box::new(v))),
+            op: BinaryOperator::And,
+            right: Box::new(Expr::Nested(Box::new(q))),
+        }),
+        (v, q) => v.or(q),
+    };
+
+    Ok(merged)
+}
+
+/// Expands the views given in FROM statements of the subqueries in the given WHERE statement
+fn expand_subqueries(
+    expr: &mut Expr,
+    views: &BTreeMap<String, String>,
+    expanded: &mut Vec<String>,
+) -> Result<(), ParserError> {
+    match expr {
+        Expr::BinaryOp { left, right, .. } => {
+            expand_subqueries(left, views, expanded)?;
+            expand_subqueries(right, views, expanded)
+        }
+        Expr::UnaryOp { expr, .. } | Expr::Nested(expr) => expand_subqueries(expr, views, expanded),
+        Expr::InSubquery { subquery, .. } | Expr::Subquery(subquery) => {
+            let from_view =
+                matches!(&*subquery.body, SetExpr::Select(s) if view_name(s, views).is_some());
+            **subquery = expand_query(subquery, views, expanded)?;
+
+            // The subquery runs on the contexts of the outer query, not on the ones of the view
+            if let (true, SetExpr::Select(s)) = (from_view, subquery.body.as_mut()) {
+                s.from.clear();
+            }
+            Ok(())
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Binds the given values to the placeholders of all the given queries, see `bind_params`. The
+/// values without a placeholder in any of them are rejected, a query may use any of the values
+pub(crate) fn bind_all(
+    queries: &[&str],
+    params: &HashMap<String, String>,
+) -> Result<Vec<String>, ParserError> {
+    let mut used = HashSet::new();
+    let bound = queries
+        .iter()
+        .map(|sql| bind(sql, params, &mut used))
+        .collect::<Result<Vec<String>, ParserError>>()?;
+
+    let mut unused: Vec<&String> = params.keys().filter(|k| !used.contains(*k)).collect();
+    unused.sort();
+    match unused.first() {
+        Some(key) => Err(ParserError::UnusedParam(key.to_string())),
+        None => Ok(bound),
+    }
+}
+
+/// Replaces the `$key` placeholders outside of the quotes, the double quoted identifiers and the
+/// `--` comments with the given values as escaped string literals, i.e. `'value'`. A `$` without a
+/// key is kept as it is, i.e. `$$`. The placeholders without a value are rejected
+pub(crate) fn bind_params(
+    sql: &str,
+    params: &HashMap<String, String>,
+) -> Result<String, ParserError> {
+    bind(sql, params, &mut HashSet::new())
+}
+
+/// Binds the given values, the keys of the placeholders are added to the given used ones
+fn bind(
+    sql: &str,
+    params: &HashMap<String, String>,
+    used: &mut HashSet<String>,
+) -> Result<String, ParserError> {
+    let mut bound = String::with_capacity(sql.len());
+    let mut quote = None;
+    let mut comment = false;
+    let mut chars = sql.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\n' if comment => comment = false,
+            _ if comment => {}
+            '\'' | '"' if quote.is_none() => quote = Some(c),
+            _ if quote == Some(c) => quote = None,
+            '-' if quote.is_none() && chars.peek() == Some(&'-') => comment = true,
+            '$' if quote.is_none() => {
+                let mut key = String::new();
+                while let Some(&k) = chars.peek() {
+                    if !(k.is_ascii_alphanumeric() || k == '_') {
+                        break;
+                    }
+                    key.push(k);
+                    chars.next();
+                }
+
+                if key.is_empty() {
+                    bound.push(c);
+                    continue;
+                }
+
+                let value = params
+                    .get(&key)
+                    .ok_or_else(|| ParserError::MissingParam(key.clone()))?;
+                bound.push('\'');
+                bound.push_str(&value.replace('\'', "''"));
+                bound.push('\'');
+                used.insert(key);
+                continue;
+            }
+            _ => {}
+        }
+        bound.push(c);
+    }
+
+    Ok(bound)
+}
+
+/// Splits the given SQL into its statements on the `;` outside of the quotes and the `--` comments,
+/// the statements with only whitespace or comments are dropped
+pub(crate) fn split_statements(sql: &str) -> Vec<String> {
+    let mut statements = Vec::new();
+    let mut statement = String::new();
+    let mut quote = None;
+    let mut comment = false;
+    let mut chars = sql.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\n' if comment => comment = false,
+            _ if comment => {}
+            '\'' | '"' if quote.is_none() => quote = Some(c),
+            _ if quote == Some(c) => quote = None,
+            '-' if quote.is_none() && chars.peek() == Some(&'-') => comment = true,
+            ';' if quote.is_none() => {
+                statements.push(std::mem::take(&mut statement));
+                continue;
+            }
+            _ => {}
+        }
+        statement.push(c);
+    }
+    statements.push(statement);
+
+    statements
+        .into_iter()
+        .map(|s| s.trim().to_string())
+        .filter(|s| {
+            s.lines()
+                .any(|l| !l.trim().is_empty() && !l.trim().starts_with("--"))
+        })
+        .collect()
+}
+
+/// The SQL rewritten to be parsed by sqlparser
+pub(crate) struct RewrittenSql {
+    pub sql: String,
+    /// The char offset in the given SQL for each char of the rewritten one
+    offsets: Vec<usize>,
+    /// The char count of the given SQL
+    len: usize,
+}
+
+impl RewrittenSql {
+    fn push(&mut self, c: char, offset: usize) {
+        self.sql.push(c);
+        self.offsets.push(offset);
+    }
+
+    /// Maps the char offset of the rewritten SQL back to the given SQL
+    pub fn original_offset(&self, offset: usize) -> usize {
+        self.offsets.get(offset).copied().unwrap_or(self.len)
+    }
+}
+
+/// The state of the EXCEPT list of FROM statement while rewriting it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ExceptList {
+    /// Right after EXCEPT, the list is parenthesized if '(' follows
+    Start,
+    /// Ends with FROM statement, i.e. 'EXCEPT dev, staging WHERE'
+    Bare,
+    /// Ends with ')', i.e. 'EXCEPT (dev, staging)'
+    Parenthesized,
+}
+
+/// Rewrites the given SQL to be parsed by sqlparser:
+/// * Wraps the bare identifiers containing `-` with double quotes, i.e. `kube-system` => `"kube-system"`
+/// * Replaces the `REGEXP` operator with `~`, sqlparser does not support it
+/// * Expands the `EXISTS (<condition>)` shorthand to `EXISTS (SELECT * WHERE <condition>)`
+/// * Rewrites the lookups to a field segment, i.e. `conditions['Ready']` => `conditions."[Ready]"`
+/// * Quotes `*` of FROM statement and rewrites its `EXCEPT` list to the excluded contexts prefixed
+///   with `!`, i.e. `FROM * EXCEPT dev` => `FROM "*", "!dev"`
+/// * Quotes the string literals of FROM statement as identifiers, i.e. `FROM 'file:./manifests'`
+///   => `FROM "file:./manifests"`
+/// * Quotes `user` followed by a field, sqlparser parses it as the `USER` function otherwise,
+///   i.e. `user.name` of the `kubeconfig` table => `"user".name`
+///
+/// String literals and already quoted identifiers are kept as-is.
+fn rewrite_sql(sql: &str) -> RewrittenSql {
+    let is_word = |c: char| c.is_ascii_alphanumeric() || c == '_';
+
+    let mut rewritten = RewrittenSql {
+        sql: String::with_capacity(sql.len()),
+        offsets: Vec::with_capacity(sql.len()),
+        len: sql.chars().count(),
     };
+    let mut quote: Option<char> = None;
+    // Whether the quoted string is a literal of FROM statement, quoted as an identifier
+    let mut from_literal = false;
+    let mut chars = sql.chars().enumerate().peekable();
+    // The parenthesis depth, and the one of FROM statement while in it
+    let mut depth = 0;
+    let mut from: Option<usize> = None;
+    let mut except: Option<ExceptList> = None;
 
+    while let Some((i, c)) = chars.next() {
+        match quote {
+            Some(q) => {
+                if c == q {
+                    quote = None;
+                }
+                match c == q && from_literal {
+                    true => rewritten.push('"', i),
+                    false => rewritten.push(c, i),
+                }
+            }
+            None if c == '\'' && from == Some(depth) && except.is_none() => {
+                quote = Some(c);
+                from_literal = true;
+                rewritten.push('"', i);
+            }
+            None if c == '\'' || c == '"' => {
+                from_literal = false;
+                quote = Some(c);
+                rewritten.push(c, i);
+                if c == '"' && except.is_some() {
+                    rewritten.push('!', i);
+                    except = except.map(|e| match e {
+                        ExceptList::Start => ExceptList::Bare,
+                        e => e,
+                    });
+                }
+            }
+            // An identifier can not start with a digit, i.e. '-1' or '5m'
+            None if (c.is_ascii_alphabetic() || c == '_')
+                && !rewritten.sql.ends_with(|p: char| is_word(p) || p == '-') =>
+            {
+                let mut word = vec![(i, c)];
+                while let Some(&(j, n)) = chars.peek() {
+                    if is_word(n) {
+                        word.push((j, n));
+                        chars.next();
+                        continue;
+                    }
+
+                    // Only take the dash if a word char follows, i.e. not 'a - b' or 'a--'
+                    let mut lookahead = chars.clone();
+                    lookahead.next();
+                    match lookahead.peek() {
+                        Some(&(_, w)) if n == '-' && is_word(w) => {
+                            word.push((j, n));
+                            chars.next();
+                        }
+                        _ => break,
+                    }
+                }
+
+                let text: String = word.iter().map(|(_, c)| c).collect();
+                let keyword = text.to_lowercase();
+                if from == Some(depth)
+                    && ["where", "group", "order", "limit", "having"].contains(&keyword.as_str())
+                {
+                    from = None;
+                    except = None;
+                }
+
+                if from == Some(depth) && keyword == "except" {
+                    rewritten.push(',', i);
+                    except = Some(ExceptList::Start);
+                } else if let Some(e) = except {
+                    if e == ExceptList::Start {
+                        except = Some(ExceptList::Bare);
+                    }
+                    ['"', '!'].iter().for_each(|q| rewritten.push(*q, i));
+                    word.iter().for_each(|(j, c)| rewritten.push(*c, *j));
+                    rewritten.push('"', i + word.len() - 1);
+                } else if text.eq_ignore_ascii_case("regexp") {
+                    rewritten.push('~', i);
+                } else if text.eq_ignore_ascii_case("exists") {
+                    word.iter().for_each(|(j, c)| rewritten.push(*c, *j));
+
+                    let rest: String = chars.clone().map(|(_, c)| c).collect();
+                    let is_shorthand = rest
+                        .trim_start()
+                        .strip_prefix('(')
+                        .is_some_and(|r| !r.trim_start().to_lowercase().starts_with("select"));
+                    if is_shorthand {
+                        for (j, p) in chars.by_ref() {
+                            rewritten.push(p, j);
+                            if p == '(' {
+                                "SELECT * WHERE ".chars().for_each(|s| rewritten.push(s, j));
+                                break;
+                            }
+                        }
+                    }
+                } else if text.contains('-')
+                    || (keyword == "user" && chars.peek().is_some_and(|&(_, n)| n == '.'))
+                {
+                    rewritten.push('"', i);
+                    word.iter().for_each(|(j, c)| rewritten.push(*c, *j));
+                    rewritten.push('"', i + word.len() - 1);
+                } else {
+                    if keyword == "from" {
+                        from = Some(depth);
+                    }
+                    word.iter().for_each(|(j, c)| rewritten.push(*c, *j));
+                }
+            }
+            None if c == '[' && rewritten.sql.ends_with(|p: char| is_word(p) || p == '"') => {
+                match lookup_key(chars.clone()) {
+                    Some((key, len)) => {
+                        ['.', '"', '['].iter().for_each(|s| rewritten.push(*s, i));
+                        key.iter().for_each(|(j, k)| rewritten.push(*k, *j));
+                        [']', '"'].iter().for_each(|s| rewritten.push(*s, i + len));
+                        chars.nth(len - 1);
+                    }
+                    None => rewritten.push(c, i),
+                }
+            }
+            None if c == '(' && except == Some(ExceptList::Start) => {
+                except = Some(ExceptList::Parenthesized);
+            }
+            None if c == ')' && except == Some(ExceptList::Parenthesized) => except = None,
+            None if c == '*' && from == Some(depth) && except.is_none() => {
+                ['"', '*', '"'].iter().for_each(|s| rewritten.push(*s, i));
+            }
+            None => {
+                match c {
+                    '(' => depth += 1,
+                    ')' => depth = depth.saturating_sub(1),
+                    _ => {}
+                }
+                // FROM statement ends with its subquery or statement
+                if c == ';' || from.is_some_and(|d| d > depth) {
+                    from = None;
+                    except = None;
+                }
+                rewritten.push(c, i)
+            }
+        }
+    }
+
+    rewritten
+}
+
+/// Returns the chars of the quoted key following '[' with their offsets, and the count of the
+/// chars up to the closing ']', i.e. "'Ready']"
+fn lookup_key(chars: impl Iterator<Item = (usize, char)>) -> Option<(Vec<(usize, char)>, usize)> {
+    let mut chars = chars.enumerate().skip_while(|(_, (_, c))| *c == ' ');
+    let (_, (_, quote)) = chars.next()?;
+    if quote != '\'' {
+        return None;
+    }
+
+    let mut key = Vec::new();
+    let mut closed = false;
+    for (_, (j, c)) in chars.by_ref() {
+        match c {
+            '\'' => {
+                closed = true;
+                break;
+            }
+            '"' | '[' | ']' => return None,
+            _ => key.push((j, c)),
+        }
+    }
+
+    let (n, (_, c)) = chars.find(|(_, (_, c))| *c != ' ')?;
+    (closed && !key.is_empty() && c == ']').then_some((key, n + 1))
+}
+
+#[tracing::instrument(name = "plan", skip_all)]
+fn parse_query(
+    sql: &str,
+    query: &ast::Query,
+    mut plan_context: planner::PlanContext,
+) -> Result<ApiStatement, ParserError> {
     let mut queries = ApiQueries {
         namespaces: vec![],
+        all_namespaces: false,
+        excluded_namespaces: plan_context.excluded_namespaces.clone(),
         contexts: vec![],
         queries: vec![],
+        projections: vec![],
+        distinct: false,
+        clusters: vec![],
+        sources: vec![],
     };
 
     match &*query.body {
@@ -119,11 +1192,98 @@ pub(crate) fn parse_sql(sql: &str) -> Result<ApiQueries, ParserError> {
                 return Err(ParserError::SelectProjectionsRequired);
             }
 
+            if let Some(table) = local_table(&s.from) {
+                return parse_local(sql, s, table, plan_context).map(ApiStatement::Local);
+            }
+
+            if !s.group_by.is_empty() {
+                return parse_aggregates(s, &plan_context).map(ApiStatement::Aggregate);
+            }
+
+            queries.distinct = s.distinct;
+            queries.contexts = parse_from(&s.from, &plan_context)?;
+
             // SELECT ...
             for p in &s.projection {
                 match p {
+                    // i.e. 'pod.status.phase' to print the field of the matched resources
+                    SelectItem::UnnamedExpr(Expr::CompoundIdentifier(ids))
+                        if parse_projection(ids, &plan_context).is_some() =>
+                    {
+                        queries.projections.extend(parse_projection(ids, &plan_context));
+                    }
+                    // i.e. 'lower(pod.metadata.name)' to print the field with the functions applied,
+                    // or 'UNNEST(pod.spec.containers.image)' to print each value on its own row
+                    SelectItem::UnnamedExpr(e @ Expr::Function(_)) => {
+                        let (unnest, field) = unnested(e);
+                        let plan = field
+                            .plan(&mut plan_context)
+                            .map_err(|e| plan_error(sql, &queries.contexts, &e))?;
+                        let (ids, functions) = match plan {
+                            Value::Strings(ids) if unnest => (ids, vec![]),
+                            Value::Function(ids, functions) => (ids, functions),
+                            _ => (vec![], vec![]),
+                        };
+                        match ids.first() {
+                            Some(kind)
+                                if ids.len() >= 2
+                                    && (plan_context.kind(kind).is_some()
+                                        || kind == planner::SOURCE) =>
+                            {
+                                queries.projections.push(Projection {
+                                    kind: plan_context.kind_name(kind),
+                                    fields: ids[1..].to_vec(),
+                                    functions,
+                                    unnest,
+                                });
+                            }
+                            _ => {
+                                return Err(ParserError::Unsupported(format!(
+                                    "SELECT function requires a field with a kind, i.e. 'lower(pod.metadata.name)': {}",
+                                    e
+                                )))
+                            }
+                        }
+                    }
+                    // i.e. 'kube-system', string literals are kept as-is
+                    SelectItem::UnnamedExpr(Expr::Value(ast::Value::SingleQuotedString(ns))) => {
+                        queries.namespaces.push(ns.clone());
+                    }
+                    // i.e. '__context', the metadata columns of each row
+                    SelectItem::UnnamedExpr(Expr::Identifier(i))
+                        if planner::source_column(&i.value).is_some() =>
+                    {
+                        queries.projections.push(Projection {
+                            kind: planner::SOURCE.to_string(),
+                            fields: planner::source_column(&i.value)
+                                .into_iter()
+                                .map(str::to_string)
+                                .collect(),
+                            functions: vec![],
+                            unnest: false,
+                        });
+                    }
+                    SelectItem::UnnamedExpr(Expr::Identifier(ns)) => {
+                        queries.namespaces.push(ns.value.clone());
+                    }
+                    // i.e. 'app.status.health', the short name of a custom resource
+                    SelectItem::UnnamedExpr(Expr::CompoundIdentifier(ids)) => {
+                        let path = ids
+                            .iter()
+                            .map(|i| i.value.as_str())
+                            .collect::<Vec<&str>>()
+                            .join(".");
+                        return Err(plan_error(
+                            sql,
+                            &queries.contexts,
+                            &PlanError::UnknownKind(path),
+                        ));
+                    }
                     SelectItem::UnnamedExpr(o) => {
-                        queries.namespaces.push(o.to_string().replace('_', "-"));
+                        return Err(ParserError::Unsupported(format!(
+                            "SELECT statement does only support namespaces and fields: {}",
+                            o
+                        )))
                     }
                     SelectItem::ExprWithAlias { .. } => {
                         return Err(ParserError::Unsupported(
@@ -136,80 +1296,51 @@ pub(crate) fn parse_sql(sql: &str) -> Result<ApiQueries, ParserError> {
                                 .to_string(),
                         ))
                     }
-                    SelectItem::Wildcard(_) => {
-                        return Err(ParserError::Unsupported(
-                            "SELECT statement does not support Wildcard selector!".to_string(),
-                        ))
+                    // i.e. "* EXCEPT ('kube-system', kube-public)", all the namespaces but the given ones
+                    SelectItem::Wildcard(options)
+                        if options.opt_exclude.is_none() && options.opt_rename.is_none() =>
+                    {
+                        queries.all_namespaces = true;
+                        if let Some(except) = &options.opt_except {
+                            queries.excluded_namespaces.push(except.first_element.value.clone());
+                            queries.excluded_namespaces.extend(
+                                except.additional_elements.iter().map(|ns| ns.value.clone()),
+                            );
+                        }
+                    }
+                    SelectItem::Wildcard(options) => {
+                        return Err(ParserError::Unsupported(format!(
+                            "SELECT * only supports EXCEPT with the namespaces, i.e. \"* EXCEPT ('kube-system')\": *{}",
+                            options
+                        )))
                     }
                 }
             }
 
-            if s.from.is_empty() {
-                return Err(ParserError::SelectFromRequired);
+            if !plan_context.override_namespaces.is_empty() {
+                queries.namespaces = plan_context.override_namespaces.clone();
+                queries.all_namespaces = false;
             }
 
-            // FROM ...
-            for f in &s.from {
-                if !f.joins.is_empty() {
-                    return Err(ParserError::Unsupported(
-                        "FROM statement does not support Join!".to_string(),
-                    ));
-                }
-                match &f.relation {
-                    TableFactor::Table {
-                        name,
-                        alias,
-                        args,
-                        with_hints,
-                        ..
-                    } => {
-                        if alias.is_some() {
-                            return Err(ParserError::Unsupported(
-                                "FROM statement does not support Table aliases!".to_string(),
-                            ));
-                        }
+            if queries.namespaces.is_empty() && !queries.all_namespaces {
+                return Err(ParserError::SelectProjectionsRequired);
+            }
 
-                        if let Some(args) = args {
-                            if !args.is_empty() {
-                                return Err(ParserError::Unsupported(
-                                    "FROM statement does not support Table ARGS!".to_string(),
-                                ));
-                            }
-                        }
-                        if !with_hints.is_empty() {
-                            return Err(ParserError::Unsupported(
-                                "FROM statement does not support Table HINT!".to_string(),
-                            ));
-                        }
-                        queries.contexts.push(name.to_string().replace('_', "-"));
-                    }
-                    TableFactor::Derived { .. } => {
-                        return Err(ParserError::Unsupported(
-                            "FROM statement does not support Derived!".to_string(),
-                        ))
-                    }
-                    TableFactor::TableFunction { .. } => {
-                        return Err(ParserError::Unsupported(
-                            "FROM statement does not support TableFunction!".to_string(),
-                        ))
-                    }
-                    TableFactor::NestedJoin { .. } => {
-                        return Err(ParserError::Unsupported(
-                            "FROM statement does not support NestedJoin!".to_string(),
-                        ))
-                    }
-                    TableFactor::UNNEST { .. } => {
-                        return Err(ParserError::Unsupported(
-                            "FROM statement does not support UNNEST!".to_string(),
-                        ))
-                    }
-                }
+            if queries.distinct && queries.projections.is_empty() {
+                return Err(ParserError::Unsupported(
+                    "SELECT DISTINCT requires at least one field, i.e. 'pod.spec.containers.image'"
+                        .to_string(),
+                ));
             }
 
             // WHERE
             if let Some(w) = &s.selection {
-                let mut plan_context = planner::PlanContext::default();
-                let plan = w.to_owned().plan(&mut plan_context).unwrap();
+                // The predicates can be scoped with the given contexts and namespaces
+                plan_context.contexts = queries.contexts.clone();
+                plan_context.namespaces = queries.namespaces.clone();
+
+                let plan = planner::plan_predicate(w, &mut plan_context)
+                    .map_err(|e| plan_error(sql, &queries.contexts, &e))?;
                 match plan {
                     Value::Queries(q) => queries.queries = q,
                     Value::Query(q) => queries.queries.push(q),
@@ -220,6 +1351,51 @@ pub(crate) fn parse_sql(sql: &str) -> Result<ApiQueries, ParserError> {
                         )))
                     }
                 }
+
+                // i.e. "cluster.version < '1.27'", the contexts are selected before the query
+                queries.clusters = split_pseudo_kind(
+                    &mut queries,
+                    planner::CLUSTER,
+                    &format!("'{}' predicates", planner::CLUSTER),
+                )?;
+                // i.e. "__context LIKE 'prod-%'", the contexts and namespaces are selected as well
+                queries.sources =
+                    split_pseudo_kind(&mut queries, planner::SOURCE, "metadata column predicates")?;
+
+                // i.e. 'status.phase' printed next to the names of the pods by --display
+                if !queries.distinct {
+                    let display = display_projections(&queries, &plan_context);
+                    queries.projections.extend(display);
+                }
+
+                // i.e. 'pod.Status.Phase' or 'pod.spec.container.image', as in the schemas
+                validator::resolve_fields(&mut queries.queries, &mut queries.projections);
+
+                // The predicates on the unknown fields are still evaluated, as NULL
+                let unknown_fields = validator::unknown_fields(&queries.clusters)
+                    .into_iter()
+                    .chain(validator::unknown_fields(&queries.queries));
+                for unknown in unknown_fields {
+                    tracing::warn!(predicate = %unknown.predicate, "{}", unknown);
+                }
+
+                // Only the kinds given in WHERE statement are printed, the metadata columns
+                // are printed for all of them
+                for p in &queries.projections {
+                    if p.kind != planner::SOURCE
+                        && !queries
+                            .queries
+                            .iter()
+                            .any(|q| q.kind.eq_ignore_ascii_case(&p.kind))
+                    {
+                        return Err(ParserError::Unsupported(format!(
+                            "SELECT field '{}.{}' requires a WHERE statement for '{}'!",
+                            p.kind,
+                            p.path(),
+                            p.kind
+                        )));
+                    }
+                }
             } else {
                 return Err(ParserError::Unsupported(
                     "WHERE statement is required in order to set --field-selector!".to_string(),
@@ -234,9 +1410,665 @@ pub(crate) fn parse_sql(sql: &str) -> Result<ApiQueries, ParserError> {
         }
     }
 
-    Ok(queries)
+    Ok(ApiStatement::Query(queries))
+}
+
+/// Returns the local table given in FROM statement, if it is the only one, i.e. 'kubeconfig'
+fn local_table(from: &[TableWithJoins]) -> Option<LocalTable> {
+    match from {
+        [TableWithJoins {
+            relation: TableFactor::Table { name, .. },
+            ..
+        }] => LocalTable::from_name(&object_name(name)),
+        _ => None,
+    }
+}
+
+/// Parses a SELECT statement on the given local table, the SELECT fields and the predicates are
+/// its columns, i.e. 'cluster.server'. The metadata columns, the subqueries and GROUP BY are
+/// not supported, there are no contexts nor namespaces
+fn parse_local(
+    sql: &str,
+    s: &Select,
+    table: LocalTable,
+    mut plan_context: planner::PlanContext,
+) -> Result<ApiLocal, ParserError> {
+    let unsupported = |what: &str| {
+        ParserError::Unsupported(format!(
+            "{} is not supported on the local table '{}'!",
+            what, table
+        ))
+    };
+    if !s.from[0].joins.is_empty() {
+        return Err(unsupported("Join"));
+    }
+    if !s.group_by.is_empty() {
+        return Err(unsupported("GROUP BY"));
+    }
+
+    plan_context.table = Some(table.to_string());
+    let mut local = ApiLocal {
+        table,
+        projections: vec![],
+        queries: vec![],
+        distinct: s.distinct,
+    };
+
+    let unsupported_field = |p: &SelectItem| {
+        ParserError::Unsupported(format!(
+            "SELECT statement on the local table '{}' does only support its columns: {}",
+            table, p
+        ))
+    };
+
+    // SELECT ...
+    for p in &s.projection {
+        let plan = match p {
+            // i.e. 'name, cluster.server' or 'lower(user.name)'
+            SelectItem::UnnamedExpr(e) => e
+                .plan(&mut plan_context)
+                .map_err(|e| plan_error(sql, &[], &e))?,
+            SelectItem::Wildcard(options)
+                if options.opt_except.is_none()
+                    && options.opt_exclude.is_none()
+                    && options.opt_rename.is_none() =>
+            {
+                local
+                    .projections
+                    .extend(table.columns().iter().map(|c| Projection {
+                        kind: table.to_string(),
+                        fields: c.split('.').map(str::to_string).collect(),
+                        functions: vec![],
+                        unnest: false,
+                    }));
+                continue;
+            }
+            _ => return Err(unsupported_field(p)),
+        };
+        let (ids, functions) = match plan {
+            Value::Strings(ids) => (ids, vec![]),
+            Value::Function(ids, functions) => (ids, functions),
+            _ => return Err(unsupported_field(p)),
+        };
+        local.projections.push(Projection {
+            kind: table.to_string(),
+            fields: ids[1..].to_vec(),
+            functions,
+            unnest: false,
+        });
+    }
+
+    // WHERE
+    if let Some(w) = &s.selection {
+        match planner::plan_predicate(w, &mut plan_context).map_err(|e| plan_error(sql, &[], &e))? {
+            Value::Queries(q) => local.queries = q,
+            Value::Query(q) => local.queries.push(q),
+            plan => {
+                return Err(ParserError::Unsupported(format!(
+                    "Unable to handle unsupported query plan: {:?}",
+                    plan
+                )))
+            }
+        }
+    }
+    if local.queries.iter().any(|q| q.subquery.is_some()) {
+        return Err(unsupported("Subquery"));
+    }
+
+    Ok(local)
+}
+
+/// Renders the given plan error against the query. The unknown kinds are returned with the
+/// contexts of the query, so they can be looked up in the short names discovered on them
+fn plan_error(sql: &str, contexts: &[String], e: &PlanError) -> ParserError {
+    let diagnostic = diagnostic::plan_error(sql, e);
+    match e {
+        PlanError::UnknownKind(_) => ParserError::UnknownKind(contexts.to_vec(), diagnostic),
+        _ => ParserError::Plan(diagnostic),
+    }
+}
+
+/// Parses the given CompoundIdentifier starts with a kind, i.e. 'pod.status.phase'
+fn parse_projection(ids: &[Ident], plan_context: &planner::PlanContext) -> Option<Projection> {
+    if ids.len() < 2 || plan_context.kind(&ids[0].value).is_none() {
+        return None;
+    }
+
+    Some(Projection {
+        kind: plan_context.kind_name(&ids[0].value),
+        fields: ids[1..].iter().map(|i| i.value.clone()).collect(),
+        functions: vec![],
+        unnest: false,
+    })
+}
+
+/// Returns the display fields of the kinds given in WHERE statement, none of whose fields is selected
+fn display_projections(
+    queries: &ApiQueries,
+    plan_context: &planner::PlanContext,
+) -> Vec<Projection> {
+    let mut projections = Vec::new();
+    for (kind, path) in &plan_context.display {
+        let kind = plan_context.kind_name(kind);
+        let queried = queries
+            .queries
+            .iter()
+            .any(|q| plan_context.kind_name(&q.kind) == kind);
+        if queried && !queries.projections.iter().any(|p| p.kind == kind) {
+            projections.push(Projection {
+                kind,
+                fields: path.split('.').map(str::to_string).collect(),
+                functions: vec![],
+                unnest: false,
+            });
+        }
+    }
+    projections
+}
+
+/// Returns whether the given SELECT field is unnested and the field itself, i.e. 'true' and
+/// 'pod.spec.containers.image' of 'UNNEST(pod.spec.containers.image)'
+fn unnested(expr: &Expr) -> (bool, &Expr) {
+    match expr {
+        Expr::Function(f) if f.name.to_string().eq_ignore_ascii_case("unnest") => {
+            match f.args.as_slice() {
+                [FunctionArg::Unnamed(FunctionArgExpr::Expr(field))] => (true, field),
+                _ => (false, expr),
+            }
+        }
+        _ => (false, expr),
+    }
+}
+
+fn parse_aggregates(
+    s: &Select,
+    plan_context: &planner::PlanContext,
+) -> Result<ApiAggregates, ParserError> {
+    // GROUP BY ...
+    let group_by = match s.group_by.as_slice() {
+        [Expr::Identifier(i)] if i.value.eq_ignore_ascii_case("node") => GroupBy::Node,
+        [Expr::Identifier(i)] if i.value.eq_ignore_ascii_case("namespace") => GroupBy::Namespace,
+        _ => {
+            return Err(ParserError::Unsupported(
+                "GROUP BY statement does only support 'node' and 'namespace'!".to_string(),
+            ))
+        }
+    };
+
+    if s.selection.is_some() {
+        return Err(ParserError::Unsupported(
+            "WHERE statement is not supported with GROUP BY!".to_string(),
+        ));
+    }
+
+    if s.distinct {
+        return Err(ParserError::Unsupported(
+            "DISTINCT is not supported with GROUP BY!".to_string(),
+        ));
+    }
+
+    if !plan_context.override_namespaces.is_empty() {
+        return Err(ParserError::Unsupported(
+            "--namespaces is not supported with GROUP BY, all the namespaces are grouped!"
+                .to_string(),
+        ));
+    }
+
+    // SELECT node.allocatable.cpu, SUM(pod.requests.cpu), COUNT(*) ...
+    let mut columns: Vec<Aggregate> = Vec::new();
+    for p in &s.projection {
+        let column = match p {
+            SelectItem::UnnamedExpr(e) => parse_aggregate(e, &group_by, plan_context),
+            _ => None,
+        };
+
+        match column {
+            Some(c) => columns.push(c),
+            None => {
+                return Err(ParserError::Unsupported(format!(
+                    "SELECT statement does only support '{}' fields, COUNT(*) and SUM of 'pod' fields with GROUP BY: {}",
+                    group_by, p
+                )))
+            }
+        }
+    }
+
+    // HAVING COUNT(*) > 50 ...
+    let mut having: Vec<Having> = Vec::new();
+    if let Some(h) = &s.having {
+        parse_having(h, &group_by, plan_context, &mut having)?;
+    }
+
+    Ok(ApiAggregates {
+        contexts: parse_from(&s.from, plan_context)?,
+        group_by,
+        columns,
+        having,
+    })
+}
+
+/// Parses a column of GROUP BY statement, i.e. 'node.allocatable.cpu', 'SUM(pod.requests.cpu)' or 'COUNT(*)'
+fn parse_aggregate(
+    expr: &Expr,
+    group_by: &GroupBy,
+    plan_context: &planner::PlanContext,
+) -> Option<Aggregate> {
+    match expr {
+        Expr::CompoundIdentifier(ids) => parse_projection(ids, plan_context)
+            .filter(|p| p.kind == group_by.to_string())
+            .map(Aggregate::Field),
+        Expr::Function(f) if f.name.to_string().eq_ignore_ascii_case("sum") => {
+            match f.args.as_slice() {
+                [FunctionArg::Unnamed(FunctionArgExpr::Expr(Expr::CompoundIdentifier(ids)))] => {
+                    parse_projection(ids, plan_context)
+                        .filter(|p| p.kind == ResourceType::Pod.to_string())
+                        .map(Aggregate::Sum)
+                }
+                _ => None,
+            }
+        }
+        Expr::Function(f) if f.name.to_string().eq_ignore_ascii_case("count") => {
+            match f.args.as_slice() {
+                [FunctionArg::Unnamed(FunctionArgExpr::Wildcard)] => Some(Aggregate::Count),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Parses the comparisons of the aggregates combined with AND, i.e. 'COUNT(*) > 50 AND SUM(pod.requests.cpu) > 4'
+fn parse_having(
+    expr: &Expr,
+    group_by: &GroupBy,
+    plan_context: &planner::PlanContext,
+    having: &mut Vec<Having>,
+) -> Result<(), ParserError> {
+    match expr {
+        Expr::Nested(e) => parse_having(e, group_by, plan_context, having),
+        Expr::BinaryOp {
+            left,
+            op: BinaryOperator::And,
+            right,
+        } => {
+            parse_having(left, group_by, plan_context, having)?;
+            parse_having(right, group_by, plan_context, having)
+        }
+        Expr::BinaryOp { left, op, right }
+            if matches!(
+                op,
+                BinaryOperator::Eq
+                    | BinaryOperator::NotEq
+                    | BinaryOperator::Gt
+                    | BinaryOperator::GtEq
+                    | BinaryOperator::Lt
+                    | BinaryOperator::LtEq
+            ) =>
+        {
+            let aggregate = parse_aggregate(left, group_by, plan_context).ok_or_else(|| {
+                ParserError::Unsupported(format!(
+                    "HAVING statement does only support '{}' fields, COUNT(*) and SUM of 'pod' fields: {}",
+                    group_by, left
+                ))
+            })?;
+            let value = match &**right {
+                Expr::Value(ast::Value::Number(v, _))
+                | Expr::Value(ast::Value::SingleQuotedString(v)) => v.clone(),
+                _ => {
+                    return Err(ParserError::Unsupported(format!(
+                        "HAVING statement does only support numbers and quoted values: {}",
+                        right
+                    )))
+                }
+            };
+
+            having.push(Having {
+                aggregate,
+                op: op.clone(),
+                value,
+            });
+            Ok(())
+        }
+        _ => Err(ParserError::Unsupported(format!(
+            "HAVING statement does only support comparisons combined with AND: {}",
+            expr
+        ))),
+    }
+}
+
+/// Returns the contexts given in FROM statement, or the default ones if not given.
+/// The ones given by --contexts take precedence over both
+fn parse_from(
+    from: &[TableWithJoins],
+    plan_context: &planner::PlanContext,
+) -> Result<Vec<String>, ParserError> {
+    let mut contexts: Vec<ContextSelector> = Vec::new();
+
+    if !plan_context.override_contexts.is_empty() {
+        return Ok(plan_context.override_contexts.clone());
+    }
+
+    if from.is_empty() && !plan_context.default_contexts.is_empty() {
+        return Ok(plan_context.default_contexts.clone());
+    }
+
+    if from.is_empty() {
+        return Err(ParserError::SelectFromRequired);
+    }
+
+    // FROM ...
+    for f in from {
+        if !f.joins.is_empty() {
+            return Err(ParserError::Unsupported(
+                "FROM statement does not support Join!".to_string(),
+            ));
+        }
+        match &f.relation {
+            TableFactor::Table {
+                name,
+                alias,
+                args,
+                with_hints,
+                ..
+            } => {
+                if alias.is_some() {
+                    return Err(ParserError::Unsupported(
+                        "FROM statement does not support Table aliases!".to_string(),
+                    ));
+                }
+
+                if !with_hints.is_empty() {
+                    return Err(ParserError::Unsupported(
+                        "FROM statement does not support Table HINT!".to_string(),
+                    ));
+                }
+                match args {
+                    // i.e. "labels(env = 'prod')"
+                    Some(args) if object_name(name).eq_ignore_ascii_case("labels") => {
+                        contexts.push(ContextSelector::Labels(
+                            args.iter()
+                                .map(parse_label)
+                                .collect::<Result<Vec<_>, _>>()?,
+                        ));
+                    }
+                    Some(args) if !args.is_empty() => {
+                        return Err(ParserError::Unsupported(
+                            "FROM statement does not support Table ARGS!".to_string(),
+                        ));
+                    }
+                    _ => contexts.push(ContextSelector::from(object_name(name))),
+                }
+            }
+            TableFactor::Derived { .. } => {
+                return Err(ParserError::Unsupported(
+                    "FROM statement does not support Derived!".to_string(),
+                ))
+            }
+            TableFactor::TableFunction { .. } => {
+                return Err(ParserError::Unsupported(
+                    "FROM statement does not support TableFunction!".to_string(),
+                ))
+            }
+            TableFactor::NestedJoin { .. } => {
+                return Err(ParserError::Unsupported(
+                    "FROM statement does not support NestedJoin!".to_string(),
+                ))
+            }
+            TableFactor::UNNEST { .. } => {
+                return Err(ParserError::Unsupported(
+                    "FROM statement does not support UNNEST!".to_string(),
+                ))
+            }
+        }
+    }
+
+    select_contexts(&contexts)
+}
+
+/// A context given in FROM statement, or the contexts of the kubeconfig it selects
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum ContextSelector {
+    Name(String),
+    /// i.e. '*', all the contexts of the kubeconfig
+    All,
+    /// i.e. "labels(env = 'prod', tier != 'dev')", the contexts matching all the labels.
+    /// A label is the key, the value and whether they must be equal
+    Labels(Vec<(String, String, bool)>),
+    /// i.e. 'EXCEPT dev', rewritten as '"!dev"'. The context is not queried even if selected
+    Except(String),
+}
+
+impl From<String> for ContextSelector {
+    fn from(name: String) -> ContextSelector {
+        match name.strip_prefix('!') {
+            _ if name == "*" => ContextSelector::All,
+            Some(except) => ContextSelector::Except(except.to_string()),
+            None => ContextSelector::Name(name),
+        }
+    }
+}
+
+/// Parses a label of 'labels(...)' in FROM statement, i.e. "env = 'prod'" or "tier != 'dev'"
+fn parse_label(arg: &FunctionArg) -> Result<(String, String, bool), ParserError> {
+    if let FunctionArg::Unnamed(FunctionArgExpr::Expr(Expr::BinaryOp { left, op, right })) = arg {
+        let key = match &**left {
+            Expr::Identifier(i) => Some(i.value.clone()),
+            Expr::CompoundIdentifier(ids) => Some(object_name(&ObjectName(ids.clone()))),
+            _ => None,
+        };
+        let equal = match op {
+            BinaryOperator::Eq => Some(true),
+            BinaryOperator::NotEq => Some(false),
+            _ => None,
+        };
+        if let (Some(key), Some(equal), Expr::Value(ast::Value::SingleQuotedString(value))) =
+            (key, equal, &**right)
+        {
+            return Ok((key, value.clone(), equal));
+        }
+    }
+
+    Err(ParserError::Unsupported(format!(
+        "FROM labels(...) only supports \"key = 'value'\" and \"key != 'value'\": {}",
+        arg
+    )))
+}
+
+/// Returns the names of the given contexts in FROM order, with the ones selected by '*' and
+/// by labels but without the excepted ones. The kubeconfig is read only if any is selected
+fn select_contexts(selectors: &[ContextSelector]) -> Result<Vec<String>, ParserError> {
+    let selects = selectors
+        .iter()
+        .any(|s| matches!(s, ContextSelector::All | ContextSelector::Labels(_)));
+    let kubeconfig = match selects {
+        true => contexts::list(&parse_kubeconfig()?),
+        false => vec![],
+    };
+
+    let mut names: Vec<String> = Vec::new();
+    for selector in selectors {
+        let selected = kubeconfig.iter().filter(|c| match selector {
+            ContextSelector::All => true,
+            ContextSelector::Labels(labels) => labels
+                .iter()
+                .all(|(key, value, equal)| (c.labels.get(key) == Some(value)) == *equal),
+            _ => false,
+        });
+        match selector {
+            ContextSelector::Name(name) => names.push(name.clone()),
+            _ => selected.for_each(|c| {
+                if !names.contains(&c.name) {
+                    names.push(c.name.clone());
+                }
+            }),
+        }
+    }
+    names.retain(|name| !selectors.contains(&ContextSelector::Except(name.clone())));
+
+    if selects && names.is_empty() {
+        return Err(ParserError::NoContextSelected);
+    }
+    Ok(names)
+}
+
+/// Returns the name without quotes, i.e. '"kube-system"' => 'kube-system'
+fn object_name(name: &ObjectName) -> String {
+    name.0
+        .iter()
+        .map(|i| i.value.as_str())
+        .collect::<Vec<&str>>()
+        .join(".")
+}
+
+fn parse_insert(
+    table_name: &ObjectName,
+    columns: &[Ident],
+    source: &ast::Query,
+    plan_context: &planner::PlanContext,
+) -> Result<ApiInserts, ParserError> {
+    let rows = match &*source.body {
+        SetExpr::Values(v) => &v.rows,
+        _ => {
+            return Err(ParserError::Unsupported(
+                "INSERT statement does only support VALUES!".to_string(),
+            ))
+        }
+    };
+
+    // The ones given by --contexts and --namespaces take precedence, as for SELECT
+    let mut inserts = ApiInserts {
+        contexts: match plan_context.override_contexts.is_empty() {
+            true => vec![object_name(table_name)],
+            false => plan_context.override_contexts.clone(),
+        },
+        namespaces: match plan_context.override_namespaces.is_empty() {
+            true => columns.iter().map(|c| c.value.clone()).collect(),
+            false => plan_context.override_namespaces.clone(),
+        },
+        manifests: vec![],
+    };
+
+    // VALUES ('<manifest>'), ('<manifest>') ...
+    for row in rows {
+        for expr in row {
+            match expr {
+                Expr::Value(ast::Value::SingleQuotedString(s)) => {
+                    inserts.manifests.push(parse_manifest(s)?)
+                }
+                _ => {
+                    return Err(ParserError::Unsupported(format!(
+                        "INSERT statement does only support quoted YAML or JSON manifests: {}",
+                        expr
+                    )))
+                }
+            }
+        }
+    }
+
+    Ok(inserts)
+}
+
+fn parse_manifest(manifest: &str) -> Result<serde_json::Value, ParserError> {
+    // YAML is a superset of JSON, so both of them can be given here
+    let value: serde_json::Value =
+        serde_yaml::from_str(manifest).map_err(|e| ParserError::InvalidManifest(e.to_string()))?;
+
+    for field in &["apiVersion", "kind"] {
+        if value.get(field).and_then(|v| v.as_str()).is_none() {
+            return Err(ParserError::InvalidManifest(format!(
+                "'{}' field is required",
+                field
+            )));
+        }
+    }
+
+    if value.pointer("/metadata/name").is_none() {
+        return Err(ParserError::InvalidManifest(
+            "'metadata.name' field is required".to_string(),
+        ));
+    }
+
+    Ok(value)
 }
 
 pub(crate) fn parse_kubeconfig() -> Result<Kubeconfig, ParserError> {
     kube::config::Kubeconfig::read().map_err(ParserError::KubeConfigError)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn field_selector_of_the_field_labels() {
+        assert!(ResourceType::Pod.supports_field_selector("status.phase"));
+        assert!(ResourceType::Pod.supports_field_selector("spec.nodeName"));
+        assert!(ResourceType::Pod.supports_field_selector("metadata.name"));
+        assert!(ResourceType::Node.supports_field_selector("spec.unschedulable"));
+        assert!(ResourceType::ConfigMap.supports_field_selector("metadata.namespace"));
+    }
+
+    #[test]
+    fn no_field_selector_of_the_other_fields() {
+        assert!(!ResourceType::Pod.supports_field_selector("spec.containers.image"));
+        assert!(!ResourceType::Deployment.supports_field_selector("spec.replicas"));
+        assert!(!ResourceType::Service.supports_field_selector("spec.type"));
+        assert!(!ResourceType::Node.supports_field_selector("status.phase"));
+        assert!(!ResourceType::PodMetrics.supports_field_selector("metadata.name"));
+        assert!(!ResourceType::HelmRelease.supports_field_selector("metadata.name"));
+    }
+
+    fn params(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn binds_the_placeholders() {
+        let bound = bind_params(
+            "SELECT * FROM $ctx WHERE pod.metadata.name = $name",
+            &params(&[("ctx", "prod"), ("name", "it's")]),
+        );
+        assert_eq!(
+            bound.unwrap(),
+            "SELECT * FROM 'prod' WHERE pod.metadata.name = 'it''s'"
+        );
+    }
+
+    #[test]
+    fn binds_no_placeholders_in_quotes_or_comments() {
+        let sql = "SELECT \"$ns\" FROM x WHERE pod.metadata.name = '$name' -- $name\nAND $app";
+        let bound = bind_params(sql, &params(&[("app", "api")]));
+        assert_eq!(
+            bound.unwrap(),
+            "SELECT \"$ns\" FROM x WHERE pod.metadata.name = '$name' -- $name\nAND 'api'"
+        );
+    }
+
+    #[test]
+    fn keeps_the_dollars_without_a_key() {
+        let bound = bind_params("SELECT '$' || $$ || $", &HashMap::new());
+        assert_eq!(bound.unwrap(), "SELECT '$' || $$ || $");
+    }
+
+    #[test]
+    fn rejects_the_unknown_params() {
+        let bound = bind_params("SELECT $1 FROM x", &HashMap::new());
+        assert!(matches!(bound, Err(ParserError::MissingParam(key)) if key == "1"));
+    }
+
+    #[test]
+    fn rejects_the_params_unused_by_all_the_queries() {
+        let params = params(&[("a", "1"), ("b", "2")]);
+        let bound = bind_all(&["SELECT $a FROM x", "SELECT $b FROM y"], &params);
+        assert_eq!(
+            bound.unwrap(),
+            vec!["SELECT '1' FROM x", "SELECT '2' FROM y"]
+        );
+
+        let bound = bind_all(&["SELECT $a FROM x -- $b"], &params);
+        assert!(matches!(bound, Err(ParserError::UnusedParam(key)) if key == "b"));
+    }
+}
@@ -17,25 +17,327 @@
 // IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
 // CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
 
+use crate::derived;
+use crate::eval;
+use crate::functions::{self, Call};
+use crate::parser::ResourceType;
+use regex::{Regex, RegexBuilder};
 use sqlparser::ast;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
 use thiserror::Error;
 
+/// The pseudo-kind of the API server of each context, its predicates select the contexts to
+/// query, i.e. "cluster.version < '1.27'"
+pub const CLUSTER: &str = "cluster";
+
+/// The pseudo-kind of the metadata columns of each row, the context, namespace and cluster
+/// server it was listed from. Its predicates select the contexts and namespaces to query
+pub const SOURCE: &str = "__source";
+
+/// The metadata columns of each row, given without a kind, i.e. "__context LIKE 'prod-%'"
+pub const SOURCE_COLUMNS: [&str; 3] = ["__context", "__namespace", "__cluster_server"];
+
+/// Returns the metadata column of the given identifier, if it is one
+pub fn source_column(ident: &str) -> Option<&'static str> {
+    SOURCE_COLUMNS
+        .iter()
+        .find(|c| c.eq_ignore_ascii_case(ident))
+        .copied()
+}
+
 #[derive(Debug, Clone)]
 pub struct Query {
     pub key: Option<ast::BinaryOperator>,
+    /// The predicate applies only to this context if given, i.e. 'ctx_prod.pod.status.phase'
+    pub context: Option<String>,
+    /// The predicate applies only to this namespace if given, i.e. 'kube_system.pod.status.phase'
+    pub namespace: Option<String>,
     pub kind: String,
-    pub field1: String,
-    pub field2: String,
+    pub fields: Vec<String>,
     pub eq: String,
     pub op: ast::BinaryOperator,
+    /// Whether the strings are compared case-insensitively, i.e. '--case-insensitive'
+    pub case_insensitive: bool,
+    /// The compiled pattern of the regex operators, i.e. '~', compiled once per query
+    pub pattern: Option<Regex>,
+    /// The values of IN operator, i.e. "IN ('Running', 'Pending')"
+    pub values: Vec<String>,
+    /// The subquery of IN operator, its results are substituted into the values before evaluation
+    pub subquery: Option<Box<SubQuery>>,
+    /// The field path of the same object to compare with instead of eq, i.e. 'spec.maxReplicas'
+    pub other: Option<Vec<String>>,
+    /// The functions applied to the field values before the comparison, the innermost first
+    pub functions: Vec<Call>,
+    /// Whether any or all the values of an array field must match, i.e. 'ALL(pod.spec.containers.image)'.
+    /// If not given, any value matches the operator and none matches the negated one
+    pub quantifier: Option<Quantifier>,
+}
+
+/// How a predicate on an array field matches its values, the negated operators included
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Quantifier {
+    Any,
+    All,
+}
+
+impl fmt::Display for Quantifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Quantifier::Any => write!(f, "ANY"),
+            Quantifier::All => write!(f, "ALL"),
+        }
+    }
+}
+
+impl Query {
+    /// Returns the field path without kind, i.e. 'status.phase'
+    pub fn path(&self) -> String {
+        path(&self.fields)
+    }
+
+    /// Returns the '--field-selector' to filter on API Server side, if possible.
+    /// Derived fields can only be evaluated on client side.
+    pub fn field_selector(&self) -> Option<String> {
+        if self.case_insensitive
+            || self.other.is_some()
+            || self.quantifier.is_some()
+            || !self.functions.is_empty()
+            || self.fields.iter().any(|f| eval::lookup(f).is_some())
+            || eval::shorthand(&self.kind, &self.fields, None).is_some()
+            || eval::joins_node(&self.kind, &self.fields)
+            || eval::joins_backends(&self.kind, &self.fields)
+            || eval::joins_pdbs(&self.kind, &self.fields)
+            || eval::joins_consumers(&self.kind, &self.fields)
+            || derived::find(&self.kind, &self.path()).is_some()
+        {
+            return None;
+        }
+
+        match ResourceType::from_str(&self.kind.to_lowercase()) {
+            Ok(kind) if kind.supports_field_selector(&self.path()) => {}
+            _ => return None,
+        }
+
+        let op = match self.op {
+            ast::BinaryOperator::Eq => "=",
+            ast::BinaryOperator::NotEq => "!=",
+            _ => return None,
+        };
+
+        Some(format!("{}{}{}", self.path(), op, self.eq))
+    }
+
+    /// Returns the operator as written in SQL, i.e. 'NOT ILIKE' instead of 'OPERATOR(NOT.ILIKE)'
+    pub fn operator(&self) -> String {
+        match &self.op {
+            ast::BinaryOperator::PGCustomBinaryOperator(op) => op.join(" "),
+            op => op.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for Query {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut field = String::new();
+        for scope in self.context.iter().chain(self.namespace.iter()) {
+            field.push_str(&format!("{}.", scope));
+        }
+        field.push_str(&qualified(&self.kind, &self.fields));
+        let field = functions::display(&self.functions, &field);
+        match self.quantifier {
+            Some(quantifier) => write!(f, "{}({}) {}", quantifier, field, self.operator())?,
+            None => write!(f, "{} {}", field, self.operator())?,
+        }
+        match &self.subquery {
+            Some(q) => write!(f, " ({})", q),
+            None if self.operator().ends_with("IN") => write!(f, " ({})", self.values.join(", ")),
+            None if is_test(&self.op) => Ok(()),
+            None => match &self.other {
+                Some(other) => write!(f, " {}", qualified(&self.kind, other)),
+                None => write!(f, " {}", self.eq),
+            },
+        }
+    }
 }
 
+/// Returns the given fields as path, the lookups as written in SQL, i.e. "conditions['Ready'].status"
+pub fn path(fields: &[String]) -> String {
+    let mut path = String::new();
+    for field in fields {
+        match eval::lookup(field) {
+            Some(key) => path.push_str(&format!("['{}']", key)),
+            None if path.is_empty() => path.push_str(field),
+            None => path.push_str(&format!(".{}", field)),
+        }
+    }
+    path
+}
+
+/// Returns the field path with its kind, the metadata columns are written without, i.e. '__context'
+fn qualified(kind: &str, fields: &[String]) -> String {
+    match kind {
+        SOURCE => path(fields),
+        _ => format!("{}.{}", kind, path(fields)),
+    }
+}
+
+/// sqlparser has no BinaryOperator for LIKE and ILIKE, so they are planned as custom operators
+pub fn like_operator(negated: bool, case_insensitive: bool) -> ast::BinaryOperator {
+    let mut op = Vec::new();
+    if negated {
+        op.push("NOT".to_string());
+    }
+    op.push(if case_insensitive { "ILIKE" } else { "LIKE" }.to_string());
+
+    ast::BinaryOperator::PGCustomBinaryOperator(op)
+}
+
+/// Same as LIKE, IN and NOT IN are planned as custom operators
+pub fn in_operator(negated: bool) -> ast::BinaryOperator {
+    let op: &[&str] = match negated {
+        true => &["NOT", "IN"],
+        false => &["IN"],
+    };
+
+    ast::BinaryOperator::PGCustomBinaryOperator(op.iter().map(|o| o.to_string()).collect())
+}
+
+/// Same as LIKE, IS [NOT] TRUE and IS [NOT] FALSE are planned as custom operators
+pub fn boolean_operator(value: bool, negated: bool) -> ast::BinaryOperator {
+    let mut op = vec!["IS".to_string()];
+    if negated {
+        op.push("NOT".to_string());
+    }
+    op.push(if value { "TRUE" } else { "FALSE" }.to_string());
+
+    ast::BinaryOperator::PGCustomBinaryOperator(op)
+}
+
+/// Same as LIKE, IS NULL and IS NOT NULL are planned as custom operators
+pub fn null_operator(negated: bool) -> ast::BinaryOperator {
+    let op: &[&str] = match negated {
+        true => &["IS", "NOT", "NULL"],
+        false => &["IS", "NULL"],
+    };
+
+    ast::BinaryOperator::PGCustomBinaryOperator(op.iter().map(|o| o.to_string()).collect())
+}
+
+/// A field given in SELECT statement to print for each resource, i.e. 'pod.status.phase'
+#[derive(Debug, Clone)]
+pub struct Projection {
+    pub kind: String,
+    pub fields: Vec<String>,
+    /// The functions applied to the field values, the innermost first, i.e. 'lower'
+    pub functions: Vec<Call>,
+    /// Print each value of the array on its own row, i.e. 'UNNEST(pod.spec.containers.image)'
+    pub unnest: bool,
+}
+
+impl Projection {
+    /// Returns the field path without kind, i.e. 'status.phase'
+    pub fn path(&self) -> String {
+        path(&self.fields)
+    }
+
+    /// Returns the field path wrapped into the functions, i.e. 'lower(metadata.name)'
+    pub fn name(&self) -> String {
+        functions::display(&self.functions, &self.path())
+    }
+}
+
+impl fmt::Display for Projection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let field = qualified(&self.kind, &self.fields);
+        write!(f, "{}", functions::display(&self.functions, &field))
+    }
+}
+
+/// A subquery of IN operator, i.e. "(SELECT node.metadata.name WHERE node.spec.unschedulable = 'true')".
+/// It is executed on the same contexts and namespaces before the outer query.
+#[derive(Debug, Clone)]
+pub struct SubQuery {
+    /// The field to return of the matched resources
+    pub projection: Projection,
+    /// The conditions on the resources, combined with their keys from left to right
+    pub queries: Vec<Query>,
+}
+
+impl fmt::Display for SubQuery {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SELECT {}", self.projection)?;
+        for (i, q) in self.queries.iter().enumerate() {
+            match (i, &q.key) {
+                (0, _) => write!(f, " WHERE {}", q)?,
+                (_, Some(k)) => write!(f, " {} {}", k, q)?,
+                (_, None) => write!(f, " {}", q)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// What the pods are grouped by in a GROUP BY statement
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GroupBy {
+    /// The node the pods are scheduled on, via 'spec.nodeName'
+    Node,
+    /// The namespace of the pods
+    Namespace,
+}
+
+impl fmt::Display for GroupBy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GroupBy::Node => write!(f, "node"),
+            GroupBy::Namespace => write!(f, "namespace"),
+        }
+    }
+}
+
+/// A column of a GROUP BY statement, i.e. 'node.allocatable.cpu' or 'SUM(pod.requests.cpu)'
+#[derive(Debug, Clone)]
+pub enum Aggregate {
+    /// A field of the grouped resource itself
+    Field(Projection),
+    /// Sum of the field of all resources in the group
+    Sum(Projection),
+    /// Count of all resources in the group, i.e. 'COUNT(*)'
+    Count,
+}
+
+impl fmt::Display for Aggregate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Aggregate::Field(p) => write!(f, "{}", p),
+            Aggregate::Sum(p) => write!(f, "SUM({})", p),
+            Aggregate::Count => write!(f, "COUNT(*)"),
+        }
+    }
+}
+
+/// A condition of a HAVING statement on an aggregate, i.e. 'COUNT(*) > 50'
+#[derive(Debug, Clone)]
+pub struct Having {
+    pub aggregate: Aggregate,
+    pub op: ast::BinaryOperator,
+    pub value: String,
+}
+
+// The values are short-lived during planning, boxing the queries is not worth it
+#[allow(clippy::large_enum_variant)]
 #[derive(Debug, Clone)]
 pub enum Value {
     Strings(Vec<String>),
     String(String),
     Query(Query),
     Queries(Vec<Query>),
+    SubQuery(Box<SubQuery>),
+    Bool(bool),
+    /// The function calls on a field, the innermost first, i.e. 'lower(image_tag(pod.spec.containers.image))'
+    Function(Vec<String>, Vec<Call>),
 }
 
 #[derive(Error, Debug, Clone)]
@@ -43,18 +345,65 @@ pub enum PlanError {
     #[error("Unknown PlanError: {0}")]
     Unknown(String),
 
-    #[error("PlanQuery for {0} unsupported: {1}")]
+    #[error("Unsupported {0} in WHERE statement: '{1}'")]
     Unsupported(String, String),
 
-    #[error("Type mismatch L: {0:?}, R: {1:?}!")]
-    TypeMismatch(Box<Value>, Box<Value>),
+    #[error("Unknown kind of the field: '{0}'")]
+    UnknownKind(String),
+
+    #[error("Unknown scope '{1}' in WHERE statement: '{0}'")]
+    UnknownScope(String, String),
+
+    #[error("Invalid regex pattern '{0}': {1}")]
+    InvalidPattern(String, String),
 }
 
 type PlanResult = Result<Value, PlanError>;
 
 #[derive(Debug, Clone, Default)]
 pub struct PlanContext {
-    // todo
+    /// Plan all the string comparisons as case-insensitive
+    pub case_insensitive: bool,
+    /// The contexts given in FROM statement, the predicates can be scoped with
+    pub contexts: Vec<String>,
+    /// The namespaces given in SELECT statement, the predicates can be scoped with
+    pub namespaces: Vec<String>,
+    /// The contexts to query if FROM statement is not given
+    pub default_contexts: Vec<String>,
+    /// The contexts given by --contexts, queried instead of the ones of FROM statement
+    pub override_contexts: Vec<String>,
+    /// The namespaces given by --namespaces, queried instead of the ones of SELECT statement
+    pub override_namespaces: Vec<String>,
+    /// The namespaces never queried by 'SELECT *', in addition to the ones of its EXCEPT
+    pub excluded_namespaces: Vec<String>,
+    /// The queries of the views by their names, expanded in FROM statement
+    pub views: BTreeMap<String, String>,
+    /// The short names and plurals of the custom resources discovered on the clusters, i.e. 'app'
+    pub aliases: BTreeMap<String, ResourceType>,
+    /// The field printed of each kind if none of its fields is selected, i.e. 'status.phase' of 'pod'
+    pub display: BTreeMap<String, String>,
+    /// The local table given in FROM statement, i.e. 'kubeconfig'. Its columns are given without
+    /// a kind, so they are planned as its fields, i.e. 'cluster.server' as 'kubeconfig.cluster.server'
+    pub table: Option<String>,
+}
+
+impl PlanContext {
+    /// Returns the kind of the given name, its short name or plural, i.e. 'pod' of 'po' or 'Pods'
+    pub fn kind(&self, name: &str) -> Option<ResourceType> {
+        let name = name.to_lowercase();
+        ResourceType::from_str(&name)
+            .ok()
+            .or_else(|| self.aliases.get(&name).cloned())
+    }
+
+    /// Returns the name of the kind the given name resolves to, the name itself otherwise,
+    /// i.e. 'pod' of 'po' and 'cluster' of 'cluster'
+    pub fn kind_name(&self, name: &str) -> String {
+        match self.kind(name) {
+            Some(kind) => kind.to_string(),
+            None => name.to_lowercase(),
+        }
+    }
 }
 
 pub trait PlanQuery {
@@ -68,7 +417,121 @@ impl PlanQuery for ast::Expr {
             ast::Expr::CompoundIdentifier(identifiers) => {
                 CompoundIdentifier { identifiers }.plan(context)
             }
+            ast::Expr::Identifier(ident) => match (&context.table, source_column(&ident.value)) {
+                // i.e. 'name' of 'kubeconfig', a column of the local table
+                (Some(table), _) => Ok(Value::Strings(vec![table.clone(), ident.value.clone()])),
+                // i.e. '__context', the metadata columns are the only fields without a kind
+                (None, Some(column)) => {
+                    Ok(Value::Strings(vec![SOURCE.to_string(), column.to_string()]))
+                }
+                (None, None) => Err(PlanError::Unsupported("Expr".to_string(), self.to_string())),
+            },
             ast::Expr::BinaryOp { left, op, right } => BinaryOp { left, op, right }.plan(context),
+            ast::Expr::IsNull(expr) | ast::Expr::IsNotNull(expr) => test(
+                expr,
+                &null_operator(matches!(self, ast::Expr::IsNotNull(_))),
+                self,
+                context,
+            ),
+            ast::Expr::IsTrue(expr) | ast::Expr::IsNotTrue(expr) => test(
+                expr,
+                &boolean_operator(true, matches!(self, ast::Expr::IsNotTrue(_))),
+                self,
+                context,
+            ),
+            ast::Expr::IsFalse(expr) | ast::Expr::IsNotFalse(expr) => test(
+                expr,
+                &boolean_operator(false, matches!(self, ast::Expr::IsNotFalse(_))),
+                self,
+                context,
+            ),
+            // i.e. 'NOT pod.spec.hostNetwork', only the boolean fields can be negated
+            ast::Expr::UnaryOp {
+                op: ast::UnaryOperator::Not,
+                expr,
+            } => test(expr, &boolean_operator(false, false), self, context),
+            ast::Expr::InList {
+                expr,
+                list,
+                negated,
+            } => {
+                let mut values: Vec<String> = Vec::new();
+                for v in list {
+                    match v.plan(context)? {
+                        Value::String(v) => values.push(v),
+                        Value::Bool(b) => values.push(b.to_string()),
+                        _ => {
+                            return Err(PlanError::Unsupported(
+                                "IN value".to_string(),
+                                v.to_string(),
+                            ))
+                        }
+                    }
+                }
+
+                let (quantifier, expr) = quantified(expr);
+                let (input, functions) = match expr.plan(context)? {
+                    Value::Strings(input) => (input, vec![]),
+                    Value::Function(input, calls) => (input, calls),
+                    _ => return Err(PlanError::Unsupported("Expr".to_string(), self.to_string())),
+                };
+
+                match (BinaryOpQuery {
+                    op: &in_operator(*negated),
+                    input: &input,
+                    eq: &String::new(),
+                })
+                .plan(context)?
+                {
+                    Value::Query(mut q) => {
+                        q.values = values;
+                        q.functions = functions;
+                        q.quantifier = quantifier;
+                        Ok(Value::Query(q))
+                    }
+                    v => Ok(v),
+                }
+            }
+            ast::Expr::InSubquery {
+                expr,
+                subquery,
+                negated,
+            } => BinaryOp {
+                left: expr,
+                op: &in_operator(*negated),
+                right: &ast::Expr::Subquery(subquery.clone()),
+            }
+            .plan(context),
+            ast::Expr::Subquery(query) => query.plan(context),
+            ast::Expr::Function(function) => FunctionCall { function }.plan(context),
+            ast::Expr::Exists { subquery, negated } => Exists {
+                subquery,
+                negated: *negated,
+            }
+            .plan(context),
+            ast::Expr::Nested(expr) => expr.plan(context),
+            ast::Expr::Like {
+                negated,
+                expr,
+                pattern,
+                escape_char: None,
+            } => BinaryOp {
+                left: expr,
+                op: &like_operator(*negated, false),
+                right: pattern,
+            }
+            .plan(context),
+            ast::Expr::ILike {
+                negated,
+                expr,
+                pattern,
+                escape_char: None,
+            } => BinaryOp {
+                left: expr,
+                op: &like_operator(*negated, true),
+                right: pattern,
+            }
+            .plan(context),
             _ => Err(PlanError::Unsupported("Expr".to_string(), self.to_string())),
         }
     }
@@ -80,6 +543,8 @@ impl PlanQuery for ast::Value {
             ast::Value::SingleQuotedString(s) | ast::Value::DoubleQuotedString(s) => {
                 Ok(Value::String(s.clone()))
             }
+            ast::Value::Number(n, _) => Ok(Value::String(n.clone())),
+            ast::Value::Boolean(b) => Ok(Value::Bool(*b)),
             _ => Err(PlanError::Unsupported(
                 "Value".to_string(),
                 self.to_string(),
@@ -93,13 +558,92 @@ struct CompoundIdentifier<'a> {
 }
 
 impl<'a> PlanQuery for CompoundIdentifier<'a> {
-    fn plan(&self, _context: &mut PlanContext) -> PlanResult {
+    fn plan(&self, context: &mut PlanContext) -> PlanResult {
         Ok(Value::Strings(
-            self.identifiers.iter().cloned().map(|e| e.value).collect(),
+            context
+                .table
+                .iter()
+                .cloned()
+                .chain(self.identifiers.iter().cloned().map(|e| e.value))
+                .collect(),
         ))
     }
 }
 
+/// i.e. "split_part(pod.spec.containers.image, '/', 1)", the first argument is the field or
+/// another function call on it, the others are literals
+struct FunctionCall<'a> {
+    function: &'a ast::Function,
+}
+
+impl<'a> PlanQuery for FunctionCall<'a> {
+    fn plan(&self, context: &mut PlanContext) -> PlanResult {
+        let name = self.function.name.to_string();
+        let function = functions::find(&name)
+            .ok_or_else(|| PlanError::Unsupported("function".to_string(), name.clone()))?;
+
+        let mut args = Vec::new();
+        for arg in &self.function.args {
+            match arg {
+                ast::FunctionArg::Unnamed(ast::FunctionArgExpr::Expr(e)) => args.push(e),
+                _ => {
+                    return Err(PlanError::Unsupported(
+                        "function argument".to_string(),
+                        arg.to_string(),
+                    ))
+                }
+            }
+        }
+
+        let literals = args.len().saturating_sub(1);
+        if args.is_empty()
+            || literals < function.args
+            || (literals > function.args && !function.variadic)
+        {
+            return Err(PlanError::Unsupported(
+                format!(
+                    "number of arguments, {} expects a field and {}{} more",
+                    function.name,
+                    function.args,
+                    if function.variadic { " or" } else { "" }
+                ),
+                self.function.to_string(),
+            ));
+        }
+
+        let (input, mut calls) = match args[0].plan(context)? {
+            Value::Strings(input) => (input, vec![]),
+            Value::Function(input, calls) => (input, calls),
+            _ => {
+                return Err(PlanError::Unsupported(
+                    "function argument, the first one must be a field".to_string(),
+                    args[0].to_string(),
+                ))
+            }
+        };
+
+        let mut literals = Vec::new();
+        for arg in &args[1..] {
+            match arg.plan(context)? {
+                Value::String(v) => literals.push(v),
+                Value::Bool(b) => literals.push(b.to_string()),
+                _ => {
+                    return Err(PlanError::Unsupported(
+                        "function argument, only literals can follow the field".to_string(),
+                        arg.to_string(),
+                    ))
+                }
+            }
+        }
+
+        calls.push(Call {
+            name: function.name.to_string(),
+            args: literals,
+        });
+        Ok(Value::Function(input, calls))
+    }
+}
+
 struct BinaryOp<'a> {
     op: &'a ast::BinaryOperator,
     left: &'a ast::Expr,
@@ -108,8 +652,54 @@ struct BinaryOp<'a> {
 
 impl<'a> PlanQuery for BinaryOp<'a> {
     fn plan(&self, context: &mut PlanContext) -> PlanResult {
-        let l = self.left.plan(context)?;
+        // i.e. 'pod.spec.hostNetwork AND ...', the bare fields are 'IS TRUE'
+        if matches!(self.op, ast::BinaryOperator::And | ast::BinaryOperator::Or) {
+            let l = predicate(self.left, context)?;
+            let r = predicate(self.right, context)?;
+            return self.plan_values(l, r, context);
+        }
+
+        // i.e. "'x' = ANY(pod.spec.containers.image)", planned as "ANY(...) = 'x'"
+        if let (Some(quantifier), field) = quantified(self.right) {
+            let op = flip(self.op).ok_or_else(|| {
+                PlanError::Unsupported(
+                    "quantified comparison operator".to_string(),
+                    format!("{} {} {}", self.left, self.op, self.right),
+                )
+            })?;
+            let planned = BinaryOp {
+                op: &op,
+                left: field,
+                right: self.left,
+            }
+            .plan(context)?;
+            return Ok(quantify(planned, Some(quantifier)));
+        }
+
+        // i.e. "ALL(pod.spec.containers.image) LIKE 'registry.local/%'"
+        let (quantifier, left) = quantified(self.left);
+        let l = left.plan(context)?;
         let r = self.right.plan(context)?;
+        Ok(quantify(self.plan_values(l, r, context)?, quantifier))
+    }
+}
+
+impl<'a> BinaryOp<'a> {
+    /// Plans the operator on the given planned sides
+    fn plan_values(&self, l: Value, mut r: Value, context: &mut PlanContext) -> PlanResult {
+        // i.e. 'pod.spec.hostNetwork = true', the booleans can only be compared for equality
+        if let Value::Bool(b) = r {
+            if !matches!(
+                self.op,
+                ast::BinaryOperator::Eq | ast::BinaryOperator::NotEq
+            ) {
+                return Err(PlanError::Unsupported(
+                    "boolean comparison operator".to_string(),
+                    format!("{} {} {}", self.left, self.op, self.right),
+                ));
+            }
+            r = Value::String(b.to_string());
+        }
 
         match (l, r) {
             (Value::Strings(a), Value::String(b)) => BinaryOpQuery {
@@ -118,6 +708,90 @@ impl<'a> PlanQuery for BinaryOp<'a> {
                 eq: &b,
             }
             .plan(context),
+            (Value::Function(a, calls), Value::String(b)) => match (BinaryOpQuery {
+                op: self.op,
+                input: &a,
+                eq: &b,
+            })
+            .plan(context)?
+            {
+                Value::Query(mut q) => {
+                    q.functions = calls;
+                    Ok(Value::Query(q))
+                }
+                v => Ok(v),
+            },
+            // i.e. 'IN (SELECT ...)', or '= (SELECT ...)' as the scalar subquery
+            (Value::Strings(a), Value::SubQuery(subquery)) => {
+                let op = match self.op {
+                    ast::BinaryOperator::Eq => in_operator(false),
+                    ast::BinaryOperator::NotEq => in_operator(true),
+                    ast::BinaryOperator::PGCustomBinaryOperator(_) => self.op.clone(),
+                    _ => {
+                        return Err(PlanError::Unsupported(
+                            "subquery operator".to_string(),
+                            self.op.to_string(),
+                        ))
+                    }
+                };
+
+                match (BinaryOpQuery {
+                    op: &op,
+                    input: &a,
+                    eq: &String::new(),
+                })
+                .plan(context)?
+                {
+                    Value::Query(mut q) => {
+                        q.subquery = Some(subquery);
+                        Ok(Value::Query(q))
+                    }
+                    v => Ok(v),
+                }
+            }
+            // i.e. 'deployment.status.readyReplicas < deployment.spec.replicas', the regex
+            // patterns are compiled once per query, so they can not be read from the object
+            (Value::Strings(a), Value::Strings(b)) => {
+                if matches!(
+                    self.op,
+                    ast::BinaryOperator::PGRegexMatch
+                        | ast::BinaryOperator::PGRegexIMatch
+                        | ast::BinaryOperator::PGRegexNotMatch
+                        | ast::BinaryOperator::PGRegexNotIMatch
+                ) {
+                    return Err(PlanError::Unsupported(
+                        "field comparison operator".to_string(),
+                        self.op.to_string(),
+                    ));
+                }
+
+                let plan = |input: &[String], context: &mut PlanContext| match (BinaryOpQuery {
+                    op: self.op,
+                    input,
+                    eq: &String::new(),
+                })
+                .plan(context)?
+                {
+                    Value::Query(q) => Ok(q),
+                    _ => Err(PlanError::Unknown(input.join("."))),
+                };
+                let mut q = plan(&a, context)?;
+                let other = plan(&b, context)?;
+
+                // Both sides are evaluated on the same object
+                if !q.kind.eq_ignore_ascii_case(&other.kind)
+                    || (other.context.is_some() && other.context != q.context)
+                    || (other.namespace.is_some() && other.namespace != q.namespace)
+                {
+                    return Err(PlanError::Unsupported(
+                        "field comparison of different objects".to_string(),
+                        format!("{} {} {}", self.left, self.op, self.right),
+                    ));
+                }
+
+                q.other = Some(other.fields);
+                Ok(Value::Query(q))
+            }
             (Value::Query(input), Value::Query(mut eq)) => {
                 let mut v = vec![input];
                 eq.key = Some(self.op.clone());
@@ -131,7 +805,10 @@ impl<'a> PlanQuery for BinaryOp<'a> {
                 v.push(eq);
                 Ok(Value::Queries(v))
             }
-            (x, y) => Err(PlanError::TypeMismatch(Box::new(x), Box::new(y))),
+            _ => Err(PlanError::Unsupported(
+                "BinaryOp".to_string(),
+                format!("{} {} {}", self.left, self.op, self.right),
+            )),
         }
     }
 }
@@ -143,18 +820,453 @@ struct BinaryOpQuery<'a> {
 }
 
 impl<'a> PlanQuery for BinaryOpQuery<'a> {
-    fn plan(&self, _context: &mut PlanContext) -> PlanResult {
-        if self.input.len() != 3 {
-            return Err(PlanError::Unknown("WHERE statement does only support three length CompoundIdentifier: i.e. 'pod.status.phase'".to_string()));
+    fn plan(&self, context: &mut PlanContext) -> PlanResult {
+        // i.e. 'pod.spec.priority + 1', it could not be evaluated on any resource
+        if !is_comparison(self.op) {
+            return Err(PlanError::Unsupported(
+                "operator".to_string(),
+                self.op.to_string(),
+            ));
         }
 
+        let is_kind = |k: &str| {
+            context.kind(k).is_some()
+                || k.eq_ignore_ascii_case(CLUSTER)
+                || k == SOURCE
+                || context.table.as_deref() == Some(k)
+        };
+        let is_scope = |s: &String| context.contexts.contains(s) || context.namespaces.contains(s);
+
+        // i.e. 'ctx_prod.pod.status.phase', 'kube_system.pod.status.phase' or both
+        // 'ctx_prod.kube_system.pod.status.phase', the predicate applies only to the given scope.
+        // A scope named like a short name is still a scope if a kind follows, i.e. 'ns.po.status'
+        let scopes: Vec<&String> = self
+            .input
+            .iter()
+            .enumerate()
+            .take_while(|(i, s)| {
+                !is_kind(s)
+                    || (is_scope(s) && self.input.get(i + 1).is_some_and(|next| is_kind(next)))
+            })
+            .map(|(_, s)| s)
+            .collect();
+        let input = &self.input[scopes.len()..];
+
+        let kind = match input {
+            [kind, _, ..] => kind,
+            [] if self.input.len() >= 2 => {
+                return Err(PlanError::UnknownKind(self.input.join(".")))
+            }
+            _ => return Err(PlanError::Unknown("WHERE statement does only support CompoundIdentifier with a kind and field(s): i.e. 'pod.status.phase'".to_string())),
+        };
+
+        let is_context = |s: &&String| context.contexts.contains(s);
+        let is_namespace = |s: &&String| context.namespaces.contains(s);
+        let (scope_context, scope_namespace) = match scopes.as_slice() {
+            [] => (None, None),
+            [c] if is_context(c) => (Some(c.to_string()), None),
+            [n] if is_namespace(n) => (None, Some(n.to_string())),
+            [c, n] if is_context(c) && is_namespace(n) => {
+                (Some(c.to_string()), Some(n.to_string()))
+            }
+            _ => {
+                let unknown = scopes
+                    .iter()
+                    .find(|s| !is_context(s) && !is_namespace(s))
+                    .unwrap_or(&scopes[0]);
+                return Err(PlanError::UnknownScope(
+                    self.input.join("."),
+                    unknown.to_string(),
+                ));
+            }
+        };
+
+        let pattern = match self.op {
+            ast::BinaryOperator::PGRegexMatch | ast::BinaryOperator::PGRegexNotMatch => {
+                Some(compile(self.eq, context.case_insensitive)?)
+            }
+            ast::BinaryOperator::PGRegexIMatch | ast::BinaryOperator::PGRegexNotIMatch => {
+                Some(compile(self.eq, true)?)
+            }
+            _ => None,
+        };
+
         Ok(Value::Query(Query {
             key: None,
-            kind: self.input.get(0).unwrap().to_string(),
-            field1: self.input.get(1).unwrap().to_string(),
-            field2: self.input.get(2).unwrap().to_string(),
-            eq: self.eq.replace('_', "-"),
+            context: scope_context,
+            namespace: scope_namespace,
+            kind: context.kind_name(kind),
+            fields: input[1..].to_vec(),
+            eq: self.eq.to_string(),
             op: self.op.clone(),
+            case_insensitive: context.case_insensitive,
+            pattern,
+            values: vec![],
+            subquery: None,
+            quantifier: None,
+            other: None,
+            functions: vec![],
         }))
     }
 }
+
+impl PlanQuery for ast::Query {
+    fn plan(&self, context: &mut PlanContext) -> PlanResult {
+        let unsupported = |reason: &str| {
+            Err(PlanError::Unsupported(
+                "subquery".to_string(),
+                format!("{}: {}", reason, self),
+            ))
+        };
+
+        let select = match &*self.body {
+            ast::SetExpr::Select(s) => s,
+            _ => return unsupported("only SELECT is supported"),
+        };
+
+        if !select.from.is_empty() {
+            return unsupported("FROM is not supported, the contexts of the outer query are used");
+        }
+
+        // SELECT node.metadata.name
+        let projection = match select.projection.as_slice() {
+            [ast::SelectItem::UnnamedExpr(e @ ast::Expr::CompoundIdentifier(_))] => {
+                match e.plan(context)? {
+                    Value::Strings(ids) if ids.len() >= 2 && context.kind(&ids[0]).is_some() => {
+                        Projection {
+                            kind: context.kind_name(&ids[0]),
+                            fields: ids[1..].to_vec(),
+                            functions: vec![],
+                            unnest: false,
+                        }
+                    }
+                    _ => return unsupported("SELECT does only support a single field with a kind"),
+                }
+            }
+            _ => return unsupported("SELECT does only support a single field with a kind"),
+        };
+
+        // WHERE node.spec.unschedulable = 'true'
+        let queries = match &select.selection {
+            Some(w) => match plan_predicate(w, context)? {
+                Value::Query(q) => vec![q],
+                Value::Queries(q) => q,
+                _ => return unsupported("unsupported WHERE statement"),
+            },
+            None => vec![],
+        };
+
+        if queries
+            .iter()
+            .any(|q| !q.kind.eq_ignore_ascii_case(&projection.kind))
+        {
+            return unsupported("WHERE does only support the kind of the SELECT field");
+        }
+
+        Ok(Value::SubQuery(Box::new(SubQuery {
+            projection,
+            queries,
+        })))
+    }
+}
+
+/// Whether the operator tests a field without a value, i.e. 'IS NULL' or 'IS NOT TRUE'
+pub fn is_test(op: &ast::BinaryOperator) -> bool {
+    match op {
+        ast::BinaryOperator::PGCustomBinaryOperator(op) => matches!(
+            op.last().map(String::as_str),
+            Some("NULL") | Some("TRUE") | Some("FALSE")
+        ),
+        _ => false,
+    }
+}
+
+/// Plans the given field tested with the operator, the functions are applied before the test,
+/// i.e. 'pod.spec.nodeName IS NULL'. Only the boolean tests can be quantified
+fn test(
+    field: &ast::Expr,
+    op: &ast::BinaryOperator,
+    expr: &ast::Expr,
+    context: &mut PlanContext,
+) -> PlanResult {
+    let (quantifier, field) = quantified(field);
+    if quantifier.is_some()
+        && !matches!(op, ast::BinaryOperator::PGCustomBinaryOperator(o) if o.last().is_some_and(|o| o != "NULL"))
+    {
+        return Err(PlanError::Unsupported(
+            "quantified test".to_string(),
+            expr.to_string(),
+        ));
+    }
+
+    let (input, functions) = match field.plan(context)? {
+        Value::Strings(input) => (input, vec![]),
+        Value::Function(input, calls) => (input, calls),
+        _ => return Err(PlanError::Unsupported("Expr".to_string(), expr.to_string())),
+    };
+
+    match (BinaryOpQuery {
+        op,
+        input: &input,
+        eq: &String::new(),
+    })
+    .plan(context)?
+    {
+        Value::Query(mut q) => {
+            q.functions = functions;
+            q.quantifier = quantifier;
+            Ok(Value::Query(q))
+        }
+        v => Ok(v),
+    }
+}
+
+/// Plans the given expression as a predicate of WHERE statement, a bare field is tested as
+/// 'IS TRUE', i.e. 'WHERE pod.spec.hostNetwork' or 'WHERE ALL(pod.status.containerStatuses.ready)'
+fn predicate(expr: &ast::Expr, context: &mut PlanContext) -> PlanResult {
+    if let (Some(_), _) = quantified(expr) {
+        return test(expr, &boolean_operator(true, false), expr, context);
+    }
+
+    match expr.plan(context)? {
+        Value::Strings(_) | Value::Function(_, _) => {
+            test(expr, &boolean_operator(true, false), expr, context)
+        }
+        v => Ok(v),
+    }
+}
+
+/// Plans the given WHERE statement, a bare field is tested as 'IS TRUE'
+pub fn plan_predicate(expr: &ast::Expr, context: &mut PlanContext) -> PlanResult {
+    predicate(expr, context)
+}
+
+/// Returns the quantifier of the given array field and the field itself, i.e. 'ALL' and
+/// 'pod.spec.containers.image' of 'ALL(pod.spec.containers.image)'
+fn quantified(expr: &ast::Expr) -> (Option<Quantifier>, &ast::Expr) {
+    match expr {
+        ast::Expr::AnyOp(field) => (Some(Quantifier::Any), field),
+        ast::Expr::AllOp(field) => (Some(Quantifier::All), field),
+        ast::Expr::Function(f) => {
+            let quantifier = match f.name.to_string().to_uppercase().as_str() {
+                "ANY" => Quantifier::Any,
+                "ALL" => Quantifier::All,
+                _ => return (None, expr),
+            };
+            match f.args.as_slice() {
+                [ast::FunctionArg::Unnamed(ast::FunctionArgExpr::Expr(field))] => {
+                    (Some(quantifier), field)
+                }
+                _ => (None, expr),
+            }
+        }
+        _ => (None, expr),
+    }
+}
+
+/// Sets the quantifier of the given planned query, if any
+fn quantify(value: Value, quantifier: Option<Quantifier>) -> Value {
+    match value {
+        Value::Query(mut q) if quantifier.is_some() => {
+            q.quantifier = quantifier;
+            Value::Query(q)
+        }
+        v => v,
+    }
+}
+
+/// Returns the operator with its sides swapped, i.e. '>' of '<'
+fn flip(op: &ast::BinaryOperator) -> Option<ast::BinaryOperator> {
+    match op {
+        ast::BinaryOperator::Eq | ast::BinaryOperator::NotEq => Some(op.clone()),
+        ast::BinaryOperator::Lt => Some(ast::BinaryOperator::Gt),
+        ast::BinaryOperator::Gt => Some(ast::BinaryOperator::Lt),
+        ast::BinaryOperator::LtEq => Some(ast::BinaryOperator::GtEq),
+        ast::BinaryOperator::GtEq => Some(ast::BinaryOperator::LtEq),
+        _ => None,
+    }
+}
+
+/// Whether the operator compares a field with a value, the only ones that can be evaluated
+fn is_comparison(op: &ast::BinaryOperator) -> bool {
+    match op {
+        ast::BinaryOperator::Eq
+        | ast::BinaryOperator::NotEq
+        | ast::BinaryOperator::Gt
+        | ast::BinaryOperator::GtEq
+        | ast::BinaryOperator::Lt
+        | ast::BinaryOperator::LtEq
+        | ast::BinaryOperator::PGRegexMatch
+        | ast::BinaryOperator::PGRegexIMatch
+        | ast::BinaryOperator::PGRegexNotMatch
+        | ast::BinaryOperator::PGRegexNotIMatch => true,
+        ast::BinaryOperator::PGCustomBinaryOperator(op) => matches!(
+            op.last().map(String::as_str),
+            Some("LIKE") | Some("ILIKE") | Some("IN") | Some("NULL") | Some("TRUE") | Some("FALSE")
+        ),
+        _ => false,
+    }
+}
+
+fn compile(pattern: &str, case_insensitive: bool) -> Result<Regex, PlanError> {
+    RegexBuilder::new(pattern)
+        .case_insensitive(case_insensitive)
+        .build()
+        .map_err(|e| {
+            // The syntax errors are multiline with the pattern itself, keep only the reason
+            let reason = e.to_string();
+            let reason = reason.lines().last().unwrap_or_default();
+            PlanError::InvalidPattern(
+                pattern.to_string(),
+                reason.trim_start_matches("error: ").to_string(),
+            )
+        })
+}
+
+/// i.e. "NOT EXISTS (SELECT * WHERE service.metadata.name = deployment.metadata.name)" to find the
+/// deployments without a matching service, planned as the subquery
+/// "deployment.metadata.name NOT IN (SELECT service.metadata.name)"
+struct Exists<'a> {
+    subquery: &'a ast::Query,
+    negated: bool,
+}
+
+impl<'a> PlanQuery for Exists<'a> {
+    fn plan(&self, context: &mut PlanContext) -> PlanResult {
+        let unsupported = |reason: &str| {
+            Err(PlanError::Unsupported(
+                "EXISTS".to_string(),
+                format!("{}: {}", reason, self.subquery),
+            ))
+        };
+
+        let select = match &*self.subquery.body {
+            ast::SetExpr::Select(s) => s,
+            _ => return unsupported("only SELECT is supported"),
+        };
+
+        if !select.from.is_empty() {
+            return unsupported("FROM is not supported, the contexts of the outer query are used");
+        }
+
+        let selection = match &select.selection {
+            Some(w) => w,
+            None => return unsupported("a WHERE statement with a correlation is required, i.e. 'service.metadata.name = deployment.metadata.name'"),
+        };
+
+        // The first comparison of two fields is the correlation, the rest filters the subquery
+        let mut correlation: Option<(Vec<String>, Vec<String>)> = None;
+        let mut conditions: Vec<&ast::Expr> = Vec::new();
+        for c in conjuncts(selection) {
+            if let ast::Expr::BinaryOp {
+                left,
+                op: ast::BinaryOperator::Eq,
+                right,
+            } = c
+            {
+                if let (None, ast::Expr::CompoundIdentifier(_), ast::Expr::CompoundIdentifier(_)) =
+                    (&correlation, &**left, &**right)
+                {
+                    // The fields of the same kind are compared on the same object instead
+                    match (left.plan(context)?, right.plan(context)?) {
+                        (Value::Strings(l), Value::Strings(r))
+                            if !l[0].eq_ignore_ascii_case(&r[0]) =>
+                        {
+                            correlation = Some((l, r));
+                            continue;
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            conditions.push(c);
+        }
+
+        let (left, right) = match correlation {
+            Some(c) => c,
+            None => return unsupported("a correlation is required, i.e. 'service.metadata.name = deployment.metadata.name'"),
+        };
+
+        // The kind of the SELECT field is the kind of the subquery, otherwise the left one
+        let selected = match select.projection.as_slice() {
+            [ast::SelectItem::UnnamedExpr(ast::Expr::CompoundIdentifier(ids))] => {
+                ids.first().map(|i| context.kind_name(&i.value))
+            }
+            _ => None,
+        };
+        let (inner, outer) = match (selected, right.first()) {
+            (Some(s), Some(r)) if s == context.kind_name(r) => (right, left),
+            _ => (left, right),
+        };
+
+        if inner.len() < 2 || context.kind(&inner[0]).is_none() {
+            return Err(PlanError::UnknownKind(inner.join(".")));
+        }
+
+        let projection = Projection {
+            kind: context.kind_name(&inner[0]),
+            fields: inner[1..].to_vec(),
+            functions: vec![],
+            unnest: false,
+        };
+
+        let mut queries: Vec<Query> = Vec::new();
+        for c in conditions {
+            let planned = match plan_predicate(c, context)? {
+                Value::Query(q) => vec![q],
+                Value::Queries(q) => q,
+                _ => return unsupported("unsupported WHERE statement"),
+            };
+            for (i, mut q) in planned.into_iter().enumerate() {
+                if i == 0 && !queries.is_empty() {
+                    q.key = Some(ast::BinaryOperator::And);
+                }
+                queries.push(q);
+            }
+        }
+
+        if queries
+            .iter()
+            .any(|q| !q.kind.eq_ignore_ascii_case(&projection.kind))
+        {
+            return unsupported(&format!(
+                "only the conditions on '{}' are supported besides the correlation",
+                projection.kind
+            ));
+        }
+
+        match (BinaryOpQuery {
+            op: &in_operator(self.negated),
+            input: &outer,
+            eq: &String::new(),
+        })
+        .plan(context)?
+        {
+            Value::Query(mut q) => {
+                q.subquery = Some(Box::new(SubQuery {
+                    projection,
+                    queries,
+                }));
+                Ok(Value::Query(q))
+            }
+            v => Ok(v),
+        }
+    }
+}
+
+/// Returns the conditions combined with AND, i.e. 'a = 1 AND (b = 2 AND c = 3)' => [a = 1, b = 2, c = 3]
+fn conjuncts(expr: &ast::Expr) -> Vec<&ast::Expr> {
+    match expr {
+        ast::Expr::BinaryOp {
+            left,
+            op: ast::BinaryOperator::And,
+            right,
+        } => {
+            let mut c = conjuncts(left);
+            c.extend(conjuncts(right));
+            c
+        }
+        ast::Expr::Nested(e) => conjuncts(e),
+        _ => vec![expr],
+    }
+}
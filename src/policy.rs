@@ -0,0 +1,451 @@
+// Copyright (c) 2021 Dentrax
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software is furnished to do so,
+// subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+use crate::error::{KubesqlError, Result};
+use crate::executor;
+use crate::parser::{self, ApiQueries, ApiStatement};
+use crate::planner::PlanContext;
+use crate::printer::ResultTable;
+use crate::sarif::{self, Issue, Rule, SarifError};
+use crate::source::{DataSource, Sources};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::Path;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum PolicyError {
+    #[error("Unable to read the policies '{0}': {1}")]
+    Read(String, std::io::Error),
+
+    #[error("Invalid policies '{0}': {1}")]
+    Invalid(String, serde_yaml::Error),
+
+    #[error("Unable to write the JUnit report '{0}': {1}")]
+    Write(String, std::io::Error),
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PolicyFile {
+    policies: Vec<Policy>,
+}
+
+/// A query and the number of the rows it is expected to return in each of its contexts
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Policy {
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    pub query: String,
+    /// No rows are expected if not given, i.e. the query matches the violating resources
+    #[serde(default)]
+    pub expect: Expectation,
+}
+
+/// The bounds of the number of the rows, all of the given ones must hold
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Expectation {
+    pub rows: Option<usize>,
+    pub min_rows: Option<usize>,
+    pub max_rows: Option<usize>,
+}
+
+impl Expectation {
+    /// Whether the given number of the rows is expected, only zero if no bound is given
+    pub fn matches(&self, rows: usize) -> bool {
+        if self.rows.is_none() && self.min_rows.is_none() && self.max_rows.is_none() {
+            return rows == 0;
+        }
+
+        self.rows.is_none_or(|n| rows == n)
+            && self.min_rows.is_none_or(|n| rows >= n)
+            && self.max_rows.is_none_or(|n| rows <= n)
+    }
+}
+
+impl fmt::Display for Expectation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut bounds = Vec::new();
+        if let Some(n) = self.rows {
+            bounds.push(format!("= {}", n));
+        }
+        if let Some(n) = self.min_rows {
+            bounds.push(format!(">= {}", n));
+        }
+        if let Some(n) = self.max_rows {
+            bounds.push(format!("<= {}", n));
+        }
+        if bounds.is_empty() {
+            bounds.push("= 0".to_string());
+        }
+        write!(f, "rows {}", bounds.join(" and "))
+    }
+}
+
+/// The result of checking a policy on a context
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Verdict {
+    Pass,
+    /// The query returned an unexpected number of rows
+    Fail,
+    /// The query is invalid or could not run on the context
+    Error,
+}
+
+impl fmt::Display for Verdict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Verdict::Pass => write!(f, "PASS"),
+            Verdict::Fail => write!(f, "FAIL"),
+            Verdict::Error => write!(f, "ERROR"),
+        }
+    }
+}
+
+/// A row returned by the query of a policy, the matched resource or its unique values if DISTINCT
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum Match {
+    Resource {
+        kind: String,
+        /// None for the cluster-scoped kinds
+        namespace: Option<String>,
+        name: String,
+    },
+    Values(Vec<String>),
+}
+
+impl fmt::Display for Match {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Match::Resource {
+                kind,
+                namespace: Some(namespace),
+                name,
+            } => write!(f, "{}/{}/{}", kind, namespace, name),
+            Match::Resource {
+                kind,
+                namespace: None,
+                name,
+            } => write!(f, "{}/{}", kind, name),
+            Match::Values(values) => write!(f, "{}", values.join(", ")),
+        }
+    }
+}
+
+/// The outcome of a policy on a context
+#[derive(Debug, Serialize)]
+pub struct Outcome {
+    pub policy: String,
+    pub description: Option<String>,
+    /// None if the query of the policy is invalid, so it does not run on any context
+    pub context: Option<String>,
+    pub verdict: Verdict,
+    pub rows: Option<usize>,
+    pub expected: String,
+    pub matched: Vec<Match>,
+    pub error: Option<String>,
+}
+
+impl Outcome {
+    fn error(policy: &Policy, context: Option<&str>, error: KubesqlError) -> Outcome {
+        Outcome {
+            policy: policy.name.clone(),
+            description: policy.description.clone(),
+            context: context.map(str::to_string),
+            verdict: Verdict::Error,
+            rows: None,
+            expected: policy.expect.to_string(),
+            matched: Vec::new(),
+            error: Some(error.to_string()),
+        }
+    }
+}
+
+/// Reads the policies of the given YAML file
+pub fn load(path: &Path) -> std::result::Result<Vec<Policy>, PolicyError> {
+    let file = path.display().to_string();
+    let content = std::fs::read_to_string(path).map_err(|e| PolicyError::Read(file.clone(), e))?;
+    let policies: PolicyFile =
+        serde_yaml::from_str(&content).map_err(|e| PolicyError::Invalid(file, e))?;
+    Ok(policies.policies)
+}
+
+/// Runs the query of each policy on each of its contexts separately, read from their data sources
+/// or the default one if given, i.e. the snapshot. A failing policy or context does not stop the
+/// others, it is an error outcome
+pub async fn check(
+    policies: &[Policy],
+    default_contexts: &[String],
+    default: Option<&dyn DataSource>,
+) -> Vec<Outcome> {
+    let mut outcomes = Vec::new();
+
+    for policy in policies {
+        let (mut api_queries, sources) = match plan(policy, default_contexts, default).await {
+            Ok(planned) => planned,
+            Err(e) => {
+                outcomes.push(Outcome::error(policy, None, e));
+                continue;
+            }
+        };
+
+        for ctx in std::mem::take(&mut api_queries.contexts) {
+            api_queries.contexts = vec![ctx.clone()];
+            let selected = match executor::resolve_namespaces(&mut api_queries, &sources).await {
+                Ok(()) => executor::select(&api_queries, &sources).await,
+                Err(e) => Err(e),
+            };
+            let tables = match selected {
+                Ok(tables) => tables,
+                // The resources of an AND predicate are required otherwise
+                Err(KubesqlError::NoResourceFound(_)) => Vec::new(),
+                Err(e) => {
+                    outcomes.push(Outcome::error(policy, Some(&ctx), e));
+                    continue;
+                }
+            };
+
+            let matched = matched(&tables, api_queries.distinct);
+            outcomes.push(Outcome {
+                policy: policy.name.clone(),
+                description: policy.description.clone(),
+                context: Some(ctx),
+                verdict: match policy.expect.matches(matched.len()) {
+                    true => Verdict::Pass,
+                    false => Verdict::Fail,
+                },
+                rows: Some(matched.len()),
+                expected: policy.expect.to_string(),
+                matched,
+                error: None,
+            });
+        }
+    }
+
+    outcomes
+}
+
+/// Writes the given outcomes into the given file as JUnit XML, a test suite per policy and a test
+/// case per context, i.e. for the test reports of Jenkins or GitLab. The invalid policies have a
+/// single erroneous test case
+pub fn write_junit(outcomes: &[Outcome], path: &Path) -> std::result::Result<(), PolicyError> {
+    std::fs::write(path, junit(outcomes))
+        .map_err(|e| PolicyError::Write(path.display().to_string(), e))
+}
+
+/// Writes the given outcomes into the given file as a SARIF log, the policies are its rules and
+/// each resource returned by a failed one is a result. A failed policy without any returned
+/// resource, i.e. too few rows, is a result of the context
+pub fn write_sarif(
+    policies: &[Policy],
+    outcomes: &[Outcome],
+    path: &Path,
+    source: &Path,
+) -> std::result::Result<(), SarifError> {
+    let rules: Vec<Rule> = policies
+        .iter()
+        .map(|p| Rule {
+            id: p.name.clone(),
+            description: p.description.clone(),
+            level: "error",
+        })
+        .collect();
+
+    let mut issues = Vec::new();
+    let mut failures = Vec::new();
+    for o in outcomes {
+        let context = match (&o.context, o.verdict) {
+            (_, Verdict::Pass) => continue,
+            (Some(context), Verdict::Fail) => context,
+            (context, _) => {
+                let error = o.error.clone().unwrap_or_default();
+                failures.push((
+                    o.policy.clone(),
+                    match context {
+                        Some(context) => format!("{}: {}", context, error),
+                        None => error,
+                    },
+                ));
+                continue;
+            }
+        };
+
+        let summary = format!(
+            "expected {}, returned {}",
+            o.expected,
+            o.rows.unwrap_or_default()
+        );
+        if o.matched.is_empty() {
+            issues.push(Issue {
+                rule: o.policy.clone(),
+                message: format!("'{}' failed: {}", o.policy, summary),
+                context: context.clone(),
+                namespace: None,
+                kind: None,
+                name: None,
+            });
+        }
+        issues.extend(o.matched.iter().map(|m| {
+            let (namespace, kind, name) = match m {
+                Match::Resource {
+                    kind,
+                    namespace,
+                    name,
+                } => (namespace.clone(), Some(kind.clone()), Some(name.clone())),
+                Match::Values(_) => (None, None, None),
+            };
+            Issue {
+                rule: o.policy.clone(),
+                message: format!("{} violates '{}': {}", m, o.policy, summary),
+                context: context.clone(),
+                namespace,
+                kind,
+                name,
+            }
+        }));
+    }
+
+    sarif::write(path, &rules, &issues, &failures, Some(source))
+}
+
+fn junit(outcomes: &[Outcome]) -> String {
+    let count = |outcomes: &[&Outcome], verdict: Verdict| {
+        outcomes.iter().filter(|o| o.verdict == verdict).count()
+    };
+
+    // The outcomes of a policy are consecutive
+    let mut suites: Vec<Vec<&Outcome>> = Vec::new();
+    for o in outcomes {
+        match suites.last_mut() {
+            Some(suite) if suite[0].policy == o.policy => suite.push(o),
+            _ => suites.push(vec![o]),
+        }
+    }
+
+    let all: Vec<&Outcome> = outcomes.iter().collect();
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str(&format!(
+        "<testsuites name=\"kubesql\" tests=\"{}\" failures=\"{}\" errors=\"{}\">\n",
+        all.len(),
+        count(&all, Verdict::Fail),
+        count(&all, Verdict::Error)
+    ));
+
+    for suite in suites {
+        xml.push_str(&format!(
+            "  <testsuite name=\"{}\" tests=\"{}\" failures=\"{}\" errors=\"{}\">\n",
+            escape(&suite[0].policy),
+            suite.len(),
+            count(&suite, Verdict::Fail),
+            count(&suite, Verdict::Error)
+        ));
+
+        for o in suite {
+            xml.push_str(&format!(
+                "    <testcase classname=\"{}\" name=\"{}\"",
+                escape(&o.policy),
+                escape(o.context.as_deref().unwrap_or(&o.policy))
+            ));
+            match o.verdict {
+                Verdict::Pass => xml.push_str("/>\n"),
+                Verdict::Fail => xml.push_str(&format!(
+                    ">\n      <failure message=\"expected {}, returned {}\">{}</failure>\n    </testcase>\n",
+                    escape(&o.expected),
+                    o.rows.unwrap_or_default(),
+                    escape(
+                        &o.matched
+                            .iter()
+                            .map(Match::to_string)
+                            .collect::<Vec<String>>()
+                            .join("\n")
+                    )
+                )),
+                // The attributes are single line, i.e. the parser errors point at the query below
+                Verdict::Error => {
+                    let error = o.error.as_deref().unwrap_or_default();
+                    xml.push_str(&format!(
+                        ">\n      <error message=\"{}\">{}</error>\n    </testcase>\n",
+                        escape(error.lines().next().unwrap_or_default()),
+                        escape(error)
+                    ))
+                }
+            }
+        }
+
+        xml.push_str("  </testsuite>\n");
+    }
+
+    xml.push_str("</testsuites>\n");
+    xml
+}
+
+/// Escapes the given text for the XML attributes and elements
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// Parses the query of the given policy, opens the data sources of its contexts and validates them
+async fn plan<'a>(
+    policy: &Policy,
+    default_contexts: &[String],
+    default: Option<&'a dyn DataSource>,
+) -> Result<(ApiQueries, Sources<'a>)> {
+    let plan_context = PlanContext {
+        default_contexts: default_contexts.to_vec(),
+        ..Default::default()
+    };
+    let api_queries = match parser::parse_sql(&policy.query, plan_context)? {
+        ApiStatement::Query(q) => q,
+        _ => {
+            return Err(KubesqlError::Unsupported(
+                "Only SELECT statements without GROUP BY can be policies".to_string(),
+            ))
+        }
+    };
+
+    let sources = Sources::new(default).open(&api_queries.contexts)?;
+    sources.validate_contexts(&api_queries.contexts)?;
+
+    Ok((api_queries, sources))
+}
+
+/// Returns the rows of the given tables as the matched resources, or their values if distinct
+fn matched(tables: &[ResultTable], distinct: bool) -> Vec<Match> {
+    tables
+        .iter()
+        .flat_map(|t| {
+            t.rows.iter().map(move |row| match distinct {
+                true => Match::Values(row.clone()),
+                false => Match::Resource {
+                    kind: t.kind.clone(),
+                    namespace: Some(row[1].clone()).filter(|ns| !ns.is_empty()),
+                    name: row[2].clone(),
+                },
+            })
+        })
+        .collect()
+}
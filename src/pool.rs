@@ -0,0 +1,100 @@
+// Copyright (c) 2021 Dentrax
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software is furnished to do so,
+// subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+use crate::error::{KubesqlError, Result};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::path::PathBuf;
+use std::sync::{Mutex, OnceLock};
+use std::time::SystemTime;
+
+/// The clients of the contexts, built on the first query of each context and reused by the
+/// following ones for the lifetime of the process, i.e. in the REPL, the daemon and the server
+#[derive(Default)]
+pub struct ClientPool {
+    clients: Mutex<HashMap<String, Pooled>>,
+}
+
+/// A client and the modification time of the kubeconfig it is built from
+struct Pooled {
+    client: kube::Client,
+    modified: Option<SystemTime>,
+}
+
+/// Returns the pool shared by all the queries of the process
+pub fn shared() -> &'static ClientPool {
+    static POOL: OnceLock<ClientPool> = OnceLock::new();
+    POOL.get_or_init(ClientPool::default)
+}
+
+impl ClientPool {
+    /// Returns the client of the given context, built if not pooled yet. It is rebuilt if the
+    /// kubeconfig has changed since, i.e. a rotated token or certificate. The tokens of the exec
+    /// and the auth provider plugins are refreshed by the client itself once they expire
+    pub async fn client(&self, context: &str) -> Result<kube::Client> {
+        let modified = modified();
+        if let Some(pooled) = self.clients.lock().unwrap().get(context) {
+            if pooled.modified == modified {
+                return Ok(pooled.client.clone());
+            }
+        }
+
+        // Not locked while building, the contexts are built concurrently
+        let config = kube::Config::from_kubeconfig(&kube::config::KubeConfigOptions {
+            context: Some(context.to_string()),
+            ..Default::default()
+        })
+        .await
+        .map_err(|e| KubesqlError::KubeConfig(context.to_string(), e))?;
+        let client = kube::Client::try_from(config)?;
+        tracing::debug!(context = %context, "client built");
+
+        self.clients.lock().unwrap().insert(
+            context.to_string(),
+            Pooled {
+                client: client.clone(),
+                modified,
+            },
+        );
+        Ok(client)
+    }
+
+    /// Removes the client of the given context if its credentials are rejected, so the next query
+    /// builds it again from the kubeconfig with fresh credentials
+    pub fn evict_unauthorized(&self, context: &str, error: &kube::Error) {
+        if matches!(error, kube::Error::Api(e) if e.code == 401)
+            && self.clients.lock().unwrap().remove(context).is_some()
+        {
+            tracing::debug!(context = %context, "client evicted");
+        }
+    }
+}
+
+/// Returns the latest modification time of the kubeconfig files, read from $KUBECONFIG or
+/// '~/.kube/config' like the clients do. None if none of them exists
+fn modified() -> Option<SystemTime> {
+    let paths: Vec<PathBuf> = match std::env::var_os("KUBECONFIG") {
+        Some(paths) if !paths.is_empty() => std::env::split_paths(&paths).collect(),
+        _ => vec![PathBuf::from(std::env::var_os("HOME").unwrap_or_default()).join(".kube/config")],
+    };
+
+    paths
+        .iter()
+        .filter_map(|path| std::fs::metadata(path).and_then(|m| m.modified()).ok())
+        .max()
+}
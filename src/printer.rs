@@ -17,21 +17,393 @@
 // IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
 // CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
 
-use crate::parser::ResourceType;
-use crate::planner::Query;
-use k8s_openapi::api::core::v1::Pod;
-use k8s_openapi::api::{apps::v1::Deployment, core::v1::Service};
+use crate::aggregator::AggregateRow;
+use crate::anonymizer::Anonymizer;
+use crate::api_builder::{Failure, Limits};
+use crate::applier::AppliedItem;
+use crate::audit::Finding;
+use crate::catalog::Column;
+use crate::contexts::{self, Context, Health, Namespace};
+use crate::eval;
+use crate::executor::{Estimate, Stats};
+use crate::history::Entry;
+use crate::images::Image;
+use crate::library::LibraryQuery;
+use crate::parser::{ApiAggregates, ResourceType};
+use crate::planner::{self, Aggregate, Projection, Query};
+use crate::policy::Outcome;
+use crate::quantity;
+use crate::template::{Syntax, Template};
+use crate::trend::Point;
+use k8s_openapi::chrono::{DateTime, Utc};
 use kube::api::ObjectList;
-// use kube::Resource;
+use kube::{Resource, ResourceExt};
 use prettytable::{Cell, Row, Table};
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt;
+use std::str::FromStr;
+
+/// The output format of the results
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum Output {
+    /// Nested tables of contexts and namespaces, printed after all queries are done
+    #[default]
+    Table,
+    /// One JSON object per line, printed as soon as the resource is found
+    NdJson,
+    /// The JSON objects rendered through the template as a list, after all queries are done
+    Template(Template),
+    /// Horizontal bars of the COUNT(*) and SUM(...) values of GROUP BY, tables otherwise
+    Chart,
+    /// A block per matched resource or GROUP BY row, a line per column, tables otherwise
+    Vertical,
+}
+
+impl Output {
+    /// Whether the results are printed as JSON objects, either as lines or through a template
+    pub fn is_json(&self) -> bool {
+        !matches!(self, Output::Table | Output::Chart | Output::Vertical)
+    }
+}
+
+impl fmt::Display for Output {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Output::Table => write!(f, "table"),
+            Output::NdJson => write!(f, "ndjson"),
+            Output::Template(t) => write!(f, "{}={}", t.syntax, t.source),
+            Output::Chart => write!(f, "chart"),
+            Output::Vertical => write!(f, "vertical"),
+        }
+    }
+}
+
+impl FromStr for Output {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Output, Self::Err> {
+        match input.split_once('=') {
+            Some(("jsonpath", t)) => Ok(Output::Template(Template::parse(t, Syntax::JsonPath)?)),
+            Some(("go-template", t)) => {
+                Ok(Output::Template(Template::parse(t, Syntax::GoTemplate)?))
+            }
+            _ => match input {
+                "table" => Ok(Output::Table),
+                "ndjson" => Ok(Output::NdJson),
+                "chart" => Ok(Output::Chart),
+                "vertical" => Ok(Output::Vertical),
+                _ => Err(format!("Unexpected output format: {}", input)),
+            },
+        }
+    }
+}
+
+/// The layout of the table output
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Layout {
+    /// The first one of nested, flat and vertical fitting in the terminal, nested if the output
+    /// is not a terminal
+    #[default]
+    Auto,
+    /// A row per kind, a column per context, a table per namespace inside
+    Nested,
+    /// A table per kind, a row per resource
+    Flat,
+    /// A block per resource, a line per column
+    Vertical,
+}
+
+impl FromStr for Layout {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Layout, Self::Err> {
+        match input {
+            "auto" => Ok(Layout::Auto),
+            "nested" => Ok(Layout::Nested),
+            "flat" => Ok(Layout::Flat),
+            "vertical" => Ok(Layout::Vertical),
+            _ => Err(format!("Unexpected layout: {}", input)),
+        }
+    }
+}
+
+/// The order of the matched resources, mirrors kubectl's --sort-by
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortBy {
+    Name,
+    /// The newest first
+    Age,
+    Namespace,
+    Context,
+}
+
+impl FromStr for SortBy {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<SortBy, Self::Err> {
+        match input {
+            "name" => Ok(SortBy::Name),
+            "age" => Ok(SortBy::Age),
+            "namespace" => Ok(SortBy::Namespace),
+            "context" => Ok(SortBy::Context),
+            _ => Err(format!("Unexpected sort key: {}", input)),
+        }
+    }
+}
+
+/// What identifies the same resource replicated to many contexts, to print it once
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DedupeBy {
+    /// The namespace and the name, along with the SELECT field values
+    Name,
+}
+
+impl FromStr for DedupeBy {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<DedupeBy, Self::Err> {
+        match input {
+            "name" => Ok(DedupeBy::Name),
+            _ => Err(format!("Unexpected dedupe key: {}", input)),
+        }
+    }
+}
+
+/// The options shared by all the printers
+#[derive(Debug, Clone, Default)]
+pub struct PrintOptions {
+    pub output: Output,
+    pub layout: Layout,
+    /// Sorts the resources across the contexts and namespaces, grouped by them and by name if None
+    pub sort_by: Option<SortBy>,
+    /// Collapses the same resources of many contexts into one row listing them, if given
+    pub dedupe_by: Option<DedupeBy>,
+    /// Values longer than this are truncated with an ellipsis, unlimited if None
+    pub max_col_width: Option<usize>,
+    /// Style the headers and the errors with the terminal colors
+    pub color: bool,
+    /// The width of the terminal the auto layout fits the tables in, unlimited if None
+    pub width: Option<usize>,
+    /// Replaces the names of the contexts, namespaces and resources with pseudonyms, if given
+    pub anonymizer: Option<Anonymizer>,
+}
+
+impl PrintOptions {
+    /// Prints the given JSON objects one per line, or renders the template with them as a list
+    fn print_json(&self, values: Vec<serde_json::Value>) {
+        match &self.output {
+            Output::Template(template) => {
+                print!(
+                    "{}",
+                    template.render(&serde_json::json!({ "kind": "List", "items": values }))
+                );
+            }
+            _ => values.iter().for_each(|v| println!("{}", v)),
+        }
+    }
+
+    /// Returns the given name through the anonymizer if given, as it is otherwise
+    fn anonymize(&self, name: &str, pseudonym: impl Fn(&Anonymizer, &str) -> String) -> String {
+        match &self.anonymizer {
+            Some(anonymizer) => pseudonym(anonymizer, name),
+            None => name.to_string(),
+        }
+    }
+
+    /// Returns the given values of the SELECT fields with the names among them replaced by their
+    /// pseudonyms if given, i.e. the node of 'spec.nodeName'
+    fn anonymize_fields(
+        &self,
+        kind: &ResourceType,
+        projections: &[&Projection],
+        fields: Vec<String>,
+    ) -> Vec<String> {
+        let anonymizer = match &self.anonymizer {
+            Some(anonymizer) => anonymizer,
+            None => return fields,
+        };
+        projections
+            .iter()
+            .zip(fields)
+            .map(|(p, value)| {
+                anonymizer
+                    .field(&kind.to_string(), &p.name(), &value)
+                    .unwrap_or(value)
+            })
+            .collect()
+    }
+
+    /// Creates a cell of the given value, truncated to the max column width
+    fn cell(&self, value: &str) -> Cell {
+        Cell::new(&self.truncate(value))
+    }
+
+    /// Creates a header cell of the given value, bold if colored
+    fn header(&self, value: &str) -> Cell {
+        match self.color {
+            true => self.cell(value).style_spec("b"),
+            false => self.cell(value),
+        }
+    }
+
+    /// Creates a cell of the given error value, red if colored
+    fn error(&self, value: &str) -> Cell {
+        match self.color {
+            true => self.cell(value).style_spec("Fr"),
+            false => self.cell(value),
+        }
+    }
+
+    /// Prints the given table, with the styles even if the output is not a terminal if colored
+    fn print(&self, table: &Table) {
+        match self.color {
+            true => {
+                let _ = table.print_tty(true);
+            }
+            false => {
+                table.printstd();
+            }
+        }
+    }
+
+    /// Prints the given rows as a table, or as a block per row if the layout is vertical or the
+    /// table does not fit in the terminal
+    fn print_rows(&self, header: Row, rows: Vec<Row>) {
+        let mut table = Table::init(rows);
+        table.set_titles(header.clone());
+
+        match self.vertical() || (self.layout == Layout::Auto && !self.fits(&table)) {
+            true => self.print_records(&header, &table),
+            false => self.print(&table),
+        }
+    }
+
+    /// Whether the rows are printed as blocks regardless of the width of the terminal
+    fn vertical(&self) -> bool {
+        self.output == Output::Vertical || self.layout == Layout::Vertical
+    }
+
+    /// Prints each row as a block of its columns one per line, like the expanded mode of psql
+    fn print_records(&self, header: &Row, table: &Table) {
+        let keys: Vec<String> = header.iter().map(Cell::get_content).collect();
+        let key_width = keys.iter().map(|k| k.chars().count()).max().unwrap_or(0);
+
+        let value_width = table
+            .row_iter()
+            .flat_map(|row| row.iter().map(Cell::get_content).collect::<Vec<String>>())
+            .flat_map(|v| v.lines().map(|l| l.chars().count()).collect::<Vec<usize>>())
+            .max()
+            .unwrap_or(0);
+
+        for (i, row) in table.row_iter().enumerate() {
+            let values: Vec<String> = row.iter().map(Cell::get_content).collect();
+            // The title of the record is cut at the border of the columns as in psql
+            let title = format!(
+                "{:-<key_width$}+{}",
+                format!("-[ RECORD {} ]", i + 1),
+                "-".repeat(value_width + 1),
+                key_width = key_width + 1
+            );
+            let width = self.width.unwrap_or(usize::MAX);
+            println!("{}", title.chars().take(width).collect::<String>());
+            for (key, value) in keys.iter().zip(values.iter()) {
+                // The lines of a value after the first one are aligned under it
+                for (j, line) in value
+                    .lines()
+                    .chain(value.is_empty().then_some(""))
+                    .enumerate()
+                {
+                    let key = if j == 0 { key.as_str() } else { "" };
+                    println!("{:<key_width$} | {}", key, line, key_width = key_width);
+                }
+            }
+        }
+    }
+
+    /// Whether the given table is as wide as the terminal at most, always if not a terminal
+    fn fits(&self, table: &Table) -> bool {
+        self.width.is_none_or(|width| {
+            let longest = table.to_string().lines().map(|l| l.chars().count()).max();
+            longest.unwrap_or(0) <= width
+        })
+    }
+
+    fn truncate(&self, value: &str) -> String {
+        match self.max_col_width {
+            Some(max) if value.chars().count() > max => {
+                let mut v: String = value.chars().take(max.saturating_sub(1)).collect();
+                v.push('…');
+                v
+            }
+            _ => value.to_string(),
+        }
+    }
+}
+
+/// Returns the width of the terminal, None if the output is not a terminal. $COLUMNS overrides it
+pub fn terminal_width() -> Option<usize> {
+    use std::io::IsTerminal;
+
+    if let Some(columns) = std::env::var("COLUMNS").ok().and_then(|c| c.parse().ok()) {
+        return Some(columns);
+    }
+    if !std::io::stdout().is_terminal() {
+        return None;
+    }
+    window_width()
+}
+
+#[cfg(unix)]
+fn window_width() -> Option<usize> {
+    let mut size = libc::winsize {
+        ws_row: 0,
+        ws_col: 0,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    // SAFETY: TIOCGWINSZ only writes the size of the terminal into the given struct
+    match unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) } {
+        0 if size.ws_col > 0 => Some(size.ws_col as usize),
+        _ => None,
+    }
+}
+
+#[cfg(not(unix))]
+fn window_width() -> Option<usize> {
+    None
+}
 
 #[derive(Debug)]
 pub struct PrintItem<'a> {
     pub context: &'a str,
     pub namespace: &'a str,
     pub kind: ResourceType,
-    pub value: String,
+    pub name: String,
+    pub created: Option<DateTime<Utc>>,
+    /// Values of the SELECT fields given for the kind, in the same order
+    pub fields: Vec<String>,
+}
+
+/// The results of a kind as plain rows, i.e. to export them into a file
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ResultTable {
+    pub kind: String,
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<String>>,
+    /// The contexts which could not list the kind, i.e. a missing CRD or an expired token
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub failures: Vec<ContextFailure>,
+}
+
+/// Why a kind could not be listed on a context, printed in place of its resources
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ContextFailure {
+    pub context: String,
+    /// The namespace the failure is of, None if of all the namespaces
+    pub namespace: Option<String>,
+    pub status: Failure,
 }
 
 #[derive(Debug, Default)]
@@ -41,6 +413,19 @@ pub struct Printer<'a> {
     contexts: Option<&'a [String]>,
     namespaces: Option<&'a [String]>,
     queries: Option<&'a [Query]>,
+    projections: Option<&'a [Projection]>,
+    options: PrintOptions,
+    /// Print only the unique values of the SELECT fields
+    distinct: bool,
+    /// The unique SELECT field values of each kind, if distinct
+    rows: BTreeSet<(String, Vec<String>)>,
+    /// The servers of the clusters by context, i.e. to select '__cluster_server'
+    servers: BTreeMap<String, String>,
+    /// The kinds the contexts could not list, in the order they are found
+    failures: Vec<(ResourceType, ContextFailure)>,
+    /// The kind, context, namespace, name and index of the unnested array values of the inserted
+    /// rows, 0 if none is unnested. A resource matching several queries is inserted once
+    inserted: HashSet<(ResourceType, &'a str, &'a str, String, usize)>,
 }
 
 impl<'a> Printer<'a> {
@@ -70,74 +455,504 @@ impl<'a> Printer<'a> {
         self
     }
 
-    pub fn insert_deployments(
+    /// Set the given SELECT fields
+    pub fn projections(mut self, projections: &'a [Projection]) -> Printer<'a> {
+        self.projections = Option::from(projections);
+        self
+    }
+
+    /// Set the servers of the clusters by context
+    pub fn servers(mut self, servers: BTreeMap<String, String>) -> Printer<'a> {
+        self.servers = servers;
+        self
+    }
+
+    /// Set the given print options
+    pub fn options(mut self, options: PrintOptions) -> Printer<'a> {
+        self.options = options;
+        self
+    }
+
+    /// Set whether only the unique values of the SELECT fields are printed
+    pub fn distinct(mut self, distinct: bool) -> Printer<'a> {
+        self.distinct = distinct;
+        self
+    }
+
+    pub fn insert<K: Resource + Serialize + Clone>(
         &mut self,
         ctx: &'a str,
         ns: &'a str,
-        objects: ObjectList<Deployment>,
+        kind: ResourceType,
+        objects: ObjectList<K>,
     ) {
-        let v = objects
-            .items
-            .into_iter()
-            .map(|x| x.metadata.name.unwrap())
-            .collect::<Vec<String>>();
-        self.items.push(PrintItem {
-            context: ctx,
-            namespace: ns,
-            kind: ResourceType::Deployment,
-            value: v.join("\n"),
-        })
+        let projections = self.projections_of(&kind);
+        let server = self.servers.get(ctx);
+        let source = contexts::source(
+            &self.options.anonymize(ctx, Anonymizer::context),
+            &self.options.anonymize(ns, Anonymizer::namespace),
+            server
+                .map(|s| self.options.anonymize(s, Anonymizer::server))
+                .as_ref(),
+        );
+
+        if self.distinct {
+            return self.insert_distinct(&kind, &projections, &source, objects);
+        }
+
+        for x in objects.items {
+            let object = serde_json::to_value(&x).unwrap_or_default();
+            let rows = unnest(
+                &projections,
+                resolved_on_all(&projections, &object, &source),
+            );
+
+            for (index, fields) in rows.into_iter().enumerate() {
+                let fields = self.options.anonymize_fields(&kind, &projections, fields);
+                let item = PrintItem {
+                    context: ctx,
+                    namespace: ns,
+                    kind: kind.clone(),
+                    name: self
+                        .options
+                        .anonymize(&x.name_any(), |a, name| a.name(&kind.to_string(), name)),
+                    created: x.creation_timestamp().map(|t| t.0),
+                    fields,
+                };
+
+                // A resource is inserted once, even if it matches several queries
+                let key = (kind.clone(), ctx, ns, item.name.clone(), index);
+                if !self.inserted.insert(key) {
+                    continue;
+                }
+
+                // Sorted ones are printed once all of them are inserted
+                if self.streamed() {
+                    println!("{}", self.to_json(&item, &projections));
+                }
+
+                self.items.push(item);
+            }
+        }
     }
 
-    pub fn insert_pods(&mut self, ctx: &'a str, ns: &'a str, objects: ObjectList<Pod>) {
-        let v = objects
-            .items
-            .into_iter()
-            .map(|x| x.metadata.name.unwrap())
-            .collect::<Vec<String>>();
-        self.items.push(PrintItem {
-            context: ctx,
-            namespace: ns,
-            kind: ResourceType::Pod,
-            value: v.join("\n"),
+    /// Records that the context could not list the kind in the namespace, it is printed distinctly
+    /// from having none of them. The failures of all the namespaces are recorded once
+    pub fn failed(&mut self, ctx: &str, ns: &str, kind: ResourceType, failure: Failure) {
+        let failure = ContextFailure {
+            context: ctx.to_string(),
+            namespace: Some(ns)
+                .filter(|ns| failure.is_namespaced() && !ns.is_empty())
+                .map(str::to_string),
+            status: failure,
+        };
+        if self.failures.contains(&(kind.clone(), failure.clone())) {
+            return;
+        }
+
+        if self.streamed() {
+            println!(
+                "{}",
+                failure_json(&kind.to_string(), &self.anonymized(&failure))
+            );
+        }
+        self.failures.push((kind, failure));
+    }
+
+    /// Returns why the context could not list the kind in the namespace, if it could not
+    fn failure(&self, kind: &ResourceType, context: &str, ns: &str) -> Option<Failure> {
+        self.failures
+            .iter()
+            .find(|(k, f)| {
+                k == kind && f.context == context && f.namespace.as_ref().is_none_or(|n| n == ns)
+            })
+            .map(|(_, f)| f.status)
+    }
+
+    /// Returns the failures of the contexts to list the kind, in FROM order
+    fn failures_of(&self, kind: &ResourceType) -> Vec<ContextFailure> {
+        let contexts = self.contexts.unwrap();
+        let mut failures: Vec<ContextFailure> = self
+            .failures
+            .iter()
+            .filter(|(k, _)| k == kind)
+            .map(|(_, f)| f.clone())
+            .collect();
+        failures.sort_by_key(|f| contexts.iter().position(|c| *c == f.context));
+        failures.iter().map(|f| self.anonymized(f)).collect()
+    }
+
+    /// Returns the given failure with the pseudonyms of its context and namespace, if anonymized
+    fn anonymized(&self, failure: &ContextFailure) -> ContextFailure {
+        match &self.options.anonymizer {
+            Some(anonymizer) => anonymizer.failure(failure),
+            None => failure.clone(),
+        }
+    }
+
+    /// Keeps only the unique values of the SELECT fields, arrays on the field paths are flattened,
+    /// so a row is inserted for each combination of the values
+    fn insert_distinct<K: Serialize + Clone>(
+        &mut self,
+        kind: &ResourceType,
+        projections: &[&Projection],
+        source: &serde_json::Value,
+        objects: ObjectList<K>,
+    ) {
+        for x in objects.items {
+            let object = serde_json::to_value(&x).unwrap_or_default();
+
+            let mut rows: Vec<Vec<String>> = vec![vec![]];
+            for p in projections {
+                let mut values = eval::resolve_projection_all(p, resolved_on(p, &object, source));
+                if values.is_empty() {
+                    values.push("-".to_string());
+                }
+                rows = rows
+                    .iter()
+                    .flat_map(|r| {
+                        values.iter().map(move |v| {
+                            let mut r = r.clone();
+                            r.push(v.clone());
+                            r
+                        })
+                    })
+                    .collect();
+            }
+
+            for row in rows {
+                let row = (
+                    kind.to_string(),
+                    self.options.anonymize_fields(kind, projections, row),
+                );
+                if self.rows.contains(&row) {
+                    continue;
+                }
+
+                if self.streamed() {
+                    println!("{}", distinct_json(projections, &row));
+                }
+
+                self.rows.insert(row);
+            }
+        }
+    }
+
+    /// Whether the results are printed as NDJSON during insertion, the sorted ones are not
+    fn streamed(&self) -> bool {
+        self.options.output == Output::NdJson
+            && (self.distinct
+                || (self.options.sort_by.is_none() && self.options.dedupe_by.is_none()))
+    }
+
+    /// Prints one table per kind, a row for each unique value of the SELECT fields
+    fn print_distinct(&self) {
+        for kind in self.kinds() {
+            let projections = self.projections_of(&kind);
+            if projections.is_empty() {
+                continue;
+            }
+
+            let header = Row::new(
+                projections
+                    .iter()
+                    .map(|p| self.options.header(&p.name()))
+                    .collect(),
+            );
+            let rows = self
+                .rows
+                .iter()
+                .filter(|(k, _)| *k == kind.to_string())
+                .map(|(_, row)| Row::new(row.iter().map(|v| self.options.cell(v)).collect()))
+                .collect();
+
+            println!("{}", kind.to_string().to_uppercase());
+            self.options.print_rows(header, rows);
+            print_failures(&self.failures_of(&kind), &self.options);
+        }
+    }
+
+    fn to_json(&self, item: &PrintItem, projections: &[&Projection]) -> serde_json::Value {
+        // The cluster-scoped kinds have no namespace
+        let namespace = Some(item.namespace)
+            .filter(|ns| !ns.is_empty())
+            .map(|ns| self.options.anonymize(ns, Anonymizer::namespace));
+        let mut value = serde_json::json!({
+            "context": self.options.anonymize(item.context, Anonymizer::context),
+            "namespace": namespace,
+            "kind": item.kind.to_string(),
+            "name": item.name,
         });
+
+        if !projections.is_empty() {
+            value["fields"] = projections
+                .iter()
+                .zip(item.fields.iter())
+                .map(|(p, f)| (p.name(), serde_json::Value::from(f.as_str())))
+                .collect::<serde_json::Map<String, serde_json::Value>>()
+                .into();
+        }
+
+        value
+    }
+
+    /// Returns the SELECT fields given for the kind, the metadata columns are given for all
+    fn projections_of(&self, kind: &ResourceType) -> Vec<&'a Projection> {
+        self.projections
+            .unwrap_or_default()
+            .iter()
+            .filter(|p| p.kind == planner::SOURCE || p.kind.eq_ignore_ascii_case(&kind.to_string()))
+            .collect()
     }
 
-    pub fn insert_services(&mut self, ctx: &'a str, ns: &'a str, objects: ObjectList<Service>) {
-        let v = objects
+    /// Returns the matched resources of the kind in the given context and namespace
+    fn items_of(&self, kind: &ResourceType, context: &str, ns: &str) -> Vec<&PrintItem<'a>> {
+        let mut items = self
             .items
-            .into_iter()
-            .map(|x| x.metadata.name.unwrap())
-            .collect::<Vec<String>>();
-        self.items.push(PrintItem {
-            context: ctx,
-            namespace: ns,
-            kind: ResourceType::Service,
-            value: v.join("\n"),
+            .iter()
+            .filter(|f| f.kind == *kind && f.context == context && f.namespace == ns)
+            .collect::<Vec<&PrintItem>>();
+
+        // Keep the output stable regardless of the query order
+        self.sort(&mut items);
+        items
+    }
+
+    /// Returns the matched resources of the kind in all the contexts and namespaces, grouped by
+    /// them in the given order unless sorted by --sort-by
+    fn rows_of(&self, kind: &ResourceType) -> Vec<&PrintItem<'a>> {
+        let mut items = Vec::new();
+        for context in self.contexts.unwrap() {
+            for ns in self.namespaces_of(kind) {
+                items.extend(self.items_of(kind, context, ns));
+            }
+        }
+
+        if self.options.sort_by.is_some() {
+            self.sort(&mut items);
+        }
+        items
+    }
+
+    /// Sorts the resources by --sort-by, by name if not given. The ties are sorted by name
+    fn sort(&self, items: &mut [&PrintItem]) {
+        items.sort_by(|a, b| {
+            let ordering = match self.options.sort_by {
+                Some(SortBy::Age) => b.created.cmp(&a.created),
+                Some(SortBy::Namespace) => a.namespace.cmp(b.namespace),
+                Some(SortBy::Context) => a.context.cmp(b.context),
+                Some(SortBy::Name) | None => Ordering::Equal,
+            };
+            ordering.then_with(|| a.name.cmp(&b.name))
         });
     }
 
+    /// Returns the given namespaces, or only the empty one if the kind is cluster-scoped
+    fn namespaces_of(&self, kind: &ResourceType) -> Vec<&'a str> {
+        match kind.is_cluster_scoped() {
+            true => vec![""],
+            false => self
+                .namespaces
+                .unwrap()
+                .iter()
+                .map(String::as_str)
+                .collect(),
+        }
+    }
+
+    /// Returns the kinds given in WHERE statement, in the given order
+    fn kinds(&self) -> Vec<ResourceType> {
+        let mut kinds: Vec<ResourceType> = Vec::new();
+        for q in self.queries.unwrap() {
+            if let Ok(kind) = ResourceType::from_str(&q.kind.to_lowercase()) {
+                if !kinds.contains(&kind) {
+                    kinds.push(kind);
+                }
+            }
+        }
+        kinds
+    }
+
+    /// Renders the matched resources of the kind in the given context and namespace
+    fn cell(&self, kind: &ResourceType, context: &str, ns: &str) -> Cell {
+        let items = self.items_of(kind, context, ns);
+
+        if let Some(failure) = self.failure(kind, context, ns) {
+            return self.options.error(&failure.to_string());
+        }
+
+        if items.is_empty() {
+            return Cell::new("-");
+        }
+
+        let projections = self.projections_of(kind);
+        if projections.is_empty() {
+            let names = items
+                .iter()
+                .map(|x| self.options.truncate(&x.name))
+                .collect::<Vec<String>>();
+            return Cell::new(&names.join("\n"));
+        }
+
+        let mut header = vec![self.options.header("NAME")];
+        header.extend(projections.iter().map(|p| self.options.header(&p.name())));
+
+        let mut table = Table::new();
+        table.add_row(Row::new(header));
+        for x in items {
+            let mut cells = vec![self.options.cell(&x.name)];
+            cells.extend(x.fields.iter().map(|f| self.options.cell(f)));
+            table.add_row(Row::new(cells));
+        }
+
+        Cell::from(&table)
+    }
+
+    /// Prints one table per kind, a row for each matched resource
+    fn print_flat(&self) {
+        for kind in self.kinds() {
+            let projections = self.projections_of(&kind);
+
+            let mut header = vec![
+                self.options.header("CONTEXT"),
+                self.options.header("NAMESPACE"),
+                self.options.header("NAME"),
+            ];
+            header.extend(projections.iter().map(|p| self.options.header(&p.name())));
+
+            let mut rows = Vec::new();
+            for x in self.rows_of(&kind) {
+                let namespace = self.options.anonymize(x.namespace, Anonymizer::namespace);
+                let mut cells = vec![
+                    self.options
+                        .cell(&self.options.anonymize(x.context, Anonymizer::context)),
+                    self.options.cell(
+                        Some(namespace.as_str())
+                            .filter(|n| !n.is_empty())
+                            .unwrap_or("-"),
+                    ),
+                    self.options.cell(&x.name),
+                ];
+                cells.extend(x.fields.iter().map(|f| self.options.cell(f)));
+                rows.push(Row::new(cells));
+            }
+
+            for failure in self.failures_of(&kind) {
+                rows.push(failure_row(&failure, &self.options));
+            }
+
+            println!("{}", kind.to_string().to_uppercase());
+            self.options.print_rows(Row::new(header), rows);
+        }
+    }
+
+    /// Returns one table per kind, a row for each matched resource or unique value if distinct
+    pub fn tables(&self) -> Vec<ResultTable> {
+        let mut tables = Vec::new();
+
+        for kind in self.kinds() {
+            let projections = self.projections_of(&kind);
+            let paths = projections.iter().map(|p| p.name());
+
+            if self.distinct {
+                tables.push(ResultTable {
+                    kind: kind.to_string(),
+                    columns: paths.collect(),
+                    rows: self
+                        .rows
+                        .iter()
+                        .filter(|(k, _)| *k == kind.to_string())
+                        .map(|(_, row)| row.clone())
+                        .collect(),
+                    failures: self.failures_of(&kind),
+                });
+                continue;
+            }
+
+            let mut columns = vec![
+                "context".to_string(),
+                "namespace".to_string(),
+                "name".to_string(),
+            ];
+            columns.extend(paths);
+
+            let mut rows = Vec::new();
+            for x in self.rows_of(&kind) {
+                let mut row = vec![
+                    self.options.anonymize(x.context, Anonymizer::context),
+                    self.options.anonymize(x.namespace, Anonymizer::namespace),
+                    x.name.clone(),
+                ];
+                row.extend(x.fields.iter().cloned());
+                rows.push(row);
+            }
+
+            tables.push(ResultTable {
+                kind: kind.to_string(),
+                columns,
+                rows,
+                failures: self.failures_of(&kind),
+            });
+        }
+
+        tables
+    }
+
     pub fn print(self) {
-        // 1. Creating tables for all given contexts
+        // Already printed during insertion
+        if self.streamed() {
+            return;
+        }
 
-        // Represents 'Context - Table' mapping
-        let mut table_context_pods: HashMap<String, Table> = HashMap::new();
-        let mut table_context_deployments: HashMap<String, Table> = HashMap::new();
-        let mut table_context_services: HashMap<String, Table> = HashMap::new();
+        if self.options.output.is_json() {
+            let mut values = Vec::new();
+            for kind in self.kinds() {
+                let projections = self.projections_of(&kind);
+                match self.distinct {
+                    true => values.extend(
+                        self.rows
+                            .iter()
+                            .filter(|(k, _)| *k == kind.to_string())
+                            .map(|row| distinct_json(&projections, row)),
+                    ),
+                    false => values.extend(
+                        self.rows_of(&kind)
+                            .into_iter()
+                            .map(|x| self.to_json(x, &projections)),
+                    ),
+                }
+                values.extend(
+                    self.failures_of(&kind)
+                        .iter()
+                        .map(|f| failure_json(&kind.to_string(), f)),
+                );
+            }
+            return self.options.print_json(values);
+        }
 
-        let should_append_pod: bool = self
-            .queries
-            .unwrap()
-            .iter()
-            .any(|x| x.kind.eq_ignore_ascii_case(&ResourceType::Pod.to_string()));
-        let should_append_deployment: bool = self.queries.unwrap().iter().any(|x| {
-            x.kind
-                .eq_ignore_ascii_case(&ResourceType::Deployment.to_string())
-        });
-        let should_append_service: bool = self.queries.unwrap().iter().any(|x| {
-            x.kind
-                .eq_ignore_ascii_case(&ResourceType::Service.to_string())
-        });
+        if self.distinct {
+            return self.print_distinct();
+        }
+
+        if self.options.layout == Layout::Flat || self.options.vertical() {
+            return self.print_flat();
+        }
+
+        // The flat tables are narrower, each one falls back to the vertical layout on its own
+        let table = self.nested();
+        match self.options.layout == Layout::Auto && !self.options.fits(&table) {
+            true => self.print_flat(),
+            false => self.options.print(&table),
+        }
+    }
+
+    /// Returns a row per kind, a column per context and a table per namespace inside
+    fn nested(&self) -> Table {
+        // 1. Collect the kinds given in WHERE statement, in the given order
+        let kinds = self.kinds();
+
+        // Represents 'Kind - Context Tables' mapping, the tables are in the same order of FROM
+        let mut table_kind_contexts: Vec<Vec<Table>> = vec![Vec::new(); kinds.len()];
 
         // 2. Initialize the all contexts
         for context in self.contexts.unwrap() {
@@ -146,81 +961,32 @@ impl<'a> Printer<'a> {
                 .namespaces
                 .unwrap()
                 .iter()
-                .map(|x| Cell::new(x))
+                .map(|x| {
+                    self.options
+                        .cell(&self.options.anonymize(x, Anonymizer::namespace))
+                })
                 .collect::<Vec<Cell>>();
             table_ctx.add_row(Row::new(cells));
 
-            let mut table_ctx_pods = table_ctx.clone();
-            let mut table_ctx_deployments = table_ctx.clone();
-            let mut table_ctx_services = table_ctx.clone();
-
-            let mut cells_pods: Vec<Cell> = Vec::new();
-            let mut cells_deployments: Vec<Cell> = Vec::new();
-            let mut cells_services: Vec<Cell> = Vec::new();
-
-            for ns in self.namespaces.unwrap() {
-                if should_append_pod {
-                    let pods = self
-                        .items
-                        .iter()
-                        .filter(|f| {
-                            f.kind == ResourceType::Pod
-                                && *f.context == *context
-                                && *f.namespace == *ns
-                        })
-                        .map(|m| m.value.clone())
-                        .collect::<String>();
-                    if !pods.is_empty() {
-                        cells_pods.push(Cell::new(&pods));
-                    } else {
-                        cells_pods.push(Cell::new("-"));
-                    }
+            for (i, kind) in kinds.iter().enumerate() {
+                // The namespace dimension collapses for the cluster-scoped kinds
+                if kind.is_cluster_scoped() {
+                    let cell = self.cell(kind, context, "");
+                    table_kind_contexts[i].push(Table::init(vec![Row::new(vec![cell])]));
+                    continue;
                 }
 
-                if should_append_deployment {
-                    let deployments = self
-                        .items
-                        .iter()
-                        .filter(|f| {
-                            f.kind == ResourceType::Deployment
-                                && *f.context == *context
-                                && *f.namespace == *ns
-                        })
-                        .map(|m| m.value.clone())
-                        .collect::<String>();
-                    if !deployments.is_empty() {
-                        cells_deployments.push(Cell::new(&deployments));
-                    } else {
-                        cells_deployments.push(Cell::new("-"));
-                    }
-                }
+                let mut table_ctx_kind = table_ctx.clone();
+                let cells = self
+                    .namespaces
+                    .unwrap()
+                    .iter()
+                    .map(|ns| self.cell(kind, context, ns))
+                    .collect::<Vec<Cell>>();
+                table_ctx_kind.add_row(Row::new(cells));
 
-                if should_append_service {
-                    let services = self
-                        .items
-                        .iter()
-                        .filter(|f| {
-                            f.kind == ResourceType::Service
-                                && *f.context == *context
-                                && *f.namespace == *ns
-                        })
-                        .map(|m| m.value.clone())
-                        .collect::<String>();
-                    if !services.is_empty() {
-                        cells_services.push(Cell::new(&services));
-                    } else {
-                        cells_services.push(Cell::new("-"));
-                    }
-                }
+                table_kind_contexts[i].push(table_ctx_kind);
             }
-
-            table_ctx_pods.add_row(Row::new(cells_pods));
-            table_ctx_deployments.add_row(Row::new(cells_deployments));
-            table_ctx_services.add_row(Row::new(cells_services));
-
-            table_context_pods.insert(context.clone(), table_ctx_pods);
-            table_context_deployments.insert(context.clone(), table_ctx_deployments);
-            table_context_services.insert(context.clone(), table_ctx_services);
         }
 
         let mut row: Vec<Row> = vec![];
@@ -229,38 +995,955 @@ impl<'a> Printer<'a> {
             .contexts
             .unwrap()
             .iter()
-            .map(|x| Cell::new(x.as_str()))
+            .map(|x| {
+                self.options
+                    .cell(&self.options.anonymize(x, Anonymizer::context))
+            })
             .collect::<Vec<Cell>>();
-        cs.insert(0, Cell::new("KIND / CONTEXT"));
+        cs.insert(0, self.options.header("KIND / CONTEXT"));
         row.push(Row::new(cs));
 
-        if should_append_pod {
-            let mut rows_pod: Row = table_context_pods
+        for (kind, tables) in kinds.iter().zip(table_kind_contexts.iter()) {
+            let mut rows_kind: Row = tables.iter().map(Cell::from).collect::<Row>();
+            rows_kind.insert_cell(0, Cell::new(&kind.to_string()));
+            row.push(rows_kind);
+        }
+
+        Table::init(row)
+    }
+}
+
+/// Collapses the rows of the same resource in many contexts into one, with the contexts it exists
+/// in as 'contexts'. The rows of a resource whose SELECT field values differ are kept apart
+pub fn dedupe(tables: Vec<ResultTable>, by: DedupeBy) -> Vec<ResultTable> {
+    tables
+        .into_iter()
+        .map(|mut table| {
+            // The unique values of SELECT DISTINCT have no context
+            let context = match table.columns.iter().position(|c| c == "context") {
+                Some(i) => i,
+                None => return table,
+            };
+
+            let mut rows: Vec<Vec<String>> = Vec::new();
+            let mut seen: HashMap<Vec<String>, usize> = HashMap::new();
+            for row in table.rows {
+                let key = match by {
+                    DedupeBy::Name => row
+                        .iter()
+                        .enumerate()
+                        .filter(|(i, _)| *i != context)
+                        .map(|(_, v)| v.clone())
+                        .collect(),
+                };
+
+                match seen.get(&key) {
+                    Some(&i) => {
+                        let contexts = &mut rows[i][context];
+                        contexts.push(',');
+                        contexts.push_str(&row[context]);
+                    }
+                    None => {
+                        seen.insert(key, rows.len());
+                        rows.push(row);
+                    }
+                }
+            }
+
+            table.columns[context] = "contexts".to_string();
+            table.rows = rows;
+            table
+        })
+        .collect()
+}
+
+/// Returns the values of each SELECT field of the object, all the values of the unnested ones
+/// and the joined values of the others
+fn resolved_on_all(
+    projections: &[&Projection],
+    object: &serde_json::Value,
+    source: &serde_json::Value,
+) -> Vec<Vec<String>> {
+    projections
+        .iter()
+        .map(|p| {
+            let object = resolved_on(p, object, source);
+            match p.unnest {
+                true => eval::resolve_projection_all(p, object),
+                false => eval::resolve_projection(p, object).into_iter().collect(),
+            }
+        })
+        .collect()
+}
+
+/// Returns the rows of the given SELECT field values, one for each value of the unnested arrays,
+/// zipped by their indices. The other values are repeated, the missing ones are '-'
+fn unnest(projections: &[&Projection], values: Vec<Vec<String>>) -> Vec<Vec<String>> {
+    let rows = projections
+        .iter()
+        .zip(values.iter())
+        .filter(|(p, _)| p.unnest)
+        .map(|(_, v)| v.len())
+        .max()
+        .unwrap_or_default()
+        .max(1);
+
+    (0..rows)
+        .map(|i| {
+            projections
+                .iter()
+                .zip(values.iter())
+                .map(|(p, v)| match p.unnest {
+                    true => v.get(i),
+                    false => v.first(),
+                })
+                .map(|v| v.cloned().unwrap_or_else(|| "-".to_string()))
+                .collect()
+        })
+        .collect()
+}
+
+/// Returns the object the SELECT field is resolved on, the metadata columns on the source of
+/// the object
+fn resolved_on<'v>(
+    p: &Projection,
+    object: &'v serde_json::Value,
+    source: &'v serde_json::Value,
+) -> &'v serde_json::Value {
+    match p.kind == planner::SOURCE {
+        true => source,
+        false => object,
+    }
+}
+
+/// Returns the given unique values of the SELECT fields of a kind as a JSON object
+fn distinct_json(projections: &[&Projection], row: &(String, Vec<String>)) -> serde_json::Value {
+    let fields = projections
+        .iter()
+        .zip(row.1.iter())
+        .map(|(p, f)| (p.name(), serde_json::Value::from(f.as_str())))
+        .collect::<serde_json::Map<String, serde_json::Value>>();
+    serde_json::json!({ "kind": row.0, "fields": fields })
+}
+
+/// Returns the failure of a context to list a kind as a JSON object, in place of its resources
+fn failure_json(kind: &str, failure: &ContextFailure) -> serde_json::Value {
+    serde_json::json!({
+        "context": failure.context,
+        "namespace": failure.namespace,
+        "kind": kind,
+        "status": failure.status,
+    })
+}
+
+/// Returns the failure of a context to list a kind as a row of the flat layout, in place of its
+/// resources
+fn failure_row(failure: &ContextFailure, options: &PrintOptions) -> Row {
+    Row::new(vec![
+        options.cell(&failure.context),
+        options.cell(failure.namespace.as_deref().unwrap_or("-")),
+        options.error(&failure.status.to_string()),
+    ])
+}
+
+/// Prints the failures of the contexts to list a kind under its table, one row per failure
+fn print_failures(failures: &[ContextFailure], options: &PrintOptions) {
+    if failures.is_empty() {
+        return;
+    }
+
+    let mut table = Table::new();
+    for failure in failures {
+        let source = match &failure.namespace {
+            Some(ns) => format!("{}/{}", failure.context, ns),
+            None => failure.context.clone(),
+        };
+        table.add_row(Row::new(vec![
+            options.error(&failure.status.to_string().to_uppercase()),
+            options.cell(&source),
+        ]));
+    }
+    options.print(&table);
+}
+
+/// Prints the result of each manifest of an INSERT statement per context and namespace
+pub fn print_applied(items: &[AppliedItem], options: &PrintOptions) {
+    if options.output.is_json() {
+        return options.print_json(
+            items
+                .iter()
+                .map(|item| {
+                    serde_json::json!({
+                        "context": item.context,
+                        "namespace": item.namespace,
+                        "kind": item.kind,
+                        "name": item.name,
+                        "error": item.error,
+                    })
+                })
+                .collect(),
+        );
+    }
+
+    let mut table = Table::new();
+    table.add_row(Row::new(vec![
+        options.header("CONTEXT"),
+        options.header("NAMESPACE"),
+        options.header("KIND"),
+        options.header("NAME"),
+        options.header("STATUS"),
+    ]));
+
+    for item in items {
+        table.add_row(Row::new(vec![
+            options.cell(&item.context),
+            options.cell(item.namespace.as_deref().unwrap_or("-")),
+            options.cell(&item.kind),
+            options.cell(&item.name),
+            match &item.error {
+                Some(e) => options.error(e),
+                None => options.cell("applied"),
+            },
+        ]));
+    }
+
+    options.print(&table);
+}
+
+/// Prints the fields of a kind listed by SHOW COLUMNS
+pub fn print_columns(columns: &[Column], options: &PrintOptions) {
+    if options.output.is_json() {
+        return options.print_json(
+            columns
+                .iter()
+                .map(|c| serde_json::json!({ "field": c.path, "type": c.ty }))
+                .collect(),
+        );
+    }
+
+    let mut table = Table::new();
+    table.add_row(Row::new(vec![
+        options.header("FIELD"),
+        options.header("TYPE"),
+    ]));
+
+    for c in columns {
+        table.add_row(Row::new(vec![options.cell(&c.path), options.cell(&c.ty)]));
+    }
+
+    options.print(&table);
+}
+
+/// Prints the contexts of the kubeconfig listed by SHOW CONTEXTS, the current one is marked with '*'
+pub fn print_contexts(contexts: &[Context], options: &PrintOptions) {
+    if options.output.is_json() {
+        return options.print_json(
+            contexts
+                .iter()
+                .filter_map(|c| serde_json::to_value(c).ok())
+                .collect(),
+        );
+    }
+
+    let mut table = Table::new();
+    table.add_row(Row::new(vec![
+        options.header("CURRENT"),
+        options.header("NAME"),
+        options.header("CLUSTER"),
+        options.header("SERVER"),
+        options.header("USER"),
+        options.header("NAMESPACE"),
+        options.header("LABELS"),
+    ]));
+
+    for c in contexts {
+        let labels = c
+            .labels
+            .iter()
+            .map(|(k, v)| format!("{}={}", k, v))
+            .collect::<Vec<_>>()
+            .join(",");
+        table.add_row(Row::new(vec![
+            options.cell(if c.current { "*" } else { "" }),
+            options.cell(&c.name),
+            options.cell(c.cluster.as_deref().unwrap_or("-")),
+            options.cell(c.server.as_deref().unwrap_or("-")),
+            options.cell(c.user.as_deref().unwrap_or("-")),
+            options.cell(c.namespace.as_deref().unwrap_or("-")),
+            options.cell(match labels.is_empty() {
+                true => "-",
+                false => &labels,
+            }),
+        ]));
+    }
+
+    options.print(&table);
+}
+
+/// Prints the health of the API server of each context checked by ping
+pub fn print_health(health: &[Health], options: &PrintOptions) {
+    if options.output.is_json() {
+        return options.print_json(
+            health
                 .iter()
-                .map(|x| Cell::from(x.1))
-                .collect::<Row>();
-            rows_pod.insert_cell(0, Cell::new("pod"));
-            row.push(rows_pod);
+                .filter_map(|h| serde_json::to_value(h).ok())
+                .collect(),
+        );
+    }
+
+    let mut table = Table::new();
+    table.add_row(Row::new(vec![
+        options.header("CONTEXT"),
+        options.header("SERVER"),
+        options.header("STATUS"),
+        options.header("VERSION"),
+        options.header("LATENCY"),
+        options.header("ERROR"),
+    ]));
+
+    for h in health {
+        table.add_row(Row::new(vec![
+            options.cell(&h.context),
+            options.cell(h.server.as_deref().unwrap_or("-")),
+            options.cell(&h.status.to_string()),
+            options.cell(h.version.as_deref().unwrap_or("-")),
+            options.cell(
+                &h.latency_ms
+                    .map(|ms| format!("{}ms", ms))
+                    .unwrap_or_else(|| "-".to_string()),
+            ),
+            options.cell(h.error.as_deref().unwrap_or("-")),
+        ]));
+    }
+
+    options.print(&table);
+}
+
+/// Prints the resources matched by the checks of an audit report
+pub fn print_findings(findings: &[Finding], options: &PrintOptions) {
+    if options.output.is_json() {
+        return options.print_json(
+            findings
+                .iter()
+                .filter_map(|f| serde_json::to_value(f).ok())
+                .collect(),
+        );
+    }
+
+    let mut table = Table::new();
+    table.add_row(Row::new(vec![
+        options.header("CONTEXT"),
+        options.header("NAMESPACE"),
+        options.header("KIND"),
+        options.header("NAME"),
+        options.header("REASON"),
+    ]));
+
+    for f in findings {
+        table.add_row(Row::new(vec![
+            options.cell(&f.context),
+            options.cell(&f.namespace),
+            options.cell(&f.kind),
+            options.cell(&f.name),
+            options.cell(&f.reason),
+        ]));
+    }
+
+    options.print(&table);
+}
+
+/// Prints the outcome of each policy on each context, with the returned rows or the error
+pub fn print_outcomes(outcomes: &[Outcome], options: &PrintOptions) {
+    if options.output.is_json() {
+        return options.print_json(
+            outcomes
+                .iter()
+                .filter_map(|o| serde_json::to_value(o).ok())
+                .collect(),
+        );
+    }
+
+    let mut table = Table::new();
+    table.add_row(Row::new(vec![
+        options.header("POLICY"),
+        options.header("CONTEXT"),
+        options.header("RESULT"),
+        options.header("ROWS"),
+        options.header("EXPECTED"),
+        options.header("DETAILS"),
+    ]));
+
+    for o in outcomes {
+        let details = match &o.error {
+            Some(error) => options.truncate(error),
+            None => o
+                .matched
+                .iter()
+                .map(|m| options.truncate(&m.to_string()))
+                .collect::<Vec<String>>()
+                .join("\n"),
+        };
+        table.add_row(Row::new(vec![
+            options.cell(&o.policy),
+            options.cell(o.context.as_deref().unwrap_or("-")),
+            options.cell(&o.verdict.to_string()),
+            Cell::new(&o.rows.map_or_else(|| "-".to_string(), |r| r.to_string())),
+            options.cell(&o.expected),
+            Cell::new(&details),
+        ]));
+    }
+
+    options.print(&table);
+}
+
+/// Prints the namespaces of each context listed by SHOW NAMESPACES
+pub fn print_namespaces(namespaces: &[Namespace], options: &PrintOptions) {
+    if options.output.is_json() {
+        return options.print_json(
+            namespaces
+                .iter()
+                .filter_map(|ns| serde_json::to_value(ns).ok())
+                .collect(),
+        );
+    }
+
+    let mut table = Table::new();
+    table.add_row(Row::new(vec![
+        options.header("CONTEXT"),
+        options.header("NAMESPACE"),
+        options.header("STATUS"),
+    ]));
+
+    for ns in namespaces {
+        table.add_row(Row::new(vec![
+            options.cell(&ns.context),
+            options.cell(&ns.name),
+            options.cell(ns.status.as_deref().unwrap_or("-")),
+        ]));
+    }
+
+    options.print(&table);
+}
+
+/// Prints the rows of a GROUP BY statement
+pub fn print_aggregates(aggregates: &ApiAggregates, rows: &[AggregateRow], options: &PrintOptions) {
+    if options.output.is_json() {
+        let mut values = Vec::new();
+        for row in rows {
+            let mut value = serde_json::json!({
+                "context": row.context,
+                aggregates.group_by.to_string(): row.group,
+            });
+            for (c, v) in aggregates.columns.iter().zip(row.values.iter()) {
+                value[c.to_string()] = serde_json::Value::from(v.as_str());
+            }
+            values.push(value);
+        }
+        return options.print_json(values);
+    }
+
+    let charted = aggregates
+        .columns
+        .iter()
+        .any(|c| !matches!(c, Aggregate::Field(_)));
+    if options.output == Output::Chart && charted {
+        return print_chart(aggregates, rows, options);
+    }
+
+    let mut header = vec![
+        options.header("CONTEXT"),
+        options.header(&aggregates.group_by.to_string().to_uppercase()),
+    ];
+    header.extend(
+        aggregates
+            .columns
+            .iter()
+            .map(|c| options.header(&c.to_string())),
+    );
+
+    let mut table = Table::new();
+    for row in rows {
+        let mut cells = vec![options.cell(&row.context), options.cell(&row.group)];
+        cells.extend(row.values.iter().map(|v| options.cell(v)));
+        table.add_row(Row::new(cells));
+    }
+
+    if options.output == Output::Vertical {
+        return options.print_records(&Row::new(header), &table);
+    }
+
+    table.insert_row(0, Row::new(header));
+    options.print(&table);
+}
+
+/// The length of the bar of the largest value of a chart, in characters
+const CHART_WIDTH: usize = 40;
+
+/// Prints the COUNT(*) and SUM(...) columns of the groups as horizontal bars, a table per column
+/// scaled to its largest value. The fields of the grouped resources are not charted
+fn print_chart(aggregates: &ApiAggregates, rows: &[AggregateRow], options: &PrintOptions) {
+    for (i, column) in aggregates.columns.iter().enumerate() {
+        if matches!(column, Aggregate::Field(_)) {
+            continue;
+        }
+
+        let values = rows
+            .iter()
+            .map(|r| quantity::parse(&r.values[i]).unwrap_or_default())
+            .collect::<Vec<f64>>();
+        let max = values.iter().copied().fold(0.0, f64::max);
+
+        let mut table = Table::new();
+        table.add_row(Row::new(vec![
+            options.header("CONTEXT"),
+            options.header(&aggregates.group_by.to_string().to_uppercase()),
+            options.header(&column.to_string()),
+        ]));
+
+        for (row, value) in rows.iter().zip(values) {
+            table.add_row(Row::new(vec![
+                options.cell(&row.context),
+                options.cell(&row.group),
+                // The bar is not truncated, only the group is
+                Cell::new(format!("{} {}", bar(value, max), row.values[i]).trim_start()),
+            ]));
+        }
+
+        options.print(&table);
+    }
+}
+
+/// Returns the bar of the given value relative to the max, in eighths of a character
+fn bar(value: f64, max: f64) -> String {
+    const EIGHTHS: [char; 8] = [' ', '▏', '▎', '▍', '▌', '▋', '▊', '▉'];
+
+    if max <= 0.0 || value <= 0.0 {
+        return String::new();
+    }
+    let eighths = (value / max * (CHART_WIDTH * 8) as f64).round() as usize;
+    let mut bar = "█".repeat(eighths / 8);
+    if !eighths.is_multiple_of(8) {
+        bar.push(EIGHTHS[eighths % 8]);
+    }
+    bar
+}
+
+/// Returns the rows of a GROUP BY statement as a table named after the group
+pub fn aggregate_table(aggregates: &ApiAggregates, rows: &[AggregateRow]) -> ResultTable {
+    let mut columns = vec!["context".to_string(), aggregates.group_by.to_string()];
+    columns.extend(aggregates.columns.iter().map(|c| c.to_string()));
+
+    ResultTable {
+        kind: aggregates.group_by.to_string(),
+        columns,
+        rows: rows
+            .iter()
+            .map(|r| {
+                let mut row = vec![r.context.clone(), r.group.clone()];
+                row.extend(r.values.iter().cloned());
+                row
+            })
+            .collect(),
+        failures: Vec::new(),
+    }
+}
+
+/// Prints the given result tables one per kind as in the flat layout, i.e. with the output of --exec.
+/// The fixed columns are upper-cased, the SELECT fields are kept as given.
+pub fn print_tables(tables: &[ResultTable], options: &PrintOptions) {
+    const FIXED: [&str; 5] = ["context", "contexts", "namespace", "name", "exec"];
+
+    if options.output.is_json() {
+        let mut values = Vec::new();
+        for table in tables {
+            for row in &table.rows {
+                let mut value = serde_json::json!({ "kind": table.kind });
+                for (c, v) in table.columns.iter().zip(row.iter()) {
+                    // The cluster-scoped kinds have no namespace
+                    value[c] = match (c.as_str(), v.as_str()) {
+                        ("namespace", "") => serde_json::Value::Null,
+                        _ => serde_json::Value::from(v.as_str()),
+                    };
+                }
+                values.push(value);
+            }
+            values.extend(table.failures.iter().map(|f| failure_json(&table.kind, f)));
         }
+        return options.print_json(values);
+    }
 
-        if should_append_deployment {
-            let mut rows_deployment: Row = table_context_deployments
+    for table in tables
+        .iter()
+        .filter(|t| !t.rows.is_empty() || !t.failures.is_empty())
+    {
+        let header = table
+            .columns
+            .iter()
+            .map(|c| match FIXED.contains(&c.as_str()) {
+                true => options.header(&c.to_uppercase()),
+                false => options.header(c),
+            })
+            .collect();
+
+        let mut rows = Vec::new();
+        for row in &table.rows {
+            rows.push(Row::new(
+                row.iter()
+                    .map(|v| {
+                        options.cell(Some(v.as_str()).filter(|v| !v.is_empty()).unwrap_or("-"))
+                    })
+                    .collect(),
+            ));
+        }
+
+        // The unique values of SELECT DISTINCT have no context, the contexts are listed under them
+        let contextual = table
+            .columns
+            .first()
+            .is_some_and(|c| c == "context" || c == "contexts");
+        if contextual {
+            for failure in &table.failures {
+                rows.push(failure_row(failure, options));
+            }
+        }
+
+        println!("{}", table.kind.to_uppercase());
+        options.print_rows(Row::new(header), rows);
+        if !contextual {
+            print_failures(&table.failures, options);
+        }
+    }
+}
+
+/// Prints the list calls of each kind estimated by EXPLAIN, with their total
+pub fn print_estimate(estimate: &Estimate, options: &PrintOptions) {
+    if options.output.is_json() {
+        return options.print_json(
+            estimate
+                .kinds
                 .iter()
-                .map(|x| Cell::from(x.1))
-                .collect::<Row>();
-            rows_deployment.insert_cell(0, Cell::new("deployment"));
-            row.push(rows_deployment);
+                .map(|(kind, namespaces)| {
+                    serde_json::json!({
+                        "kind": kind.to_string(),
+                        "contexts": estimate.contexts,
+                        "namespaces": namespaces,
+                        "list_calls": estimate.contexts * namespaces,
+                    })
+                })
+                .collect(),
+        );
+    }
+
+    let mut table = Table::new();
+    table.add_row(Row::new(vec![
+        options.header("KIND"),
+        options.header("CONTEXTS"),
+        options.header("NAMESPACES"),
+        options.header("LIST CALLS"),
+    ]));
+
+    for (kind, namespaces) in &estimate.kinds {
+        table.add_row(Row::new(vec![
+            options.cell(&kind.to_string()),
+            options.cell(&estimate.contexts.to_string()),
+            options.cell(&namespaces.to_string()),
+            options.cell(&(estimate.contexts * namespaces).to_string()),
+        ]));
+    }
+    if estimate.kinds.len() > 1 {
+        table.add_row(Row::new(vec![
+            options.header("TOTAL"),
+            Cell::new(""),
+            Cell::new(""),
+            options.cell(&estimate.list_calls().to_string()),
+        ]));
+    }
+
+    options.print(&table);
+}
+
+/// Prints the statistics of each context and their total to stderr, not to mix them with the results
+pub fn print_stats(stats: &[(String, Stats)], options: &PrintOptions) {
+    let mut table = Table::new();
+    table.add_row(Row::new(vec![
+        options.header("CONTEXT"),
+        options.header("API CALLS"),
+        options.header("SCANNED"),
+        options.header("MATCHED"),
+        options.header("BYTES"),
+        options.header("TIME"),
+    ]));
+
+    let mut total = Stats::default();
+    for (context, s) in stats {
+        total.add(s);
+        let context = options.anonymize(context, Anonymizer::context);
+        table.add_row(stats_row(options.cell(&context), s));
+    }
+    if stats.len() > 1 {
+        table.add_row(stats_row(options.header("TOTAL"), &total));
+    }
+
+    let _ = table.print(&mut std::io::stderr());
+}
+
+/// Notes on stderr that the results are truncated, if the lists stopped at --max-rows or --max-bytes
+pub fn print_truncated(limits: &Limits) {
+    if limits.truncated {
+        eprintln!(
+            "The results are truncated, the lists stopped at {} resources and {} bytes (--max-rows, --max-bytes)",
+            limits.rows, limits.bytes
+        );
+    }
+}
+
+fn stats_row(first: Cell, stats: &Stats) -> Row {
+    Row::new(vec![
+        first,
+        Cell::new(&stats.api_calls.to_string()),
+        Cell::new(&stats.scanned.to_string()),
+        Cell::new(&stats.matched.to_string()),
+        Cell::new(&stats.bytes.to_string()),
+        Cell::new(&format!("{}ms", stats.duration.as_millis())),
+    ])
+}
+
+/// Prints the given history entries with their IDs
+pub fn print_history(entries: &[(usize, Entry)], options: &PrintOptions) {
+    if options.output.is_json() {
+        let mut values = Vec::new();
+        for (id, entry) in entries {
+            let mut value = serde_json::to_value(entry).unwrap_or_default();
+            value["id"] = serde_json::Value::from(*id);
+            values.push(value);
         }
+        return options.print_json(values);
+    }
+
+    let mut table = Table::new();
+    table.add_row(Row::new(vec![
+        options.header("ID"),
+        options.header("TIME"),
+        options.header("DURATION"),
+        options.header("ROWS"),
+        options.header("CONTEXTS"),
+        options.header("QUERY"),
+    ]));
+
+    for (id, entry) in entries {
+        table.add_row(Row::new(vec![
+            Cell::new(&id.to_string()),
+            Cell::new(&entry.timestamp.format("%Y-%m-%d %H:%M:%S").to_string()),
+            Cell::new(&format!("{}ms", entry.duration_ms)),
+            match &entry.error {
+                Some(_) => options.error("error"),
+                None => Cell::new(&entry.rows.to_string()),
+            },
+            options.cell(&entry.contexts.join(", ")),
+            options.cell(&entry.query),
+        ]));
+    }
+
+    options.print(&table);
+}
+
+/// Prints the title of a section of a report, i.e. of a statement of many. It is not truncated
+pub fn print_title(title: &str, options: &PrintOptions) {
+    let cell = match options.color {
+        true => Cell::new(title).style_spec("b"),
+        false => Cell::new(title),
+    };
+    options.print(&Table::init(vec![Row::new(vec![cell])]));
+}
+
+/// Prints the queries of the library, a row for each one
+pub fn print_library(queries: &[LibraryQuery], options: &PrintOptions) {
+    if options.output.is_json() {
+        let values = queries
+            .iter()
+            .map(|q| serde_json::to_value(q).unwrap_or_default())
+            .collect();
+        return options.print_json(values);
+    }
+
+    let mut table = Table::new();
+    table.add_row(Row::new(vec![
+        options.header("NAME"),
+        options.header("TAGS"),
+        options.header("DESCRIPTION"),
+    ]));
+
+    for query in queries {
+        table.add_row(Row::new(vec![
+            options.cell(&query.name),
+            options.cell(&query.tags.join(", ")),
+            options.cell(query.description.as_deref().unwrap_or("-")),
+        ]));
+    }
 
-        if should_append_service {
-            let mut rows_service: Row = table_context_services
+    options.print(&table);
+}
+
+/// Prints the number of rows of each query of the library run, or its error
+pub fn print_library_results(
+    queries: &[LibraryQuery],
+    results: &[Result<usize, String>],
+    options: &PrintOptions,
+) {
+    let mut table = Table::new();
+    table.add_row(Row::new(vec![
+        options.header("QUERY"),
+        options.header("TAGS"),
+        options.header("ROWS"),
+    ]));
+
+    for (query, result) in queries.iter().zip(results) {
+        table.add_row(Row::new(vec![
+            options.cell(&query.name),
+            options.cell(&query.tags.join(", ")),
+            match result {
+                Ok(rows) => Cell::new(&rows.to_string()),
+                Err(e) => options.error(e),
+            },
+        ]));
+    }
+
+    options.print(&table);
+}
+
+/// Prints the counts of the recorded runs of a query, a row for each namespace of a context per run
+pub fn print_trend(points: &[Point], options: &PrintOptions) {
+    if options.output.is_json() {
+        let values = points
+            .iter()
+            .map(|p| serde_json::to_value(p).unwrap_or_default())
+            .collect();
+        return options.print_json(values);
+    }
+
+    let mut table = Table::new();
+    table.add_row(Row::new(vec![
+        options.header("TIME"),
+        options.header("CONTEXT"),
+        options.header("NAMESPACE"),
+        options.header("ROWS"),
+        options.header("CHANGE"),
+    ]));
+
+    for point in points {
+        table.add_row(Row::new(vec![
+            Cell::new(&point.timestamp.format("%Y-%m-%d %H:%M:%S").to_string()),
+            options.cell(
+                Some(point.context.as_str())
+                    .filter(|c| !c.is_empty())
+                    .unwrap_or("-"),
+            ),
+            options.cell(
+                Some(point.namespace.as_str())
+                    .filter(|n| !n.is_empty())
+                    .unwrap_or("-"),
+            ),
+            Cell::new(&point.rows.to_string()),
+            Cell::new(&match point.change {
+                Some(change) if change > 0 => format!("+{}", change),
+                Some(change) => change.to_string(),
+                None => "-".to_string(),
+            }),
+        ]));
+    }
+
+    options.print(&table);
+}
+
+/// Prints the image inventory, a row for each unique image
+pub fn print_images(images: &[Image], options: &PrintOptions) {
+    if options.output.is_json() {
+        return options.print_json(
+            images
+                .iter()
+                .map(|image| serde_json::to_value(image).unwrap_or_default())
+                .collect(),
+        );
+    }
+
+    let mut table = Table::new();
+    table.add_row(Row::new(vec![
+        options.header("REPOSITORY"),
+        options.header("TAG"),
+        options.header("DIGEST"),
+        options.header("PODS"),
+        options.header("CONTEXT/NAMESPACE"),
+    ]));
+
+    for image in images {
+        table.add_row(Row::new(vec![
+            options.cell(&image.repository),
+            options.cell(image.tag.as_deref().unwrap_or("-")),
+            options.cell(image.digest.as_deref().unwrap_or("-")),
+            Cell::new(&image.pods.to_string()),
+            Cell::new(
+                &image
+                    .locations
+                    .iter()
+                    .map(|l| options.truncate(l))
+                    .collect::<Vec<String>>()
+                    .join("\n"),
+            ),
+        ]));
+    }
+
+    options.print(&table);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use k8s_openapi::api::core::v1::Pod;
+
+    fn pods(names: &[&str]) -> ObjectList<Pod> {
+        ObjectList {
+            types: Default::default(),
+            metadata: Default::default(),
+            items: names
                 .iter()
-                .map(|x| Cell::from(x.1))
-                .collect::<Row>();
-            rows_service.insert_cell(0, Cell::new("service"));
-            row.push(rows_service);
+                .map(|name| {
+                    serde_json::from_value(serde_json::json!({ "metadata": { "name": name } }))
+                        .unwrap()
+                })
+                .collect(),
         }
+    }
 
-        Table::init(row).printstd();
+    #[test]
+    fn inserts_a_resource_matching_several_queries_once() {
+        let mut printer = Printer::new();
+        printer.insert(
+            "ctx",
+            "default",
+            ResourceType::Pod,
+            pods(&["api-1", "api-2"]),
+        );
+        printer.insert(
+            "ctx",
+            "default",
+            ResourceType::Pod,
+            pods(&["api-2", "api-3"]),
+        );
+        printer.insert("ctx", "other", ResourceType::Pod, pods(&["api-2"]));
+
+        let names: Vec<(&str, &str)> = printer
+            .items
+            .iter()
+            .map(|i| (i.namespace, i.name.as_str()))
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                ("default", "api-1"),
+                ("default", "api-2"),
+                ("default", "api-3"),
+                ("other", "api-2")
+            ]
+        );
     }
 }
@@ -0,0 +1,98 @@
+// Copyright (c) 2021 Dentrax
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software is furnished to do so,
+// subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+/// Parses the given Kubernetes quantity into its base unit, i.e. '500m' => 0.5, '1Ki' => 1024
+/// See: https://kubernetes.io/docs/reference/kubernetes-api/common-definitions/quantity/
+pub fn parse(quantity: &str) -> Option<f64> {
+    let quantity = quantity.trim();
+    let split = quantity
+        .find(|c: char| !(c.is_ascii_digit() || c == '.' || c == '+' || c == '-'))
+        .unwrap_or(quantity.len());
+    let (number, suffix) = quantity.split_at(split);
+    let number: f64 = number.parse().ok()?;
+
+    let multiplier = match suffix {
+        "" => 1.0,
+        "n" => 1e-9,
+        "u" => 1e-6,
+        "m" => 1e-3,
+        "k" => 1e3,
+        "M" => 1e6,
+        "G" => 1e9,
+        "T" => 1e12,
+        "P" => 1e15,
+        "E" => 1e18,
+        "Ki" => 1024f64,
+        "Mi" => 1024f64.powi(2),
+        "Gi" => 1024f64.powi(3),
+        "Ti" => 1024f64.powi(4),
+        "Pi" => 1024f64.powi(5),
+        "Ei" => 1024f64.powi(6),
+        // Decimal exponent, i.e. '1e3'
+        _ if suffix.starts_with(['e', 'E']) => 10f64.powi(suffix[1..].parse().ok()?),
+        _ => return None,
+    };
+
+    Some(number * multiplier)
+}
+
+/// Formats the given CPU cores as millicores, i.e. 0.5 => '500m'
+pub fn format_cpu(cores: f64) -> String {
+    // Adding zero normalizes the negative zero of an empty sum
+    format!("{}m", (cores * 1e3).ceil() + 0.0)
+}
+
+/// Formats the given bytes as mebibytes, i.e. 1048576 => '1Mi'
+pub fn format_memory(bytes: f64) -> String {
+    format!("{}Mi", (bytes / 1024f64.powi(2)).ceil() + 0.0)
+}
+
+/// Formats the given value according to the resource name, i.e. 'cpu' or 'memory'
+pub fn format(resource: &str, value: f64) -> String {
+    match resource {
+        "cpu" => format_cpu(value),
+        "memory" | "ephemeral-storage" => format_memory(value),
+        _ => (value + 0.0).to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_the_suffixes() {
+        assert_eq!(parse("500m"), Some(0.5));
+        assert_eq!(parse("2"), Some(2.0));
+        assert_eq!(parse("1k"), Some(1e3));
+        assert_eq!(parse("512Mi"), Some(512.0 * 1024.0 * 1024.0));
+        assert_eq!(parse("1Gi"), Some(1024.0 * 1024.0 * 1024.0));
+        assert_eq!(parse("1e3"), Some(1e3));
+        assert_eq!(parse("1Xi"), None);
+        assert_eq!(parse("Gi"), None);
+    }
+
+    #[test]
+    fn formats_the_resources() {
+        assert_eq!(format("cpu", 0.25), "250m");
+        assert_eq!(format("memory", 1024.0 * 1024.0), "1Mi");
+        assert_eq!(format("pods", 110.0), "110");
+        assert_eq!(format_cpu(0.0), "0m");
+    }
+}
@@ -0,0 +1,142 @@
+// Copyright (c) 2021 Dentrax
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software is furnished to do so,
+// subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+use serde_json::json;
+use std::path::Path;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum SarifError {
+    #[error("Unable to write the SARIF log '{0}': {1}")]
+    Write(String, std::io::Error),
+}
+
+/// A rule of the log, i.e. a policy or a check of an audit report
+pub struct Rule {
+    pub id: String,
+    pub description: Option<String>,
+    /// The level of its results, 'error' or 'warning'
+    pub level: &'static str,
+}
+
+/// A result of a rule, the resource it matched. The unique values of a DISTINCT query have no
+/// kind nor name, they are located at the context
+pub struct Issue {
+    pub rule: String,
+    pub message: String,
+    pub context: String,
+    pub namespace: Option<String>,
+    pub kind: Option<String>,
+    pub name: Option<String>,
+}
+
+impl Issue {
+    /// i.e. 'context1/default/pod/api-1'
+    fn location(&self) -> String {
+        std::iter::once(&self.context)
+            .chain(self.namespace.iter())
+            .chain(self.kind.iter())
+            .chain(self.name.iter())
+            .map(String::as_str)
+            .collect::<Vec<&str>>()
+            .join("/")
+    }
+}
+
+/// Writes the given results into the given file as a SARIF 2.1.0 log, i.e. for GitHub code
+/// scanning. The resources are logical locations, the source file of the rules is the physical
+/// one if given. The rules failed to run are the notifications of the invocation
+pub fn write(
+    path: &Path,
+    rules: &[Rule],
+    issues: &[Issue],
+    failures: &[(String, String)],
+    source: Option<&Path>,
+) -> Result<(), SarifError> {
+    let results: Vec<serde_json::Value> = issues
+        .iter()
+        .map(|issue| {
+            let mut location = json!({
+                "logicalLocations": [{
+                    "fullyQualifiedName": issue.location(),
+                    "name": issue.name.as_ref().unwrap_or(&issue.context),
+                    "kind": "resource",
+                }],
+            });
+            if let Some(source) = source {
+                location["physicalLocation"] = json!({
+                    "artifactLocation": { "uri": source.display().to_string() },
+                });
+            }
+
+            json!({
+                "ruleId": issue.rule,
+                "ruleIndex": rules.iter().position(|r| r.id == issue.rule),
+                "level": rules.iter().find(|r| r.id == issue.rule).map_or("warning", |r| r.level),
+                "message": { "text": issue.message },
+                "locations": [location],
+                "partialFingerprints": { "kubesqlResource/v1": issue.location() },
+                "properties": {
+                    "context": issue.context,
+                    "namespace": issue.namespace,
+                    "kind": issue.kind,
+                    "name": issue.name,
+                },
+            })
+        })
+        .collect();
+
+    let log = json!({
+        "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": "kubesql",
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "informationUri": "https://github.com/Dentrax/kubesql",
+                    "rules": rules
+                        .iter()
+                        .map(|r| json!({
+                            "id": r.id,
+                            "shortDescription": { "text": r.description.as_ref().unwrap_or(&r.id) },
+                            "defaultConfiguration": { "level": r.level },
+                        }))
+                        .collect::<Vec<serde_json::Value>>(),
+                },
+            },
+            "invocations": [{
+                "executionSuccessful": failures.is_empty(),
+                "toolExecutionNotifications": failures
+                    .iter()
+                    .map(|(rule, message)| json!({
+                        "level": "error",
+                        "message": { "text": message },
+                        "descriptor": { "id": rule },
+                    }))
+                    .collect::<Vec<serde_json::Value>>(),
+            }],
+            "results": results,
+        }],
+    });
+
+    serde_json::to_string_pretty(&log)
+        .map_err(std::io::Error::from)
+        .and_then(|content| std::fs::write(path, content))
+        .map_err(|e| SarifError::Write(path.display().to_string(), e))
+}
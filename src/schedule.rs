@@ -0,0 +1,350 @@
+// Copyright (c) 2021 Dentrax
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software is furnished to do so,
+// subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+use crate::error::Result;
+use crate::notify::{self, Notification, NotifyError, Target};
+use crate::printer::ResultTable;
+use crate::template::{Syntax, Template};
+use k8s_openapi::chrono::{DateTime, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use thiserror::Error;
+
+/// The gauges written by the metrics sinks, in the order of their values in a metric
+const GAUGES: [(&str, &str); 3] = [
+    (
+        "kubesql_schedule_rows",
+        "The number of the rows returned by the last successful run of the schedule",
+    ),
+    (
+        "kubesql_schedule_success",
+        "Whether the last run of the schedule succeeded",
+    ),
+    (
+        "kubesql_schedule_last_run_timestamp_seconds",
+        "The time of the last run of the schedule",
+    ),
+];
+
+#[derive(Error, Debug)]
+pub enum ScheduleError {
+    #[error("Unable to read the schedules '{0}': {1}")]
+    Read(String, std::io::Error),
+
+    #[error("Invalid schedules '{0}': {1}")]
+    Invalid(String, serde_yaml::Error),
+
+    #[error("Invalid cron expression '{1}' of the schedule '{0}': {2}")]
+    Cron(String, String, cron::error::Error),
+
+    #[error("Invalid template of the schedule '{0}': {1}")]
+    Template(String, String),
+
+    #[error("The schedule '{0}' is defined more than once")]
+    Duplicate(String),
+
+    #[error("Unable to write to '{0}': {1}")]
+    Write(String, std::io::Error),
+
+    #[error(transparent)]
+    Notify(#[from] NotifyError),
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ScheduleFile {
+    schedules: Vec<Schedule>,
+}
+
+/// A query run on a cron schedule, the results of each run are sent to all of its sinks
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Schedule {
+    pub name: String,
+    /// The 5 fields of crontab, or 6 with the seconds first, in UTC
+    pub cron: String,
+    pub query: String,
+    pub sinks: Vec<Sink>,
+    /// The go-template of the messages of the notify sinks, a summary of the rows if not given
+    #[serde(default)]
+    pub template: Option<String>,
+}
+
+/// Where the results of the runs are sent to
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "lowercase", deny_unknown_fields)]
+pub enum Sink {
+    /// Appends each run to the file as a JSON line
+    File(PathBuf),
+    /// Posts each run to the URL as JSON
+    Webhook(String),
+    /// Writes the rows of the last runs into the file in the Prometheus text format,
+    /// i.e. for the textfile collector of the node exporter
+    Metrics(PathBuf),
+    /// Sends the non-empty results to the webhook or the Slack channel, i.e. 'slack:#ops'
+    Notify(Target),
+}
+
+/// A run of a schedule as sent to the file and webhook sinks, either its tables or its error
+#[derive(Debug, Serialize)]
+struct Run<'a> {
+    schedule: &'a str,
+    time: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tables: Option<&'a [ResultTable]>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// The last run of a schedule, as exported by the metrics sinks
+#[derive(Debug, Default)]
+struct Metric {
+    /// The rows of all the tables, kept from the previous run if the last one failed
+    rows: usize,
+    success: bool,
+    time: i64,
+}
+
+impl Metric {
+    fn values(&self) -> [i64; 3] {
+        [self.rows as i64, self.success as i64, self.time]
+    }
+}
+
+/// Reads the schedules from the given YAML file
+pub fn load(path: &Path) -> std::result::Result<Vec<Schedule>, ScheduleError> {
+    let file = path.display().to_string();
+    let content =
+        std::fs::read_to_string(path).map_err(|e| ScheduleError::Read(file.clone(), e))?;
+    let schedules: ScheduleFile =
+        serde_yaml::from_str(&content).map_err(|e| ScheduleError::Invalid(file, e))?;
+
+    Ok(schedules.schedules)
+}
+
+/// Keeps the next run of each schedule, the caller runs their queries once due
+/// and dispatches the results to their sinks
+#[derive(Debug, Default)]
+pub struct Scheduler {
+    entries: Vec<Entry>,
+    metrics: BTreeMap<String, Metric>,
+}
+
+#[derive(Debug)]
+struct Entry {
+    schedule: Schedule,
+    cron: cron::Schedule,
+    template: Option<Template>,
+    /// None if the cron expression has no upcoming time
+    next: Option<DateTime<Utc>>,
+}
+
+impl Scheduler {
+    pub fn new(schedules: Vec<Schedule>) -> std::result::Result<Scheduler, ScheduleError> {
+        let mut names = HashSet::new();
+        let mut entries = Vec::new();
+        for schedule in schedules {
+            if !names.insert(schedule.name.clone()) {
+                return Err(ScheduleError::Duplicate(schedule.name));
+            }
+            let cron = parse_cron(&schedule.cron).map_err(|e| {
+                ScheduleError::Cron(schedule.name.clone(), schedule.cron.clone(), e)
+            })?;
+            let template = schedule
+                .template
+                .as_ref()
+                .map(|t| Template::parse(t, Syntax::GoTemplate))
+                .transpose()
+                .map_err(|e| ScheduleError::Template(schedule.name.clone(), e))?;
+            let next = cron.upcoming(Utc).next();
+            entries.push(Entry {
+                schedule,
+                cron,
+                template,
+                next,
+            });
+        }
+
+        Ok(Scheduler {
+            entries,
+            metrics: BTreeMap::new(),
+        })
+    }
+
+    /// Waits until the next runs are due and returns their schedules, never if there is none.
+    /// Nothing changes until it returns, so it can be cancelled, i.e. in a select
+    pub async fn due(&mut self) -> Vec<Schedule> {
+        let next = match self.entries.iter().filter_map(|e| e.next).min() {
+            Some(next) => next,
+            None => return std::future::pending().await,
+        };
+        tokio::time::sleep((next - Utc::now()).to_std().unwrap_or_default()).await;
+
+        let now = Utc::now();
+        self.entries
+            .iter_mut()
+            .filter(|e| e.next.is_some_and(|next| next <= now))
+            .map(|e| {
+                e.next = e.cron.after(&now).next();
+                e.schedule.clone()
+            })
+            .collect()
+    }
+
+    /// Sends the results of a run of the given schedule to its sinks,
+    /// a failing sink is logged and does not stop the others
+    pub async fn dispatch(&mut self, schedule: &Schedule, result: Result<Vec<ResultTable>>) {
+        let time = Utc::now();
+        let metric = self.metrics.entry(schedule.name.clone()).or_default();
+        metric.time = time.timestamp();
+        metric.success = result.is_ok();
+
+        let run = match &result {
+            Ok(tables) => {
+                metric.rows = tables.iter().map(|t| t.rows.len()).sum();
+                tracing::info!("Ran the schedule '{}': {} rows", schedule.name, metric.rows);
+                Run {
+                    schedule: &schedule.name,
+                    time: time.to_rfc3339_opts(SecondsFormat::Secs, true),
+                    tables: Some(tables),
+                    error: None,
+                }
+            }
+            Err(e) => {
+                tracing::warn!("Unable to run the schedule '{}': {}", schedule.name, e);
+                Run {
+                    schedule: &schedule.name,
+                    time: time.to_rfc3339_opts(SecondsFormat::Secs, true),
+                    tables: None,
+                    error: Some(e.to_string()),
+                }
+            }
+        };
+        let json = serde_json::to_string(&run).unwrap_or_default();
+
+        for sink in &schedule.sinks {
+            let sent = match sink {
+                Sink::File(path) => append(path, &json),
+                Sink::Webhook(url) => notify::post(url, json.clone())
+                    .await
+                    .map_err(ScheduleError::from),
+                Sink::Metrics(path) => self.write_metrics(path),
+                Sink::Notify(target) => match &result {
+                    Ok(tables) => self.notify(schedule, target, tables).await,
+                    Err(_) => Ok(()),
+                },
+            };
+            if let Err(e) = sent {
+                tracing::warn!(
+                    "Unable to send the run of the schedule '{}': {}",
+                    schedule.name,
+                    e
+                );
+            }
+        }
+    }
+
+    /// Sends the results of the schedule to the target, rendered through its template if given
+    async fn notify(
+        &self,
+        schedule: &Schedule,
+        target: &Target,
+        tables: &[ResultTable],
+    ) -> std::result::Result<(), ScheduleError> {
+        let template = self
+            .entries
+            .iter()
+            .find(|e| e.schedule.name == schedule.name)
+            .and_then(|e| e.template.clone());
+        let notification = Notification {
+            target: target.clone(),
+            query: schedule.query.clone(),
+            template,
+        };
+        notification.send(tables).await?;
+        Ok(())
+    }
+
+    /// Replaces the given file with the last runs of the schedules having it as sink.
+    /// It is written aside and renamed, so the collector never reads a partial file
+    fn write_metrics(&self, path: &Path) -> std::result::Result<(), ScheduleError> {
+        let metrics = self
+            .entries
+            .iter()
+            .filter(|e| {
+                e.schedule
+                    .sinks
+                    .iter()
+                    .any(|s| matches!(s, Sink::Metrics(p) if p == path))
+            })
+            .filter_map(|e| Some((&e.schedule.name, self.metrics.get(&e.schedule.name)?)))
+            .collect::<Vec<_>>();
+
+        let mut content = String::new();
+        for (i, (name, help)) in GAUGES.iter().enumerate() {
+            content.push_str(&format!(
+                "# HELP {} {}\n# TYPE {} gauge\n",
+                name, help, name
+            ));
+            for (schedule, metric) in &metrics {
+                content.push_str(&format!(
+                    "{}{{schedule=\"{}\"}} {}\n",
+                    name,
+                    escape(schedule),
+                    metric.values()[i]
+                ));
+            }
+        }
+
+        let file = path.display().to_string();
+        let mut temp = path.as_os_str().to_owned();
+        temp.push(".tmp");
+        std::fs::write(&temp, content)
+            .and_then(|_| std::fs::rename(&temp, path))
+            .map_err(|e| ScheduleError::Write(file, e))
+    }
+}
+
+/// Parses the cron expression, the seconds are zero if not given
+fn parse_cron(expression: &str) -> std::result::Result<cron::Schedule, cron::error::Error> {
+    match expression.split_whitespace().count() {
+        5 => cron::Schedule::from_str(&format!("0 {}", expression)),
+        _ => cron::Schedule::from_str(expression),
+    }
+}
+
+fn append(path: &Path, line: &str) -> std::result::Result<(), ScheduleError> {
+    std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .and_then(|mut file| writeln!(file, "{}", line))
+        .map_err(|e| ScheduleError::Write(path.display().to_string(), e))
+}
+
+/// Escapes the value of a label of the Prometheus text format
+pub fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
@@ -0,0 +1,202 @@
+// Copyright (c) 2021 Dentrax
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software is furnished to do so,
+// subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+use crate::cli::{GlobalArgs, ServeArgs};
+use crate::error::{KubesqlError, Result};
+use crate::parser::ApiStatement;
+use crate::planner::PlanContext;
+use crate::printer::{Printer, ResultTable};
+use crate::schedule::{self, Scheduler};
+use crate::snapshot::Snapshot;
+use crate::source::{DataSource, Sources};
+use crate::{aggregator, executor, parser, printer, views};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tracing::Instrument;
+
+/// The maximum size of a query sent to the server
+const MAX_BODY_SIZE: usize = 1024 * 1024;
+
+/// Serves the queries over HTTP until interrupted, one request at a time:
+/// 'POST /query' with the query as body returns the results as '{"tables": [...]}',
+/// 'GET /healthz' returns '{"status": "ok"}'. The scheduled queries run between the requests
+pub async fn serve(global: &GlobalArgs, args: &ServeArgs) -> Result<()> {
+    let offline = match args.offline {
+        true => Some(Snapshot::load(&global.snapshot_path())?),
+        false => None,
+    };
+
+    let mut scheduler = match &args.schedule {
+        Some(path) => Scheduler::new(schedule::load(path)?)?,
+        None => Scheduler::default(),
+    };
+
+    let listener = TcpListener::bind(&args.listen)
+        .await
+        .map_err(|e| KubesqlError::Serve(args.listen.clone(), e))?;
+    eprintln!("Listening on http://{}", args.listen);
+
+    loop {
+        let (stream, peer) = tokio::select! {
+            accepted = listener.accept() => {
+                accepted.map_err(|e| KubesqlError::Serve(args.listen.clone(), e))?
+            }
+            due = scheduler.due() => {
+                for schedule in due {
+                    let result = crate::with_timeout(global.timeout, query(&schedule.query, default(&offline)))
+                        .instrument(tracing::info_span!("schedule", name = %schedule.name))
+                        .await;
+                    scheduler.dispatch(&schedule, result).await;
+                }
+                continue;
+            }
+        };
+
+        // A broken connection does not stop the server
+        if let Err(e) = handle(stream, global, default(&offline))
+            .instrument(tracing::info_span!("request", %peer))
+            .await
+        {
+            tracing::warn!("Unable to handle the request of {}: {}", peer, e);
+        }
+    }
+}
+
+async fn handle(
+    mut stream: TcpStream,
+    global: &GlobalArgs,
+    offline: Option<&dyn DataSource>,
+) -> std::io::Result<()> {
+    let (status, body) = match read_request(&mut stream).await? {
+        Some((method, path, _)) if method == "GET" && path == "/healthz" => {
+            ("200 OK", serde_json::json!({ "status": "ok" }))
+        }
+        Some((method, path, sql)) if method == "POST" && path == "/query" => {
+            match crate::with_timeout(global.timeout, query(&sql, offline)).await {
+                Ok(tables) => ("200 OK", serde_json::json!({ "tables": tables })),
+                Err(e) => (
+                    "400 Bad Request",
+                    serde_json::json!({ "error": e.to_string() }),
+                ),
+            }
+        }
+        Some(_) => (
+            "404 Not Found",
+            serde_json::json!({ "error": "only 'POST /query' and 'GET /healthz' are supported" }),
+        ),
+        None => (
+            "413 Payload Too Large",
+            serde_json::json!({ "error": format!("the query must be at most {} bytes", MAX_BODY_SIZE) }),
+        ),
+    };
+
+    let body = body.to_string();
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+/// Returns the method, path and body of the request, None if the body is too large
+async fn read_request(stream: &mut TcpStream) -> std::io::Result<Option<(String, String, String)>> {
+    let mut reader = BufReader::new(stream);
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).await?;
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_string();
+    let path = parts.next().unwrap_or_default().to_string();
+
+    let mut content_length = 0;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header).await? == 0 || header.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().unwrap_or(0);
+            }
+        }
+    }
+
+    if content_length > MAX_BODY_SIZE {
+        return Ok(None);
+    }
+
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body).await?;
+
+    Ok(Some((
+        method,
+        path,
+        String::from_utf8_lossy(&body).to_string(),
+    )))
+}
+
+/// Returns the snapshot of --offline as the default data source, if given
+fn default(offline: &Option<Snapshot>) -> Option<&dyn DataSource> {
+    offline.as_ref().map(|s| s as &dyn DataSource)
+}
+
+/// Runs the given query and returns its results without printing them, the contexts without a
+/// data source are read from the given one if any. The server is read-only, so INSERT statements
+/// are not supported
+#[tracing::instrument(name = "execute", skip_all)]
+pub(crate) async fn query(sql: &str, offline: Option<&dyn DataSource>) -> Result<Vec<ResultTable>> {
+    let plan_context = PlanContext {
+        views: views::load()?,
+        ..Default::default()
+    };
+    let statement = parser::parse_sql(sql, plan_context)?;
+    let contexts = statement.contexts();
+    let sources = Sources::new(offline).open(&contexts)?;
+    sources.validate_contexts(&contexts)?;
+    let live = sources.live(&contexts).len() == contexts.len();
+
+    match (statement, live) {
+        (ApiStatement::Query(mut q), _) => {
+            executor::resolve_namespaces(&mut q, &sources).await?;
+
+            let apis = executor::build_apis(&q, &sources, false, None).await?;
+            let mut printer = Printer::builder()
+                .contexts(&q.contexts)
+                .namespaces(&q.namespaces)
+                .queries(q.queries.as_slice())
+                .projections(q.projections.as_slice())
+                .servers(executor::servers(&q))
+                .distinct(q.distinct);
+
+            executor::execute(&q, &apis, &mut printer).await?;
+            Ok(printer.tables())
+        }
+        (ApiStatement::Aggregate(a), true) => {
+            let rows = aggregator::aggregate(&a).await?;
+            Ok(vec![printer::aggregate_table(&a, &rows)])
+        }
+        _ => Err(KubesqlError::Unsupported(
+            "Only SELECT statements are supported by the server, GROUP BY is not supported offline"
+                .to_string(),
+        )),
+    }
+}
@@ -0,0 +1,204 @@
+// Copyright (c) 2021 Dentrax
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software is furnished to do so,
+// subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+use crate::exporter::{self, Export, ExportError};
+use crate::notify::{self, NotifyError};
+use crate::printer::{self, PrintOptions, ResultTable};
+use crate::schedule;
+use crate::trend;
+use futures::future::BoxFuture;
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+use thiserror::Error;
+
+/// The gauge written by the Prometheus sinks
+const ROWS_GAUGE: (&str, &str) = (
+    "kubesql_query_rows",
+    "The number of the rows returned by the query",
+);
+
+#[derive(Error, Debug)]
+pub enum SinkError {
+    #[error("Unable to write the results to '{0}': {1}")]
+    Write(String, std::io::Error),
+
+    #[error(transparent)]
+    Export(#[from] ExportError),
+
+    #[error(transparent)]
+    Notify(#[from] NotifyError),
+}
+
+/// Receives the results of a query in place of the output, i.e. to write them into a file or
+/// to post them. New sinks are added as a variant of the destinations
+pub trait Sink: Send + Sync {
+    /// Writes the results of all the kinds, once the query is done
+    fn write<'a>(&'a self, tables: &'a [ResultTable]) -> BoxFuture<'a, Result<(), SinkError>>;
+}
+
+/// Where the results of a query are sent to with --sink, i.e. 'json:results.json'
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Destination {
+    /// Prints the results in the format of --output
+    Stdout,
+    /// Writes the query and its results into the file as JSON, replacing it
+    Json(PathBuf),
+    /// Posts the query and its results to the URL as JSON, even without any row
+    Webhook(String),
+    /// Exports the results into the file as --export does, i.e. 'sqlite:results.db'
+    Export(Export),
+    /// Writes the number of the rows of each context and namespace into the file in the
+    /// Prometheus text format, i.e. for the textfile collector of the node exporter
+    Prometheus(PathBuf),
+}
+
+impl fmt::Display for Destination {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Destination::Stdout => write!(f, "stdout"),
+            Destination::Json(path) => write!(f, "json:{}", path.display()),
+            Destination::Webhook(url) => write!(f, "webhook:{}", url),
+            Destination::Export(export) => write!(f, "{}", export),
+            Destination::Prometheus(path) => write!(f, "prometheus:{}", path.display()),
+        }
+    }
+}
+
+impl FromStr for Destination {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Destination, Self::Err> {
+        match input.split_once(':') {
+            None if input == "stdout" => Ok(Destination::Stdout),
+            Some(("json", path)) if !path.is_empty() => Ok(Destination::Json(PathBuf::from(path))),
+            Some(("webhook", url)) if !url.is_empty() => Ok(Destination::Webhook(url.to_string())),
+            Some(("prometheus", path)) if !path.is_empty() => {
+                Ok(Destination::Prometheus(PathBuf::from(path)))
+            }
+            Some(("sqlite", _)) | Some(("parquet", _)) => {
+                Export::from_str(input).map(Destination::Export)
+            }
+            _ => Err(format!(
+                "Unexpected sink: {}, must be 'stdout', 'json:PATH', 'webhook:URL', 'sqlite:PATH', \
+                 'parquet:PATH' or 'prometheus:PATH'",
+                input
+            )),
+        }
+    }
+}
+
+impl Destination {
+    /// Opens the sink of the results of the given query, printed with the given options
+    pub fn open(&self, query: &str, options: &PrintOptions) -> Box<dyn Sink> {
+        match self {
+            Destination::Stdout => Box::new(Stdout(options.clone())),
+            Destination::Json(path) => Box::new(Json {
+                path: path.clone(),
+                query: query.to_string(),
+            }),
+            Destination::Webhook(url) => Box::new(Webhook {
+                url: url.clone(),
+                query: query.to_string(),
+            }),
+            Destination::Export(export) => Box::new(export.clone()),
+            Destination::Prometheus(path) => Box::new(Prometheus {
+                path: path.clone(),
+                query: query.to_string(),
+            }),
+        }
+    }
+}
+
+struct Stdout(PrintOptions);
+
+impl Sink for Stdout {
+    fn write<'a>(&'a self, tables: &'a [ResultTable]) -> BoxFuture<'a, Result<(), SinkError>> {
+        Box::pin(async move {
+            printer::print_tables(tables, &self.0);
+            Ok(())
+        })
+    }
+}
+
+struct Json {
+    path: PathBuf,
+    query: String,
+}
+
+impl Sink for Json {
+    fn write<'a>(&'a self, tables: &'a [ResultTable]) -> BoxFuture<'a, Result<(), SinkError>> {
+        Box::pin(async move {
+            let body = serde_json::json!({ "query": self.query, "tables": tables });
+            std::fs::write(&self.path, format!("{}\n", body))
+                .map_err(|e| SinkError::Write(self.path.display().to_string(), e))
+        })
+    }
+}
+
+struct Webhook {
+    url: String,
+    query: String,
+}
+
+impl Sink for Webhook {
+    fn write<'a>(&'a self, tables: &'a [ResultTable]) -> BoxFuture<'a, Result<(), SinkError>> {
+        Box::pin(async move {
+            let body = serde_json::json!({ "query": self.query, "tables": tables });
+            Ok(notify::post(&self.url, body.to_string()).await?)
+        })
+    }
+}
+
+impl Sink for Export {
+    fn write<'a>(&'a self, tables: &'a [ResultTable]) -> BoxFuture<'a, Result<(), SinkError>> {
+        Box::pin(async move { Ok(exporter::export(self, tables)?) })
+    }
+}
+
+struct Prometheus {
+    path: PathBuf,
+    query: String,
+}
+
+impl Sink for Prometheus {
+    /// Replaces the file with the counts of the results. It is written aside and renamed, so the
+    /// collector never reads a partial file
+    fn write<'a>(&'a self, tables: &'a [ResultTable]) -> BoxFuture<'a, Result<(), SinkError>> {
+        Box::pin(async move {
+            let (name, help) = ROWS_GAUGE;
+            let mut content = format!("# HELP {} {}\n# TYPE {} gauge\n", name, help, name);
+            for count in trend::Sample::new(&self.query, tables).counts {
+                content.push_str(&format!(
+                    "{}{{context=\"{}\",namespace=\"{}\"}} {}\n",
+                    name,
+                    schedule::escape(&count.context),
+                    schedule::escape(&count.namespace),
+                    count.rows
+                ));
+            }
+
+            let mut temp = self.path.as_os_str().to_owned();
+            temp.push(".tmp");
+            std::fs::write(&temp, content)
+                .and_then(|_| std::fs::rename(&temp, &self.path))
+                .map_err(|e| SinkError::Write(self.path.display().to_string(), e))
+        })
+    }
+}
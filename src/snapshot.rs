@@ -0,0 +1,264 @@
+// Copyright (c) 2021 Dentrax
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software is furnished to do so,
+// subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+use crate::api_builder::{ApiBuilder, Failure};
+use crate::error::Result;
+use crate::executor;
+use crate::parser::ResourceType;
+use crate::source::DataSource;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum SnapshotError {
+    #[error("Unable to read the snapshot '{0}': {1}")]
+    Read(String, std::io::Error),
+
+    #[error("Unable to write the snapshot '{0}': {1}")]
+    Write(String, std::io::Error),
+
+    #[error("Invalid snapshot '{0}': {1}")]
+    Invalid(String, serde_json::Error),
+
+    #[error("Context not found in the snapshot: {0:?}")]
+    ContextNotFound(Vec<String>),
+}
+
+/// The listed resources of the contexts, to run the queries offline
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Snapshot {
+    /// Represents 'Context - Namespace - Kind - Resources' mapping,
+    /// nodes are not namespaced so they are stored with an empty namespace
+    pub contexts: BTreeMap<String, BTreeMap<String, BTreeMap<String, Vec<serde_json::Value>>>>,
+    /// Why the kinds could not be listed, by context, namespace and kind like the resources
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub failures: BTreeMap<String, BTreeMap<String, BTreeMap<String, Failure>>>,
+}
+
+impl Snapshot {
+    /// Reads the snapshot from the given file
+    pub fn load(path: &Path) -> std::result::Result<Snapshot, SnapshotError> {
+        let file = path.display().to_string();
+        let content =
+            std::fs::read_to_string(path).map_err(|e| SnapshotError::Read(file.clone(), e))?;
+        serde_json::from_str(&content).map_err(|e| SnapshotError::Invalid(file, e))
+    }
+
+    /// Writes the snapshot into the given file, the missing parent directories are created
+    pub fn save(&self, path: &Path) -> std::result::Result<(), SnapshotError> {
+        let file = path.display().to_string();
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent).map_err(|e| SnapshotError::Write(file.clone(), e))?;
+        }
+
+        let content =
+            serde_json::to_string(self).map_err(|e| SnapshotError::Invalid(file.clone(), e))?;
+        std::fs::write(path, content).map_err(|e| SnapshotError::Write(file, e))
+    }
+
+    fn insert(&mut self, ctx: &str, ns: &str, kind: &ResourceType, items: Vec<serde_json::Value>) {
+        self.contexts
+            .entry(ctx.to_string())
+            .or_default()
+            .entry(namespace_of(kind, ns).to_string())
+            .or_default()
+            .insert(kind.to_string(), items);
+    }
+
+    fn fail(&mut self, ctx: &str, ns: &str, kind: &ResourceType, failure: Failure) {
+        self.failures
+            .entry(ctx.to_string())
+            .or_default()
+            .entry(namespace_of(kind, ns).to_string())
+            .or_default()
+            .insert(kind.to_string(), failure);
+    }
+
+    /// Replaces all the resources of the kind in the given context with the given ones,
+    /// grouped by their namespaces. The given ones are listed, so unique by namespace and name
+    pub fn replace(&mut self, ctx: &str, kind: &ResourceType, items: Vec<serde_json::Value>) {
+        let namespaces = self.contexts.entry(ctx.to_string()).or_default();
+        namespaces.values_mut().for_each(|kinds| {
+            kinds.remove(&kind.to_string());
+        });
+
+        for item in items {
+            namespaces
+                .entry(namespace_of(kind, namespace(&item)).to_string())
+                .or_default()
+                .entry(kind.to_string())
+                .or_default()
+                .push(item);
+        }
+    }
+
+    /// Inserts the given resource, or replaces the one with the same namespace and name
+    pub fn upsert(&mut self, ctx: &str, kind: &ResourceType, item: serde_json::Value) {
+        let items = self
+            .contexts
+            .entry(ctx.to_string())
+            .or_default()
+            .entry(namespace_of(kind, namespace(&item)).to_string())
+            .or_default()
+            .entry(kind.to_string())
+            .or_default();
+
+        match items.iter_mut().find(|i| name(i) == name(&item)) {
+            Some(i) => *i = item,
+            None => items.push(item),
+        }
+    }
+
+    /// Returns the number of the kinds which could not be listed into the snapshot
+    pub fn failed(&self) -> usize {
+        self.failures
+            .values()
+            .flat_map(|namespaces| namespaces.values())
+            .map(BTreeMap::len)
+            .sum()
+    }
+
+    /// Returns the number of the resources in the snapshot
+    pub fn len(&self) -> usize {
+        self.contexts
+            .values()
+            .flat_map(|namespaces| namespaces.values())
+            .flat_map(|kinds| kinds.values())
+            .map(Vec::len)
+            .sum()
+    }
+}
+
+/// The namespaces not in the snapshot have no resources
+impl DataSource for Snapshot {
+    fn list(&self, ctx: &str, ns: &str, kind: &ResourceType) -> Vec<serde_json::Value> {
+        self.contexts
+            .get(ctx)
+            .and_then(|namespaces| namespaces.get(namespace_of(kind, ns)))
+            .and_then(|kinds| kinds.get(&kind.to_string()))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    fn namespaces(&self, ctx: &str) -> BTreeSet<String> {
+        self.contexts
+            .get(ctx)
+            .into_iter()
+            .flat_map(|namespaces| namespaces.keys())
+            .filter(|ns| !ns.is_empty())
+            .cloned()
+            .collect()
+    }
+
+    fn has_context(&self, ctx: &str) -> bool {
+        self.contexts.contains_key(ctx)
+    }
+}
+
+/// The cluster-scoped kinds are not namespaced, so they are stored once per context
+fn namespace_of<'a>(kind: &ResourceType, ns: &'a str) -> &'a str {
+    match kind.is_cluster_scoped() {
+        true => "",
+        false => ns,
+    }
+}
+
+fn namespace(item: &serde_json::Value) -> &str {
+    item["metadata"]["namespace"].as_str().unwrap_or_default()
+}
+
+fn name(item: &serde_json::Value) -> &str {
+    item["metadata"]["name"].as_str().unwrap_or_default()
+}
+
+/// The snapshot file used if not given, '~/.kubesql/snapshot.json'
+pub fn default_path() -> PathBuf {
+    let home = std::env::var_os("HOME").unwrap_or_default();
+    PathBuf::from(home).join(".kubesql").join("snapshot.json")
+}
+
+/// Lists all the supported kinds in the given contexts and namespaces
+pub async fn take(contexts: &[String], namespaces: &[String]) -> Result<Snapshot> {
+    let mut snapshot = Snapshot::default();
+
+    for ctx in contexts {
+        for ns in namespaces {
+            let api = ApiBuilder::builder()
+                .context(ctx.clone())
+                .namespace(ns.clone())
+                .build()
+                .await?;
+
+            for kind in ResourceType::all() {
+                // The cluster-scoped kinds are listed once per context
+                if kind.is_cluster_scoped() && ns != &namespaces[0] {
+                    continue;
+                }
+
+                let items = match executor::list_kind(kind, &api, &Default::default()).await {
+                    Ok(items) => items,
+                    // The optional kinds are skipped on any error, i.e. metrics-server unavailable
+                    Err(e) if kind.is_optional() => {
+                        tracing::warn!("Skipping {} of '{}/{}': {}", kind, ctx, ns, e);
+                        continue;
+                    }
+                    Err(e) => return Err(e),
+                };
+                // i.e. forbidden or a missing CRD, the snapshot is taken without the kind
+                if let Some(failure) = api.failure(kind) {
+                    tracing::debug!("Skipping {} of '{}/{}': {}", kind, ctx, ns, failure);
+                    snapshot.fail(ctx, ns, kind, failure);
+                    continue;
+                }
+                snapshot.insert(ctx, ns, kind, items);
+            }
+        }
+    }
+
+    Ok(snapshot)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(ns: &str, name: &str) -> serde_json::Value {
+        serde_json::json!({ "metadata": { "namespace": ns, "name": name } })
+    }
+
+    #[test]
+    fn replaces_the_resources_of_the_kind_by_namespace() {
+        let mut snapshot = Snapshot::default();
+        snapshot.replace("ctx", &ResourceType::Pod, vec![item("a", "old")]);
+        snapshot.replace(
+            "ctx",
+            &ResourceType::Pod,
+            vec![item("a", "p1"), item("b", "p2")],
+        );
+        snapshot.replace("ctx", &ResourceType::Node, vec![item("", "n1")]);
+
+        let namespaces = &snapshot.contexts["ctx"];
+        assert_eq!(namespaces["a"]["pod"], vec![item("a", "p1")]);
+        assert_eq!(namespaces["b"]["pod"], vec![item("b", "p2")]);
+        assert_eq!(namespaces[""]["node"], vec![item("", "n1")]);
+        assert_eq!(snapshot.len(), 3);
+    }
+}
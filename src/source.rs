@@ -0,0 +1,133 @@
+// Copyright (c) 2021 Dentrax
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software is furnished to do so,
+// subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+use crate::manifests::{self, ManifestError};
+use crate::parser::{self, ParserError, ResourceType};
+use crate::snapshot::SnapshotError;
+use crate::validator::{self, ValidationError};
+use std::collections::{BTreeMap, BTreeSet};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum SourceError {
+    #[error(transparent)]
+    Manifest(#[from] ManifestError),
+
+    #[error(transparent)]
+    Snapshot(#[from] SnapshotError),
+
+    #[error(transparent)]
+    Validation(#[from] ValidationError),
+
+    #[error(transparent)]
+    Parser(#[from] ParserError),
+}
+
+/// Reads the resources of the contexts in place of their API Servers, i.e. a snapshot, the
+/// manifests of a directory or the cache of the daemon. No request is made to the clusters
+pub trait DataSource: Send + Sync {
+    /// Returns the resources of the kind in the given context and namespace, the cluster-scoped
+    /// kinds are in all the namespaces
+    fn list(&self, context: &str, namespace: &str, kind: &ResourceType) -> Vec<serde_json::Value>;
+
+    /// Returns the namespaces having any resource in the given context, in order
+    fn namespaces(&self, context: &str) -> BTreeSet<String>;
+
+    /// Whether the resources of the given context are in the data source
+    fn has_context(&self, context: &str) -> bool;
+}
+
+/// Opens the data source of a context given with its scheme, i.e. 'file:./manifests', along
+/// with the rest of the context after the scheme, i.e. './manifests'
+type Open = fn(&str, &str) -> Result<Box<dyn DataSource>, SourceError>;
+
+/// The schemes of the contexts given in FROM statement and the data sources they are read from
+const SCHEMES: [(&str, Open); 1] = [(manifests::PREFIX, open_manifests)];
+
+fn open_manifests(context: &str, path: &str) -> Result<Box<dyn DataSource>, SourceError> {
+    Ok(Box::new(manifests::load(context, path)?))
+}
+
+/// The data sources of the contexts of a query, selected by the scheme of each context given in
+/// FROM statement. The contexts without a scheme are read from the default data source if
+/// given, i.e. the snapshot of --offline, and from their API Servers otherwise
+#[derive(Default)]
+pub struct Sources<'a> {
+    default: Option<&'a dyn DataSource>,
+    /// The data sources of the contexts with a scheme by their names
+    opened: BTreeMap<String, Box<dyn DataSource>>,
+}
+
+impl<'a> Sources<'a> {
+    pub fn new(default: Option<&'a dyn DataSource>) -> Sources<'a> {
+        Sources {
+            default,
+            opened: BTreeMap::new(),
+        }
+    }
+
+    /// Opens the data source of each given context with a scheme, the opened ones are kept
+    pub fn open(mut self, contexts: &[String]) -> Result<Sources<'a>, SourceError> {
+        for context in contexts {
+            if self.opened.contains_key(context) {
+                continue;
+            }
+            if let Some((scheme, open)) = SCHEMES.iter().find(|(s, _)| context.starts_with(s)) {
+                let source = open(context, &context[scheme.len()..])?;
+                self.opened.insert(context.clone(), source);
+            }
+        }
+        Ok(self)
+    }
+
+    /// Returns the data source of the given context, None if it is read from its API Server
+    pub fn get(&self, context: &str) -> Option<&dyn DataSource> {
+        match self.opened.get(context) {
+            Some(source) => Some(source.as_ref()),
+            None => self.default,
+        }
+    }
+
+    /// Returns the given contexts read from their API Servers, in order
+    pub fn live(&self, contexts: &[String]) -> Vec<String> {
+        contexts
+            .iter()
+            .filter(|c| self.get(c).is_none())
+            .cloned()
+            .collect()
+    }
+
+    /// Fails if any of the given contexts is not in its data source, the ones read from their
+    /// API Servers must be in the kubeconfig
+    pub fn validate_contexts(&self, contexts: &[String]) -> Result<(), SourceError> {
+        let not_found: Vec<String> = contexts
+            .iter()
+            .filter(|c| self.get(c).is_some_and(|source| !source.has_context(c)))
+            .cloned()
+            .collect();
+        if !not_found.is_empty() {
+            return Err(SnapshotError::ContextNotFound(not_found).into());
+        }
+
+        let live = self.live(contexts);
+        if !live.is_empty() {
+            validator::validate_contexts(parser::parse_kubeconfig()?, &live)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,323 @@
+// Copyright (c) 2021 Dentrax
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software is furnished to do so,
+// subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+use std::fmt;
+
+/// The syntax of a template, only the delimiters and the array steps differ
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Syntax {
+    /// i.e. '{.items[*].name}'
+    JsonPath,
+    /// i.e. '{{range .items}}{{.name}}{{end}}'
+    GoTemplate,
+}
+
+impl Syntax {
+    fn delimiters(&self) -> (&'static str, &'static str) {
+        match self {
+            Syntax::JsonPath => ("{", "}"),
+            Syntax::GoTemplate => ("{{", "}}"),
+        }
+    }
+}
+
+impl fmt::Display for Syntax {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Syntax::JsonPath => write!(f, "jsonpath"),
+            Syntax::GoTemplate => write!(f, "go-template"),
+        }
+    }
+}
+
+/// A step of a field path
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Step {
+    /// i.e. '.name' or "['status.phase']"
+    Field(String),
+    /// i.e. '[0]'
+    Index(usize),
+    /// i.e. '[*]', each element of the array
+    All,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Node {
+    Text(String),
+    Path(Vec<Step>),
+    /// Renders the nodes for each value of the path, the paths inside are relative to the value
+    Range(Vec<Step>, Vec<Node>),
+}
+
+/// A parsed template of kubectl's '-o jsonpath' or '-o go-template' outputs, rendered against the
+/// results as a list, i.e. '{"items": [...]}'. Only the field paths, the ranges over the arrays
+/// and the string literals are supported, i.e. '{range .items[*]}{.name}{"\n"}{end}'
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Template {
+    pub syntax: Syntax,
+    /// The template as given
+    pub source: String,
+    nodes: Vec<Node>,
+}
+
+impl Template {
+    pub fn parse(source: &str, syntax: Syntax) -> Result<Template, String> {
+        let (open, close) = syntax.delimiters();
+
+        // The nodes of the enclosing ranges, the last one is the innermost
+        let mut stack: Vec<(Vec<Step>, Vec<Node>)> = vec![(vec![], vec![])];
+        let mut rest = source;
+
+        while !rest.is_empty() {
+            let (text, action) = match rest.find(open) {
+                Some(i) => (&rest[..i], Some(&rest[i + open.len()..])),
+                None => (rest, None),
+            };
+            if !text.is_empty() {
+                stack
+                    .last_mut()
+                    .unwrap()
+                    .1
+                    .push(Node::Text(text.to_string()));
+            }
+            // A delimiter ending the template opens an empty action, unclosed as well
+            let action = match action {
+                Some(action) => action,
+                None => break,
+            };
+
+            let end = find_close(action, close)
+                .ok_or_else(|| format!("unclosed action, expected '{}': {}", close, action))?;
+            let expr = action[..end].trim();
+            rest = &action[end + close.len()..];
+
+            if let Some(path) = expr.strip_prefix("range ") {
+                stack.push((parse_path(path.trim())?, vec![]));
+            } else if expr == "end" {
+                if stack.len() == 1 {
+                    return Err("'end' without 'range'".to_string());
+                }
+                let (path, nodes) = stack.pop().unwrap();
+                stack.last_mut().unwrap().1.push(Node::Range(path, nodes));
+            } else if expr.starts_with('"') {
+                stack
+                    .last_mut()
+                    .unwrap()
+                    .1
+                    .push(Node::Text(parse_string(expr)?));
+            } else {
+                stack
+                    .last_mut()
+                    .unwrap()
+                    .1
+                    .push(Node::Path(parse_path(expr)?));
+            }
+        }
+
+        if stack.len() > 1 {
+            return Err("'range' without 'end'".to_string());
+        }
+
+        Ok(Template {
+            syntax,
+            source: source.to_string(),
+            nodes: stack.pop().unwrap().1,
+        })
+    }
+
+    /// Renders the template against the given value, the missing fields are rendered empty
+    pub fn render(&self, value: &serde_json::Value) -> String {
+        let mut output = String::new();
+        render(&self.nodes, value, &mut output);
+        output
+    }
+}
+
+/// Returns the index of the close delimiter outside of the string literals
+fn find_close(action: &str, close: &str) -> Option<usize> {
+    let mut quoted = false;
+    let mut escaped = false;
+
+    for (i, c) in action.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            _ if !quoted && action[i..].starts_with(close) => return Some(i),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Parses a double quoted string literal, i.e. '"\n"'
+fn parse_string(expr: &str) -> Result<String, String> {
+    let inner = expr
+        .strip_prefix('"')
+        .and_then(|e| e.strip_suffix('"'))
+        .ok_or_else(|| format!("invalid string literal: {}", expr))?;
+
+    let mut value = String::new();
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        match (c, c == '\\') {
+            (_, true) => match chars.next() {
+                Some('n') => value.push('\n'),
+                Some('t') => value.push('\t'),
+                Some(c) => value.push(c),
+                None => return Err(format!("invalid string literal: {}", expr)),
+            },
+            (c, false) => value.push(c),
+        }
+    }
+    Ok(value)
+}
+
+/// Parses a field path relative to the current value, i.e. ".items[*].fields['status.phase']"
+fn parse_path(expr: &str) -> Result<Vec<Step>, String> {
+    let path = expr.strip_prefix('$').unwrap_or(expr);
+    if !path.is_empty() && !path.starts_with('.') && !path.starts_with('[') {
+        return Err(format!(
+            "unsupported expression, expected a field path: {}",
+            expr
+        ));
+    }
+
+    let mut steps = Vec::new();
+    let mut chars = path.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '.' => {
+                let mut field = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c == '.' || c == '[' {
+                        break;
+                    }
+                    field.push(c);
+                    chars.next();
+                }
+                // '.' alone is the current value
+                if !field.is_empty() {
+                    steps.push(Step::Field(field));
+                }
+            }
+            '[' => {
+                let mut index = String::new();
+                for c in chars.by_ref() {
+                    if c == ']' {
+                        break;
+                    }
+                    index.push(c);
+                }
+
+                let index = index.trim();
+                let quoted = index
+                    .strip_prefix('\'')
+                    .and_then(|i| i.strip_suffix('\''))
+                    .or_else(|| index.strip_prefix('"').and_then(|i| i.strip_suffix('"')));
+                steps.push(match (index, quoted) {
+                    (_, Some(field)) => Step::Field(field.to_string()),
+                    ("*", _) => Step::All,
+                    _ => Step::Index(index.parse().map_err(|_| {
+                        format!("unsupported index, expected a number or '*': [{}]", index)
+                    })?),
+                });
+            }
+            _ => return Err(format!("unexpected '{}' in the field path: {}", c, expr)),
+        }
+    }
+
+    Ok(steps)
+}
+
+/// Returns the values of the path, the ones of '[*]' are flattened
+fn resolve<'a>(steps: &[Step], value: &'a serde_json::Value) -> Vec<&'a serde_json::Value> {
+    let mut values = vec![value];
+    for step in steps {
+        values = values
+            .into_iter()
+            .flat_map(|v| match step {
+                Step::Field(f) => v.get(f).into_iter().collect(),
+                Step::Index(i) => v.get(i).into_iter().collect(),
+                Step::All => match v {
+                    serde_json::Value::Array(a) => a.iter().collect(),
+                    serde_json::Value::Object(o) => o.values().collect(),
+                    _ => vec![],
+                },
+            })
+            .collect();
+    }
+    values
+}
+
+fn render(nodes: &[Node], value: &serde_json::Value, output: &mut String) {
+    for node in nodes {
+        match node {
+            Node::Text(text) => output.push_str(text),
+            Node::Path(steps) => {
+                let values = resolve(steps, value)
+                    .into_iter()
+                    .filter(|v| !v.is_null())
+                    .map(|v| match v {
+                        serde_json::Value::String(s) => s.clone(),
+                        _ => v.to_string(),
+                    })
+                    .collect::<Vec<String>>();
+                output.push_str(&values.join(" "));
+            }
+            Node::Range(steps, inner) => {
+                // i.e. '{{range .items}}', the elements of the array
+                let mut values = resolve(steps, value);
+                if let ([serde_json::Value::Array(a)], false) =
+                    (values.as_slice(), steps.contains(&Step::All))
+                {
+                    values = a.iter().collect();
+                }
+                for v in values {
+                    render(inner, v, output);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_the_unclosed_actions() {
+        for source in &["name: {{", "name: {{ .metadata.name", "{{ \"}}"] {
+            let error = Template::parse(source, Syntax::GoTemplate).err().unwrap();
+            assert!(
+                error.starts_with("unclosed action"),
+                "{}: {}",
+                source,
+                error
+            );
+        }
+        let error = Template::parse("name: {", Syntax::JsonPath).err().unwrap();
+        assert!(error.starts_with("unclosed action"), "{}", error);
+    }
+
+    #[test]
+    fn parses_the_text_after_the_last_action() {
+        let template = Template::parse("{{ .metadata.name }} done", Syntax::GoTemplate).unwrap();
+        assert_eq!(template.nodes.len(), 2);
+    }
+}
@@ -0,0 +1,207 @@
+// Copyright (c) 2021 Dentrax
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software is furnished to do so,
+// subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+use crate::printer::ResultTable;
+use k8s_openapi::chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum TrendError {
+    #[error("Unable to read the trends '{0}': {1}")]
+    Read(String, std::io::Error),
+
+    #[error("Unable to write the trends '{0}': {1}")]
+    Write(String, std::io::Error),
+
+    #[error("Invalid trend sample at line {0}: {1}")]
+    Invalid(usize, serde_json::Error),
+
+    #[error("The query was never run with --record: {0}")]
+    NotRecorded(String),
+}
+
+/// The result summary of a run of a query, one line of the trends file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Sample {
+    pub timestamp: DateTime<Utc>,
+    pub query: String,
+    pub counts: Vec<Count>,
+}
+
+/// The number of the result rows in a namespace of a context. Both are empty if the results
+/// have no such column, i.e. the namespace of the cluster-scoped kinds
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Count {
+    pub context: String,
+    pub namespace: String,
+    pub rows: usize,
+}
+
+/// The count of a namespace of a context in a sample, with its change since the previous sample
+#[derive(Debug, Clone, Serialize)]
+pub struct Point {
+    pub timestamp: DateTime<Utc>,
+    pub context: String,
+    pub namespace: String,
+    pub rows: usize,
+    /// None on the first sample
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub change: Option<i64>,
+}
+
+impl Sample {
+    /// Counts the rows of the given results per context and namespace. The rows deduped into many
+    /// contexts are counted in each of them
+    pub fn new(query: &str, tables: &[ResultTable]) -> Sample {
+        let mut counts: BTreeMap<(String, String), usize> = BTreeMap::new();
+        for table in tables {
+            let position = |name: &str| table.columns.iter().position(|c| c == name);
+            let context = position("context").or_else(|| position("contexts"));
+            let namespace = position("namespace");
+
+            for row in &table.rows {
+                let namespace = namespace.map(|i| row[i].clone()).unwrap_or_default();
+                let contexts = match context {
+                    Some(i) => row[i].split(',').map(str::to_string).collect(),
+                    None => vec![String::new()],
+                };
+                for context in contexts {
+                    *counts.entry((context, namespace.clone())).or_default() += 1;
+                }
+            }
+        }
+
+        Sample {
+            timestamp: Utc::now(),
+            query: normalize(query),
+            counts: counts
+                .into_iter()
+                .map(|((context, namespace), rows)| Count {
+                    context,
+                    namespace,
+                    rows,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// The trends file, '~/.kubesql/trends.jsonl'
+pub fn path() -> PathBuf {
+    let home = std::env::var_os("HOME").unwrap_or_default();
+    PathBuf::from(home).join(".kubesql").join("trends.jsonl")
+}
+
+/// The query with its whitespace collapsed and without the trailing semicolon,
+/// so the same query matches however it is formatted
+fn normalize(query: &str) -> String {
+    query
+        .trim()
+        .trim_end_matches(';')
+        .split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ")
+}
+
+/// Appends the given sample to the trends file
+pub fn record(sample: &Sample) -> Result<(), TrendError> {
+    let path = path();
+    let file = path.display().to_string();
+
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| TrendError::Write(file.clone(), e))?;
+    }
+
+    serde_json::to_string(sample)
+        .map_err(std::io::Error::from)
+        .and_then(|line| {
+            let mut f = OpenOptions::new().create(true).append(true).open(&path)?;
+            writeln!(f, "{}", line)
+        })
+        .map_err(|e| TrendError::Write(file, e))
+}
+
+/// Returns all the samples, the oldest first
+pub fn load() -> Result<Vec<Sample>, TrendError> {
+    let path = path();
+    let content = match std::fs::read_to_string(&path) {
+        Ok(c) => c,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(TrendError::Read(path.display().to_string(), e)),
+    };
+
+    content
+        .lines()
+        .enumerate()
+        .filter(|(_, l)| !l.trim().is_empty())
+        .map(|(i, l)| serde_json::from_str(l).map_err(|e| TrendError::Invalid(i + 1, e)))
+        .collect()
+}
+
+/// Returns the counts of the last samples of the given query, the oldest first. A namespace missing
+/// from a sample but counted in the previous one is returned with no rows, i.e. all deleted
+pub fn trend(query: &str, limit: usize) -> Result<Vec<Point>, TrendError> {
+    let query = normalize(query);
+    let samples = load()?
+        .into_iter()
+        .filter(|s| s.query == query)
+        .collect::<Vec<Sample>>();
+    if samples.is_empty() {
+        return Err(TrendError::NotRecorded(query));
+    }
+
+    let shown = samples.len().saturating_sub(limit);
+    let mut points = Vec::new();
+    let mut previous: Option<BTreeMap<(String, String), usize>> = None;
+    for (i, sample) in samples.into_iter().enumerate() {
+        let timestamp = sample.timestamp;
+        let mut counts: BTreeMap<(String, String), usize> = sample
+            .counts
+            .into_iter()
+            .map(|c| ((c.context, c.namespace), c.rows))
+            .collect();
+        if let Some(previous) = &previous {
+            previous.keys().for_each(|key| {
+                counts.entry(key.clone()).or_default();
+            });
+        }
+
+        // The samples before the shown ones are only compared against
+        if i >= shown {
+            points.extend(counts.iter().map(|(key, rows)| {
+                Point {
+                    timestamp,
+                    context: key.0.clone(),
+                    namespace: key.1.clone(),
+                    rows: *rows,
+                    change: previous
+                        .as_ref()
+                        .map(|p| *rows as i64 - p.get(key).copied().unwrap_or_default() as i64),
+                }
+            }));
+        }
+        previous = Some(counts.into_iter().filter(|(_, rows)| *rows > 0).collect());
+    }
+
+    Ok(points)
+}
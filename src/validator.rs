@@ -17,7 +17,19 @@
 // IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
 // CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
 
+use crate::catalog;
+use crate::config::Guard;
+use crate::contexts;
+use crate::diagnostic;
+use crate::eval;
+use crate::executor::Estimate;
+use crate::parser::ResourceType;
+use crate::planner::{self, Projection, Query};
+use k8s_openapi::apimachinery::pkg::version::Info;
 use kube::config::Kubeconfig;
+use std::fmt;
+use std::io::IsTerminal;
+use std::str::FromStr;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -25,6 +37,29 @@ use thiserror::Error;
 pub enum ValidationError {
     #[error("ValidationError: Context not found in your KUBECONFIG: {0:?}")]
     ContextNotFound(Vec<String>),
+
+    #[error("ValidationError: {0} is not allowed on the protected contexts: {1:?}")]
+    ProtectedContext(String, Vec<String>),
+
+    #[error(
+        "ValidationError: {0} on the protected contexts {1:?} requires --i-know-what-im-doing"
+    )]
+    Unconfirmed(String, Vec<String>),
+
+    #[error(
+        "ValidationError: The query makes about {0} list calls, more than {1} requires --force"
+    )]
+    Costly(usize, usize),
+}
+
+/// The contexts the mutations are guarded on, i.e. INSERT and --exec
+#[derive(Debug, Clone, Default)]
+pub struct Protection {
+    /// The LIKE patterns of the context names, i.e. 'prod-%'
+    pub contexts: Vec<String>,
+    pub guard: Guard,
+    /// Whether --i-know-what-im-doing is given
+    pub confirmed: bool,
 }
 
 pub fn validate_contexts(kubeconfig: Kubeconfig, ctxs: &[String]) -> Result<(), ValidationError> {
@@ -41,3 +76,195 @@ pub fn validate_contexts(kubeconfig: Kubeconfig, ctxs: &[String]) -> Result<(),
 
     Ok(())
 }
+
+/// Fails if the given mutation would run on any protected context, unless confirmed and not denied
+pub fn validate_mutation(
+    protection: &Protection,
+    mutation: &str,
+    ctxs: &[String],
+) -> Result<(), ValidationError> {
+    let protected = ctxs
+        .iter()
+        .filter(|c| protection.contexts.iter().any(|p| eval::like(c, p, false)))
+        .cloned()
+        .collect::<Vec<String>>();
+
+    match protection.guard {
+        _ if protected.is_empty() => Ok(()),
+        Guard::Deny => Err(ValidationError::ProtectedContext(
+            mutation.to_string(),
+            protected,
+        )),
+        Guard::Confirm if !protection.confirmed => Err(ValidationError::Unconfirmed(
+            mutation.to_string(),
+            protected,
+        )),
+        Guard::Confirm => {
+            tracing::warn!(
+                "Running {} on the protected contexts {:?}",
+                mutation,
+                protected
+            );
+            Ok(())
+        }
+    }
+}
+
+/// Asks whether to run a query making more list calls than allowed, fails if not confirmed or if
+/// the input is not a terminal to ask on
+pub fn confirm_cost(estimate: &Estimate, max_calls: Option<usize>) -> Result<(), ValidationError> {
+    let calls = estimate.list_calls();
+    let max_calls = match max_calls {
+        Some(max_calls) if calls > max_calls => max_calls,
+        _ => return Ok(()),
+    };
+
+    let stdin = std::io::stdin();
+    if stdin.is_terminal() {
+        eprint!(
+            "The query makes about {} list calls to {} contexts, run it anyway? [y/N] ",
+            calls, estimate.contexts
+        );
+        // Not confirmed if the answer cannot be read
+        let mut answer = String::new();
+        stdin.read_line(&mut answer).ok();
+        if matches!(answer.trim().to_lowercase().as_str(), "y" | "yes") {
+            return Ok(());
+        }
+    }
+
+    Err(ValidationError::Costly(calls, max_calls))
+}
+
+/// A predicate of WHERE statement on a field that does not exist on its kind
+#[derive(Debug, Clone)]
+pub struct UnknownField {
+    /// i.e. 'pod.status.phaze'
+    pub field: String,
+    /// i.e. 'pod.status.phaze = Running'
+    pub predicate: String,
+    /// The closest known field, i.e. 'pod.status.phase'
+    pub suggestion: Option<String>,
+}
+
+impl fmt::Display for UnknownField {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Unknown field '{}' in WHERE statement, it is always NULL",
+            self.field
+        )?;
+        match &self.suggestion {
+            Some(s) => write!(f, ", did you mean '{}'?", s),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Returns the predicates on the fields that do not exist in the schemas of their kinds, the
+/// subqueries and the compared fields included. They are evaluated anyway, as the field may be
+/// newer than the schemas, i.e. 'pod.status.phaze = Running' never matches.
+pub fn unknown_fields(queries: &[Query]) -> Vec<UnknownField> {
+    let mut unknown = Vec::new();
+
+    for q in queries {
+        if q.kind.eq_ignore_ascii_case(planner::CLUSTER) {
+            unknown.extend(unknown_cluster_field(q));
+            continue;
+        }
+
+        let kind = match ResourceType::from_str(&q.kind.to_lowercase()) {
+            Ok(kind) => kind,
+            Err(_) => continue,
+        };
+
+        for path in std::iter::once(q.path()).chain(q.other.iter().map(|o| planner::path(o))) {
+            if !catalog::contains(&kind, &path) {
+                unknown.push(UnknownField {
+                    field: format!("{}.{}", q.kind, path),
+                    predicate: q.to_string(),
+                    suggestion: catalog::suggest(&kind, &path).map(|s| format!("{}.{}", q.kind, s)),
+                });
+            }
+        }
+
+        if let Some(subquery) = &q.subquery {
+            let projection = &subquery.projection;
+            if let Ok(kind) = ResourceType::from_str(&projection.kind) {
+                if !catalog::contains(&kind, &projection.path()) {
+                    unknown.push(UnknownField {
+                        field: format!("{}.{}", projection.kind, projection.path()),
+                        predicate: q.to_string(),
+                        suggestion: catalog::suggest(&kind, &projection.path())
+                            .map(|s| format!("{}.{}", projection.kind, s)),
+                    });
+                }
+            }
+            unknown.extend(unknown_fields(&subquery.queries));
+        }
+    }
+
+    unknown
+}
+
+/// Renames the fields of the queries and the SELECT fields that do not exist in the schemas of
+/// their kinds to the ones they match regardless of the case or the plural, the subqueries and
+/// the compared fields included, i.e. 'pod.Status.Phase' to 'pod.status.phase'
+pub fn resolve_fields(queries: &mut [Query], projections: &mut [Projection]) {
+    for q in queries.iter_mut() {
+        resolve(&q.kind, &mut q.fields);
+        if let Some(other) = q.other.as_mut() {
+            resolve(&q.kind, other);
+        }
+        if let Some(subquery) = q.subquery.as_mut() {
+            resolve(&subquery.projection.kind, &mut subquery.projection.fields);
+            resolve_fields(&mut subquery.queries, &mut []);
+        }
+    }
+
+    for p in projections.iter_mut() {
+        resolve(&p.kind, &mut p.fields);
+    }
+}
+
+/// Renames the given fields of the kind as in its schema, if they are unknown as given
+fn resolve(kind: &str, fields: &mut Vec<String>) {
+    let kind = match ResourceType::from_str(&kind.to_lowercase()) {
+        Ok(kind) => kind,
+        Err(_) => return,
+    };
+
+    if catalog::contains(&kind, &planner::path(fields)) {
+        return;
+    }
+
+    if let Some(resolved) = catalog::resolve(&kind, fields) {
+        let path = planner::path(&resolved);
+        if catalog::contains(&kind, &path) {
+            tracing::debug!(field = %planner::path(fields), %path, "resolved");
+            *fields = resolved;
+        }
+    }
+}
+
+/// Returns the field of the given 'cluster' predicate if the version of an API Server has no such
+/// field, i.e. 'cluster.verson'
+fn unknown_cluster_field(q: &Query) -> Option<UnknownField> {
+    let cluster = contexts::cluster(&Info::default());
+    let fields = cluster.as_object()?;
+    let path = q.path();
+    if fields.contains_key(&path) {
+        return None;
+    }
+
+    Some(UnknownField {
+        field: format!("{}.{}", q.kind, path),
+        predicate: q.to_string(),
+        suggestion: fields
+            .keys()
+            .map(|k| (diagnostic::distance(k, &path), k))
+            .filter(|(d, _)| *d <= 3)
+            .min()
+            .map(|(_, k)| format!("{}.{}", q.kind, k)),
+    })
+}
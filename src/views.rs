@@ -0,0 +1,97 @@
+// Copyright (c) 2021 Dentrax
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software is furnished to do so,
+// subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+use crate::parser::ApiView;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum ViewError {
+    #[error("Unable to read the views '{0}': {1}")]
+    Read(String, std::io::Error),
+
+    #[error("Unable to write the views '{0}': {1}")]
+    Write(String, std::io::Error),
+
+    #[error("Invalid views '{0}': {1}")]
+    Invalid(String, serde_json::Error),
+
+    #[error("View already exists: {0}, use CREATE OR REPLACE VIEW to replace it")]
+    Exists(String),
+
+    #[error("View name is already a kubeconfig context: {0}")]
+    Context(String),
+
+    #[error("View not found: {0}")]
+    NotFound(String),
+}
+
+/// The views file, '~/.kubesql/views.json'
+pub fn path() -> PathBuf {
+    let home = std::env::var_os("HOME").unwrap_or_default();
+    PathBuf::from(home).join(".kubesql").join("views.json")
+}
+
+/// Returns the queries of the views by their names, none if the file does not exist
+pub fn load() -> Result<BTreeMap<String, String>, ViewError> {
+    let path = path();
+    let file = path.display().to_string();
+
+    match std::fs::read_to_string(&path) {
+        Ok(content) => serde_json::from_str(&content).map_err(|e| ViewError::Invalid(file, e)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(BTreeMap::new()),
+        Err(e) => Err(ViewError::Read(file, e)),
+    }
+}
+
+fn save(views: &BTreeMap<String, String>) -> Result<(), ViewError> {
+    let path = path();
+    let file = path.display().to_string();
+
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| ViewError::Write(file.clone(), e))?;
+    }
+
+    serde_json::to_string_pretty(views)
+        .map_err(std::io::Error::from)
+        .and_then(|content| std::fs::write(&path, content))
+        .map_err(|e| ViewError::Write(file, e))
+}
+
+/// Saves the given view, fails if it already exists unless replaced
+pub fn create(view: &ApiView) -> Result<(), ViewError> {
+    let mut views = load()?;
+    if views.contains_key(&view.name) && !view.or_replace {
+        return Err(ViewError::Exists(view.name.clone()));
+    }
+
+    views.insert(view.name.clone(), view.query.clone());
+    save(&views)
+}
+
+/// Removes the given views, fails if any of them does not exist unless allowed
+pub fn drop(names: &[String], if_exists: bool) -> Result<(), ViewError> {
+    let mut views = load()?;
+    for name in names {
+        if views.remove(name).is_none() && !if_exists {
+            return Err(ViewError::NotFound(name.clone()));
+        }
+    }
+    save(&views)
+}